@@ -13,6 +13,8 @@ import net.mullvad.mullvadvpn.util.Intermittent
 
 private const val API_IP_ADDRESS_FILE = "api-ip-address.txt"
 private const val RELAYS_FILE = "relays.json"
+private const val RELAYS_SIGNATURE_FILE = "relays.json.sig"
+private const val RELAYS_ETAG_FILE = "relays.json.etag"
 
 class DaemonInstance(val vpnService: MullvadVpnService) {
     private enum class Command {
@@ -77,6 +79,7 @@ class DaemonInstance(val vpnService: MullvadVpnService) {
     private fun prepareFiles() {
         FileMigrator(File("/data/data/net.mullvad.mullvadvpn"), vpnService.filesDir).apply {
             migrate(RELAYS_FILE)
+            migrate(RELAYS_SIGNATURE_FILE)
             migrate("settings.json")
             migrate("daemon.log")
             migrate("daemon.old.log")
@@ -90,6 +93,11 @@ class DaemonInstance(val vpnService: MullvadVpnService) {
         FileResourceExtractor(vpnService).apply {
             extract(API_IP_ADDRESS_FILE, false)
             extract(RELAYS_FILE, shouldOverwriteRelayList)
+            extract(RELAYS_SIGNATURE_FILE, shouldOverwriteRelayList)
+        }
+
+        if (shouldOverwriteRelayList) {
+            File(vpnService.filesDir, RELAYS_ETAG_FILE).delete()
         }
     }
 
@@ -39,6 +39,11 @@ while [[ "$#" -gt 0 ]]; do
     shift
 done
 
+if [[ -n ${RELAY_LIST_SIGNING_KEY:-""} ]]; then
+    echo "Signing the bundled relay list and requiring signed relay lists"
+    CARGO_ARGS+=(--features relay-list-signatures)
+fi
+
 if [[ "$(uname -s)" == "Darwin" && -z ${TARGET:-""} ]]; then
     echo "Defaulting to universal macOS target since no target was provided"
     TARGET=(x86_64-apple-darwin aarch64-apple-darwin)
@@ -14,4 +14,6 @@ fi
 pkill -x "mullvad-gui" || true
 
 rm -f /var/cache/mullvad-vpn/relays.json
+rm -f /var/cache/mullvad-vpn/relays.json.sig
+rm -f /var/cache/mullvad-vpn/relays.json.etag
 rm -f /var/cache/mullvad-vpn/api-ip-address.txt
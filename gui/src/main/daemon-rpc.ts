@@ -40,6 +40,7 @@ import {
   IWireguardPublicKey,
   ISettings,
   ConnectionConfig,
+  Connectivity,
   DaemonEvent,
   RelaySettings,
   RelaySettingsUpdate,
@@ -827,6 +828,12 @@ function convertFromTunnelStateErrorCause(
     }
     case grpcTypes.ErrorState.Cause.SPLIT_TUNNEL_ERROR:
       return { reason: 'split_tunnel_error' };
+    case grpcTypes.ErrorState.Cause.CAPTIVE_PORTAL_LOGIN:
+      return { reason: 'captive_portal_login' };
+    case grpcTypes.ErrorState.Cause.ACCOUNT_EXPIRED:
+      return { reason: 'account_expired' };
+    case grpcTypes.ErrorState.Cause.CAPTIVE_PORTAL:
+      return { reason: 'captive_portal' };
     case grpcTypes.ErrorState.Cause.VPN_PERMISSION_DENIED:
       // VPN_PERMISSION_DENIED is only ever created on Android
       throw invalidErrorStateCause;
@@ -1120,11 +1127,39 @@ function convertFromDaemonEvent(data: grpcTypes.DaemonEvent): DaemonEvent {
     };
   }
 
+  const connectivity = data.getConnectivity();
+  if (connectivity !== undefined) {
+    return { connectivity: convertFromConnectivity(connectivity) };
+  }
+
+  const accountExpiry = data.getAccountExpiry();
+  if (accountExpiry !== undefined) {
+    return {
+      accountExpiry: {
+        expiry: accountExpiry.getExpiry()!.toDate().toISOString(),
+        expired: accountExpiry.getExpired(),
+        thresholdHours: accountExpiry.getThresholdHours(),
+      },
+    };
+  }
+
   return {
     appVersionInfo: data.getVersionInfo()!.toObject(),
   };
 }
 
+function convertFromConnectivity(data: grpcTypes.Connectivity): Connectivity {
+  switch (data.getState()) {
+    case grpcTypes.Connectivity.State.OFFLINE:
+      return 'offline';
+    case grpcTypes.Connectivity.State.CAPTIVE_PORTAL:
+      return 'captive-portal';
+    case grpcTypes.Connectivity.State.ONLINE:
+    default:
+      return 'online';
+  }
+}
+
 function convertFromKeygenEvent(data: grpcTypes.KeygenEvent): KeygenEvent {
   switch (data.getEvent()) {
     case grpcTypes.KeygenEvent.KeygenEvent.TOO_MANY_KEYS:
@@ -1308,7 +1308,8 @@ class ApplicationMain {
       const executable = resolveBin('mullvad-problem-report');
       const args = ['collect', '--output', reportPath];
       if (toRedact) {
-        args.push('--redact', toRedact);
+        // `--redact` takes a regular expression, so the string is escaped to be matched literally
+        args.push('--redact', toRedact.replace(/[.*+?^${}()|[\]\\]/g, '\\$&'));
       }
 
       return new Promise((resolve, reject) => {
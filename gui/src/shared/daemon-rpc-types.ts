@@ -40,7 +40,10 @@ export type ErrorStateCause =
         | 'set_dns_error'
         | 'start_tunnel_error'
         | 'is_offline'
-        | 'split_tunnel_error';
+        | 'split_tunnel_error'
+        | 'captive_portal_login'
+        | 'account_expired'
+        | 'captive_portal';
     }
   | { reason: 'set_firewall_policy_error'; details: FirewallPolicyError }
   | { reason: 'tunnel_parameter_error'; details: TunnelParameterError }
@@ -99,7 +102,17 @@ export type DaemonEvent =
   | { settings: ISettings }
   | { relayList: IRelayList }
   | { wireguardKey: KeygenEvent }
-  | { appVersionInfo: IAppVersionInfo };
+  | { appVersionInfo: IAppVersionInfo }
+  | { connectivity: Connectivity }
+  | { accountExpiry: IAccountExpiryEvent };
+
+export interface IAccountExpiryEvent {
+  expiry: string;
+  expired: boolean;
+  thresholdHours: number;
+}
+
+export type Connectivity = 'online' | 'offline' | 'captive-portal';
 
 export interface ITunnelStateRelayInfo {
   endpoint: ITunnelEndpoint;
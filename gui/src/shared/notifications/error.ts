@@ -51,7 +51,11 @@ export class ErrorNotificationProvider
 
       return {
         indicator:
-          this.context.tunnelState.details.cause.reason === 'is_offline' ? 'warning' : 'error',
+          this.context.tunnelState.details.cause.reason === 'is_offline' ||
+          this.context.tunnelState.details.cause.reason === 'captive_portal_login' ||
+          this.context.tunnelState.details.cause.reason === 'captive_portal'
+            ? 'warning'
+            : 'error',
         title: !this.context.tunnelState.details.blockFailure
           ? messages.pgettext('in-app-notifications', 'BLOCKING INTERNET')
           : messages.pgettext('in-app-notifications', 'NETWORK TRAFFIC MIGHT BE LEAKING'),
@@ -143,6 +147,21 @@ function getMessage(errorDetails: IErrorState, accountExpiry?: string): string {
           'notifications',
           'Unable to communicate with Mullvad kernel driver. Try reconnecting or contact support.',
         );
+      case 'captive_portal_login':
+        return messages.pgettext(
+          'notifications',
+          'Temporarily allowing the traffic needed to log in to the network.',
+        );
+      case 'account_expired':
+        return messages.pgettext(
+          'notifications',
+          'You have no more VPN time left on this account. Please log in on our website to buy more credit.',
+        );
+      case 'captive_portal':
+        return messages.pgettext(
+          'notifications',
+          'The network requires you to log in before you can connect.',
+        );
     }
   }
 }
@@ -20,6 +20,9 @@ const config = {
   extraResources: [
     { from: distAssets('ca.crt'), to: '.' },
     { from: distAssets('relays.json'), to: '.' },
+    ...(fs.existsSync(distAssets('relays.json.sig'))
+      ? [{ from: distAssets('relays.json.sig'), to: '.' }]
+      : []),
     { from: distAssets('api-ip-address.txt'), to: '.' },
     { from: root('CHANGELOG.md'), to: '.' },
   ],
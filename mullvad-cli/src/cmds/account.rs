@@ -1,7 +1,7 @@
-use crate::{new_rpc_client, Command, Error, Result};
-use clap::value_t_or_exit;
+use crate::{format, new_rpc_client, Command, Error, Result};
+use clap::{value_t_or_exit, values_t_or_exit};
 use itertools::Itertools;
-use mullvad_management_interface::{types::Timestamp, Code};
+use mullvad_management_interface::{types, Code};
 use mullvad_types::account::AccountToken;
 use std::io::{self, Write};
 
@@ -38,6 +38,25 @@ impl Command for Account {
                 clap::SubCommand::with_name("create")
                     .about("Creates a new account and sets it as the active one"),
             )
+            .subcommand(
+                clap::SubCommand::with_name("expiry-notifications")
+                    .about("Control when to be notified before the account expires")
+                    .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        clap::SubCommand::with_name("get")
+                            .about("Display the notification thresholds"),
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("set")
+                            .about("Change the notification thresholds")
+                            .arg(
+                                clap::Arg::with_name("hours")
+                                    .help("Hours before expiry at which to notify")
+                                    .multiple(true)
+                                    .required(false),
+                            ),
+                    ),
+            )
             .subcommand(
                 clap::SubCommand::with_name("redeem")
                     .about("Redeems a voucher")
@@ -73,6 +92,8 @@ impl Command for Account {
             self.set(None).await
         } else if let Some(_matches) = matches.subcommand_matches("create") {
             self.create().await
+        } else if let Some(matches) = matches.subcommand_matches("expiry-notifications") {
+            self.handle_expiry_notifications(matches).await
         } else if let Some(matches) = matches.subcommand_matches("redeem") {
             let voucher = value_t_or_exit!(matches.value_of("voucher"), String);
             self.redeem_voucher(voucher).await
@@ -106,7 +127,7 @@ impl Account {
                 .into_inner();
             println!(
                 "Expires at     : {}",
-                Self::format_expiry(&expiry.expiry.unwrap())
+                format::local_time(&expiry.expiry.unwrap())
             );
         } else {
             println!("No account configured");
@@ -114,6 +135,37 @@ impl Account {
         Ok(())
     }
 
+    async fn handle_expiry_notifications(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        if let Some(set_matches) = matches.subcommand_matches("set") {
+            let hours = if set_matches.is_present("hours") {
+                values_t_or_exit!(set_matches.values_of("hours"), u32)
+            } else {
+                vec![]
+            };
+            rpc.set_expiry_notification_hours(types::ExpiryNotificationHours { hours })
+                .await?;
+            println!("Updated expiry notifications");
+        } else if let Some(_matches) = matches.subcommand_matches("get") {
+            let hours = rpc
+                .get_settings(())
+                .await?
+                .into_inner()
+                .expiry_notification_hours;
+            if hours.is_empty() {
+                println!("Expiry notifications: only when expired");
+            } else {
+                println!(
+                    "Expiry notifications: {} hours before expiry",
+                    hours.iter().join(", ")
+                );
+            }
+        } else {
+            unreachable!("No expiry-notifications command given");
+        }
+        Ok(())
+    }
+
     async fn create(&self) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         rpc.create_new_account(()).await?;
@@ -134,7 +186,7 @@ impl Account {
                 );
                 println!(
                     "New expiry date: {}",
-                    Self::format_expiry(&submission.new_expiry.unwrap())
+                    format::local_time(&submission.new_expiry.unwrap())
                 );
                 Ok(())
             }
@@ -162,10 +214,4 @@ impl Account {
             format!("{} seconds", dur.num_seconds())
         }
     }
-
-    fn format_expiry(expiry: &Timestamp) -> String {
-        let ndt = chrono::NaiveDateTime::from_timestamp(expiry.seconds, expiry.nanos as u32);
-        let utc = chrono::DateTime::<chrono::Utc>::from_utc(ndt, chrono::Utc);
-        utc.with_timezone(&chrono::Local).to_string()
-    }
 }
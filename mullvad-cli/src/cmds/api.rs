@@ -0,0 +1,103 @@
+use crate::{format, new_rpc_client, Command, Result};
+use itertools::Itertools;
+use mullvad_management_interface::types;
+
+pub struct Api;
+
+#[mullvad_management_interface::async_trait]
+impl Command for Api {
+    fn name(&self) -> &'static str {
+        "api"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Inspect the connection to the Mullvad API")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("status").about(
+                    "Show request statistics for every endpoint the API was reached through",
+                ),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("status", _) => Self::status().await,
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+impl Api {
+    async fn status() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let status = rpc.get_api_status(()).await?.into_inner();
+        if status.endpoints.is_empty() {
+            println!("No requests have been made to the API");
+            return Ok(());
+        }
+        for (index, endpoint) in status.endpoints.iter().enumerate() {
+            if index > 0 {
+                println!();
+            }
+            Self::print_endpoint(endpoint);
+        }
+        Ok(())
+    }
+
+    fn print_endpoint(endpoint: &types::ApiEndpointStatus) {
+        println!("{} ({})", endpoint.endpoint, endpoint.connection_mode);
+        println!("  Successful requests: {}", endpoint.successes);
+
+        let total_failures: u64 = endpoint.failures.iter().map(|failure| failure.count).sum();
+        if total_failures > 0 {
+            println!(
+                "  Failed requests: {} ({})",
+                total_failures,
+                endpoint
+                    .failures
+                    .iter()
+                    .map(|failure| format!("{}: {}", format_kind(failure.kind), failure.count))
+                    .join(", ")
+            );
+        } else {
+            println!("  Failed requests: 0");
+        }
+
+        println!(
+            "  Latency: {}",
+            endpoint
+                .latency
+                .iter()
+                .filter(|bucket| bucket.count > 0)
+                .map(|bucket| match bucket.upper_bound_ms {
+                    Some(bound) => format!("<= {} ms: {}", bound, bucket.count),
+                    None => format!("slower: {}", bucket.count),
+                })
+                .join(", ")
+        );
+
+        if let Some(error) = &endpoint.last_error {
+            println!(
+                "  Last error: {} ({}), at {}",
+                error.message,
+                format_kind(error.kind),
+                error
+                    .timestamp
+                    .as_ref()
+                    .map(|timestamp| format::local_time(timestamp).to_string())
+                    .unwrap_or_default()
+            );
+        }
+    }
+}
+
+fn format_kind(kind: i32) -> &'static str {
+    match types::RequestErrorKind::from_i32(kind) {
+        Some(types::RequestErrorKind::Network) => "network",
+        Some(types::RequestErrorKind::Timeout) => "timeout",
+        Some(types::RequestErrorKind::ApiStatus) => "API status",
+        Some(types::RequestErrorKind::Other) | None => "other",
+    }
+}
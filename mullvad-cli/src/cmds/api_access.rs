@@ -0,0 +1,206 @@
+use crate::{new_rpc_client, Command, Error, Result};
+use clap::value_t;
+use mullvad_management_interface::types;
+use mullvad_types::access_method::{AccessMethod, ApiAccessMethod};
+use std::{convert::TryFrom, net::SocketAddr};
+use talpid_types::net::openvpn;
+
+pub struct ApiAccess;
+
+#[mullvad_management_interface::async_trait]
+impl Command for ApiAccess {
+    fn name(&self) -> &'static str {
+        "api-access"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Manage the methods used to reach the Mullvad API")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("list")
+                    .about("List API access methods in the order in which they are tried"),
+            )
+            .subcommand(create_add_subcommand())
+            .subcommand(
+                clap::SubCommand::with_name("remove")
+                    .about("Remove a custom API access method")
+                    .arg(name_arg()),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("enable")
+                    .about("Enable an API access method")
+                    .arg(name_arg()),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("disable")
+                    .about("Disable an API access method")
+                    .arg(name_arg()),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("current")
+                    .about("Show the API access method that is currently in use"),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("list", _) => Self::list().await,
+            ("add", Some(add_matches)) => Self::add(add_matches).await,
+            ("remove", Some(remove_matches)) => {
+                Self::remove(remove_matches.value_of("name").unwrap()).await
+            }
+            ("enable", Some(enable_matches)) => {
+                Self::set_enabled(enable_matches.value_of("name").unwrap(), true).await
+            }
+            ("disable", Some(disable_matches)) => {
+                Self::set_enabled(disable_matches.value_of("name").unwrap(), false).await
+            }
+            ("current", _) => Self::current().await,
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+fn name_arg() -> clap::Arg<'static, 'static> {
+    clap::Arg::with_name("name")
+        .help("Name of the API access method")
+        .required(true)
+}
+
+fn create_add_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("add")
+        .about("Add a custom API access method")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            clap::SubCommand::with_name("socks5")
+                .about("Reach the API through a SOCKS5 proxy that requires no authentication")
+                .arg(name_arg())
+                .arg(
+                    clap::Arg::with_name("address")
+                        .help("Address of the proxy, e.g. 192.168.1.1:1080")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("http-connect")
+                .about("Reach the API through an HTTP proxy that supports the CONNECT method")
+                .arg(name_arg())
+                .arg(
+                    clap::Arg::with_name("address")
+                        .help("Address of the proxy, e.g. 192.168.1.1:8080")
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::with_name("username")
+                        .help("Username for basic authentication")
+                        .requires("password"),
+                )
+                .arg(clap::Arg::with_name("password").help("Password for basic authentication")),
+        )
+}
+
+impl ApiAccess {
+    async fn list() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
+        let access_methods = settings
+            .api_access_methods
+            .map(|methods| methods.access_methods)
+            .unwrap_or_default();
+        for method in access_methods {
+            let method = ApiAccessMethod::try_from(method)
+                .map_err(|_| Error::CommandFailed("Received invalid API access method"))?;
+            Self::print_method(&method);
+        }
+        Ok(())
+    }
+
+    async fn add(matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let method = match matches.subcommand() {
+            ("socks5", Some(socks5_matches)) => {
+                let name = socks5_matches.value_of("name").unwrap().to_owned();
+                let address = value_t!(socks5_matches.value_of("address"), SocketAddr)
+                    .unwrap_or_else(|e| e.exit());
+                ApiAccessMethod::new(name, AccessMethod::Socks5(address))
+            }
+            ("http-connect", Some(http_matches)) => {
+                let name = http_matches.value_of("name").unwrap().to_owned();
+                let address = value_t!(http_matches.value_of("address"), SocketAddr)
+                    .unwrap_or_else(|e| e.exit());
+                let auth = match (
+                    http_matches.value_of("username"),
+                    http_matches.value_of("password"),
+                ) {
+                    (Some(username), Some(password)) => Some(openvpn::ProxyAuth {
+                        username: username.to_owned(),
+                        password: password.to_owned(),
+                    }),
+                    _ => None,
+                };
+                let proxy = openvpn::HttpConnectProxySettings { address, auth };
+                if let Err(error) = openvpn::validate_proxy_settings(
+                    &openvpn::ProxySettings::HttpConnect(proxy.clone()),
+                ) {
+                    clap::Error::with_description(&error, clap::ErrorKind::InvalidValue).exit();
+                }
+                ApiAccessMethod::new(name, AccessMethod::HttpConnect(proxy))
+            }
+            _ => unreachable!("unhandled access method type"),
+        };
+
+        let mut rpc = new_rpc_client().await?;
+        rpc.add_api_access_method(types::ApiAccessMethod::from(&method))
+            .await?;
+        println!("Added API access method \"{}\"", method.name);
+        Ok(())
+    }
+
+    async fn remove(name: &str) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.remove_api_access_method(name.to_owned()).await?;
+        println!("Removed API access method \"{}\"", name);
+        Ok(())
+    }
+
+    async fn set_enabled(name: &str, enabled: bool) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_api_access_method_enabled(types::ApiAccessMethodToggle {
+            name: name.to_owned(),
+            enabled,
+        })
+        .await?;
+        println!(
+            "API access method \"{}\" has been {}",
+            name,
+            if enabled { "enabled" } else { "disabled" }
+        );
+        Ok(())
+    }
+
+    async fn current() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let method = rpc.get_current_api_access_method(()).await;
+        match method {
+            Ok(method) => {
+                let method = ApiAccessMethod::try_from(method.into_inner())
+                    .map_err(|_| Error::CommandFailed("Received invalid API access method"))?;
+                Self::print_method(&method);
+            }
+            Err(status) if status.code() == mullvad_management_interface::Code::NotFound => {
+                println!("No API access method could be used. Connecting directly");
+            }
+            Err(status) => return Err(Error::RpcFailed(status)),
+        }
+        Ok(())
+    }
+
+    fn print_method(method: &ApiAccessMethod) {
+        println!(
+            "{} ({}){}",
+            method.name,
+            method.access_method,
+            if method.enabled { "" } else { " - disabled" }
+        );
+    }
+}
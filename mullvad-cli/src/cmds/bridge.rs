@@ -167,6 +167,33 @@ fn create_set_custom_settings_subcommand() -> clap::App<'static, 'static> {
                         .index(4),
                 ),
         )
+        .subcommand(
+            clap::SubCommand::with_name("http-connect")
+                .about("Registers a remote HTTP proxy that supports the CONNECT method")
+                .arg(
+                    clap::Arg::with_name("remote-ip")
+                        .help("Specifies the IP of the remote proxy server")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    clap::Arg::with_name("remote-port")
+                        .help("Specifies the port the remote proxy server is listening on")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    clap::Arg::with_name("username")
+                        .help("Specifies the username for basic authentication")
+                        .requires("password")
+                        .index(3),
+                )
+                .arg(
+                    clap::Arg::with_name("password")
+                        .help("Specifies the password for basic authentication")
+                        .index(4),
+                ),
+        )
 }
 
 fn create_set_state_subcommand() -> clap::App<'static, 'static> {
@@ -179,6 +206,7 @@ fn create_set_state_subcommand() -> clap::App<'static, 'static> {
                 .index(1)
                 .possible_values(&["auto", "on", "off"]),
         )
+        .args(&super::relay::profile_args())
 }
 
 impl Bridge {
@@ -215,6 +243,9 @@ impl Bridge {
                 openvpn::ProxySettings::Shadowsocks(shadowsocks_proxy) => {
                     Self::print_shadowsocks_proxy(&shadowsocks_proxy)
                 }
+                openvpn::ProxySettings::HttpConnect(http_proxy) => {
+                    Self::print_http_connect_proxy(&http_proxy)
+                }
             },
             BridgeSettings::Normal(constraints) => {
                 println!("Bridge constraints - {}", constraints)
@@ -285,10 +316,16 @@ impl Bridge {
             "off" => BridgeState::Off,
             _ => unreachable!(),
         };
-        let mut rpc = new_rpc_client().await?;
-        rpc.set_bridge_state(types::BridgeState::from(state))
-            .await?;
-        Ok(())
+        match matches.value_of("profile") {
+            #[cfg(target_os = "linux")]
+            Some(profile) => super::network_profile::set_bridge_state(profile, state).await,
+            _ => {
+                let mut rpc = new_rpc_client().await?;
+                rpc.set_bridge_state(types::BridgeState::from(state))
+                    .await?;
+                Ok(())
+            }
+        }
     }
 
     async fn handle_bridge_set_custom_settings(matches: &clap::ArgMatches<'_>) -> Result<()> {
@@ -361,6 +398,33 @@ impl Bridge {
                 panic!("{}", error);
             }
 
+            let mut rpc = new_rpc_client().await?;
+            rpc.set_bridge_settings(types::BridgeSettings::from(BridgeSettings::Custom(
+                packed_proxy,
+            )))
+            .await?;
+        } else if let Some(args) = matches.subcommand_matches("http-connect") {
+            let remote_ip =
+                value_t!(args.value_of("remote-ip"), IpAddr).unwrap_or_else(|e| e.exit());
+            let remote_port =
+                value_t!(args.value_of("remote-port"), u16).unwrap_or_else(|e| e.exit());
+
+            let auth = match (args.value_of("username"), args.value_of("password")) {
+                (Some(username), Some(password)) => Some(openvpn::ProxyAuth {
+                    username: username.to_string(),
+                    password: password.to_string(),
+                }),
+                _ => None,
+            };
+            let proxy = openvpn::HttpConnectProxySettings {
+                address: SocketAddr::new(remote_ip, remote_port),
+                auth,
+            };
+            let packed_proxy = openvpn::ProxySettings::HttpConnect(proxy);
+            if let Err(error) = openvpn::validate_proxy_settings(&packed_proxy) {
+                panic!("{}", error);
+            }
+
             let mut rpc = new_rpc_client().await?;
             rpc.set_bridge_settings(types::BridgeSettings::from(BridgeSettings::Custom(
                 packed_proxy,
@@ -399,6 +463,18 @@ impl Bridge {
         println!("  cipher: {}", proxy.cipher);
     }
 
+    fn print_http_connect_proxy(proxy: &openvpn::HttpConnectProxySettings) {
+        println!("proxy: HTTP CONNECT");
+        println!("  server address: {}", proxy.address);
+
+        if let Some(ref auth) = proxy.auth {
+            println!("  auth username: {}", auth.username);
+            println!("  auth password: {}", auth.password);
+        } else {
+            println!("  auth: none");
+        }
+    }
+
     async fn list_bridge_relays() -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let mut locations = rpc
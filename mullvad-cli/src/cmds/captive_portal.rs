@@ -0,0 +1,151 @@
+use crate::{new_rpc_client, Command, Result};
+use clap::value_t;
+use mullvad_management_interface::types;
+use std::time::Duration;
+#[cfg(target_os = "linux")]
+use talpid_types::net::ConnectivityProbe;
+
+/// Default length of the captive portal login window, in seconds.
+const DEFAULT_LOGIN_DURATION_SECS: &str = "180";
+
+pub struct CaptivePortal;
+
+#[mullvad_management_interface::async_trait]
+impl Command for CaptivePortal {
+    fn name(&self) -> &'static str {
+        "captive-portal"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        let subcommand = clap::SubCommand::with_name(self.name())
+            .about("Log in to captive portals while network traffic is blocked")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(create_login_subcommand());
+        #[cfg(target_os = "linux")]
+        let subcommand = subcommand.subcommand(create_probe_subcommand());
+        subcommand
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("login", Some(login_matches)) => self.login(login_matches).await,
+            #[cfg(target_os = "linux")]
+            ("probe", Some(probe_matches)) => match probe_matches.subcommand() {
+                ("get", _) => self.get_probe().await,
+                ("set", Some(set_matches)) => {
+                    self.set_probe(set_matches.value_of("url").unwrap().to_owned())
+                        .await
+                }
+                ("unset", _) => self.set_probe(String::new()).await,
+                _ => unreachable!("No probe command given"),
+            },
+            _ => unreachable!("No captive-portal command given"),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn create_probe_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("probe")
+        .about(
+            "Manage the HTTP server that is used to detect captive portals. While a captive \
+             portal is detected, the tunnel is not connected",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            clap::SubCommand::with_name("get").about("Display the current connectivity probe"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("set")
+                .about("Detect captive portals using an HTTP server")
+                .arg(
+                    clap::Arg::with_name("url")
+                        .help(
+                            "URL of a server that responds with 204 No Content, e.g. \
+                             http://example.com/generate_204",
+                        )
+                        .required(true)
+                        .validator(|url| {
+                            url.parse::<ConnectivityProbe>()
+                                .map(|_| ())
+                                .map_err(|error| error.to_string())
+                        }),
+                ),
+        )
+        .subcommand(clap::SubCommand::with_name("unset").about("Stop detecting captive portals"))
+}
+
+fn create_login_subcommand() -> clap::App<'static, 'static> {
+    let subcommand = clap::SubCommand::with_name("login")
+        .about(
+            "Temporarily allow HTTP, HTTPS and DNS traffic to the networks assigned to the host by \
+             the gateway, so that a captive portal can be logged in to. The tunnel is reconnected \
+             afterwards. Not supported on Windows",
+        )
+        .arg(
+            clap::Arg::with_name("duration")
+                .long("duration")
+                .short("d")
+                .help("Number of seconds to allow the traffic for")
+                .default_value(DEFAULT_LOGIN_DURATION_SECS),
+        );
+    #[cfg(target_os = "linux")]
+    let subcommand = subcommand.arg(
+        clap::Arg::with_name("browser_cgroup")
+            .long("browser-cgroup")
+            .help(
+                "Also allow HTTP, HTTPS and DNS traffic to any host from processes in the \
+                 net_cls cgroup with this class ID",
+            )
+            .takes_value(true),
+    );
+    subcommand
+}
+
+impl CaptivePortal {
+    async fn login(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let duration = value_t!(matches.value_of("duration"), u64).unwrap_or_else(|e| e.exit());
+        #[cfg(target_os = "linux")]
+        let browser_cgroup = if matches.is_present("browser_cgroup") {
+            Some(value_t!(matches.value_of("browser_cgroup"), u32).unwrap_or_else(|e| e.exit()))
+        } else {
+            None
+        };
+        #[cfg(not(target_os = "linux"))]
+        let browser_cgroup = None;
+
+        let mut rpc = new_rpc_client().await?;
+        let login_issued = rpc
+            .captive_portal_login(types::CaptivePortalLogin {
+                duration: Some(types::Duration::from(Duration::from_secs(duration))),
+                browser_cgroup,
+            })
+            .await?
+            .into_inner();
+        if login_issued {
+            println!("Allowing captive portal login for {} seconds", duration);
+        } else {
+            println!("Network traffic is not blocked. Nothing to do");
+        }
+        Ok(())
+    }
+    #[cfg(target_os = "linux")]
+    async fn get_probe(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
+        if settings.connectivity_probe.is_empty() {
+            println!("Connectivity probe: none");
+        } else {
+            println!("Connectivity probe: {}", settings.connectivity_probe);
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn set_probe(&self, url: String) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_connectivity_probe(url).await?;
+        println!("Updated connectivity probe");
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{format, new_rpc_client, Command, Result};
+use clap::value_t;
+use mullvad_management_interface::types;
+use std::time::Duration;
+
+const LOG_LEVELS: &[&str] = &["off", "error", "warn", "info", "debug", "trace"];
+
+const DATE_TIME_FORMAT_STR: &str = "[%Y-%m-%d %H:%M:%S%.3f]";
+
+pub struct Debug;
+
+#[mullvad_management_interface::async_trait]
+impl Command for Debug {
+    fn name(&self) -> &'static str {
+        "debug"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Tools for troubleshooting the daemon")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(create_log_level_subcommand())
+            .subcommand(create_logs_subcommand())
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("log-level", Some(log_level_matches)) => self.set_log_level(log_level_matches).await,
+            ("logs", Some(logs_matches)) => self.logs(logs_matches).await,
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+fn create_log_level_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("log-level")
+        .about("Change the log level of the daemon without restarting it")
+        .arg(
+            clap::Arg::with_name("level")
+                .required(true)
+                .possible_values(LOG_LEVELS),
+        )
+        .arg(
+            clap::Arg::with_name("module")
+                .long("module")
+                .short("m")
+                .help(
+                    "Only change the level of this module and its submodules, such as \
+                     talpid_core::firewall",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("timeout")
+                .long("timeout")
+                .short("t")
+                .help("Restore the previous level after this many seconds")
+                .takes_value(true),
+        )
+}
+
+fn create_logs_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("logs")
+        .about("Show recent log records from the daemon")
+        .arg(
+            clap::Arg::with_name("follow")
+                .long("follow")
+                .short("f")
+                .help("Keep printing new log records as they are logged"),
+        )
+        .arg(
+            clap::Arg::with_name("level")
+                .long("level")
+                .short("l")
+                .help("Most verbose level of the records to show")
+                .possible_values(LOG_LEVELS)
+                .default_value("trace"),
+        )
+        .arg(
+            clap::Arg::with_name("module")
+                .long("module")
+                .short("m")
+                .help("Only show records from this module and its submodules")
+                .takes_value(true),
+        )
+}
+
+impl Debug {
+    async fn set_log_level(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let level = parse_log_level(matches.value_of("level").unwrap());
+        let module = matches.value_of("module").unwrap_or("").to_owned();
+        let timeout = if matches.is_present("timeout") {
+            Some(value_t!(matches.value_of("timeout"), u64).unwrap_or_else(|e| e.exit()))
+        } else {
+            None
+        };
+
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_log_level(types::LogLevelChange {
+            level: level as i32,
+            module: module.clone(),
+            timeout: timeout.map(|timeout| types::Duration::from(Duration::from_secs(timeout))),
+        })
+        .await?;
+
+        let target = if module.is_empty() {
+            "the daemon".to_owned()
+        } else {
+            module
+        };
+        let level = matches.value_of("level").unwrap();
+        match timeout {
+            Some(timeout) => println!(
+                "Log level of {} set to {} for {} seconds",
+                target, level, timeout
+            ),
+            None => println!("Log level of {} set to {}", target, level),
+        }
+        Ok(())
+    }
+
+    async fn logs(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let mut records = rpc
+            .stream_logs(types::LogStreamRequest {
+                level: parse_log_level(matches.value_of("level").unwrap()) as i32,
+                module: matches.value_of("module").unwrap_or("").to_owned(),
+                follow: matches.is_present("follow"),
+            })
+            .await?
+            .into_inner();
+
+        while let Some(record) = records.message().await? {
+            let timestamp = record
+                .timestamp
+                .as_ref()
+                .map(|timestamp| {
+                    format::local_time(timestamp)
+                        .format(DATE_TIME_FORMAT_STR)
+                        .to_string()
+                })
+                .unwrap_or_default();
+            println!(
+                "{}[{}][{}] {}",
+                timestamp,
+                record.module,
+                format_level(record.level),
+                record.message
+            );
+        }
+        Ok(())
+    }
+}
+
+fn parse_log_level(level: &str) -> types::LogLevel {
+    match level {
+        "off" => types::LogLevel::Off,
+        "error" => types::LogLevel::Error,
+        "warn" => types::LogLevel::Warn,
+        "info" => types::LogLevel::Info,
+        "debug" => types::LogLevel::Debug,
+        "trace" => types::LogLevel::Trace,
+        _ => unreachable!("invalid log level"),
+    }
+}
+
+fn format_level(level: i32) -> &'static str {
+    match types::LogLevel::from_i32(level) {
+        Some(types::LogLevel::Error) => "ERROR",
+        Some(types::LogLevel::Warn) => "WARN",
+        Some(types::LogLevel::Info) => "INFO",
+        Some(types::LogLevel::Debug) => "DEBUG",
+        Some(types::LogLevel::Trace) => "TRACE",
+        Some(types::LogLevel::Off) | None => "UNKNOWN",
+    }
+}
@@ -4,6 +4,12 @@ use std::collections::HashMap;
 mod account;
 pub use self::account::Account;
 
+mod api;
+pub use self::api::Api;
+
+mod api_access;
+pub use self::api_access::ApiAccess;
+
 mod auto_connect;
 pub use self::auto_connect::AutoConnect;
 
@@ -16,9 +22,15 @@ pub use self::block_when_disconnected::BlockWhenDisconnected;
 mod bridge;
 pub use self::bridge::Bridge;
 
+mod captive_portal;
+pub use self::captive_portal::CaptivePortal;
+
 mod connect;
 pub use self::connect::Connect;
 
+mod debug;
+pub use self::debug::Debug;
+
 mod disconnect;
 pub use self::disconnect::Disconnect;
 
@@ -28,6 +40,11 @@ pub use self::dns::Dns;
 mod lan;
 pub use self::lan::Lan;
 
+#[cfg(target_os = "linux")]
+mod network_profile;
+#[cfg(target_os = "linux")]
+pub use self::network_profile::NetworkProfiles;
+
 mod reconnect;
 pub use self::reconnect::Reconnect;
 
@@ -45,9 +62,17 @@ pub use self::split_tunnel::SplitTunnel;
 mod status;
 pub use self::status::Status;
 
+#[cfg(target_os = "linux")]
+mod trusted_network;
+#[cfg(target_os = "linux")]
+pub use self::trusted_network::TrustedNetworks;
+
 mod tunnel;
 pub use self::tunnel::Tunnel;
 
+mod update_channel;
+pub use self::update_channel::UpdateChannel;
+
 mod version;
 pub use self::version::Version;
 
@@ -55,21 +80,30 @@ pub use self::version::Version;
 pub fn get_commands() -> HashMap<&'static str, Box<dyn Command>> {
     let commands: Vec<Box<dyn Command>> = vec![
         Box::new(Account),
+        Box::new(Api),
+        Box::new(ApiAccess),
         Box::new(AutoConnect),
         Box::new(BetaProgram),
         Box::new(BlockWhenDisconnected),
         Box::new(Bridge),
+        Box::new(CaptivePortal),
         Box::new(Connect),
+        Box::new(Debug),
         Box::new(Disconnect),
         Box::new(Dns),
         Box::new(Reconnect),
         Box::new(Lan),
+        #[cfg(target_os = "linux")]
+        Box::new(NetworkProfiles),
         Box::new(Relay),
         Box::new(Reset),
         #[cfg(any(target_os = "linux", windows))]
         Box::new(SplitTunnel),
         Box::new(Status),
+        #[cfg(target_os = "linux")]
+        Box::new(TrustedNetworks),
         Box::new(Tunnel),
+        Box::new(UpdateChannel),
         Box::new(Version),
     ];
     let mut map = HashMap::new();
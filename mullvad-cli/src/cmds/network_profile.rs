@@ -0,0 +1,277 @@
+use crate::{new_rpc_client, Command, Error, Result};
+use mullvad_management_interface::{types, ManagementServiceClient};
+use mullvad_types::{
+    network_profile::{ActiveNetworkProfile, NetworkIdentifier, NetworkProfile},
+    relay_constraints::BridgeState,
+};
+use std::convert::TryFrom;
+
+pub struct NetworkProfiles;
+
+#[mullvad_management_interface::async_trait]
+impl Command for NetworkProfiles {
+    fn name(&self) -> &'static str {
+        "network-profile"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about(
+                "Manage relay settings and bridge states that apply on specific networks. The \
+                 settings of a profile are changed with the --profile option of \
+                 'mullvad relay set' and 'mullvad bridge set state'",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("list").about(
+                    "List network profiles in order of precedence, and show which one applies",
+                ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("add")
+                    .about("Add a network profile. It applies to no networks until some are added")
+                    .arg(name_arg()),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("remove")
+                    .about("Remove a network profile")
+                    .arg(name_arg()),
+            )
+            .subcommand(
+                network_subcommand("add-network")
+                    .about("Make a network profile apply to a network"),
+            )
+            .subcommand(
+                network_subcommand("remove-network")
+                    .about("Stop applying a network profile to a network"),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("unset")
+                    .about("Use the regular relay settings or bridge state in a network profile")
+                    .arg(name_arg())
+                    .arg(
+                        clap::Arg::with_name("setting")
+                            .required(true)
+                            .possible_values(&["relay-settings", "bridge-state"]),
+                    ),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("list", _) => Self::list().await,
+            ("add", Some(add_matches)) => Self::add(add_matches.value_of("name").unwrap()).await,
+            ("remove", Some(remove_matches)) => {
+                Self::remove(remove_matches.value_of("name").unwrap()).await
+            }
+            ("add-network", Some(network_matches)) => {
+                let name = network_matches.value_of("name").unwrap();
+                let identifier = parse_network_identifier(network_matches);
+                Self::update(name, |profile| {
+                    if !profile.networks.contains(&identifier) {
+                        profile.networks.push(identifier);
+                    }
+                })
+                .await
+            }
+            ("remove-network", Some(network_matches)) => {
+                let name = network_matches.value_of("name").unwrap();
+                let identifier = parse_network_identifier(network_matches);
+                Self::update(name, |profile| {
+                    profile.networks.retain(|network| *network != identifier)
+                })
+                .await
+            }
+            ("unset", Some(unset_matches)) => {
+                let name = unset_matches.value_of("name").unwrap();
+                match unset_matches.value_of("setting").unwrap() {
+                    "relay-settings" => Self::update(name, |profile| profile.relay_settings = None),
+                    "bridge-state" => Self::update(name, |profile| profile.bridge_state = None),
+                    _ => unreachable!("unhandled setting"),
+                }
+                .await
+            }
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+fn name_arg() -> clap::Arg<'static, 'static> {
+    clap::Arg::with_name("name")
+        .help("Name of the network profile")
+        .required(true)
+}
+
+fn network_subcommand(name: &'static str) -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name(name)
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .arg(name_arg())
+        .subcommand(
+            clap::SubCommand::with_name("ssid")
+                .about("A Wi-Fi network, identified by its SSID")
+                .arg(clap::Arg::with_name("ssid").required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("gateway-mac")
+                .about("Any network whose default gateway has the given MAC address")
+                .arg(
+                    clap::Arg::with_name("mac")
+                        .help("MAC address, e.g. 00:11:22:33:44:55")
+                        .required(true)
+                        .validator(validate_mac_address),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("interface")
+                .about("Any network reached through a network interface, e.g. eth0")
+                .arg(clap::Arg::with_name("interface").required(true)),
+        )
+}
+
+fn validate_mac_address(mac: String) -> std::result::Result<(), String> {
+    let octets: Vec<&str> = mac.split(':').collect();
+    if octets.len() == 6
+        && octets
+            .iter()
+            .all(|octet| octet.len() == 2 && u8::from_str_radix(octet, 16).is_ok())
+    {
+        Ok(())
+    } else {
+        Err(format!("Invalid MAC address: {}", mac))
+    }
+}
+
+fn parse_network_identifier(matches: &clap::ArgMatches<'_>) -> NetworkIdentifier {
+    match matches.subcommand() {
+        ("ssid", Some(ssid_matches)) => {
+            NetworkIdentifier::Ssid(ssid_matches.value_of("ssid").unwrap().to_owned())
+        }
+        ("gateway-mac", Some(mac_matches)) => {
+            NetworkIdentifier::GatewayMac(mac_matches.value_of("mac").unwrap().to_lowercase())
+        }
+        ("interface", Some(interface_matches)) => NetworkIdentifier::Interface(
+            interface_matches.value_of("interface").unwrap().to_owned(),
+        ),
+        _ => unreachable!("unhandled network type"),
+    }
+}
+
+/// Applies `update` to the relay settings of a network profile, or to the regular relay settings
+/// if the profile has none.
+pub async fn update_relay_settings(name: &str, update: types::RelaySettingsUpdate) -> Result<()> {
+    let mut rpc = new_rpc_client().await?;
+    rpc.update_network_profile_relay_settings(types::NetworkProfileRelaySettingsUpdate {
+        name: name.to_owned(),
+        update: Some(update),
+    })
+    .await
+    .map_err(|error| Error::RpcFailedExt("Failed to update network profile", error))?;
+    println!("Relay constraints of network profile \"{}\" updated", name);
+    Ok(())
+}
+
+/// Replaces the bridge state of a network profile.
+pub async fn set_bridge_state(name: &str, state: BridgeState) -> Result<()> {
+    NetworkProfiles::update(name, |profile| profile.bridge_state = Some(state)).await
+}
+
+fn find_profile(settings: &types::Settings, name: &str) -> Result<NetworkProfile> {
+    let profile = settings
+        .network_profiles
+        .iter()
+        .find(|profile| profile.name == name)
+        .cloned()
+        .ok_or(Error::InvalidCommand("No network profile has that name"))?;
+    NetworkProfile::try_from(profile)
+        .map_err(|_| Error::CommandFailed("Received invalid network profile"))
+}
+
+impl NetworkProfiles {
+    async fn list() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
+        if settings.network_profiles.is_empty() {
+            println!("No network profiles");
+            return Ok(());
+        }
+
+        for profile in settings.network_profiles {
+            let profile = NetworkProfile::try_from(profile)
+                .map_err(|_| Error::CommandFailed("Received invalid network profile"))?;
+            Self::print_profile(&profile);
+        }
+
+        match Self::get_active_profile(&mut rpc).await? {
+            Some(active) => println!(
+                "Network profile \"{}\" applies, since {} matches {}",
+                active.profile.name, active.network, active.identifier
+            ),
+            None => println!("No network profile applies. The regular settings are used"),
+        }
+        Ok(())
+    }
+
+    async fn get_active_profile(
+        rpc: &mut ManagementServiceClient,
+    ) -> Result<Option<ActiveNetworkProfile>> {
+        match rpc.get_active_network_profile(()).await {
+            Ok(active) => ActiveNetworkProfile::try_from(active.into_inner())
+                .map(Some)
+                .map_err(|_| Error::CommandFailed("Received invalid network profile")),
+            Err(status) if status.code() == mullvad_management_interface::Code::NotFound => {
+                Ok(None)
+            }
+            Err(status) => Err(Error::RpcFailed(status)),
+        }
+    }
+
+    fn print_profile(profile: &NetworkProfile) {
+        println!("{}", profile.name);
+        if profile.networks.is_empty() {
+            println!("\tNetworks: none");
+        } else {
+            let networks: Vec<String> = profile
+                .networks
+                .iter()
+                .map(|network| network.to_string())
+                .collect();
+            println!("\tNetworks: {}", networks.join(", "));
+        }
+        match &profile.relay_settings {
+            Some(relay_settings) => println!("\tRelay settings: {}", relay_settings),
+            None => println!("\tRelay settings: regular"),
+        }
+        match &profile.bridge_state {
+            Some(bridge_state) => println!("\tBridge state: {}", bridge_state),
+            None => println!("\tBridge state: regular"),
+        }
+    }
+
+    async fn add(name: &str) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.add_network_profile(types::NetworkProfile::from(&NetworkProfile::new(
+            name.to_owned(),
+        )))
+        .await?;
+        println!("Added network profile \"{}\"", name);
+        Ok(())
+    }
+
+    async fn remove(name: &str) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.remove_network_profile(name.to_owned()).await?;
+        println!("Removed network profile \"{}\"", name);
+        Ok(())
+    }
+
+    async fn update(name: &str, update: impl FnOnce(&mut NetworkProfile)) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
+        let mut profile = find_profile(&settings, name)?;
+        update(&mut profile);
+        rpc.update_network_profile(types::NetworkProfile::from(&profile))
+            .await?;
+        println!("Updated network profile \"{}\"", name);
+        Ok(())
+    }
+}
@@ -30,6 +30,7 @@ impl Command for Relay {
                         "Set relay server selection parameters. Such as location and port/protocol",
                     )
                     .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                    .args(&profile_args())
                     .subcommand(
                         clap::SubCommand::with_name("custom")
                             .about("Set a custom VPN relay")
@@ -136,23 +137,7 @@ impl Command for Relay {
                         clap::SubCommand::with_name("tunnel")
                             .about("Set tunnel protocol-specific constraints.")
                             .setting(clap::AppSettings::SubcommandRequiredElseHelp)
-                            .subcommand(
-                                clap::SubCommand::with_name("openvpn")
-                                    .about("Set OpenVPN-specific constraints")
-                                    .arg(
-                                        clap::Arg::with_name("port")
-                                            .help("Port to use. Either 'any' or a specific port")
-                                            .long("port")
-                                            .default_value("any"),
-                                    )
-                                    .arg(
-                                        clap::Arg::with_name("transport protocol")
-                                            .help("Transport protocol")
-                                            .long("protocol")
-                                            .possible_values(&["any", "udp", "tcp"])
-                                            .default_value("any"),
-                                    )
-                            )
+                            .subcommand(create_openvpn_constraints_subcommand())
                             .subcommand(
                                 clap::SubCommand::with_name("wireguard")
                                     .about("Set WireGuard-specific constraints")
@@ -165,7 +150,7 @@ impl Command for Relay {
                                     .arg(
                                         clap::Arg::with_name("transport protocol")
                                             .help("Transport protocol. If TCP is selected, traffic is \
-                                                   sent over TCP using a udp-over-tcp proxy")
+                                                   obfuscated using UDP-over-TCP")
                                             .long("protocol")
                                             .possible_values(&["any", "udp", "tcp"])
                                             .default_value("any"),
@@ -178,15 +163,33 @@ impl Command for Relay {
                                     )
                                     .arg(
                                         clap::Arg::with_name("entry location")
-                                            .help("Entry endpoint to use. This can be 'any', 'none', or \
-                                                   any location that is valid with 'set location', \
-                                                   such as 'se got'.")
+                                            .help("Entry endpoint to use. This can be 'any', 'none', \
+                                                   'auto', or any location that is valid with 'set \
+                                                   location', such as 'se got'. 'auto' selects the \
+                                                   entry relay closest to you.")
                                             .default_value("none")
                                             .long("entry-location")
                                             .multiple(true)
                                             .min_values(1)
                                             .max_values(3),
                                     )
+                                    .arg(
+                                        clap::Arg::with_name("entry different")
+                                            .help("Require the automatically selected entry relay \
+                                                   to be in a different country or from a different \
+                                                   provider than the exit relay")
+                                            .long("entry-different")
+                                            .possible_values(&["country", "provider"])
+                                            .multiple(true),
+                                    )
+                                    .arg(
+                                        clap::Arg::with_name("obfuscation")
+                                            .help("Obfuscation protocol to use. 'auto' only \
+                                                   obfuscates traffic when TCP is used.")
+                                            .long("obfuscation")
+                                            .possible_values(&["auto", "off", "udp2tcp"])
+                                            .default_value("auto"),
+                                    )
                             )
                     )
                     .subcommand(clap::SubCommand::with_name("tunnel-protocol")
@@ -225,49 +228,69 @@ impl Command for Relay {
 }
 
 impl Relay {
-    async fn update_constraints(&self, update: types::RelaySettingsUpdate) -> Result<()> {
-        let mut rpc = new_rpc_client().await?;
-        rpc.update_relay_settings(update)
-            .await
-            .map_err(|error| Error::RpcFailedExt("Failed to update relay settings", error))?;
-        println!("Relay constraints updated");
-        Ok(())
+    /// Updates the regular relay settings, or those of a network profile if one is given.
+    async fn update_constraints(
+        &self,
+        profile: Option<&str>,
+        update: types::RelaySettingsUpdate,
+    ) -> Result<()> {
+        match profile {
+            #[cfg(target_os = "linux")]
+            Some(profile) => super::network_profile::update_relay_settings(profile, update).await,
+            _ => {
+                let mut rpc = new_rpc_client().await?;
+                rpc.update_relay_settings(update).await.map_err(|error| {
+                    Error::RpcFailedExt("Failed to update relay settings", error)
+                })?;
+                println!("Relay constraints updated");
+                Ok(())
+            }
+        }
     }
 
     async fn set(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let profile = matches.value_of("profile");
         if let Some(custom_matches) = matches.subcommand_matches("custom") {
-            self.set_custom(custom_matches).await
+            self.set_custom(custom_matches, profile).await
         } else if let Some(location_matches) = matches.subcommand_matches("location") {
-            self.set_location(location_matches).await
+            self.set_location(location_matches, profile).await
         } else if let Some(relay_matches) = matches.subcommand_matches("hostname") {
-            self.set_hostname(relay_matches).await
+            self.set_hostname(relay_matches, profile).await
         } else if let Some(providers_matches) = matches.subcommand_matches("provider") {
-            self.set_providers(providers_matches).await
+            self.set_providers(providers_matches, profile).await
         } else if let Some(matches) = matches.subcommand_matches("tunnel") {
             if let Some(tunnel_matches) = matches.subcommand_matches("openvpn") {
-                self.set_openvpn_constraints(tunnel_matches).await
+                self.set_openvpn_constraints(tunnel_matches, profile).await
             } else if let Some(tunnel_matches) = matches.subcommand_matches("wireguard") {
-                self.set_wireguard_constraints(tunnel_matches).await
+                self.set_wireguard_constraints(tunnel_matches, profile)
+                    .await
             } else {
                 unreachable!("Invalid tunnel protocol");
             }
         } else if let Some(tunnel_matches) = matches.subcommand_matches("tunnel-protocol") {
-            self.set_tunnel_protocol(tunnel_matches).await
+            self.set_tunnel_protocol(tunnel_matches, profile).await
         } else {
             unreachable!("No set relay command given");
         }
     }
 
-    async fn set_custom(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+    async fn set_custom(
+        &self,
+        matches: &clap::ArgMatches<'_>,
+        profile: Option<&str>,
+    ) -> Result<()> {
         let custom_endpoint = match matches.subcommand() {
             ("openvpn", Some(openvpn_matches)) => Self::read_custom_openvpn_relay(openvpn_matches),
             ("wireguard", Some(wg_matches)) => Self::read_custom_wireguard_relay(wg_matches),
             (_unknown_tunnel, _) => unreachable!("No set relay command given"),
         };
 
-        self.update_constraints(types::RelaySettingsUpdate {
-            r#type: Some(types::relay_settings_update::Type::Custom(custom_endpoint)),
-        })
+        self.update_constraints(
+            profile,
+            types::RelaySettingsUpdate {
+                r#type: Some(types::relay_settings_update::Type::Custom(custom_endpoint)),
+            },
+        )
         .await
     }
 
@@ -388,7 +411,11 @@ impl Relay {
         }
     }
 
-    async fn set_hostname(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+    async fn set_hostname(
+        &self,
+        matches: &clap::ArgMatches<'_>,
+        profile: Option<&str>,
+    ) -> Result<()> {
         let hostname = matches.value_of("hostname").unwrap();
         let countries = Self::get_filtered_relays().await?;
 
@@ -417,14 +444,17 @@ impl Relay {
                 hostname: location.2.hostname.clone(),
             };
 
-            self.update_constraints(types::RelaySettingsUpdate {
-                r#type: Some(types::relay_settings_update::Type::Normal(
-                    types::NormalRelaySettingsUpdate {
-                        location: Some(location_constraint),
-                        ..Default::default()
-                    },
-                )),
-            })
+            self.update_constraints(
+                profile,
+                types::RelaySettingsUpdate {
+                    r#type: Some(types::relay_settings_update::Type::Normal(
+                        types::NormalRelaySettingsUpdate {
+                            location: Some(location_constraint),
+                            ..Default::default()
+                        },
+                    )),
+                },
+            )
             .await
         } else {
             clap::Error::with_description(
@@ -435,7 +465,11 @@ impl Relay {
         }
     }
 
-    async fn set_location(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+    async fn set_location(
+        &self,
+        matches: &clap::ArgMatches<'_>,
+        profile: Option<&str>,
+    ) -> Result<()> {
         let location_constraint = location::get_constraint_from_args(matches);
         let mut found = false;
 
@@ -481,18 +515,25 @@ impl Relay {
             }
         }
 
-        self.update_constraints(types::RelaySettingsUpdate {
-            r#type: Some(types::relay_settings_update::Type::Normal(
-                types::NormalRelaySettingsUpdate {
-                    location: Some(location_constraint),
-                    ..Default::default()
-                },
-            )),
-        })
+        self.update_constraints(
+            profile,
+            types::RelaySettingsUpdate {
+                r#type: Some(types::relay_settings_update::Type::Normal(
+                    types::NormalRelaySettingsUpdate {
+                        location: Some(location_constraint),
+                        ..Default::default()
+                    },
+                )),
+            },
+        )
         .await
     }
 
-    async fn set_providers(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+    async fn set_providers(
+        &self,
+        matches: &clap::ArgMatches<'_>,
+        profile: Option<&str>,
+    ) -> Result<()> {
         let providers =
             values_t!(matches.values_of("provider"), String).unwrap_or_else(|e| e.exit());
 
@@ -502,74 +543,112 @@ impl Relay {
             providers
         };
 
-        self.update_constraints(types::RelaySettingsUpdate {
-            r#type: Some(types::relay_settings_update::Type::Normal(
-                types::NormalRelaySettingsUpdate {
-                    providers: Some(types::ProviderUpdate { providers }),
-                    ..Default::default()
-                },
-            )),
-        })
+        self.update_constraints(
+            profile,
+            types::RelaySettingsUpdate {
+                r#type: Some(types::relay_settings_update::Type::Normal(
+                    types::NormalRelaySettingsUpdate {
+                        providers: Some(types::ProviderUpdate { providers }),
+                        ..Default::default()
+                    },
+                )),
+            },
+        )
         .await
     }
 
-    async fn set_openvpn_constraints(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+    async fn set_openvpn_constraints(
+        &self,
+        matches: &clap::ArgMatches<'_>,
+        profile: Option<&str>,
+    ) -> Result<()> {
         let port = parse_transport_port(matches)?;
-        self.update_constraints(types::RelaySettingsUpdate {
-            r#type: Some(types::relay_settings_update::Type::Normal(
-                types::NormalRelaySettingsUpdate {
-                    openvpn_constraints: Some(types::OpenvpnConstraints { port }),
-                    ..Default::default()
-                },
-            )),
-        })
+        self.update_constraints(
+            profile,
+            types::RelaySettingsUpdate {
+                r#type: Some(types::relay_settings_update::Type::Normal(
+                    types::NormalRelaySettingsUpdate {
+                        openvpn_constraints: Some(types::OpenvpnConstraints {
+                            port,
+                            wireguard_entry: matches.value_of("wireguard entry") == Some("on"),
+                        }),
+                        ..Default::default()
+                    },
+                )),
+            },
+        )
         .await
     }
 
-    async fn set_wireguard_constraints(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+    async fn set_wireguard_constraints(
+        &self,
+        matches: &clap::ArgMatches<'_>,
+        profile: Option<&str>,
+    ) -> Result<()> {
         let port = parse_transport_port(matches)?;
         let ip_version = parse_ip_version_constraint(matches.value_of("ip version").unwrap());
         let entry_location =
             parse_entry_location_constraint(matches.values_of("entry location").unwrap());
+        let automatic_entry = parse_automatic_entry(matches)?;
+        let obfuscation = match matches.value_of("obfuscation").unwrap() {
+            "auto" => types::wireguard_constraints::Obfuscation::Auto,
+            "off" => types::wireguard_constraints::Obfuscation::Off,
+            "udp2tcp" => types::wireguard_constraints::Obfuscation::Udp2tcp,
+            _ => unreachable!("Invalid obfuscation"),
+        };
 
-        self.update_constraints(types::RelaySettingsUpdate {
-            r#type: Some(types::relay_settings_update::Type::Normal(
-                types::NormalRelaySettingsUpdate {
-                    wireguard_constraints: Some(types::WireguardConstraints {
-                        port,
-                        ip_version: ip_version.option().map(|protocol| {
-                            types::IpVersionConstraint {
-                                protocol: protocol as i32,
-                            }
+        self.update_constraints(
+            profile,
+            types::RelaySettingsUpdate {
+                r#type: Some(types::relay_settings_update::Type::Normal(
+                    types::NormalRelaySettingsUpdate {
+                        wireguard_constraints: Some(types::WireguardConstraints {
+                            port,
+                            ip_version: ip_version.option().map(|protocol| {
+                                types::IpVersionConstraint {
+                                    protocol: protocol as i32,
+                                }
+                            }),
+                            entry_location,
+                            automatic_entry,
+                            obfuscation: obfuscation as i32,
                         }),
-                        entry_location,
-                    }),
-                    ..Default::default()
-                },
-            )),
-        })
+                        ..Default::default()
+                    },
+                )),
+            },
+        )
         .await
     }
 
-    async fn set_tunnel_protocol(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+    async fn set_tunnel_protocol(
+        &self,
+        matches: &clap::ArgMatches<'_>,
+        profile: Option<&str>,
+    ) -> Result<()> {
         let tunnel_type = match matches.value_of("tunnel protocol").unwrap() {
             "wireguard" => Some(types::TunnelType::Wireguard),
             "openvpn" => Some(types::TunnelType::Openvpn),
             "any" => None,
             _ => unreachable!(),
         };
-        self.update_constraints(types::RelaySettingsUpdate {
-            r#type: Some(types::relay_settings_update::Type::Normal(
-                types::NormalRelaySettingsUpdate {
-                    tunnel_type: Some(types::TunnelTypeUpdate {
-                        tunnel_type: tunnel_type.map(|tunnel_type| types::TunnelTypeConstraint {
-                            tunnel_type: tunnel_type as i32,
+        self.update_constraints(
+            profile,
+            types::RelaySettingsUpdate {
+                r#type: Some(types::relay_settings_update::Type::Normal(
+                    types::NormalRelaySettingsUpdate {
+                        tunnel_type: Some(types::TunnelTypeUpdate {
+                            tunnel_type: tunnel_type.map(|tunnel_type| {
+                                types::TunnelTypeConstraint {
+                                    tunnel_type: tunnel_type as i32,
+                                }
+                            }),
                         }),
-                    }),
-                    ..Default::default()
-                },
-            )),
-        })
+                        ..Default::default()
+                    },
+                )),
+            },
+        )
         .await
     }
 
@@ -676,6 +755,52 @@ impl Relay {
     }
 }
 
+fn create_openvpn_constraints_subcommand() -> clap::App<'static, 'static> {
+    let subcommand = clap::SubCommand::with_name("openvpn")
+        .about("Set OpenVPN-specific constraints")
+        .arg(
+            clap::Arg::with_name("port")
+                .help("Port to use. Either 'any' or a specific port")
+                .long("port")
+                .default_value("any"),
+        )
+        .arg(
+            clap::Arg::with_name("transport protocol")
+                .help("Transport protocol")
+                .long("protocol")
+                .possible_values(&["any", "udp", "tcp"])
+                .default_value("any"),
+        );
+    #[cfg(target_os = "linux")]
+    let subcommand = subcommand.arg(
+        clap::Arg::with_name("wireguard entry")
+            .help(
+                "Connect through a WireGuard entry relay. It is selected using the WireGuard \
+                 entry location",
+            )
+            .long("wireguard-entry")
+            .possible_values(&["on", "off"])
+            .default_value("off"),
+    );
+    subcommand
+}
+
+/// Returns the argument that selects a network profile to change instead of the regular settings.
+/// Network profiles are only supported on Linux.
+pub fn profile_args() -> Vec<clap::Arg<'static, 'static>> {
+    #[cfg(target_os = "linux")]
+    {
+        vec![clap::Arg::with_name("profile")
+            .long("profile")
+            .takes_value(true)
+            .value_name("NAME")
+            .help("Change the settings of a network profile instead of the regular settings")]
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        vec![]
+    }
+}
 
 fn parse_port_constraint(raw_port: &str) -> Result<Constraint<u16>> {
     match raw_port.to_lowercase().as_str() {
@@ -709,8 +834,10 @@ fn parse_entry_location_constraint<'a, T: Iterator<Item = &'a str>>(
 ) -> Option<types::RelayLocation> {
     let country = location.next().unwrap();
 
-    if country == "none" {
-        return None;
+    match country {
+        "none" => return None,
+        "auto" => return Some(types::RelayLocation::default()),
+        _ => (),
     }
 
     Some(location::get_constraint(
@@ -720,6 +847,27 @@ fn parse_entry_location_constraint<'a, T: Iterator<Item = &'a str>>(
     ))
 }
 
+fn parse_automatic_entry(matches: &clap::ArgMatches<'_>) -> Result<Option<types::AutomaticEntry>> {
+    let different: Vec<&str> = matches
+        .values_of("entry different")
+        .map(|values| values.collect())
+        .unwrap_or_default();
+
+    if matches.value_of("entry location") != Some("auto") {
+        if !different.is_empty() {
+            return Err(Error::InvalidCommand(
+                "--entry-different can only be used with '--entry-location auto'",
+            ));
+        }
+        return Ok(None);
+    }
+
+    Ok(Some(types::AutomaticEntry {
+        different_country: different.contains(&"country"),
+        different_provider: different.contains(&"provider"),
+    }))
+}
+
 fn parse_transport_port(matches: &clap::ArgMatches<'_>) -> Result<Option<types::TransportPort>> {
     let port = parse_port_constraint(matches.value_of("port").unwrap())?;
     let protocol = parse_protocol(matches.value_of("transport protocol").unwrap());
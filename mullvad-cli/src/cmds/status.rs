@@ -1,6 +1,7 @@
 use crate::{format, format::print_keygen_event, new_rpc_client, Command, Error, Result};
 use mullvad_management_interface::{
-    types::daemon_event::Event as EventType, ManagementServiceClient,
+    types::{connectivity::State as ConnectivityState, daemon_event::Event as EventType},
+    ManagementServiceClient,
 };
 
 pub struct Status;
@@ -36,6 +37,11 @@ impl Command for Status {
         let state = rpc.get_tunnel_state(()).await?.into_inner();
 
         format::print_state(&state);
+        print_account_expiry(&mut rpc).await?;
+        let connectivity = rpc.get_connectivity(()).await?.into_inner();
+        if connectivity.state != i32::from(ConnectivityState::Online) {
+            format::print_connectivity(&connectivity);
+        }
         if matches.is_present("location") {
             print_location(&mut rpc).await?;
         }
@@ -80,6 +86,12 @@ impl Command for Status {
                             print_keygen_event(&key_event);
                         }
                     }
+                    EventType::Connectivity(connectivity) => {
+                        format::print_connectivity(&connectivity);
+                    }
+                    EventType::AccountExpiry(expiry_event) => {
+                        format::print_account_expiry_event(&expiry_event);
+                    }
                 }
             }
         }
@@ -88,6 +100,18 @@ impl Command for Status {
     }
 }
 
+async fn print_account_expiry(rpc: &mut ManagementServiceClient) -> Result<()> {
+    match rpc.get_account_expiry(()).await {
+        Ok(account_data) => {
+            let expiry = account_data.into_inner().expiry.unwrap();
+            println!("Account expires: {}", format::local_time(&expiry));
+            Ok(())
+        }
+        Err(status) if status.code() == mullvad_management_interface::Code::NotFound => Ok(()),
+        Err(status) => Err(Error::RpcFailed(status)),
+    }
+}
+
 async fn print_location(rpc: &mut ManagementServiceClient) -> Result<()> {
     let location = rpc.get_current_location(()).await;
     let location = match location {
@@ -104,6 +128,9 @@ async fn print_location(rpc: &mut ManagementServiceClient) -> Result<()> {
     if !location.hostname.is_empty() {
         println!("Relay: {}", location.hostname);
     }
+    if !location.entry_hostname.is_empty() {
+        println!("Entry relay: {}", location.entry_hostname);
+    }
     if !location.ipv4.is_empty() {
         println!("IPv4: {}", location.ipv4);
     }
@@ -0,0 +1,131 @@
+use crate::{new_rpc_client, Command, Error, Result};
+use mullvad_management_interface::types;
+use mullvad_types::trusted_network::{self, TrustedNetwork};
+use std::convert::TryFrom;
+use talpid_types::net::ConnectedNetwork;
+
+pub struct TrustedNetworks;
+
+#[mullvad_management_interface::async_trait]
+impl Command for TrustedNetworks {
+    fn name(&self) -> &'static str {
+        "trusted-network"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about(
+                "Manage networks on which the tunnel is disconnected automatically. The tunnel is \
+                 connected again when the host leaves all trusted networks",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("list")
+                    .about("List trusted networks and the networks that the host is connected to"),
+            )
+            .subcommand(network_subcommand("add").about("Trust a network"))
+            .subcommand(network_subcommand("remove").about("Stop trusting a network"))
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("list", _) => Self::list().await,
+            ("add", Some(add_matches)) => Self::add(parse_network(add_matches)).await,
+            ("remove", Some(remove_matches)) => Self::remove(parse_network(remove_matches)).await,
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+fn network_subcommand(name: &'static str) -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name(name)
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            clap::SubCommand::with_name("ssid")
+                .about("A Wi-Fi network, identified by its SSID")
+                .arg(clap::Arg::with_name("ssid").required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("interface")
+                .about("Any network reached through a network interface, e.g. eth0")
+                .arg(clap::Arg::with_name("interface").required(true)),
+        )
+}
+
+fn parse_network(matches: &clap::ArgMatches<'_>) -> TrustedNetwork {
+    match matches.subcommand() {
+        ("ssid", Some(ssid_matches)) => {
+            TrustedNetwork::Ssid(ssid_matches.value_of("ssid").unwrap().to_owned())
+        }
+        ("interface", Some(interface_matches)) => {
+            TrustedNetwork::Interface(interface_matches.value_of("interface").unwrap().to_owned())
+        }
+        _ => unreachable!("unhandled network type"),
+    }
+}
+
+impl TrustedNetworks {
+    async fn list() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
+        let mut trusted_networks = trusted_network::Settings::default();
+        for network in settings.trusted_networks {
+            let network = TrustedNetwork::try_from(network)
+                .map_err(|_| Error::CommandFailed("Received invalid trusted network"))?;
+            trusted_networks
+                .add(network)
+                .map_err(|_| Error::CommandFailed("Received duplicate trusted network"))?;
+        }
+
+        if trusted_networks.networks().is_empty() {
+            println!("No trusted networks");
+        } else {
+            println!("Trusted networks:");
+            for network in trusted_networks.networks() {
+                println!("\t{}", network);
+            }
+        }
+
+        let connected_networks: Vec<ConnectedNetwork> = rpc
+            .get_connected_networks(())
+            .await?
+            .into_inner()
+            .networks
+            .into_iter()
+            .map(ConnectedNetwork::from)
+            .collect();
+        if connected_networks.is_empty() {
+            println!("Not connected to any known network");
+        } else {
+            println!("Connected networks:");
+            for network in &connected_networks {
+                println!("\t{}", network);
+            }
+        }
+        println!(
+            "The current network is {}",
+            if trusted_networks.is_trusted(&connected_networks) {
+                "trusted"
+            } else {
+                "not trusted"
+            }
+        );
+        Ok(())
+    }
+
+    async fn add(network: TrustedNetwork) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.add_trusted_network(types::TrustedNetwork::from(&network))
+            .await?;
+        println!("Added trusted network: {}", network);
+        Ok(())
+    }
+
+    async fn remove(network: TrustedNetwork) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.remove_trusted_network(types::TrustedNetwork::from(&network))
+            .await?;
+        println!("Removed trusted network: {}", network);
+        Ok(())
+    }
+}
@@ -1,8 +1,13 @@
 use crate::{format::print_keygen_event, new_rpc_client, Command, Error, Result};
 use clap::value_t;
 use mullvad_management_interface::types::{self, Timestamp, TunnelOptions};
-use mullvad_types::wireguard::DEFAULT_ROTATION_INTERVAL;
+use mullvad_types::{
+    relay_constraints::{Constraint, TransportPort},
+    retry_schedule::{RetryAttempt, RetrySchedule},
+    wireguard::DEFAULT_ROTATION_INTERVAL,
+};
 use std::{convert::TryFrom, time::Duration};
+use talpid_types::net::{openvpn, TransportProtocol, TunnelType};
 
 pub struct Tunnel;
 
@@ -19,6 +24,7 @@ impl Command for Tunnel {
             .subcommand(create_openvpn_subcommand())
             .subcommand(create_wireguard_subcommand())
             .subcommand(create_ipv6_subcommand())
+            .subcommand(create_retry_schedule_subcommand())
     }
 
     async fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
@@ -26,6 +32,12 @@ impl Command for Tunnel {
             ("openvpn", Some(openvpn_matches)) => Self::handle_openvpn_cmd(openvpn_matches).await,
             ("wireguard", Some(wg_matches)) => Self::handle_wireguard_cmd(wg_matches).await,
             ("ipv6", Some(ipv6_matches)) => Self::handle_ipv6_cmd(ipv6_matches).await,
+            ("retry-schedule", Some(matches)) => match matches.subcommand() {
+                ("get", _) => Self::process_retry_schedule_get().await,
+                ("set", Some(matches)) => Self::process_retry_schedule_set(matches).await,
+                ("reset", _) => Self::process_retry_schedule_reset().await,
+                _ => unreachable!("unhandled command"),
+            },
             _ => {
                 unreachable!("unhandled comand");
             }
@@ -66,6 +78,9 @@ fn create_wireguard_keys_subcommand() -> clap::App<'static, 'static> {
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .subcommand(clap::SubCommand::with_name("check"))
         .subcommand(clap::SubCommand::with_name("regenerate"))
+        .subcommand(
+            clap::SubCommand::with_name("history").about("Show recent automatic key rotations"),
+        )
         .subcommand(create_wireguard_keys_rotation_interval_subcommand())
 }
 
@@ -102,6 +117,8 @@ fn create_openvpn_subcommand() -> clap::App<'static, 'static> {
         .about("Manage options for OpenVPN tunnels")
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .subcommand(create_openvpn_mssfix_subcommand())
+        .subcommand(create_openvpn_cipher_subcommand())
+        .subcommand(create_openvpn_tls_version_min_subcommand())
 }
 
 fn create_openvpn_mssfix_subcommand() -> clap::App<'static, 'static> {
@@ -115,6 +132,39 @@ fn create_openvpn_mssfix_subcommand() -> clap::App<'static, 'static> {
         )
 }
 
+fn create_openvpn_cipher_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("cipher")
+        .about("Restrict the data channel to a single cipher")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::SubCommand::with_name("get"))
+        .subcommand(
+            clap::SubCommand::with_name("unset")
+                .about("Let the cipher be negotiated with the relay"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("set").arg(
+                clap::Arg::with_name("cipher")
+                    .required(true)
+                    .possible_values(&["AES-256-GCM", "CHACHA20-POLY1305"])
+                    .case_insensitive(true),
+            ),
+        )
+}
+
+fn create_openvpn_tls_version_min_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("tls-version-min")
+        .about("Configure the lowest TLS version accepted for the control channel")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::SubCommand::with_name("get"))
+        .subcommand(
+            clap::SubCommand::with_name("set").arg(
+                clap::Arg::with_name("version")
+                    .required(true)
+                    .possible_values(&["1.2", "1.3"]),
+            ),
+        )
+}
+
 fn create_ipv6_subcommand() -> clap::App<'static, 'static> {
     clap::SubCommand::with_name("ipv6")
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
@@ -129,12 +179,63 @@ fn create_ipv6_subcommand() -> clap::App<'static, 'static> {
         )
 }
 
+fn create_retry_schedule_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("retry-schedule")
+        .about("Manage the order in which tunnel protocols, ports and bridges are tried")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            clap::SubCommand::with_name("get")
+                .about("Show the schedule that applies to the current relay settings"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("set")
+                .about("Replace the default schedule")
+                .arg(
+                    clap::Arg::with_name("attempts")
+                        .help(
+                            "Attempts in the format TUNNEL[:PROTOCOL[:PORT]][+bridge], \
+                             e.g. wireguard:udp:53 or openvpn:tcp:443+bridge. The bridge is \
+                             only used if the bridge state is auto",
+                        )
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(
+                    clap::Arg::with_name("repeat-from")
+                        .help("The attempt to continue from once every attempt has been made")
+                        .long("repeat-from")
+                        .takes_value(true)
+                        .default_value("0"),
+                ),
+        )
+        .subcommand(clap::SubCommand::with_name("reset").about("Use the default schedule"))
+}
+
 impl Tunnel {
     async fn handle_openvpn_cmd(matches: &clap::ArgMatches<'_>) -> Result<()> {
         match matches.subcommand() {
             ("mssfix", Some(mssfix_matches)) => {
                 Self::handle_openvpn_mssfix_cmd(mssfix_matches).await
             }
+            ("cipher", Some(matches)) => match matches.subcommand() {
+                ("get", Some(_)) => Self::process_openvpn_cipher_get().await,
+                ("unset", Some(_)) => Self::process_openvpn_cipher_set(None).await,
+                ("set", Some(set_matches)) => {
+                    let cipher = value_t!(set_matches.value_of("cipher"), openvpn::Cipher)
+                        .unwrap_or_else(|e| e.exit());
+                    Self::process_openvpn_cipher_set(Some(cipher)).await
+                }
+                _ => unreachable!("unhandled command"),
+            },
+            ("tls-version-min", Some(matches)) => match matches.subcommand() {
+                ("get", Some(_)) => Self::process_openvpn_tls_version_min_get().await,
+                ("set", Some(set_matches)) => {
+                    let version = value_t!(set_matches.value_of("version"), openvpn::TlsVersion)
+                        .unwrap_or_else(|e| e.exit());
+                    Self::process_openvpn_tls_version_min_set(version).await
+                }
+                _ => unreachable!("unhandled command"),
+            },
             _ => unreachable!("unhandled command"),
         }
     }
@@ -160,6 +261,7 @@ impl Tunnel {
             ("key", Some(matches)) => match matches.subcommand() {
                 ("check", _) => Self::process_wireguard_key_check().await,
                 ("regenerate", _) => Self::process_wireguard_key_generate().await,
+                ("history", _) => Self::process_wireguard_key_history().await,
                 ("rotation-interval", Some(matches)) => match matches.subcommand() {
                     ("get", _) => Self::process_wireguard_rotation_interval_get().await,
                     ("set", Some(matches)) => {
@@ -271,6 +373,36 @@ impl Tunnel {
         Ok(())
     }
 
+    async fn process_wireguard_key_history() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let history = rpc
+            .get_wireguard_key_rotation_history(())
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to obtain key rotation history", error))?
+            .into_inner();
+        if history.rotations.is_empty() {
+            println!("No keys have been rotated");
+            return Ok(());
+        }
+        for rotation in history.rotations {
+            let outcome = match types::key_rotation::Outcome::from_i32(rotation.outcome) {
+                Some(types::key_rotation::Outcome::Verified) => "verified",
+                Some(types::key_rotation::Outcome::Unverified) => "applied without verification",
+                Some(types::key_rotation::Outcome::Replaced) => "replaced",
+                Some(types::key_rotation::Outcome::FellBack) => "fell back to the old key",
+                None => "unknown",
+            };
+            println!(
+                "{}: {} -> {} ({})",
+                Self::format_key_timestamp(&rotation.finished.unwrap()),
+                base64::encode(&rotation.old_key),
+                base64::encode(&rotation.new_key),
+                outcome,
+            );
+        }
+        Ok(())
+    }
+
     async fn process_wireguard_rotation_interval_get() -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
         match tunnel_options.wireguard.unwrap().rotation_interval {
@@ -308,6 +440,49 @@ impl Tunnel {
         Ok(())
     }
 
+    async fn process_retry_schedule_get() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let schedule = rpc.get_retry_schedule(()).await?.into_inner();
+        let schedule = RetrySchedule::try_from(schedule)
+            .map_err(|_| Error::CommandFailed("Received invalid retry schedule"))?;
+        for (index, attempt) in schedule.attempts.iter().enumerate() {
+            println!("{}. {}", index, attempt);
+        }
+        println!("Repeated from attempt {}", schedule.repeat_from);
+        Ok(())
+    }
+
+    async fn process_retry_schedule_set(matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let attempts = matches
+            .values_of("attempts")
+            .unwrap()
+            .map(parse_retry_attempt)
+            .collect::<Result<Vec<_>>>()?;
+        let repeat_from =
+            value_t!(matches.value_of("repeat-from"), usize).unwrap_or_else(|e| e.exit());
+        if repeat_from >= attempts.len() {
+            return Err(Error::InvalidCommand(
+                "The repeated attempt must be in the schedule",
+            ));
+        }
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_retry_schedule(types::RetrySchedule::from(RetrySchedule {
+            attempts,
+            repeat_from,
+        }))
+        .await?;
+        println!("Updated retry schedule");
+        Ok(())
+    }
+
+    async fn process_retry_schedule_reset() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_retry_schedule(types::RetrySchedule::default())
+            .await?;
+        println!("Using the default retry schedule");
+        Ok(())
+    }
+
     async fn handle_ipv6_cmd(matches: &clap::ArgMatches<'_>) -> Result<()> {
         if matches.subcommand_matches("get").is_some() {
             Self::process_ipv6_get().await
@@ -357,6 +532,53 @@ impl Tunnel {
         Ok(())
     }
 
+    async fn process_openvpn_cipher_get() -> Result<()> {
+        let tunnel_options = Self::get_tunnel_options().await?;
+        let cipher = tunnel_options
+            .openvpn
+            .unwrap()
+            .cipher
+            .map(Option::<openvpn::Cipher>::try_from)
+            .transpose()
+            .map_err(|_| Error::CommandFailed("Received invalid cipher"))?
+            .flatten();
+        match cipher {
+            Some(cipher) => println!("Cipher: {}", cipher),
+            None => println!("Cipher: any"),
+        }
+        Ok(())
+    }
+
+    async fn process_openvpn_cipher_set(cipher: Option<openvpn::Cipher>) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_openvpn_cipher(types::OpenvpnCipher::from(cipher))
+            .await?;
+        println!("Cipher setting has been updated");
+        Ok(())
+    }
+
+    async fn process_openvpn_tls_version_min_get() -> Result<()> {
+        let tunnel_options = Self::get_tunnel_options().await?;
+        let version = tunnel_options
+            .openvpn
+            .unwrap()
+            .tls_version_min
+            .map(openvpn::TlsVersion::try_from)
+            .transpose()
+            .map_err(|_| Error::CommandFailed("Received invalid TLS version"))?
+            .unwrap_or_default();
+        println!("Minimum TLS version: {}", version);
+        Ok(())
+    }
+
+    async fn process_openvpn_tls_version_min_set(version: openvpn::TlsVersion) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_openvpn_tls_version_min(types::OpenvpnTlsVersion::from(version))
+            .await?;
+        println!("Minimum TLS version has been updated");
+        Ok(())
+    }
+
     async fn process_ipv6_get() -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
         println!(
@@ -393,3 +615,44 @@ impl Tunnel {
 fn duration_hours(duration: &Duration) -> u64 {
     duration.as_secs() / 60 / 60
 }
+
+fn parse_retry_attempt(attempt: &str) -> Result<RetryAttempt> {
+    let (attempt, bridge) = match attempt.strip_suffix("+bridge") {
+        Some(attempt) => (attempt, true),
+        None => (attempt, false),
+    };
+    let mut parts = attempt.split(':');
+    let tunnel_type = match parts.next() {
+        Some("wireguard") => TunnelType::Wireguard,
+        Some("openvpn") => TunnelType::OpenVpn,
+        _ => {
+            return Err(Error::InvalidCommand(
+                "Invalid tunnel protocol. Must be \"wireguard\" or \"openvpn\".",
+            ))
+        }
+    };
+    let port = match (parts.next(), parts.next()) {
+        (None, None) | (Some("any"), None) => Constraint::Any,
+        (Some(protocol), port) => {
+            let protocol = protocol.parse::<TransportProtocol>().map_err(|_| {
+                Error::InvalidCommand("Invalid transport protocol. Must be \"udp\" or \"tcp\".")
+            })?;
+            let port = match port {
+                None | Some("any") => Constraint::Any,
+                Some(port) => Constraint::Only(port.parse::<u16>().map_err(|_| {
+                    Error::InvalidCommand("Invalid port. Must be \"any\" or [0-65535].")
+                })?),
+            };
+            Constraint::Only(TransportPort { protocol, port })
+        }
+        (None, Some(_)) => unreachable!("split yields no parts after the end"),
+    };
+    if parts.next().is_some() {
+        return Err(Error::InvalidCommand("Invalid retry attempt"));
+    }
+    Ok(RetryAttempt {
+        tunnel_type,
+        port,
+        bridge,
+    })
+}
@@ -0,0 +1,78 @@
+use crate::{new_rpc_client, Command, Result};
+use mullvad_management_interface::types;
+
+pub struct UpdateChannel;
+
+#[mullvad_management_interface::async_trait]
+impl Command for UpdateChannel {
+    fn name(&self) -> &'static str {
+        "update-channel"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Manage where version checks fetch update metadata from")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("get").about("Display the current update channel"),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("set")
+                    .about("Fetch update metadata from a self-hosted channel instead of the API")
+                    .arg(
+                        clap::Arg::with_name("source")
+                            .help(
+                                "URL or file path of the version metadata. The metadata must be \
+                                 signed, with the signature stored at the same location with \
+                                 `.sig` appended",
+                            )
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("public key")
+                            .help(
+                                "Base64 encoded Ed25519 public key that the metadata is signed \
+                                 with",
+                            )
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("unset").about("Fetch update metadata from the API"),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        match matches.subcommand() {
+            ("get", _) => self.get().await,
+            ("set", Some(set_matches)) => {
+                self.set(types::UpdateChannel {
+                    source: set_matches.value_of("source").unwrap().to_owned(),
+                    public_key: set_matches.value_of("public key").unwrap().to_owned(),
+                })
+                .await
+            }
+            ("unset", _) => self.set(types::UpdateChannel::default()).await,
+            _ => unreachable!("No update-channel command given"),
+        }
+    }
+}
+
+impl UpdateChannel {
+    async fn get(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
+        match settings.update_channel {
+            Some(channel) => println!("Update channel: {}", channel.source),
+            None => println!("Update channel: Mullvad API"),
+        }
+        Ok(())
+    }
+
+    async fn set(&self, channel: types::UpdateChannel) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_update_channel(channel).await?;
+        println!("Updated update channel");
+        Ok(())
+    }
+}
@@ -5,7 +5,8 @@ use mullvad_management_interface::types::{
     },
     tunnel_state,
     tunnel_state::State::*,
-    ErrorState, KeygenEvent, ProxyType, TransportProtocol, TunnelEndpoint, TunnelState, TunnelType,
+    AccountExpiryEvent, Connectivity, ErrorState, KeygenEvent, ProxyType, Timestamp,
+    TransportProtocol, TunnelEndpoint, TunnelState, TunnelType,
 };
 use mullvad_types::auth_failed::AuthFailed;
 use std::fmt::Write;
@@ -29,6 +30,35 @@ pub fn print_keygen_event(key_event: &KeygenEvent) {
     }
 }
 
+pub fn print_connectivity(connectivity: &Connectivity) {
+    use mullvad_management_interface::types::connectivity::State;
+
+    match State::from_i32(connectivity.state).unwrap() {
+        State::Online => println!("Connectivity: online"),
+        State::Offline => println!("Connectivity: offline"),
+        State::CaptivePortal => println!("Connectivity: captive portal suspected"),
+    }
+}
+
+pub fn print_account_expiry_event(event: &AccountExpiryEvent) {
+    let expiry = local_time(event.expiry.as_ref().unwrap());
+    if event.expired {
+        println!("Account expired at {}", expiry);
+    } else {
+        println!(
+            "Account expires in less than {} hours, at {}",
+            event.threshold_hours, expiry
+        );
+    }
+}
+
+/// Converts a timestamp received from the daemon to the local time zone.
+pub fn local_time(timestamp: &Timestamp) -> chrono::DateTime<chrono::Local> {
+    let ndt = chrono::NaiveDateTime::from_timestamp(timestamp.seconds, timestamp.nanos as u32);
+    let utc = chrono::DateTime::<chrono::Utc>::from_utc(ndt, chrono::Utc);
+    utc.with_timezone(&chrono::Local)
+}
+
 pub fn print_state(state: &TunnelState) {
     print!("Tunnel status: ");
     match state.state.as_ref().unwrap() {
@@ -70,39 +100,32 @@ fn format_endpoint(endpoint: &TunnelEndpoint) -> String {
         ),
     );
 
-    match tunnel_type {
-        TunnelType::Openvpn => {
-            if let Some(ref proxy) = endpoint.proxy {
-                write!(
-                    &mut out,
-                    " via {} {} over {}",
-                    match ProxyType::from_i32(proxy.proxy_type).expect("invalid proxy type") {
-                        ProxyType::Shadowsocks => "Shadowsocks",
-                        ProxyType::Custom => "custom bridge",
-                    },
-                    proxy.address,
-                    format_protocol(
-                        TransportProtocol::from_i32(proxy.protocol)
-                            .expect("invalid transport protocol")
-                    ),
-                )
-                .unwrap();
-            }
-        }
-        TunnelType::Wireguard => {
-            if let Some(ref entry_endpoint) = endpoint.entry_endpoint {
-                write!(
-                    &mut out,
-                    " via {} over {}",
-                    entry_endpoint.address,
-                    format_protocol(
-                        TransportProtocol::from_i32(entry_endpoint.protocol)
-                            .expect("invalid transport protocol")
-                    )
-                )
-                .unwrap();
-            }
-        }
+    if let Some(ref entry_endpoint) = endpoint.entry_endpoint {
+        write!(
+            &mut out,
+            " via {} over {}",
+            entry_endpoint.address,
+            format_protocol(
+                TransportProtocol::from_i32(entry_endpoint.protocol)
+                    .expect("invalid transport protocol")
+            )
+        )
+        .unwrap();
+    }
+    if let Some(ref proxy) = endpoint.proxy {
+        write!(
+            &mut out,
+            " via {} {} over {}",
+            match ProxyType::from_i32(proxy.proxy_type).expect("invalid proxy type") {
+                ProxyType::Shadowsocks => "Shadowsocks",
+                ProxyType::Custom => "custom bridge",
+            },
+            proxy.address,
+            format_protocol(
+                TransportProtocol::from_i32(proxy.protocol).expect("invalid transport protocol")
+            ),
+        )
+        .unwrap();
     }
 
     out
@@ -162,6 +185,9 @@ fn error_state_to_string(error_state: &ErrorState) -> String {
         VpnPermissionDenied => "The Android VPN permission was denied when creating the tunnel",
         #[cfg(target_os = "windows")]
         SplitTunnelError => "The split tunneling module reported an error",
+        CaptivePortalLogin => "Allowing traffic needed to log in to a captive portal",
+        AccountExpired => "The account has no time left",
+        CaptivePortal => "A captive portal is intercepting traffic",
         #[cfg(not(target_os = "android"))]
         _ => unreachable!("unknown error cause"),
     };
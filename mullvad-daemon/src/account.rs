@@ -1,3 +1,4 @@
+use crate::json_cache;
 use chrono::{DateTime, Utc};
 use futures::future::{abortable, AbortHandle};
 use mullvad_rpc::{
@@ -5,8 +6,9 @@ use mullvad_rpc::{
     rest::{self, Error as RestError, MullvadRestHandle},
     AccountsProxy,
 };
-use mullvad_types::account::{AccountToken, VoucherSubmission};
-use std::{future::Future, time::Duration};
+use mullvad_types::account::{AccountExpiryEvent, AccountToken, VoucherSubmission};
+use serde::{Deserialize, Serialize};
+use std::{future::Future, path::Path, time::Duration};
 use talpid_core::future_retry::{
     constant_interval, retry_future, retry_future_n, ExponentialBackoff, Jittered,
 };
@@ -18,6 +20,21 @@ const RETRY_EXPIRY_CHECK_INTERVAL_INITIAL: Duration = Duration::from_secs(4);
 const RETRY_EXPIRY_CHECK_INTERVAL_FACTOR: u32 = 5;
 const RETRY_EXPIRY_CHECK_INTERVAL_MAX: Duration = Duration::from_secs(24 * 60 * 60);
 
+const EXPIRY_CACHE_FILENAME: &str = "account-expiry.json";
+
+/// How often the cached account expiry is compared against the notification thresholds.
+pub const EXPIRY_EVALUATION_INTERVAL: Duration = Duration::from_secs(60);
+/// How often the account expiry is fetched from the API.
+pub const EXPIRY_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Last known expiry of an account. Persisted so that expiry notifications can be emitted
+/// while the API is unreachable.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedExpiry {
+    pub account_token: AccountToken,
+    pub expiry: DateTime<Utc>,
+}
+
 
 pub struct Account(());
 
@@ -172,3 +189,78 @@ fn handle_expiry_result_inner(
         Err(_) => false,
     }
 }
+
+/// Load the cached account expiry, if there is one.
+pub async fn load_expiry_cache(
+    cache_dir: &Path,
+) -> Result<Option<CachedExpiry>, json_cache::Error> {
+    json_cache::load(&cache_dir.join(EXPIRY_CACHE_FILENAME)).await
+}
+
+/// Replace the cached account expiry. `None` removes the cache.
+pub async fn save_expiry_cache(
+    cache_dir: &Path,
+    cached_expiry: Option<&CachedExpiry>,
+) -> Result<(), json_cache::Error> {
+    let path = cache_dir.join(EXPIRY_CACHE_FILENAME);
+    match cached_expiry {
+        Some(cached_expiry) => json_cache::save(&path, cached_expiry).await,
+        None => json_cache::remove(&path).await,
+    }
+}
+
+/// Returns the event that applies to an account expiring at `expiry`, given the notification
+/// thresholds in hours. The smallest threshold that has been crossed is reported.
+pub fn expiry_event(
+    expiry: DateTime<Utc>,
+    now: DateTime<Utc>,
+    threshold_hours: &[u32],
+) -> Option<AccountExpiryEvent> {
+    if expiry <= now {
+        return Some(AccountExpiryEvent {
+            expiry,
+            threshold_hours: None,
+        });
+    }
+    let remaining = expiry - now;
+    threshold_hours
+        .iter()
+        .filter(|hours| remaining <= chrono::Duration::hours(i64::from(**hours)))
+        .min()
+        .map(|hours| AccountExpiryEvent {
+            expiry,
+            threshold_hours: Some(*hours),
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_expiry_event_thresholds() {
+        let now = Utc::now();
+        let thresholds = [72, 24];
+
+        assert_eq!(
+            expiry_event(now + chrono::Duration::days(4), now, &thresholds),
+            None
+        );
+        assert_eq!(
+            expiry_event(now + chrono::Duration::days(2), now, &thresholds)
+                .and_then(|event| event.threshold_hours),
+            Some(72)
+        );
+        assert_eq!(
+            expiry_event(now + chrono::Duration::hours(3), now, &thresholds)
+                .and_then(|event| event.threshold_hours),
+            Some(24)
+        );
+        assert!(expiry_event(now, now, &thresholds)
+            .map(|event| event.is_expired())
+            .unwrap_or(false));
+        assert!(expiry_event(now - chrono::Duration::hours(1), now, &[])
+            .map(|event| event.is_expired())
+            .unwrap_or(false));
+    }
+}
@@ -0,0 +1,317 @@
+//! Selects how the API is reached, using the API access methods in the settings.
+
+use crate::{relays::RelaySelector, DaemonEventSender};
+use futures::{channel::oneshot, future};
+use mullvad_rpc::proxy::{ApiConnectionMode, ConnectionModeProvider, ProxyConfig};
+use mullvad_types::access_method::{self, AccessMethod, ApiAccessMethod};
+use parking_lot::Mutex;
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+};
+use talpid_core::mpsc::Sender;
+#[cfg(not(target_os = "android"))]
+use talpid_core::proxy::{self, ProxyMonitor, ProxyResourceData};
+#[cfg(not(target_os = "android"))]
+use talpid_types::net::openvpn::ProxySettings;
+use talpid_types::ErrorExt;
+use tokio::fs;
+
+const ACCESS_METHOD_CACHE_FILENAME: &str = "last-api-access-method.txt";
+
+/// Requests sent to the daemon by the [`AccessMethodProvider`].
+pub enum AccessMethodEvent {
+    /// The current access method failed. The next one should be selected and its connection mode
+    /// sent back.
+    Rotate(oneshot::Sender<ApiConnectionMode>),
+    /// A request succeeded using the current access method.
+    Succeeded,
+}
+
+/// Lets the request service for the API obtain new connection modes from the daemon.
+pub struct AccessMethodProvider {
+    initial: ApiConnectionMode,
+    daemon_tx: DaemonEventSender<AccessMethodEvent>,
+}
+
+impl AccessMethodProvider {
+    pub(crate) fn new(
+        initial: ApiConnectionMode,
+        daemon_tx: DaemonEventSender<AccessMethodEvent>,
+    ) -> Self {
+        Self { initial, daemon_tx }
+    }
+}
+
+impl ConnectionModeProvider for AccessMethodProvider {
+    fn initial(&self) -> ApiConnectionMode {
+        self.initial.clone()
+    }
+
+    fn rotate(&mut self) -> Pin<Box<dyn Future<Output = ApiConnectionMode> + Send>> {
+        let (tx, rx) = oneshot::channel();
+        if self.daemon_tx.send(AccessMethodEvent::Rotate(tx)).is_err() {
+            return Box::pin(future::ready(ApiConnectionMode::Direct));
+        }
+        Box::pin(async move { rx.await.unwrap_or(ApiConnectionMode::Direct) })
+    }
+
+    fn succeeded(&mut self) {
+        let _ = self.daemon_tx.send(AccessMethodEvent::Succeeded);
+    }
+}
+
+/// Keeps track of the access method in use, and of any local proxy client it requires.
+pub struct AccessMethodSelector {
+    /// Name of the access method in use.
+    current: Option<String>,
+    /// Access method to try before any other when rotating.
+    preferred: Option<String>,
+    /// Name of the last access method that was used successfully.
+    remembered: Option<String>,
+    cache_path: PathBuf,
+    #[cfg(not(target_os = "android"))]
+    resource_data: ProxyResourceData,
+    #[cfg(not(target_os = "android"))]
+    proxy_monitor: Option<Box<dyn ProxyMonitor>>,
+    proxy_endpoint: Arc<Mutex<Option<SocketAddr>>>,
+}
+
+impl AccessMethodSelector {
+    /// Loads the last working access method from the cache directory. Returns the selector along
+    /// with the connection mode to use initially.
+    pub async fn load(
+        cache_dir: &Path,
+        #[cfg(not(target_os = "android"))] resource_dir: PathBuf,
+        #[cfg(not(target_os = "android"))] log_dir: Option<PathBuf>,
+        settings: &access_method::Settings,
+    ) -> (Self, ApiConnectionMode) {
+        let cache_path = cache_dir.join(ACCESS_METHOD_CACHE_FILENAME);
+        let remembered = match fs::read_to_string(&cache_path).await {
+            Ok(name) => Some(name.trim().to_owned()),
+            Err(error) => {
+                if error.kind() != io::ErrorKind::NotFound {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to read last API access method")
+                    );
+                }
+                None
+            }
+        };
+
+        let mut selector = AccessMethodSelector {
+            current: None,
+            preferred: None,
+            remembered,
+            cache_path,
+            #[cfg(not(target_os = "android"))]
+            resource_data: ProxyResourceData {
+                resource_dir,
+                log_dir,
+            },
+            #[cfg(not(target_os = "android"))]
+            proxy_monitor: None,
+            proxy_endpoint: Arc::new(Mutex::new(None)),
+        };
+
+        let remembered_method = selector
+            .remembered
+            .as_ref()
+            .and_then(|name| settings.find(name))
+            .filter(|method| method.enabled);
+        let initial = match remembered_method.map(|method| &method.access_method) {
+            Some(AccessMethod::Direct) | None => ApiConnectionMode::Direct,
+            Some(AccessMethod::Socks5(address)) => {
+                ApiConnectionMode::Proxied(ProxyConfig::Socks5Remote(*address))
+            }
+            Some(AccessMethod::HttpConnect(proxy)) => {
+                ApiConnectionMode::Proxied(ProxyConfig::HttpConnect {
+                    address: proxy.address,
+                    auth: proxy.auth.clone(),
+                })
+            }
+            // Starting a local proxy client requires the relay list, so connect directly at first
+            // and try the remembered method as soon as that fails.
+            Some(AccessMethod::Bridges) => {
+                selector.preferred = selector.remembered.clone();
+                ApiConnectionMode::Direct
+            }
+        };
+        selector.current = match initial {
+            ApiConnectionMode::Direct => settings
+                .enabled()
+                .find(|method| method.access_method == AccessMethod::Direct)
+                .map(|method| method.name.clone()),
+            ApiConnectionMode::Proxied(_) => remembered_method.map(|method| method.name.clone()),
+        };
+        *selector.proxy_endpoint.lock() = initial.get_endpoint();
+
+        (selector, initial)
+    }
+
+    /// Returns the remote endpoint of the proxy in use, if any. This is the endpoint that must be
+    /// let through the firewall, rather than the API address.
+    pub fn proxy_endpoint(&self) -> Arc<Mutex<Option<SocketAddr>>> {
+        self.proxy_endpoint.clone()
+    }
+
+    /// Returns the access method in use, if it still exists.
+    pub fn current<'a>(
+        &self,
+        settings: &'a access_method::Settings,
+    ) -> Option<&'a ApiAccessMethod> {
+        self.current.as_ref().and_then(|name| settings.find(name))
+    }
+
+    /// Returns whether the access method in use has been removed or disabled.
+    pub fn current_is_unavailable(&self, settings: &access_method::Settings) -> bool {
+        match self.current(settings) {
+            Some(method) => !method.enabled,
+            None => self.current.is_some(),
+        }
+    }
+
+    /// Switches to the next enabled access method that can be used, and returns its connection
+    /// mode. Falls back on connecting directly if none can be used.
+    pub async fn next_connection_mode(
+        &mut self,
+        settings: &access_method::Settings,
+        relay_selector: &mut RelaySelector,
+    ) -> ApiConnectionMode {
+        #[cfg(not(target_os = "android"))]
+        self.stop_proxy();
+
+        let enabled: Vec<&ApiAccessMethod> = settings.enabled().collect();
+        let preferred = self.preferred.take();
+        let start = match preferred.as_ref().or(self.current.as_ref()) {
+            Some(name) => {
+                let position = enabled.iter().position(|method| &method.name == name);
+                match (position, preferred.is_some()) {
+                    (Some(position), true) => position,
+                    (Some(position), false) => position + 1,
+                    (None, _) => 0,
+                }
+            }
+            None => 0,
+        };
+
+        for method in enabled.iter().cycle().skip(start).take(enabled.len()) {
+            if let Some(mode) = self.connection_mode(method, relay_selector).await {
+                log::info!("Using API access method \"{}\"", method.name);
+                self.current = Some(method.name.clone());
+                *self.proxy_endpoint.lock() = mode.get_endpoint();
+                return mode;
+            }
+        }
+
+        log::warn!("No API access method could be used. Connecting directly");
+        self.current = None;
+        *self.proxy_endpoint.lock() = None;
+        ApiConnectionMode::Direct
+    }
+
+    async fn connection_mode(
+        &mut self,
+        method: &ApiAccessMethod,
+        relay_selector: &mut RelaySelector,
+    ) -> Option<ApiConnectionMode> {
+        match &method.access_method {
+            AccessMethod::Direct => Some(ApiConnectionMode::Direct),
+            AccessMethod::Socks5(address) => Some(ApiConnectionMode::Proxied(
+                ProxyConfig::Socks5Remote(*address),
+            )),
+            AccessMethod::HttpConnect(proxy) => {
+                Some(ApiConnectionMode::Proxied(ProxyConfig::HttpConnect {
+                    address: proxy.address,
+                    auth: proxy.auth.clone(),
+                }))
+            }
+            #[cfg(not(target_os = "android"))]
+            AccessMethod::Bridges => {
+                let proxy_settings = relay_selector.get_api_bridge()?;
+                let peer = match &proxy_settings {
+                    ProxySettings::Shadowsocks(shadowsocks) => shadowsocks.peer,
+                    _ => return None,
+                };
+                let port = self.start_proxy(proxy_settings).await?;
+                Some(ApiConnectionMode::Proxied(ProxyConfig::Socks5Local {
+                    port,
+                    peer,
+                }))
+            }
+            #[cfg(target_os = "android")]
+            AccessMethod::Bridges => {
+                let _ = relay_selector;
+                None
+            }
+        }
+    }
+
+    /// Starts a local proxy client and returns the port that it listens on.
+    #[cfg(not(target_os = "android"))]
+    async fn start_proxy(&mut self, proxy_settings: ProxySettings) -> Option<u16> {
+        let resource_data = ProxyResourceData {
+            resource_dir: self.resource_data.resource_dir.clone(),
+            log_dir: self.resource_data.log_dir.clone(),
+        };
+        let result = tokio::task::spawn_blocking(move || {
+            proxy::start_proxy(&proxy_settings, &resource_data)
+        })
+        .await
+        .expect("Proxy start task panicked");
+
+        match result {
+            Ok(monitor) => {
+                let port = monitor.port();
+                self.proxy_monitor = Some(monitor);
+                Some(port)
+            }
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to start proxy for API access")
+                );
+                None
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "android"))]
+    fn stop_proxy(&mut self) {
+        if let Some(mut monitor) = self.proxy_monitor.take() {
+            if let Err(error) = monitor.close_handle().close() {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to stop proxy for API access")
+                );
+            }
+        }
+    }
+
+    /// Saves the access method in use, so that it is tried first the next time the daemon starts.
+    pub async fn remember_current(&mut self) {
+        if self.current.is_none() || self.current == self.remembered {
+            return;
+        }
+        self.remembered = self.current.clone();
+        let name = self.current.clone().unwrap_or_default();
+        if let Err(error) = fs::write(&self.cache_path, name).await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to save last API access method")
+            );
+        }
+    }
+}
+
+impl Drop for AccessMethodSelector {
+    fn drop(&mut self) {
+        #[cfg(not(target_os = "android"))]
+        self.stop_proxy();
+    }
+}
@@ -1,5 +1,6 @@
 use clap::{crate_authors, crate_description, crate_name, App, Arg};
 use log;
+use mullvad_daemon::logging::LogFormat;
 
 use crate::version;
 
@@ -8,6 +9,7 @@ pub struct Config {
     pub log_level: log::LevelFilter,
     pub log_to_file: bool,
     pub log_stdout_timestamps: bool,
+    pub log_format: LogFormat,
     pub run_as_service: bool,
     pub register_service: bool,
     pub restart_service: bool,
@@ -31,6 +33,11 @@ pub fn create_config() -> Config {
     };
     let log_to_file = !matches.is_present("disable_log_to_file");
     let log_stdout_timestamps = !matches.is_present("disable_stdout_timestamps");
+    let log_format = matches
+        .value_of("log_format")
+        .unwrap()
+        .parse()
+        .expect("invalid log format");
 
     let run_as_service = cfg!(windows) && matches.is_present("run_as_service");
     let register_service = cfg!(windows) && matches.is_present("register_service");
@@ -40,6 +47,7 @@ pub fn create_config() -> Config {
         log_level,
         log_to_file,
         log_stdout_timestamps,
+        log_format,
         run_as_service,
         register_service,
         restart_service,
@@ -88,6 +96,14 @@ fn create_app() -> App<'static, 'static> {
             Arg::with_name("disable_stdout_timestamps")
                 .long("disable-stdout-timestamps")
                 .help("Don't log timestamps when logging to stdout, useful when running as a systemd service")
+        )
+        .arg(
+            Arg::with_name("log_format")
+                .long("log-format")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .help("Format of log records. \"json\" writes one JSON object per line"),
         );
 
     if cfg!(windows) {
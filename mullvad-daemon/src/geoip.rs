@@ -1,14 +1,18 @@
+use crate::json_cache;
 use futures::join;
 use mullvad_rpc::{
     self,
     rest::{Error, RequestServiceHandle},
 };
 use mullvad_types::location::{AmIMullvad, GeoIpLocation};
+use std::path::Path;
 use talpid_types::ErrorExt;
 
 const URI_V4: &str = "https://ipv4.am.i.mullvad.net/json";
 const URI_V6: &str = "https://ipv6.am.i.mullvad.net/json";
 
+const USER_LOCATION_CACHE_FILENAME: &str = "user-location.json";
+
 pub async fn send_location_request(
     request_sender: RequestServiceHandle,
 ) -> Result<GeoIpLocation, Error> {
@@ -53,6 +57,32 @@ async fn send_location_request_internal(
     mullvad_rpc::rest::deserialize_body(response).await
 }
 
+/// Load the last known location of the user, if there is one.
+pub async fn load_user_location_cache(
+    cache_dir: &Path,
+) -> Result<Option<GeoIpLocation>, json_cache::Error> {
+    json_cache::load(&cache_dir.join(USER_LOCATION_CACHE_FILENAME)).await
+}
+
+/// Replace the cached location of the user. Only the location itself is stored, not the IP
+/// addresses that it was looked up for.
+pub async fn save_user_location_cache(
+    cache_dir: &Path,
+    location: &GeoIpLocation,
+) -> Result<(), json_cache::Error> {
+    let location = GeoIpLocation {
+        ipv4: None,
+        ipv6: None,
+        ..location.clone()
+    };
+    json_cache::save(&cache_dir.join(USER_LOCATION_CACHE_FILENAME), &location).await
+}
+
+/// Remove the cached location of the user, if there is one.
+pub async fn clear_user_location_cache(cache_dir: &Path) -> Result<(), json_cache::Error> {
+    json_cache::remove(&cache_dir.join(USER_LOCATION_CACHE_FILENAME)).await
+}
+
 fn log_network_error(err: Error, version: &'static str) {
     let err_message = &format!("Unable to fetch {} GeoIP location", version);
     match err {
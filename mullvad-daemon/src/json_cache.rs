@@ -0,0 +1,95 @@
+//! Small JSON files in the cache directory, such as the last known account expiry and user
+//! location.
+
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Failed to read cache file {}", _0)]
+    Read(String, #[error(source)] io::Error),
+
+    #[error(display = "Failed to write cache file {}", _0)]
+    Write(String, #[error(source)] io::Error),
+
+    #[error(display = "Failed to remove cache file {}", _0)]
+    Remove(String, #[error(source)] io::Error),
+
+    #[error(display = "Failed to parse cache file {}", _0)]
+    Parse(String, #[error(source)] serde_json::Error),
+
+    #[error(display = "Failed to serialize cache file {}", _0)]
+    Serialize(String, #[error(source)] serde_json::Error),
+}
+
+/// Reads the value cached at `path`, if there is one.
+pub async fn load<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, Error> {
+    match fs::read_to_string(path).await {
+        Ok(content) => serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|error| Error::Parse(path.display().to_string(), error)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(Error::Read(path.display().to_string(), error)),
+    }
+}
+
+/// Replaces the value cached at `path`. The value is written to a temporary file that then
+/// replaces the cache, so that a partially written cache is never read.
+pub async fn save<T: Serialize>(path: &Path, value: &T) -> Result<(), Error> {
+    let content = serde_json::to_string_pretty(value)
+        .map_err(|error| Error::Serialize(path.display().to_string(), error))?;
+
+    let mut temp_path = OsString::from(path.as_os_str());
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    let result = match fs::write(&temp_path, content).await {
+        Ok(()) => fs::rename(&temp_path, path).await,
+        Err(error) => Err(error),
+    };
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path).await;
+    }
+    result.map_err(|error| Error::Write(path.display().to_string(), error))
+}
+
+/// Removes the value cached at `path`, if there is one.
+pub async fn remove(path: &Path) -> Result<(), Error> {
+    match fs::remove_file(path).await {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => {
+            Err(Error::Remove(path.display().to_string(), error))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let path = cache_dir.path().join("cache.json");
+
+        runtime.block_on(async {
+            assert_eq!(load::<Vec<u32>>(&path).await.unwrap(), None);
+            save(&path, &vec![1, 2]).await.unwrap();
+            save(&path, &vec![3]).await.unwrap();
+            assert_eq!(load::<Vec<u32>>(&path).await.unwrap(), Some(vec![3]));
+            remove(&path).await.unwrap();
+            remove(&path).await.unwrap();
+            assert_eq!(load::<Vec<u32>>(&path).await.unwrap(), None);
+        });
+        assert_eq!(std::fs::read_dir(cache_dir.path()).unwrap().count(), 0);
+    }
+}
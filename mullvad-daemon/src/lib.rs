@@ -7,8 +7,10 @@ extern crate serde;
 
 mod account;
 pub mod account_history;
+mod api;
 pub mod exception_logging;
 mod geoip;
+mod json_cache;
 pub mod logging;
 #[cfg(not(target_os = "android"))]
 pub mod management_interface;
@@ -21,6 +23,7 @@ pub mod settings;
 pub mod version;
 mod version_check;
 
+use chrono::{DateTime, Utc};
 use futures::{
     channel::{mpsc, oneshot},
     future::{abortable, AbortHandle, Future},
@@ -28,18 +31,24 @@ use futures::{
 };
 use log::{debug, error, info, warn};
 use mullvad_rpc::availability::ApiAvailabilityHandle;
+use mullvad_rpc::proxy::ApiConnectionMode;
 use mullvad_types::{
-    account::{AccountData, AccountToken, VoucherSubmission},
+    access_method::{self, ApiAccessMethod},
+    account::{AccountData, AccountExpiryEvent, AccountToken, VoucherSubmission},
+    api_status::ApiStatus,
     endpoint::MullvadEndpoint,
     location::GeoIpLocation,
+    network_profile::{self, ActiveNetworkProfile, NetworkProfile},
     relay_constraints::{
-        BridgeSettings, BridgeState, Constraint, InternalBridgeConstraints, RelaySettings,
-        RelaySettingsUpdate,
+        BridgeSettings, BridgeState, Constraint, InternalBridgeConstraints, RelayConstraints,
+        RelaySettings, RelaySettingsUpdate, SelectedObfuscation, TransportPort,
     },
     relay_list::{Relay, RelayList},
+    retry_schedule::RetrySchedule,
     settings::{DnsOptions, DnsState, Settings},
     states::{TargetState, TunnelState},
-    version::{AppVersion, AppVersionInfo},
+    trusted_network::{self, TrustedNetwork},
+    version::{AppVersion, AppVersionInfo, UpdateChannelSettings},
     wireguard::{KeygenEvent, RotationInterval},
 };
 use settings::SettingsPersister;
@@ -48,13 +57,14 @@ use std::os::unix::io::RawFd;
 #[cfg(target_os = "windows")]
 use std::{collections::HashSet, ffi::OsString};
 use std::{
+    collections::VecDeque,
     marker::PhantomData,
     mem,
     net::IpAddr,
     path::{Path, PathBuf},
     pin::Pin,
     sync::{mpsc as sync_mpsc, Arc, Weak},
-    time::Duration,
+    time::{Duration, Instant},
 };
 #[cfg(any(target_os = "linux", windows))]
 use talpid_core::split_tunnel;
@@ -65,7 +75,10 @@ use talpid_core::{
 #[cfg(target_os = "android")]
 use talpid_types::android::AndroidContext;
 use talpid_types::{
-    net::{openvpn, Endpoint, TransportProtocol, TunnelEndpoint, TunnelParameters, TunnelType},
+    net::{
+        obfuscation::ObfuscatorConfig, openvpn, ConnectedNetwork, Connectivity, ConnectivityProbe,
+        Endpoint, TransportProtocol, TunnelEndpoint, TunnelParameters, TunnelType,
+    },
     tunnel::{ErrorStateCause, ParameterGenerationError, TunnelStateTransition},
     ErrorExt,
 };
@@ -84,6 +97,13 @@ const FIRST_KEY_PUSH_TIMEOUT: Duration = Duration::from_secs(5);
 /// Delay between generating a new WireGuard key and reconnecting
 const WG_RECONNECT_DELAY: Duration = Duration::from_secs(4 * 60);
 
+/// Number of tunnels that may fail to come up using a rotated WireGuard key before falling back to
+/// the old key
+const MAX_PENDING_KEY_ATTEMPTS: u32 = 3;
+
+/// Number of finished key rotations to remember
+const MAX_KEY_ROTATION_HISTORY: usize = 32;
+
 lazy_static::lazy_static! {
     static ref DNS_AD_BLOCKING_SERVERS: [IpAddr; 1] = ["100.64.0.1".parse().unwrap()];
     static ref DNS_TRACKER_BLOCKING_SERVERS: [IpAddr; 1] = ["100.64.0.2".parse().unwrap()];
@@ -133,6 +153,9 @@ pub enum Error {
     #[error(display = "No matching entry relay was found")]
     NoEntryRelayAvailable,
 
+    #[error(display = "WireGuard must be obfuscated over TCP to use an HTTP proxy")]
+    HttpProxyRequiresTcp,
+
     #[error(display = "No account token is set")]
     NoAccountToken,
 
@@ -145,6 +168,15 @@ pub enum Error {
     #[error(display = "Account history error")]
     AccountHistory(#[error(source)] account_history::Error),
 
+    #[error(display = "Failed to update API access methods")]
+    AccessMethodError(#[error(source)] access_method::Error),
+
+    #[error(display = "Failed to update trusted networks")]
+    TrustedNetworkError(#[error(source)] trusted_network::Error),
+
+    #[error(display = "Failed to update network profiles")]
+    NetworkProfileError(#[error(source)] network_profile::Error),
+
     #[error(display = "Failed to clear cache directory")]
     ClearCacheError,
 
@@ -194,6 +226,13 @@ pub enum DaemonCommand {
     SetTargetState(oneshot::Sender<bool>, TargetState),
     /// Reconnect the tunnel, if one is connecting/connected.
     Reconnect(oneshot::Sender<bool>),
+    /// Temporarily allow the traffic needed to log in to a captive portal. Returns whether
+    /// traffic is currently blocked and the command was issued.
+    #[cfg(not(any(target_os = "android", windows)))]
+    CaptivePortalLogin(
+        oneshot::Sender<bool>,
+        tunnel_state_machine::CaptivePortalLogin,
+    ),
     /// Request the current state.
     GetState(oneshot::Sender<TunnelState>),
     /// Get the current geographical location.
@@ -204,6 +243,10 @@ pub enum DaemonCommand {
         ResponseTx<AccountData, mullvad_rpc::rest::Error>,
         AccountToken,
     ),
+    /// Request the last known expiry of the current account, as cached by the daemon.
+    GetAccountExpiry(oneshot::Sender<Option<DateTime<Utc>>>),
+    /// Set how many hours before the account expires that clients should be notified
+    SetExpiryNotificationHours(ResponseTx<(), settings::Error>, Vec<u32>),
     /// Request www auth token for an account
     GetWwwAuthToken(ResponseTx<String, Error>),
     /// Submit voucher to add time to the current account. Returns time added in seconds
@@ -225,16 +268,39 @@ pub enum DaemonCommand {
     SetAllowLan(ResponseTx<(), settings::Error>, bool),
     /// Set the beta program setting.
     SetShowBetaReleases(ResponseTx<(), settings::Error>, bool),
+    /// Set the channel that version checks fetch update metadata from.
+    SetUpdateChannel(
+        ResponseTx<(), settings::Error>,
+        Option<UpdateChannelSettings>,
+    ),
     /// Set the block_when_disconnected setting.
     SetBlockWhenDisconnected(ResponseTx<(), settings::Error>, bool),
     /// Set the auto-connect setting.
     SetAutoConnect(ResponseTx<(), settings::Error>, bool),
     /// Set the mssfix argument for OpenVPN
     SetOpenVpnMssfix(ResponseTx<(), settings::Error>, Option<u16>),
+    /// Set the data channel cipher for OpenVPN
+    SetOpenVpnCipher(ResponseTx<(), settings::Error>, Option<openvpn::Cipher>),
+    /// Set the minimum TLS version for OpenVPN
+    SetOpenVpnTlsVersionMin(ResponseTx<(), settings::Error>, openvpn::TlsVersion),
     /// Set proxy details for OpenVPN
     SetBridgeSettings(ResponseTx<(), settings::Error>, BridgeSettings),
     /// Set proxy state
     SetBridgeState(ResponseTx<(), settings::Error>, BridgeState),
+    /// Request the retry schedule that applies to the current relay settings
+    GetRetrySchedule(oneshot::Sender<Option<RetrySchedule>>),
+    /// Override the default retry schedule. `None` restores the default
+    SetRetrySchedule(ResponseTx<(), settings::Error>, Option<RetrySchedule>),
+    /// Add a custom API access method
+    AddApiAccessMethod(ResponseTx<(), Error>, ApiAccessMethod),
+    /// Remove a custom API access method
+    RemoveApiAccessMethod(ResponseTx<(), Error>, String),
+    /// Enable or disable an API access method
+    SetApiAccessMethodEnabled(ResponseTx<(), Error>, String, bool),
+    /// Get the API access method that is currently in use
+    GetCurrentApiAccessMethod(oneshot::Sender<Option<ApiAccessMethod>>),
+    /// Get statistics on the requests made to the API
+    GetApiStatus(oneshot::Sender<ApiStatus>),
     /// Set if IPv6 should be enabled in the tunnel
     SetEnableIpv6(ResponseTx<(), settings::Error>, bool),
     /// Set DNS options or servers to use
@@ -251,10 +317,32 @@ pub enum DaemonCommand {
     GetWireguardKey(ResponseTx<Option<wireguard::PublicKey>, Error>),
     /// Verify if the currently set wireguard key is valid.
     VerifyWireguardKey(ResponseTx<bool, Error>),
+    /// Get the most recent automatic key rotations, oldest first
+    GetWireguardKeyRotationHistory(oneshot::Sender<Vec<wireguard::KeyRotation>>),
     /// Get information about the currently running and latest app versions
     GetVersionInfo(oneshot::Sender<Option<AppVersionInfo>>),
     /// Get current version of the app
     GetCurrentVersion(oneshot::Sender<AppVersion>),
+    /// Get the connectivity of the host, as detected by the offline monitor
+    GetConnectivity(oneshot::Sender<Connectivity>),
+    /// Set the HTTP server used to detect captive portals. `None` disables the detection
+    SetConnectivityProbe(ResponseTx<(), settings::Error>, Option<ConnectivityProbe>),
+    /// Add a network on which the tunnel should be disconnected automatically
+    AddTrustedNetwork(ResponseTx<(), Error>, TrustedNetwork),
+    /// Remove a trusted network
+    RemoveTrustedNetwork(ResponseTx<(), Error>, TrustedNetwork),
+    /// Get the networks that the host is currently connected to
+    GetConnectedNetworks(oneshot::Sender<Vec<ConnectedNetwork>>),
+    /// Add a network profile
+    AddNetworkProfile(ResponseTx<(), Error>, NetworkProfile),
+    /// Replace the network profile with the same name
+    UpdateNetworkProfile(ResponseTx<(), Error>, NetworkProfile),
+    /// Apply an update to the relay settings of the named network profile
+    UpdateNetworkProfileRelaySettings(ResponseTx<(), Error>, String, RelaySettingsUpdate),
+    /// Remove a network profile
+    RemoveNetworkProfile(ResponseTx<(), Error>, String),
+    /// Get the network profile that applies to the current network, if any
+    GetActiveNetworkProfile(oneshot::Sender<Option<ActiveNetworkProfile>>),
     /// Remove settings and clear the cache
     #[cfg(not(target_os = "android"))]
     FactoryReset(ResponseTx<(), Error>),
@@ -285,6 +373,14 @@ pub enum DaemonCommand {
     /// Toggle wireguard-nt on or off
     #[cfg(target_os = "windows")]
     UseWireGuardNt(ResponseTx<(), Error>, bool),
+    /// Change the log level, of a single module if one is given, and optionally restore it after
+    /// a timeout
+    SetLogLevel(
+        oneshot::Sender<()>,
+        log::LevelFilter,
+        Option<String>,
+        Option<Duration>,
+    ),
     /// Makes the daemon exit the main loop and quit.
     Shutdown,
     /// Saves the target tunnel state and enters a blocking state. The state is restored
@@ -314,10 +410,28 @@ pub(crate) enum InternalDaemonEvent {
             Result<mullvad_types::wireguard::WireguardData, wireguard::Error>,
         ),
     ),
+    /// A new key produced by automatic key rotation
+    WgKeyRotationEvent((AccountToken, wireguard::RotatedKey)),
     /// New Account created
     NewAccountEvent(AccountToken, oneshot::Sender<Result<String, Error>>),
     /// The background job fetching new `AppVersionInfo`s got a new info object.
     NewAppVersionInfo(AppVersionInfo),
+    /// The offline monitor detected a change in connectivity.
+    ConnectivityChanged(Connectivity),
+    /// The offline monitor detected a change in the networks that the host is connected to.
+    #[cfg(target_os = "linux")]
+    NetworksChanged(Vec<ConnectedNetwork>),
+    /// The request service for the API needs a new access method, or confirmed the current one.
+    AccessMethodEvent(api::AccessMethodEvent),
+    /// Periodic reminder to evaluate, and possibly refresh, the account expiry.
+    CheckAccountExpiry,
+    /// The result of fetching the expiry of an account.
+    AccountExpiry(
+        AccountToken,
+        Result<DateTime<Utc>, mullvad_rpc::rest::Error>,
+    ),
+    /// A GeoIP location fetched while disconnected.
+    UserLocation(GeoIpLocation),
     /// The split tunnel paths or state were updated.
     #[cfg(target_os = "windows")]
     ExcludedPathsEvent(ExcludedPathsUpdate, oneshot::Sender<Result<(), Error>>),
@@ -335,6 +449,12 @@ impl From<TunnelStateTransition> for InternalDaemonEvent {
     }
 }
 
+impl From<Connectivity> for InternalDaemonEvent {
+    fn from(connectivity: Connectivity) -> Self {
+        InternalDaemonEvent::ConnectivityChanged(connectivity)
+    }
+}
+
 impl From<DaemonCommand> for InternalDaemonEvent {
     fn from(command: DaemonCommand) -> Self {
         InternalDaemonEvent::Command(command)
@@ -347,6 +467,12 @@ impl From<AppVersionInfo> for InternalDaemonEvent {
     }
 }
 
+impl From<api::AccessMethodEvent> for InternalDaemonEvent {
+    fn from(event: api::AccessMethodEvent) -> Self {
+        InternalDaemonEvent::AccessMethodEvent(event)
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 enum DaemonExecutionState {
     Running,
@@ -510,12 +636,21 @@ pub trait EventListener {
 
     /// Notify clients of a key generation event.
     fn notify_key_event(&self, key_event: KeygenEvent);
+
+    /// Notify that the connectivity of the host changed.
+    fn notify_connectivity(&self, connectivity: Connectivity);
+
+    /// Notify that the account is about to expire, or has expired.
+    fn notify_account_expiry(&self, event: AccountExpiryEvent);
 }
 
 pub struct Daemon<L: EventListener> {
     tunnel_command_tx: Arc<mpsc::UnboundedSender<TunnelCommand>>,
     tunnel_state: TunnelState,
     target_state: TargetState,
+    connectivity: Connectivity,
+    connected_networks: Vec<ConnectedNetwork>,
+    on_trusted_network: bool,
     lock_target_cache: bool,
     state: DaemonExecutionState,
     #[cfg(target_os = "linux")]
@@ -527,13 +662,23 @@ pub struct Daemon<L: EventListener> {
     settings: SettingsPersister,
     account_history: account_history::AccountHistory,
     account: account::AccountHandle,
+    /// Last known expiry of the current account
+    account_expiry: Option<account::CachedExpiry>,
+    /// Threshold of the last account expiry event that was sent to clients
+    last_expiry_threshold: Option<Option<u32>>,
+    last_expiry_refresh: Option<Instant>,
     rpc_runtime: mullvad_rpc::MullvadRpcRuntime,
     rpc_handle: mullvad_rpc::rest::MullvadRestHandle,
     wireguard_key_manager: wireguard::KeyManager,
+    /// Whether the current tunnel was set up using the pending WireGuard key
+    using_pending_key: bool,
+    key_rotation_history: VecDeque<wireguard::KeyRotation>,
     version_updater_handle: version_check::VersionUpdaterHandle,
     relay_selector: relays::RelaySelector,
+    api_access_methods: api::AccessMethodSelector,
     last_generated_relay: Option<Relay>,
     last_generated_bridge_relay: Option<Relay>,
+    last_generated_entry_relay: Option<Relay>,
     app_version_info: Option<AppVersionInfo>,
     shutdown_tasks: Vec<Pin<Box<dyn Future<Output = ()>>>>,
     /// oneshot channel that completes once the tunnel state machine has been shut down
@@ -644,12 +789,27 @@ where
         let api_availability = rpc_runtime.availability_handle();
         api_availability.suspend();
 
+        let (api_access_methods, initial_api_connection_mode) = api::AccessMethodSelector::load(
+            &cache_dir,
+            #[cfg(not(target_os = "android"))]
+            resource_dir.clone(),
+            #[cfg(not(target_os = "android"))]
+            log_dir.clone(),
+            &settings.api_access_methods,
+        )
+        .await;
+        let api_proxy_endpoint = api_access_methods.proxy_endpoint();
+
         let initial_api_endpoint = Endpoint::from_socket_address(
-            rpc_runtime.address_cache.peek_address(),
+            initial_api_connection_mode
+                .get_endpoint()
+                .unwrap_or_else(|| rpc_runtime.address_cache.peek_address()),
             TransportProtocol::Tcp,
         );
 
         let (offline_state_tx, offline_state_rx) = mpsc::unbounded();
+        #[cfg(target_os = "linux")]
+        let (network_tx, network_rx) = mpsc::unbounded();
         let tunnel_command_tx = tunnel_state_machine::spawn(
             runtime.clone(),
             tunnel_state_machine::InitialTunnelState {
@@ -660,6 +820,8 @@ where
                 reset_firewall: initial_target_state != TargetState::Secured,
                 #[cfg(windows)]
                 exclude_paths,
+                #[cfg(target_os = "linux")]
+                connectivity_probe: settings.connectivity_probe.clone(),
             },
             tunnel_parameters_generator,
             log_dir,
@@ -667,6 +829,8 @@ where
             cache_dir.clone(),
             internal_event_tx.to_specialized_sender(),
             offline_state_tx,
+            #[cfg(target_os = "linux")]
+            network_tx,
             tunnel_state_machine_shutdown_tx,
             #[cfg(target_os = "android")]
             android_context,
@@ -677,6 +841,10 @@ where
         let address_change_runtime = runtime.clone();
         let tunnel_cmd_weak_tx = Arc::downgrade(&tunnel_command_tx);
         rpc_runtime.set_address_change_listener(move |address| {
+            if api_proxy_endpoint.lock().is_some() {
+                // API traffic is sent to the proxy, so the firewall need not allow the new address
+                return Ok(());
+            }
             let (result_tx, result_rx) = oneshot::channel();
             let tx = tunnel_cmd_weak_tx.clone();
             address_change_runtime.block_on(async move {
@@ -692,24 +860,40 @@ where
             })
         });
 
-        let rpc_handle = rpc_runtime.mullvad_rest_handle();
+        let rpc_handle = rpc_runtime.mullvad_rest_handle_with_connection_modes(Box::new(
+            api::AccessMethodProvider::new(
+                initial_api_connection_mode,
+                internal_event_tx.to_specialized_sender(),
+            ),
+        ));
 
-        Self::forward_offline_state(&runtime, api_availability.clone(), offline_state_rx).await;
+        let connectivity = Self::forward_offline_state(
+            &runtime,
+            api_availability.clone(),
+            internal_event_tx.to_specialized_sender(),
+            offline_state_rx,
+        )
+        .await;
+        #[cfg(target_os = "linux")]
+        Self::forward_connected_networks(&runtime, internal_event_tx.clone(), network_rx);
 
         let relay_list_listener = event_listener.clone();
         let on_relay_list_update = move |relay_list: &RelayList| {
             relay_list_listener.notify_relay_list(relay_list.clone());
         };
 
-        let relay_selector = relays::RelaySelector::new(
+        let mut relay_selector = relays::RelaySelector::new(
             rpc_handle.clone(),
             on_relay_list_update,
             &resource_dir,
             &cache_dir,
             api_availability.clone(),
         );
+        relay_selector.set_retry_schedule(settings.retry_schedule.clone());
 
-        let app_version_info = version_check::load_cache(&cache_dir).await;
+        let update_channel =
+            version_check::UpdateChannel::from_settings(settings.update_channel.as_ref());
+        let app_version_info = version_check::load_cache(&cache_dir, &update_channel).await;
         let (version_updater, version_updater_handle) = version_check::VersionUpdater::new(
             rpc_handle.clone(),
             api_availability.clone(),
@@ -717,6 +901,7 @@ where
             internal_event_tx.to_specialized_sender(),
             app_version_info.clone(),
             settings.show_beta_releases,
+            update_channel,
         );
         tokio::spawn(version_updater.run());
         let account_history =
@@ -736,6 +921,32 @@ where
             settings.get_account_token(),
             api_availability.clone(),
         );
+        let account_expiry = match account::load_expiry_cache(&cache_dir).await {
+            Ok(cached) => cached.filter(|cached| {
+                Some(&cached.account_token) == settings.get_account_token().as_ref()
+            }),
+            Err(error) => {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to load cached account expiry")
+                );
+                None
+            }
+        };
+        Self::spawn_account_expiry_timer(internal_event_tx.clone());
+
+        // Automatic multihop entries are selected by proximity to the last known location, until
+        // it has been fetched again
+        match geoip::load_user_location_cache(&cache_dir).await {
+            Ok(Some(location)) => relay_selector.set_user_location(location),
+            Ok(None) => (),
+            Err(error) => {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to load cached user location")
+                );
+            }
+        }
 
         // Attempt to download a fresh relay list
         let mut relay_handle = relay_selector.updater_handle();
@@ -748,6 +959,9 @@ where
             tunnel_command_tx,
             tunnel_state: TunnelState::Disconnected,
             target_state: initial_target_state,
+            connectivity,
+            connected_networks: vec![],
+            on_trusted_network: false,
             lock_target_cache: false,
             state: DaemonExecutionState::Running,
             #[cfg(target_os = "linux")]
@@ -759,13 +973,20 @@ where
             settings,
             account_history,
             account,
+            account_expiry,
+            last_expiry_threshold: None,
+            last_expiry_refresh: None,
             rpc_runtime,
             rpc_handle,
             wireguard_key_manager,
+            using_pending_key: false,
+            key_rotation_history: VecDeque::new(),
             version_updater_handle,
             relay_selector,
+            api_access_methods,
             last_generated_relay: None,
             last_generated_bridge_relay: None,
+            last_generated_entry_relay: None,
             app_version_info,
             shutdown_tasks: vec![],
             tunnel_state_machine_shutdown_signal,
@@ -809,6 +1030,8 @@ where
     pub async fn run(mut self) -> Result<(), Error> {
         if self.target_state == TargetState::Secured {
             self.connect_tunnel();
+        } else if self.uses_automatic_entry() {
+            self.update_user_location();
         }
 
         while let Some(event) = self.rx.next().await {
@@ -914,12 +1137,25 @@ where
             Command(command) => self.handle_command(command).await,
             TriggerShutdown => self.trigger_shutdown_event(),
             WgKeyEvent(key_event) => self.handle_wireguard_key_event(key_event).await,
+            WgKeyRotationEvent(rotation_event) => {
+                self.handle_wireguard_key_rotation_event(rotation_event)
+                    .await
+            }
             NewAccountEvent(account_token, tx) => {
                 self.handle_new_account_event(account_token, tx).await
             }
             NewAppVersionInfo(app_version_info) => {
                 self.handle_new_app_version_info(app_version_info)
             }
+            ConnectivityChanged(connectivity) => self.handle_connectivity_change(connectivity),
+            #[cfg(target_os = "linux")]
+            NetworksChanged(networks) => self.handle_networks_change(networks).await,
+            AccessMethodEvent(event) => self.handle_access_method_event(event).await,
+            CheckAccountExpiry => self.handle_check_account_expiry(),
+            AccountExpiry(account_token, result) => {
+                self.handle_account_expiry(account_token, result).await
+            }
+            UserLocation(location) => self.handle_user_location(location).await,
             #[cfg(windows)]
             ExcludedPathsEvent(update, tx) => self.handle_new_excluded_paths(update, tx).await,
         }
@@ -950,9 +1186,15 @@ where
 
         self.unschedule_reconnect();
 
+        logging::set_tunnel_state(&tunnel_state);
         debug!("New tunnel state: {:?}", tunnel_state);
         match tunnel_state {
-            TunnelState::Disconnected => self.state.disconnected(),
+            TunnelState::Disconnected => {
+                self.state.disconnected();
+                if self.uses_automatic_entry() {
+                    self.update_user_location();
+                }
+            }
             TunnelState::Error(ref error_state) => {
                 if error_state.is_blocking() {
                     info!(
@@ -966,15 +1208,35 @@ where
                     );
                 }
 
+                if self.using_pending_key && wireguard::is_key_failure(&tunnel_state) {
+                    self.using_pending_key = false;
+                    self.record_failed_pending_key_attempt().await;
+                }
+
                 if let ErrorStateCause::AuthFailed(_) = error_state.cause() {
+                    // The account may have run out of time. If so, the daemon blocks with a more
+                    // specific cause once the expiry has been fetched.
+                    if let Some(account_token) = self.settings.get_account_token() {
+                        self.refresh_account_expiry(account_token);
+                    }
                     self.schedule_reconnect(Duration::from_secs(60)).await
                 }
             }
             _ => {}
         }
 
+        let is_connected = matches!(tunnel_state, TunnelState::Connected { .. });
+
         self.tunnel_state = tunnel_state.clone();
         self.event_listener.notify_new_state(tunnel_state);
+
+        // The pending key is only used for WireGuard tunnels and WireGuard entry relays. Neither
+        // can become connected unless a handshake has succeeded, which verifies that the relay has
+        // picked up the key
+        if is_connected && self.using_pending_key {
+            self.promote_pending_key(wireguard::KeyRotationOutcome::Verified)
+                .await;
+        }
     }
 
     async fn reset_rpc_sockets_on_tunnel_state_transition(
@@ -997,10 +1259,16 @@ where
         >,
         retry_attempt: u32,
     ) {
+        // The previous tunnel is still connecting if it closed by itself
+        if self.using_pending_key && wireguard::is_key_failure(&self.tunnel_state) {
+            self.record_failed_pending_key_attempt().await;
+        }
+        self.using_pending_key = false;
         if let Some(account_token) = self.settings.get_account_token() {
-            let result = match self.settings.get_relay_settings() {
+            let result = match self.get_relay_settings() {
                 RelaySettings::CustomTunnelEndpoint(custom_relay) => {
                     self.last_generated_relay = None;
+                    self.last_generated_entry_relay = None;
                     custom_relay
                         // TODO(emilsp): generate proxy settings for custom tunnels
                         .to_tunnel_parameters(self.settings.tunnel_options.clone(), None)
@@ -1009,12 +1277,31 @@ where
                             ParameterGenerationError::CustomTunnelHostResultionError
                         })
                 }
-                RelaySettings::Normal(constraints) => {
+                RelaySettings::Normal(mut constraints) => {
+                    // WireGuard can only reach the HTTP proxy when obfuscated over TCP
+                    if self.get_wireguard_http_proxy().is_some() {
+                        let wireguard_constraints = &mut constraints.wireguard_constraints;
+                        if wireguard_constraints.obfuscation == SelectedObfuscation::Auto {
+                            wireguard_constraints.obfuscation = SelectedObfuscation::Udp2Tcp;
+                        }
+                        let uses_tcp = wireguard_constraints
+                            .port
+                            .map(|port| port.protocol == TransportProtocol::Tcp)
+                            .unwrap_or(false);
+                        if !uses_tcp {
+                            wireguard_constraints.port = Constraint::Only(TransportPort {
+                                protocol: TransportProtocol::Tcp,
+                                port: Constraint::Any,
+                            });
+                        }
+                    }
+                    constraints.openvpn_constraints.cipher =
+                        Constraint::from(self.settings.tunnel_options.openvpn.cipher);
                     let endpoint = self
                         .relay_selector
                         .get_tunnel_endpoint(
                             &constraints,
-                            self.settings.get_bridge_state(),
+                            self.get_bridge_state(),
                             retry_attempt,
                             self.settings.get_wireguard().is_some(),
                         )
@@ -1025,6 +1312,7 @@ where
                                 &relay,
                                 endpoint,
                                 account_token,
+                                &constraints,
                                 retry_attempt,
                             )
                             .await;
@@ -1065,14 +1353,37 @@ where
         relay: &Relay,
         endpoint: MullvadEndpoint,
         account_token: String,
+        relay_constraints: &RelayConstraints,
         retry_attempt: u32,
     ) -> Result<TunnelParameters, Error> {
         let tunnel_options = self.settings.tunnel_options.clone();
         let location = relay.location.as_ref().expect("Relay has no location set");
+        let use_bridge = self.relay_selector.should_use_bridge(
+            relay_constraints,
+            retry_attempt,
+            self.settings.get_wireguard().is_some(),
+        );
         self.last_generated_bridge_relay = None;
+        self.last_generated_entry_relay = None;
         match endpoint {
             MullvadEndpoint::OpenVpn(endpoint) => {
+                let wireguard_entry = if relay_constraints.openvpn_constraints.wireguard_entry {
+                    Some(
+                        self.create_openvpn_entry_parameters(
+                            relay,
+                            endpoint,
+                            relay_constraints,
+                            retry_attempt,
+                            use_bridge,
+                        )
+                        .await?,
+                    )
+                } else {
+                    None
+                };
                 let proxy_settings = match &self.settings.bridge_settings {
+                    // Bridges are used by the WireGuard entry tunnel instead
+                    _ if wireguard_entry.is_some() => None,
                     BridgeSettings::Normal(settings) => {
                         let bridge_constraints = InternalBridgeConstraints {
                             location: settings.location.clone(),
@@ -1080,7 +1391,7 @@ where
                             // FIXME: This is temporary while talpid-core only supports TCP proxies
                             transport_protocol: Constraint::Only(TransportProtocol::Tcp),
                         };
-                        match self.settings.get_bridge_state() {
+                        match self.get_bridge_state() {
                             BridgeState::On => {
                                 let (bridge_settings, bridge_relay) = self
                                     .relay_selector
@@ -1089,13 +1400,10 @@ where
                                 self.last_generated_bridge_relay = Some(bridge_relay);
                                 Some(bridge_settings)
                             }
-                            BridgeState::Auto => {
-                                if let Some((bridge_settings, bridge_relay)) =
-                                    self.relay_selector.get_auto_proxy_settings(
-                                        &bridge_constraints,
-                                        location,
-                                        retry_attempt,
-                                    )
+                            BridgeState::Auto if use_bridge => {
+                                if let Some((bridge_settings, bridge_relay)) = self
+                                    .relay_selector
+                                    .get_proxy_settings(&bridge_constraints, location)
                                 {
                                     self.last_generated_bridge_relay = Some(bridge_relay);
                                     Some(bridge_settings)
@@ -1103,22 +1411,14 @@ where
                                     None
                                 }
                             }
-                            BridgeState::Off => None,
-                        }
-                    }
-                    BridgeSettings::Custom(proxy_settings) => {
-                        match self.settings.get_bridge_state() {
-                            BridgeState::On => Some(proxy_settings.clone()),
-                            BridgeState::Auto => {
-                                if self.relay_selector.should_use_bridge(retry_attempt) {
-                                    Some(proxy_settings.clone())
-                                } else {
-                                    None
-                                }
-                            }
-                            BridgeState::Off => None,
+                            BridgeState::Auto | BridgeState::Off => None,
                         }
                     }
+                    BridgeSettings::Custom(proxy_settings) => match self.get_bridge_state() {
+                        BridgeState::On => Some(proxy_settings.clone()),
+                        BridgeState::Auto if use_bridge => Some(proxy_settings.clone()),
+                        BridgeState::Auto | BridgeState::Off => None,
+                    },
                 };
 
                 Ok(openvpn::TunnelParameters {
@@ -1130,6 +1430,7 @@ where
                     options: tunnel_options.openvpn,
                     generic_options: tunnel_options.generic,
                     proxy: proxy_settings,
+                    wireguard_entry,
                 }
                 .into())
             }
@@ -1139,14 +1440,17 @@ where
                 ipv4_gateway,
                 ipv6_gateway,
             } => {
-                let wg_data = self.settings.get_wireguard().ok_or(Error::NoKeyAvailable)?;
-                let tunnel = wireguard::TunnelConfig {
-                    private_key: wg_data.private_key,
-                    addresses: vec![
-                        wg_data.addresses.ipv4_address.ip().into(),
-                        wg_data.addresses.ipv6_address.ip().into(),
-                    ],
+                // Obfuscated traffic is not relayed through a bridge
+                let obfuscation = self.get_wireguard_obfuscation(&peer)?;
+                #[cfg(not(target_os = "android"))]
+                let proxy_settings = match obfuscation {
+                    Some(_) => None,
+                    None => self.get_wireguard_bridge(location, use_bridge)?,
                 };
+                #[cfg(target_os = "android")]
+                let proxy_settings = None;
+
+                let tunnel = self.create_wireguard_tunnel_config().await?;
                 Ok(wireguard::TunnelParameters {
                     connection: wireguard::ConnectionConfig {
                         tunnel,
@@ -1157,12 +1461,153 @@ where
                     },
                     options: tunnel_options.wireguard.options,
                     generic_options: tunnel_options.generic,
+                    proxy: proxy_settings,
+                    obfuscation,
                 }
                 .into())
             }
         }
     }
 
+    async fn create_wireguard_tunnel_config(&mut self) -> Result<wireguard::TunnelConfig, Error> {
+        let wg_data = self.get_wireguard_data_for_attempt().await?;
+        Ok(wireguard::TunnelConfig {
+            private_key: wg_data.private_key,
+            addresses: vec![
+                wg_data.addresses.ipv4_address.ip().into(),
+                wg_data.addresses.ipv6_address.ip().into(),
+            ],
+        })
+    }
+
+    /// Creates the parameters of a WireGuard tunnel to an entry relay, which an OpenVPN tunnel to
+    /// `exit_relay` is routed through.
+    #[cfg_attr(target_os = "android", allow(unused_variables))]
+    async fn create_openvpn_entry_parameters(
+        &mut self,
+        exit_relay: &Relay,
+        exit_endpoint: Endpoint,
+        relay_constraints: &RelayConstraints,
+        retry_attempt: u32,
+        use_bridge: bool,
+    ) -> Result<wireguard::TunnelParameters, Error> {
+        let (entry_relay, entry_endpoint) = self
+            .relay_selector
+            .get_openvpn_entry_endpoint(relay_constraints, exit_relay, exit_endpoint, retry_attempt)
+            .map_err(|_| Error::NoEntryRelayAvailable)?;
+        let (peer, ipv4_gateway, ipv6_gateway) = match entry_endpoint {
+            MullvadEndpoint::Wireguard {
+                peer,
+                ipv4_gateway,
+                ipv6_gateway,
+                ..
+            } => (peer, ipv4_gateway, ipv6_gateway),
+            MullvadEndpoint::OpenVpn(_) => return Err(Error::NoEntryRelayAvailable),
+        };
+
+        let obfuscation = self.get_wireguard_obfuscation(&peer)?;
+        #[cfg(not(target_os = "android"))]
+        let proxy_settings = match obfuscation {
+            Some(_) => None,
+            None => self.get_wireguard_bridge(
+                entry_relay
+                    .location
+                    .as_ref()
+                    .expect("Relay has no location set"),
+                use_bridge,
+            )?,
+        };
+        #[cfg(target_os = "android")]
+        let proxy_settings = None;
+
+        let tunnel_options = self.settings.tunnel_options.clone();
+        let tunnel = self.create_wireguard_tunnel_config().await?;
+        self.last_generated_entry_relay = Some(entry_relay);
+        Ok(wireguard::TunnelParameters {
+            connection: wireguard::ConnectionConfig {
+                tunnel,
+                peer,
+                exit_peer: None,
+                ipv4_gateway,
+                ipv6_gateway: Some(ipv6_gateway),
+            },
+            options: tunnel_options.wireguard.options,
+            generic_options: tunnel_options.generic,
+            proxy: proxy_settings,
+            obfuscation,
+        })
+    }
+
+    /// Returns the custom HTTP proxy that WireGuard traffic must be sent through, if any.
+    fn get_wireguard_http_proxy(&self) -> Option<openvpn::HttpConnectProxySettings> {
+        match &self.settings.bridge_settings {
+            BridgeSettings::Custom(openvpn::ProxySettings::HttpConnect(proxy))
+                if self.get_bridge_state() == BridgeState::On =>
+            {
+                Some(proxy.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the obfuscator needed to reach `peer`. Obfuscated traffic is tunneled through the
+    /// custom HTTP proxy, if one is enabled.
+    fn get_wireguard_obfuscation(
+        &self,
+        peer: &wireguard::PeerConfig,
+    ) -> Result<Option<ObfuscatorConfig>, Error> {
+        let proxy = self.get_wireguard_http_proxy();
+        if proxy.is_some() && peer.protocol == TransportProtocol::Udp {
+            return Err(Error::HttpProxyRequiresTcp);
+        }
+        Ok(ObfuscatorConfig::for_peer(peer, proxy))
+    }
+
+    /// Selects a Shadowsocks bridge for a WireGuard tunnel. WireGuard traffic is relayed over
+    /// UDP, so only bridges that accept UDP are considered. Custom bridges cannot be used.
+    #[cfg(not(target_os = "android"))]
+    fn get_wireguard_bridge(
+        &mut self,
+        location: &mullvad_types::location::Location,
+        use_bridge: bool,
+    ) -> Result<Option<openvpn::ShadowsocksProxySettings>, Error> {
+        let bridge_state = self.get_bridge_state();
+        let settings = match &self.settings.bridge_settings {
+            BridgeSettings::Normal(settings) => settings,
+            BridgeSettings::Custom(_) => {
+                if bridge_state == BridgeState::On {
+                    log::error!("Custom bridges are not supported by WireGuard");
+                    return Err(Error::NoBridgeAvailable);
+                }
+                return Ok(None);
+            }
+        };
+        let bridge_constraints = InternalBridgeConstraints {
+            location: settings.location.clone(),
+            providers: settings.providers.clone(),
+            transport_protocol: Constraint::Only(TransportProtocol::Udp),
+        };
+        let selected_bridge = match bridge_state {
+            BridgeState::On => Some(
+                self.relay_selector
+                    .get_proxy_settings(&bridge_constraints, location)
+                    .ok_or(Error::NoBridgeAvailable)?,
+            ),
+            BridgeState::Auto if use_bridge => self
+                .relay_selector
+                .get_proxy_settings(&bridge_constraints, location),
+            BridgeState::Auto | BridgeState::Off => None,
+        };
+        match selected_bridge {
+            Some((openvpn::ProxySettings::Shadowsocks(bridge_settings), bridge_relay)) => {
+                self.last_generated_bridge_relay = Some(bridge_relay);
+                Ok(Some(bridge_settings))
+            }
+            Some(_) => Err(Error::NoBridgeAvailable),
+            None => Ok(None),
+        }
+    }
+
     async fn schedule_reconnect(&mut self, delay: Duration) {
         self.unschedule_reconnect();
 
@@ -1196,10 +1641,16 @@ where
         match command {
             SetTargetState(tx, state) => self.on_set_target_state(tx, state).await,
             Reconnect(tx) => self.on_reconnect(tx),
+            #[cfg(not(any(target_os = "android", windows)))]
+            CaptivePortalLogin(tx, login) => self.on_captive_portal_login(tx, login),
             GetState(tx) => self.on_get_state(tx),
             GetCurrentLocation(tx) => self.on_get_current_location(tx).await,
             CreateNewAccount(tx) => self.on_create_new_account(tx).await,
             GetAccountData(tx, account_token) => self.on_get_account_data(tx, account_token).await,
+            GetAccountExpiry(tx) => self.on_get_account_expiry(tx),
+            SetExpiryNotificationHours(tx, hours) => {
+                self.on_set_expiry_notification_hours(tx, hours).await
+            }
             GetWwwAuthToken(tx) => self.on_get_www_auth_token(tx).await,
             SubmitVoucher(tx, voucher) => self.on_submit_voucher(tx, voucher).await,
             GetRelayLocations(tx) => self.on_get_relay_locations(tx),
@@ -1210,16 +1661,33 @@ where
             UpdateRelaySettings(tx, update) => self.on_update_relay_settings(tx, update).await,
             SetAllowLan(tx, allow_lan) => self.on_set_allow_lan(tx, allow_lan).await,
             SetShowBetaReleases(tx, enabled) => self.on_set_show_beta_releases(tx, enabled).await,
+            SetUpdateChannel(tx, channel) => self.on_set_update_channel(tx, channel).await,
             SetBlockWhenDisconnected(tx, block_when_disconnected) => {
                 self.on_set_block_when_disconnected(tx, block_when_disconnected)
                     .await
             }
             SetAutoConnect(tx, auto_connect) => self.on_set_auto_connect(tx, auto_connect).await,
             SetOpenVpnMssfix(tx, mssfix_arg) => self.on_set_openvpn_mssfix(tx, mssfix_arg).await,
+            SetOpenVpnCipher(tx, cipher) => self.on_set_openvpn_cipher(tx, cipher).await,
+            SetOpenVpnTlsVersionMin(tx, version) => {
+                self.on_set_openvpn_tls_version_min(tx, version).await
+            }
             SetBridgeSettings(tx, bridge_settings) => {
                 self.on_set_bridge_settings(tx, bridge_settings).await
             }
             SetBridgeState(tx, bridge_state) => self.on_set_bridge_state(tx, bridge_state).await,
+            GetRetrySchedule(tx) => self.on_get_retry_schedule(tx),
+            SetRetrySchedule(tx, retry_schedule) => {
+                self.on_set_retry_schedule(tx, retry_schedule).await
+            }
+            AddApiAccessMethod(tx, method) => self.on_add_api_access_method(tx, method).await,
+            RemoveApiAccessMethod(tx, name) => self.on_remove_api_access_method(tx, name).await,
+            SetApiAccessMethodEnabled(tx, name, enabled) => {
+                self.on_set_api_access_method_enabled(tx, name, enabled)
+                    .await
+            }
+            GetCurrentApiAccessMethod(tx) => self.on_get_current_api_access_method(tx),
+            GetApiStatus(tx) => self.on_get_api_status(tx),
             SetEnableIpv6(tx, enable_ipv6) => self.on_set_enable_ipv6(tx, enable_ipv6).await,
             SetDnsOptions(tx, dns_servers) => self.on_set_dns_options(tx, dns_servers).await,
             SetWireguardMtu(tx, mtu) => self.on_set_wireguard_mtu(tx, mtu).await,
@@ -1230,8 +1698,22 @@ where
             GenerateWireguardKey(tx) => self.on_generate_wireguard_key(tx).await,
             GetWireguardKey(tx) => self.on_get_wireguard_key(tx).await,
             VerifyWireguardKey(tx) => self.on_verify_wireguard_key(tx).await,
+            GetWireguardKeyRotationHistory(tx) => self.on_get_wireguard_key_rotation_history(tx),
             GetVersionInfo(tx) => self.on_get_version_info(tx).await,
             GetCurrentVersion(tx) => self.on_get_current_version(tx),
+            GetConnectivity(tx) => self.on_get_connectivity(tx),
+            SetConnectivityProbe(tx, probe) => self.on_set_connectivity_probe(tx, probe).await,
+            AddTrustedNetwork(tx, network) => self.on_add_trusted_network(tx, network).await,
+            RemoveTrustedNetwork(tx, network) => self.on_remove_trusted_network(tx, network).await,
+            GetConnectedNetworks(tx) => self.on_get_connected_networks(tx),
+            AddNetworkProfile(tx, profile) => self.on_add_network_profile(tx, profile).await,
+            UpdateNetworkProfile(tx, profile) => self.on_update_network_profile(tx, profile).await,
+            UpdateNetworkProfileRelaySettings(tx, name, update) => {
+                self.on_update_network_profile_relay_settings(tx, name, update)
+                    .await
+            }
+            RemoveNetworkProfile(tx, name) => self.on_remove_network_profile(tx, name).await,
+            GetActiveNetworkProfile(tx) => self.on_get_active_network_profile(tx),
             #[cfg(not(target_os = "android"))]
             FactoryReset(tx) => self.on_factory_reset(tx).await,
             #[cfg(target_os = "linux")]
@@ -1252,6 +1734,9 @@ where
             SetSplitTunnelState(tx, enabled) => self.on_set_split_tunnel_state(tx, enabled).await,
             #[cfg(target_os = "windows")]
             UseWireGuardNt(tx, state) => self.on_use_wireguard_nt(tx, state).await,
+            SetLogLevel(tx, level, module, timeout) => {
+                self.on_set_log_level(tx, level, module, timeout)
+            }
             Shutdown => self.trigger_shutdown_event(),
             PrepareRestart => self.on_prepare_restart(),
             #[cfg(target_os = "android")]
@@ -1321,6 +1806,208 @@ where
         }
     }
 
+    async fn handle_wireguard_key_rotation_event(
+        &mut self,
+        event: (AccountToken, wireguard::RotatedKey),
+    ) {
+        let (account, rotated_key) = event;
+        if self
+            .settings
+            .get_account_token()
+            .map(|current_account| current_account != account)
+            .unwrap_or(true)
+        {
+            log::info!("Dropping rotated wireguard key since account has been changed");
+            if let wireguard::RotatedKey::Added { new_data, .. } = rotated_key {
+                self.remove_wireguard_key_in_background(account, new_data.private_key.public_key());
+            }
+            return;
+        }
+
+        match rotated_key {
+            wireguard::RotatedKey::Replaced { old_key, new_data } => {
+                let new_key = new_data.private_key.public_key();
+                self.handle_wireguard_key_event((account, Ok(new_data)))
+                    .await;
+                self.record_key_rotation(
+                    old_key.key,
+                    new_key,
+                    wireguard::KeyRotationOutcome::Replaced,
+                );
+            }
+            wireguard::RotatedKey::Added { old_key, new_data } => {
+                let current_key = self
+                    .settings
+                    .get_wireguard()
+                    .map(|data| data.private_key.public_key());
+                if current_key != Some(old_key.key) {
+                    log::info!("Dropping rotated wireguard key since the current key has changed");
+                    self.remove_wireguard_key_in_background(
+                        account,
+                        new_data.private_key.public_key(),
+                    );
+                    return;
+                }
+                if let Some(previous_data) = self.settings.get_wireguard_pending() {
+                    self.remove_wireguard_key_in_background(
+                        account.clone(),
+                        previous_data.private_key.public_key(),
+                    );
+                }
+                let new_key = new_data.private_key.public_key();
+                if let Err(error) = self.settings.set_wireguard_pending(Some(new_data)).await {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to store rotated wireguard key")
+                    );
+                    self.remove_wireguard_key_in_background(account, new_key);
+                    return;
+                }
+                self.using_pending_key = false;
+
+                if self.tunnel_uses_wireguard_key() {
+                    log::info!("Reconnecting later to verify the rotated wireguard key");
+                    self.schedule_reconnect(WG_RECONNECT_DELAY).await;
+                } else {
+                    self.promote_pending_key(wireguard::KeyRotationOutcome::Unverified)
+                        .await;
+                }
+            }
+        }
+    }
+
+    /// Returns the key to set up the next WireGuard tunnel with. A pending key from key rotation
+    /// is preferred, until `MAX_PENDING_KEY_ATTEMPTS` tunnels have failed using it.
+    async fn get_wireguard_data_for_attempt(&mut self) -> Result<wireguard::WireguardData, Error> {
+        if let Some(pending_data) = self.settings.get_wireguard_pending() {
+            if self.settings.get_wireguard_pending_attempts() < MAX_PENDING_KEY_ATTEMPTS {
+                self.using_pending_key = true;
+                return Ok(pending_data);
+            }
+            self.fall_back_from_pending_key().await;
+        }
+        self.settings.get_wireguard().ok_or(Error::NoKeyAvailable)
+    }
+
+    /// Records that a tunnel failed to come up using the pending key.
+    async fn record_failed_pending_key_attempt(&mut self) {
+        let attempts = self.settings.get_wireguard_pending_attempts() + 1;
+        log::debug!(
+            "Failed to connect using rotated wireguard key ({}/{})",
+            attempts,
+            MAX_PENDING_KEY_ATTEMPTS
+        );
+        if let Err(error) = self.settings.set_wireguard_pending_attempts(attempts).await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to store rotated wireguard key attempts")
+            );
+        }
+    }
+
+    /// Replaces the current key with the pending one, and removes the old key from the account.
+    async fn promote_pending_key(&mut self, outcome: wireguard::KeyRotationOutcome) {
+        self.using_pending_key = false;
+        let (account, new_data) = match (
+            self.settings.get_account_token(),
+            self.settings.get_wireguard_pending(),
+        ) {
+            (Some(account), Some(new_data)) => (account, new_data),
+            _ => return,
+        };
+        let old_data = self.settings.get_wireguard();
+
+        if let Err(error) = self.settings.set_wireguard(Some(new_data.clone())).await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to start using rotated wireguard key")
+            );
+            return;
+        }
+        if let Err(error) = self.settings.set_wireguard_pending(None).await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to clear rotated wireguard key")
+            );
+        }
+
+        let new_key = new_data.get_public_key();
+        if let Some(old_data) = old_data {
+            let old_key = old_data.private_key.public_key();
+            self.remove_wireguard_key_in_background(account, old_key.clone());
+            self.record_key_rotation(old_key, new_key.key.clone(), outcome);
+        }
+        self.event_listener
+            .notify_key_event(KeygenEvent::NewKey(new_key));
+        self.ensure_key_rotation().await;
+    }
+
+    /// Gives up on the pending key after failing to set up tunnels using it. The current key
+    /// remains in use, and is rotated again later.
+    async fn fall_back_from_pending_key(&mut self) {
+        self.using_pending_key = false;
+        let pending_data = match self.settings.get_wireguard_pending() {
+            Some(pending_data) => pending_data,
+            None => return,
+        };
+        log::warn!("Failed to connect using rotated wireguard key. Falling back to the old key");
+
+        if let Err(error) = self.settings.set_wireguard_pending(None).await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to clear rotated wireguard key")
+            );
+        }
+        let pending_key = pending_data.private_key.public_key();
+        if let Some(account) = self.settings.get_account_token() {
+            self.remove_wireguard_key_in_background(account, pending_key.clone());
+        }
+        if let Some(current_data) = self.settings.get_wireguard() {
+            self.record_key_rotation(
+                current_data.private_key.public_key(),
+                pending_key,
+                wireguard::KeyRotationOutcome::FellBack,
+            );
+        }
+        self.ensure_key_rotation().await;
+    }
+
+    fn record_key_rotation(
+        &mut self,
+        old_key: talpid_types::net::wireguard::PublicKey,
+        new_key: talpid_types::net::wireguard::PublicKey,
+        outcome: wireguard::KeyRotationOutcome,
+    ) {
+        log::info!("Wireguard key rotation finished: {}", outcome);
+        if self.key_rotation_history.len() >= MAX_KEY_ROTATION_HISTORY {
+            self.key_rotation_history.pop_front();
+        }
+        self.key_rotation_history.push_back(wireguard::KeyRotation {
+            old_key,
+            new_key,
+            finished: chrono::Utc::now(),
+            outcome,
+        });
+    }
+
+    fn remove_wireguard_key_in_background(
+        &self,
+        account: AccountToken,
+        key: talpid_types::net::wireguard::PublicKey,
+    ) {
+        let remove_key = self
+            .wireguard_key_manager
+            .remove_key_with_backoff(account, key);
+        tokio::spawn(async move {
+            if let Err(error) = remove_key.await {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to remove WireGuard key")
+                );
+            }
+        });
+    }
+
     async fn ensure_key_rotation(&mut self) {
         let token = match self.settings.get_account_token() {
             Some(token) => token,
@@ -1364,6 +2051,46 @@ where
         self.event_listener.notify_app_version(app_version_info);
     }
 
+    async fn handle_access_method_event(&mut self, event: api::AccessMethodEvent) {
+        match event {
+            api::AccessMethodEvent::Rotate(tx) => {
+                let (connection_mode, firewall_updated) = self.rotate_api_access_method().await;
+                tokio::spawn(async move {
+                    let _ = firewall_updated.await;
+                    let _ = tx.send(connection_mode);
+                });
+            }
+            api::AccessMethodEvent::Succeeded => self.api_access_methods.remember_current().await,
+        }
+    }
+
+    /// Switches to the next API access method and lets its endpoint through the firewall. The
+    /// returned receiver completes once the firewall has been updated.
+    async fn rotate_api_access_method(&mut self) -> (ApiConnectionMode, oneshot::Receiver<()>) {
+        let connection_mode = self
+            .api_access_methods
+            .next_connection_mode(&self.settings.api_access_methods, &mut self.relay_selector)
+            .await;
+        let endpoint = connection_mode
+            .get_endpoint()
+            .unwrap_or_else(|| self.rpc_runtime.address_cache.peek_address());
+
+        let (result_tx, result_rx) = oneshot::channel();
+        self.send_tunnel_command(TunnelCommand::AllowEndpoint(
+            Endpoint::from_socket_address(endpoint, TransportProtocol::Tcp),
+            result_tx,
+        ));
+        (connection_mode, result_rx)
+    }
+
+    fn handle_connectivity_change(&mut self, connectivity: Connectivity) {
+        if self.connectivity != connectivity {
+            log::debug!("Connectivity changed: {}", connectivity);
+            self.connectivity = connectivity;
+            self.event_listener.notify_connectivity(connectivity);
+        }
+    }
+
     #[cfg(windows)]
     async fn handle_new_excluded_paths(
         &mut self,
@@ -1413,6 +2140,24 @@ where
         }
     }
 
+    #[cfg(not(any(target_os = "android", windows)))]
+    fn on_captive_portal_login(
+        &mut self,
+        tx: oneshot::Sender<bool>,
+        login: tunnel_state_machine::CaptivePortalLogin,
+    ) {
+        if self.target_state == TargetState::Secured
+            || self.tunnel_state.is_in_error_state()
+            || self.block_when_disconnected()
+        {
+            self.send_tunnel_command(TunnelCommand::CaptivePortalLogin(login));
+            Self::oneshot_send(tx, true, "captive portal login issued");
+        } else {
+            debug!("Ignoring captive portal login command. Traffic is not blocked");
+            Self::oneshot_send(tx, false, "captive portal login issued");
+        }
+    }
+
     fn on_get_state(&self, tx: oneshot::Sender<TunnelState>) {
         Self::oneshot_send(tx, self.tunnel_state.clone(), "current state");
     }
@@ -1423,8 +2168,13 @@ where
         match &self.tunnel_state {
             Disconnected => {
                 let location = self.get_geo_location();
-                tokio::spawn(async {
-                    Self::oneshot_send(tx, location.await.ok(), "current location");
+                let daemon_tx = self.tx.clone();
+                tokio::spawn(async move {
+                    let location = location.await.ok();
+                    if let Some(location) = &location {
+                        let _ = daemon_tx.send(InternalDaemonEvent::UserLocation(location.clone()));
+                    }
+                    Self::oneshot_send(tx, location, "current location");
                 });
             }
             Connecting { location, .. } => {
@@ -1467,16 +2217,57 @@ where
         }
     }
 
-    fn build_location_from_relay(&self) -> Option<GeoIpLocation> {
-        let relay = self.last_generated_relay.as_ref()?;
-        let bridge_hostname = self
-            .last_generated_bridge_relay
-            .as_ref()
-            .map(|bridge| bridge.hostname.clone());
-        let location = relay.location.as_ref().cloned().unwrap();
-        let hostname = relay.hostname.clone();
+    /// Fetches the location of the user, which is used to select automatic multihop entry relays.
+    fn update_user_location(&mut self) {
+        let location = self.get_geo_location();
+        let daemon_tx = self.tx.clone();
+        tokio::spawn(async move {
+            if let Ok(location) = location.await {
+                let _ = daemon_tx.send(InternalDaemonEvent::UserLocation(location));
+            }
+        });
+    }
 
-        Some(GeoIpLocation {
+    async fn handle_user_location(&mut self, location: GeoIpLocation) {
+        // The request may have been sent through the tunnel, if one was established since
+        if location.mullvad_exit_ip {
+            return;
+        }
+        // The location is only kept on disk while logged in
+        if self.settings.get_account_token().is_some() {
+            if let Err(error) = geoip::save_user_location_cache(&self.cache_dir, &location).await {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to cache user location")
+                );
+            }
+        }
+        self.relay_selector.set_user_location(location);
+    }
+
+    fn uses_automatic_entry(&self) -> bool {
+        match self.get_relay_settings() {
+            RelaySettings::Normal(constraints) => {
+                constraints.wireguard_constraints.automatic_entry.is_some()
+            }
+            RelaySettings::CustomTunnelEndpoint(_) => false,
+        }
+    }
+
+    fn build_location_from_relay(&self) -> Option<GeoIpLocation> {
+        let relay = self.last_generated_relay.as_ref()?;
+        let bridge_hostname = self
+            .last_generated_bridge_relay
+            .as_ref()
+            .map(|bridge| bridge.hostname.clone());
+        let entry_hostname = self
+            .last_generated_entry_relay
+            .as_ref()
+            .map(|entry| entry.hostname.clone());
+        let location = relay.location.as_ref().cloned().unwrap();
+        let hostname = relay.hostname.clone();
+
+        Some(GeoIpLocation {
             ipv4: None,
             ipv6: None,
             country: location.country,
@@ -1486,6 +2277,7 @@ where
             mullvad_exit_ip: true,
             hostname: Some(hostname),
             bridge_hostname,
+            entry_hostname,
         })
     }
 
@@ -1510,8 +2302,15 @@ where
         account_token: AccountToken,
     ) {
         let account = self.account.clone();
+        let event_tx = self.tx.clone();
         tokio::spawn(async move {
-            let result = account.check_expiry(account_token).await;
+            let result = account.check_expiry(account_token.clone()).await;
+            if let Ok(expiry) = result {
+                let _ = event_tx.send(InternalDaemonEvent::AccountExpiry(
+                    account_token,
+                    Ok(expiry),
+                ));
+            }
             Self::oneshot_send(
                 tx,
                 result.map(|expiry| AccountData { expiry }),
@@ -1520,6 +2319,158 @@ where
         });
     }
 
+    fn on_get_account_expiry(&mut self, tx: oneshot::Sender<Option<DateTime<Utc>>>) {
+        Self::oneshot_send(
+            tx,
+            self.account_expiry.as_ref().map(|cached| cached.expiry),
+            "account expiry",
+        );
+    }
+
+    async fn on_set_expiry_notification_hours(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        hours: Vec<u32>,
+    ) {
+        match self.settings.set_expiry_notification_hours(hours).await {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_expiry_notification_hours response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.last_expiry_threshold = None;
+                    self.evaluate_account_expiry();
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_expiry_notification_hours response");
+            }
+        }
+    }
+
+    fn spawn_account_expiry_timer(event_tx: DaemonEventSender) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(account::EXPIRY_EVALUATION_INTERVAL).await;
+                if event_tx
+                    .send(InternalDaemonEvent::CheckAccountExpiry)
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+    }
+
+    fn handle_check_account_expiry(&mut self) {
+        if let Some(account_token) = self.settings.get_account_token() {
+            let refresh_due = self
+                .last_expiry_refresh
+                .map(|refreshed| refreshed.elapsed() >= account::EXPIRY_REFRESH_INTERVAL)
+                .unwrap_or(true);
+            if refresh_due {
+                self.refresh_account_expiry(account_token);
+            }
+            self.evaluate_account_expiry();
+        }
+    }
+
+    /// Fetch the expiry of the account in the background. The result is handled by
+    /// `handle_account_expiry`.
+    fn refresh_account_expiry(&mut self, account_token: AccountToken) {
+        self.last_expiry_refresh = Some(Instant::now());
+        let account = self.account.clone();
+        let event_tx = self.tx.clone();
+        tokio::spawn(async move {
+            let result = account.check_expiry(account_token.clone()).await;
+            let _ = event_tx.send(InternalDaemonEvent::AccountExpiry(account_token, result));
+        });
+    }
+
+    async fn handle_account_expiry(
+        &mut self,
+        account_token: AccountToken,
+        result: Result<DateTime<Utc>, mullvad_rpc::rest::Error>,
+    ) {
+        let expiry = match result {
+            Ok(expiry) => expiry,
+            Err(error) => {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to fetch account expiry")
+                );
+                return;
+            }
+        };
+        if self.settings.get_account_token().as_ref() != Some(&account_token) {
+            return;
+        }
+        let cached_expiry = account::CachedExpiry {
+            account_token,
+            expiry,
+        };
+        if self.account_expiry.as_ref() != Some(&cached_expiry) {
+            if let Err(error) =
+                account::save_expiry_cache(&self.cache_dir, Some(&cached_expiry)).await
+            {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to cache account expiry")
+                );
+            }
+            self.account_expiry = Some(cached_expiry);
+        }
+        self.evaluate_account_expiry();
+    }
+
+    /// Notify clients if the account has crossed a new expiry threshold, and block the tunnel if
+    /// the account has run out of time.
+    fn evaluate_account_expiry(&mut self) {
+        let expiry = match self.account_expiry {
+            Some(ref cached) => cached.expiry,
+            None => return,
+        };
+        let event =
+            account::expiry_event(expiry, Utc::now(), &self.settings.expiry_notification_hours);
+        let threshold = event.as_ref().map(|event| event.threshold_hours);
+        if threshold != self.last_expiry_threshold {
+            self.last_expiry_threshold = threshold;
+            if let Some(event) = event {
+                log::info!(
+                    "Account expires at {} ({:?} hour threshold)",
+                    event.expiry,
+                    event.threshold_hours
+                );
+                self.event_listener.notify_account_expiry(event);
+            }
+        }
+
+        #[cfg(not(target_os = "android"))]
+        if self.target_state == TargetState::Secured {
+            let is_blocked = matches!(
+                self.tunnel_state,
+                TunnelState::Error(ref error_state)
+                    if *error_state.cause() == ErrorStateCause::AccountExpired
+            );
+            if self.is_account_expired() {
+                if !is_blocked {
+                    self.send_tunnel_command(TunnelCommand::Block(ErrorStateCause::AccountExpired));
+                }
+            } else if is_blocked {
+                self.connect_tunnel();
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "android"))]
+    fn is_account_expired(&self) -> bool {
+        self.account_expiry
+            .as_ref()
+            .map(|cached| cached.expiry <= Utc::now())
+            .unwrap_or(false)
+    }
+
     async fn on_get_www_auth_token(&mut self, tx: ResponseTx<String, Error>) {
         if let Some(account_token) = self.settings.get_account_token() {
             let future = self.account.get_www_auth_token(account_token);
@@ -1547,15 +2498,19 @@ where
     ) {
         if let Some(account_token) = self.settings.get_account_token() {
             let mut account = self.account.clone();
+            let event_tx = self.tx.clone();
             tokio::spawn(async move {
-                Self::oneshot_send(
-                    tx,
-                    account
-                        .submit_voucher(account_token, voucher)
-                        .await
-                        .map_err(Error::RestError),
-                    "submit_voucher response",
-                );
+                let result = account
+                    .submit_voucher(account_token.clone(), voucher)
+                    .await
+                    .map_err(Error::RestError);
+                if let Ok(ref submission) = result {
+                    let _ = event_tx.send(InternalDaemonEvent::AccountExpiry(
+                        account_token,
+                        Ok(submission.new_expiry),
+                    ));
+                }
+                Self::oneshot_send(tx, result, "submit_voucher response");
             });
         } else {
             Self::oneshot_send(tx, Err(Error::NoAccountToken), "submit_voucher response");
@@ -1630,7 +2585,7 @@ where
                 {
                     let remove_key = self
                         .wireguard_key_manager
-                        .remove_key_with_backoff(previous_token, previous_key);
+                        .remove_key_with_backoff(previous_token.clone(), previous_key);
                     tokio::spawn(async move {
                         if let Err(error) = remove_key.await {
                             log::error!(
@@ -1642,6 +2597,12 @@ where
                         }
                     });
                 }
+                if let Some(pending_data) = self.settings.get_wireguard_pending() {
+                    self.remove_wireguard_key_in_background(
+                        previous_token,
+                        pending_data.private_key.public_key(),
+                    );
+                }
             }
             if let Err(error) = self.settings.set_wireguard(None).await {
                 log::error!(
@@ -1649,6 +2610,33 @@ where
                     error.display_chain_with_msg("Error resetting WireGuard key")
                 );
             }
+            if let Err(error) = self.settings.set_wireguard_pending(None).await {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Error resetting rotated WireGuard key")
+                );
+            }
+            self.account_expiry = None;
+            self.last_expiry_threshold = None;
+            self.last_expiry_refresh = None;
+            if let Err(error) = account::save_expiry_cache(&self.cache_dir, None).await {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to clear account expiry cache")
+                );
+            }
+            match self.settings.get_account_token() {
+                Some(account_token) => self.refresh_account_expiry(account_token),
+                None => {
+                    // The location of the user is not kept on disk after logging out
+                    if let Err(error) = geoip::clear_user_location_cache(&self.cache_dir).await {
+                        log::error!(
+                            "{}",
+                            error.display_chain_with_msg("Failed to clear user location cache")
+                        );
+                    }
+                }
+            }
             self.ensure_wireguard_keys_for_current_account().await;
         }
         Ok(account_changed)
@@ -1739,6 +2727,161 @@ where
         );
     }
 
+    fn on_get_connectivity(&mut self, tx: oneshot::Sender<Connectivity>) {
+        Self::oneshot_send(tx, self.connectivity, "get_connectivity response");
+    }
+
+    async fn on_set_connectivity_probe(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        probe: Option<ConnectivityProbe>,
+    ) {
+        match self.settings.set_connectivity_probe(probe).await {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_connectivity_probe response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    #[cfg(target_os = "linux")]
+                    self.send_tunnel_command(TunnelCommand::ConnectivityProbe(
+                        self.settings.connectivity_probe.clone(),
+                    ));
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_connectivity_probe response");
+            }
+        }
+    }
+
+    async fn on_add_trusted_network(&mut self, tx: ResponseTx<(), Error>, network: TrustedNetwork) {
+        let result = self
+            .update_trusted_networks(|trusted_networks| trusted_networks.add(network))
+            .await;
+        Self::oneshot_send(tx, result, "add_trusted_network response");
+    }
+
+    async fn on_remove_trusted_network(
+        &mut self,
+        tx: ResponseTx<(), Error>,
+        network: TrustedNetwork,
+    ) {
+        let result = self
+            .update_trusted_networks(|trusted_networks| trusted_networks.remove(&network))
+            .await;
+        Self::oneshot_send(tx, result, "remove_trusted_network response");
+    }
+
+    fn on_get_connected_networks(&mut self, tx: oneshot::Sender<Vec<ConnectedNetwork>>) {
+        Self::oneshot_send(
+            tx,
+            self.connected_networks.clone(),
+            "get_connected_networks response",
+        );
+    }
+
+    async fn on_add_network_profile(&mut self, tx: ResponseTx<(), Error>, profile: NetworkProfile) {
+        let result = self
+            .update_network_profiles(|network_profiles| network_profiles.add(profile))
+            .await;
+        Self::oneshot_send(tx, result, "add_network_profile response");
+    }
+
+    async fn on_update_network_profile(
+        &mut self,
+        tx: ResponseTx<(), Error>,
+        profile: NetworkProfile,
+    ) {
+        let result = self
+            .update_network_profiles(|network_profiles| network_profiles.replace(profile))
+            .await;
+        Self::oneshot_send(tx, result, "update_network_profile response");
+    }
+
+    async fn on_update_network_profile_relay_settings(
+        &mut self,
+        tx: ResponseTx<(), Error>,
+        name: String,
+        update: RelaySettingsUpdate,
+    ) {
+        let regular_relay_settings = self.settings.get_relay_settings();
+        let result = self
+            .update_network_profiles(|network_profiles| {
+                network_profiles.update_relay_settings(&name, &regular_relay_settings, update)
+            })
+            .await;
+        Self::oneshot_send(tx, result, "update_network_profile_relay_settings response");
+    }
+
+    async fn on_remove_network_profile(&mut self, tx: ResponseTx<(), Error>, name: String) {
+        let result = self
+            .update_network_profiles(|network_profiles| network_profiles.remove(&name))
+            .await;
+        Self::oneshot_send(tx, result, "remove_network_profile response");
+    }
+
+    fn on_get_active_network_profile(&mut self, tx: oneshot::Sender<Option<ActiveNetworkProfile>>) {
+        Self::oneshot_send(
+            tx,
+            self.get_active_network_profile(),
+            "get_active_network_profile response",
+        );
+    }
+
+    async fn update_network_profiles(
+        &mut self,
+        update: impl FnOnce(&mut network_profile::Settings) -> Result<(), network_profile::Error>,
+    ) -> Result<(), Error> {
+        let mut network_profiles = self.settings.network_profiles.clone();
+        update(&mut network_profiles).map_err(Error::NetworkProfileError)?;
+
+        let previous_settings = (self.get_relay_settings(), self.get_bridge_state());
+        let settings_changed = self
+            .settings
+            .set_network_profiles(network_profiles)
+            .await
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to update network profiles")
+                );
+                Error::SettingsError(error)
+            })?;
+        if settings_changed {
+            self.event_listener
+                .notify_settings(self.settings.to_settings());
+            self.reconnect_on_network_profile_change(previous_settings);
+        }
+        Ok(())
+    }
+
+    async fn update_trusted_networks(
+        &mut self,
+        update: impl FnOnce(&mut trusted_network::Settings) -> Result<(), trusted_network::Error>,
+    ) -> Result<(), Error> {
+        let mut trusted_networks = self.settings.trusted_networks.clone();
+        update(&mut trusted_networks).map_err(Error::TrustedNetworkError)?;
+
+        let settings_changed = self
+            .settings
+            .set_trusted_networks(trusted_networks)
+            .await
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to update trusted networks")
+                );
+                Error::SettingsError(error)
+            })?;
+        if settings_changed {
+            self.event_listener
+                .notify_settings(self.settings.to_settings());
+            self.update_trusted_network_state().await;
+        }
+        Ok(())
+    }
+
     #[cfg(not(target_os = "android"))]
     async fn on_factory_reset(&mut self, tx: ResponseTx<(), Error>) {
         let mut last_error = Ok(());
@@ -1998,6 +3141,10 @@ where
                         .notify_settings(self.settings.to_settings());
                     info!("Initiating tunnel restart because the relay settings changed");
                     self.reconnect_tunnel();
+                    if self.tunnel_state == TunnelState::Disconnected && self.uses_automatic_entry()
+                    {
+                        self.update_user_location();
+                    }
                 }
             }
             Err(e) => {
@@ -2048,6 +3195,31 @@ where
         }
     }
 
+    async fn on_set_update_channel(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        channel: Option<UpdateChannelSettings>,
+    ) {
+        match self.settings.set_update_channel(channel).await {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_update_channel response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    let channel = version_check::UpdateChannel::from_settings(
+                        self.settings.update_channel.as_ref(),
+                    );
+                    let mut handle = self.version_updater_handle.clone();
+                    handle.set_update_channel(channel).await;
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_update_channel response");
+            }
+        }
+    }
+
     async fn on_set_block_when_disconnected(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -2064,7 +3236,7 @@ where
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
                     self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
-                        block_when_disconnected,
+                        self.block_when_disconnected(),
                     ));
                 }
             }
@@ -2123,6 +3295,61 @@ where
         }
     }
 
+    async fn on_set_openvpn_cipher(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        cipher: Option<openvpn::Cipher>,
+    ) {
+        let save_result = self.settings.set_openvpn_cipher(cipher).await;
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_openvpn_cipher response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    if let Some(TunnelType::OpenVpn) = self.get_connected_tunnel_type() {
+                        info!(
+                            "Initiating tunnel restart because the OpenVPN cipher setting changed"
+                        );
+                        self.reconnect_tunnel();
+                    }
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_openvpn_cipher response");
+            }
+        }
+    }
+
+    async fn on_set_openvpn_tls_version_min(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        version: openvpn::TlsVersion,
+    ) {
+        let save_result = self.settings.set_openvpn_tls_version_min(version).await;
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_openvpn_tls_version_min response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    if let Some(TunnelType::OpenVpn) = self.get_connected_tunnel_type() {
+                        info!(
+                            "Initiating tunnel restart because the OpenVPN TLS version setting \
+                             changed"
+                        );
+                        self.reconnect_tunnel();
+                    }
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_openvpn_tls_version_min response");
+            }
+        }
+    }
+
     async fn on_set_bridge_settings(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -2174,6 +3401,144 @@ where
         Self::oneshot_send(tx, result, "on_set_bridge_state response");
     }
 
+    fn on_get_retry_schedule(&self, tx: oneshot::Sender<Option<RetrySchedule>>) {
+        let retry_schedule = match self.get_relay_settings() {
+            RelaySettings::Normal(constraints) => Some(
+                self.relay_selector
+                    .retry_schedule(&constraints, self.settings.get_wireguard().is_some()),
+            ),
+            RelaySettings::CustomTunnelEndpoint(_) => None,
+        };
+        Self::oneshot_send(tx, retry_schedule, "get_retry_schedule response");
+    }
+
+    async fn on_set_retry_schedule(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        retry_schedule: Option<RetrySchedule>,
+    ) {
+        match self.settings.set_retry_schedule(retry_schedule).await {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_retry_schedule response");
+                if settings_changed {
+                    self.relay_selector
+                        .set_retry_schedule(self.settings.retry_schedule.clone());
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                }
+            }
+            Err(e) => {
+                error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_retry_schedule response");
+            }
+        }
+    }
+
+    async fn on_add_api_access_method(
+        &mut self,
+        tx: ResponseTx<(), Error>,
+        method: ApiAccessMethod,
+    ) {
+        let result = self
+            .update_api_access_methods(|access_methods| access_methods.add(method))
+            .await;
+        Self::oneshot_send(tx, result, "add_api_access_method response");
+    }
+
+    async fn on_remove_api_access_method(&mut self, tx: ResponseTx<(), Error>, name: String) {
+        let result = self
+            .update_api_access_methods(|access_methods| access_methods.remove(&name))
+            .await;
+        Self::oneshot_send(tx, result, "remove_api_access_method response");
+    }
+
+    async fn on_set_api_access_method_enabled(
+        &mut self,
+        tx: ResponseTx<(), Error>,
+        name: String,
+        enabled: bool,
+    ) {
+        let result = self
+            .update_api_access_methods(|access_methods| {
+                access_methods.set_enabled(&name, enabled).map(|_| ())
+            })
+            .await;
+        Self::oneshot_send(tx, result, "set_api_access_method_enabled response");
+    }
+
+    fn on_get_current_api_access_method(&mut self, tx: oneshot::Sender<Option<ApiAccessMethod>>) {
+        let current = self
+            .api_access_methods
+            .current(&self.settings.api_access_methods)
+            .cloned();
+        Self::oneshot_send(tx, current, "get_current_api_access_method response");
+    }
+
+    fn on_get_api_status(&mut self, tx: oneshot::Sender<ApiStatus>) {
+        let status = self.rpc_runtime.metrics().status();
+        Self::oneshot_send(tx, status, "get_api_status response");
+    }
+
+    fn on_set_log_level(
+        &mut self,
+        tx: oneshot::Sender<()>,
+        level: log::LevelFilter,
+        module: Option<String>,
+        timeout: Option<Duration>,
+    ) {
+        let target = module.clone().unwrap_or_else(|| "the daemon".to_owned());
+        let change = logging::set_log_level(level, module);
+        info!("Log level of {} set to {}", target, level);
+
+        if let Some(timeout) = timeout {
+            tokio::spawn(async move {
+                tokio::time::sleep(timeout).await;
+                if logging::revert_log_level(change) {
+                    info!("Restored the previous log level of {}", target);
+                }
+            });
+        }
+        Self::oneshot_send(tx, (), "set_log_level response");
+    }
+
+    async fn update_api_access_methods(
+        &mut self,
+        update: impl FnOnce(&mut access_method::Settings) -> Result<(), access_method::Error>,
+    ) -> Result<(), Error> {
+        let mut access_methods = self.settings.api_access_methods.clone();
+        update(&mut access_methods).map_err(Error::AccessMethodError)?;
+
+        let settings_changed = self
+            .settings
+            .set_api_access_methods(access_methods)
+            .await
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to update API access methods")
+                );
+                Error::SettingsError(error)
+            })?;
+        if settings_changed {
+            self.event_listener
+                .notify_settings(self.settings.to_settings());
+
+            if self
+                .api_access_methods
+                .current_is_unavailable(&self.settings.api_access_methods)
+            {
+                log::info!("Switching API access method since the current one is unavailable");
+                let (connection_mode, firewall_updated) = self.rotate_api_access_method().await;
+                let service = self.rpc_handle.service();
+                tokio::spawn(async move {
+                    let _ = firewall_updated.await;
+                    service.set_connection_mode(connection_mode).await;
+                });
+            }
+        }
+        Ok(())
+    }
+
 
     async fn on_set_enable_ipv6(&mut self, tx: ResponseTx<(), settings::Error>, enable_ipv6: bool) {
         let save_result = self.settings.set_enable_ipv6(enable_ipv6).await;
@@ -2305,6 +3670,18 @@ where
             .ok_or(Error::NoAccountToken)?;
         let wireguard_data = self.settings.get_wireguard();
 
+        // A key being rotated to is superseded by the new key
+        if let Some(pending_data) = self.settings.get_wireguard_pending() {
+            self.remove_wireguard_key_in_background(
+                account_token.clone(),
+                pending_data.private_key.public_key(),
+            );
+            self.settings
+                .set_wireguard_pending(None)
+                .await
+                .map_err(Error::SettingsError)?;
+        }
+
         let gen_result = match &wireguard_data {
             Some(wireguard_data) => {
                 self.wireguard_key_manager
@@ -2325,7 +3702,7 @@ where
                     .set_wireguard(Some(new_data))
                     .await
                     .map_err(Error::SettingsError)?;
-                if let Some(TunnelType::Wireguard) = self.get_target_tunnel_type() {
+                if self.tunnel_uses_wireguard_key() {
                     self.schedule_reconnect(WG_RECONNECT_DELAY).await;
                 }
                 let keygen_event = KeygenEvent::NewKey(public_key.clone());
@@ -2360,6 +3737,17 @@ where
         Self::oneshot_send(tx, result, "get_wireguard_key response");
     }
 
+    fn on_get_wireguard_key_rotation_history(
+        &self,
+        tx: oneshot::Sender<Vec<wireguard::KeyRotation>>,
+    ) {
+        Self::oneshot_send(
+            tx,
+            self.key_rotation_history.iter().cloned().collect(),
+            "get_wireguard_key_rotation_history response",
+        );
+    }
+
     async fn on_verify_wireguard_key(&mut self, tx: ResponseTx<bool, Error>) {
         let account = match self.settings.get_account_token() {
             Some(account) => account,
@@ -2448,18 +3836,116 @@ where
     async fn forward_offline_state(
         runtime: &tokio::runtime::Handle,
         api_availability: ApiAvailabilityHandle,
-        mut offline_state_rx: mpsc::UnboundedReceiver<bool>,
-    ) {
+        connectivity_tx: DaemonEventSender<Connectivity>,
+        mut offline_state_rx: mpsc::UnboundedReceiver<Connectivity>,
+    ) -> Connectivity {
         let initial_state = offline_state_rx
             .next()
             .await
             .expect("missing initial offline state");
-        api_availability.set_offline(initial_state);
+        api_availability.set_offline(initial_state.is_offline());
         runtime.spawn(async move {
-            while let Some(is_offline) = offline_state_rx.next().await {
-                api_availability.set_offline(is_offline);
+            while let Some(connectivity) = offline_state_rx.next().await {
+                api_availability.set_offline(connectivity.is_offline());
+                if connectivity_tx.send(connectivity).is_err() {
+                    break;
+                }
             }
         });
+        initial_state
+    }
+
+    #[cfg(target_os = "linux")]
+    fn forward_connected_networks(
+        runtime: &tokio::runtime::Handle,
+        event_tx: DaemonEventSender,
+        mut network_rx: mpsc::UnboundedReceiver<Vec<ConnectedNetwork>>,
+    ) {
+        runtime.spawn(async move {
+            while let Some(networks) = network_rx.next().await {
+                if event_tx
+                    .send(InternalDaemonEvent::NetworksChanged(networks))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn handle_networks_change(&mut self, networks: Vec<ConnectedNetwork>) {
+        let previous_settings = (self.get_relay_settings(), self.get_bridge_state());
+        self.connected_networks = networks;
+        self.reconnect_on_network_profile_change(previous_settings);
+        self.update_trusted_network_state().await;
+    }
+
+    /// Returns the network profile that applies to the networks that the host is connected to.
+    fn get_active_network_profile(&self) -> Option<ActiveNetworkProfile> {
+        self.settings
+            .network_profiles
+            .active_profile(&self.connected_networks)
+    }
+
+    /// Returns the relay settings in effect. A network profile may replace the regular ones.
+    fn get_relay_settings(&self) -> RelaySettings {
+        self.settings
+            .get_effective_relay_settings(&self.connected_networks)
+    }
+
+    /// Returns the bridge state in effect. A network profile may replace the regular one.
+    fn get_bridge_state(&self) -> BridgeState {
+        self.settings
+            .get_effective_bridge_state(&self.connected_networks)
+    }
+
+    /// Reconnects if the relay settings or bridge state in effect are no longer the `previous`
+    /// ones, because a different network profile applies or the active profile was changed.
+    fn reconnect_on_network_profile_change(&mut self, previous: (RelaySettings, BridgeState)) {
+        if (self.get_relay_settings(), self.get_bridge_state()) == previous {
+            return;
+        }
+        match self.get_active_network_profile() {
+            Some(active) => info!(
+                "Network profile \"{}\" applies to {}, which matches {}",
+                active.profile.name, active.network, active.identifier
+            ),
+            None => info!("No network profile applies to the current networks"),
+        }
+        info!("Initiating tunnel restart because the relay settings changed");
+        self.reconnect_tunnel();
+    }
+
+    /// Re-evaluates whether the host is on a trusted network. Entering a trusted network
+    /// disconnects the tunnel, and leaving one connects it again. Nothing is done unless the
+    /// trusted status changes, so the user can still connect or disconnect manually.
+    async fn update_trusted_network_state(&mut self) {
+        let on_trusted_network = self
+            .settings
+            .trusted_networks
+            .is_trusted(&self.connected_networks);
+        if on_trusted_network == self.on_trusted_network {
+            return;
+        }
+        self.on_trusted_network = on_trusted_network;
+        self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
+            self.block_when_disconnected(),
+        ));
+
+        if on_trusted_network {
+            log::info!("Connected to a trusted network. Disconnecting");
+            self.set_target_state(TargetState::Unsecured).await;
+        } else if self.settings.get_account_token().is_some() {
+            log::info!("Left trusted network. Connecting");
+            self.set_target_state(TargetState::Secured).await;
+        }
+    }
+
+    /// Returns whether traffic should be blocked while disconnected. Traffic is never blocked on
+    /// a trusted network.
+    fn block_when_disconnected(&self) -> bool {
+        self.settings.block_when_disconnected && !self.on_trusted_network
     }
 
     /// Set the target state of the client. If it changed trigger the operations needed to
@@ -2508,6 +3994,15 @@ where
     }
 
     fn connect_tunnel(&mut self) {
+        #[cfg(not(target_os = "android"))]
+        if self.is_account_expired() {
+            // The cached expiry may be stale, so check whether time has been added
+            if let Some(account_token) = self.settings.get_account_token() {
+                self.refresh_account_expiry(account_token);
+            }
+            self.send_tunnel_command(TunnelCommand::Block(ErrorStateCause::AccountExpired));
+            return;
+        }
         self.send_tunnel_command(TunnelCommand::Connect);
     }
 
@@ -2533,17 +4028,13 @@ where
         }
     }
 
-    fn get_target_tunnel_type(&self) -> Option<TunnelType> {
-        match self.tunnel_state {
-            TunnelState::Connected {
-                endpoint: TunnelEndpoint { tunnel_type, .. },
-                ..
+    /// Returns whether the current or upcoming tunnel is set up using the WireGuard key.
+    fn tunnel_uses_wireguard_key(&self) -> bool {
+        match &self.tunnel_state {
+            TunnelState::Connected { endpoint, .. } | TunnelState::Connecting { endpoint, .. } => {
+                wireguard::uses_wireguard_key(endpoint)
             }
-            | TunnelState::Connecting {
-                endpoint: TunnelEndpoint { tunnel_type, .. },
-                ..
-            } => Some(tunnel_type),
-            _ => None,
+            _ => false,
         }
     }
 
@@ -2,9 +2,22 @@ use fern::{
     colors::{Color, ColoredLevelConfig},
     Output,
 };
+use futures::FutureExt;
+use lazy_static::lazy_static;
 use log;
-use std::{fmt, io, path::PathBuf};
-use talpid_core::logging::rotate_log;
+use mullvad_types::states::TunnelState;
+use parking_lot::{Mutex, RwLock};
+use std::{
+    cmp,
+    collections::{BTreeMap, VecDeque},
+    fmt, io,
+    path::PathBuf,
+    str::FromStr,
+};
+use talpid_core::logging::{
+    log_rotation_policy_errors, rotate_log, RotatingLogFile, ROTATION_POLICY,
+};
+use tokio::sync::{broadcast, mpsc};
 
 #[derive(err_derive::Error, Debug)]
 pub enum Error {
@@ -59,44 +72,81 @@ const LINE_SEPARATOR: &str = "\r\n";
 
 const DATE_TIME_FORMAT_STR: &str = "[%Y-%m-%d %H:%M:%S%.3f]";
 
+/// Number of recent log records kept in memory and sent to new log stream subscribers.
+const LOG_HISTORY_LENGTH: usize = 1000;
+/// Number of log records that a log stream subscriber may lag behind before records are dropped.
+const LOG_STREAM_CAPACITY: usize = 256;
+
+lazy_static! {
+    static ref LOG_FILTER: RwLock<LogFilter> = RwLock::new(LogFilter::new(log::LevelFilter::Info));
+    static ref LOG_STREAM: Mutex<LogStream> = Mutex::new(LogStream::new());
+    static ref TUNNEL_CONTEXT: RwLock<Option<TunnelContext>> = RwLock::new(None);
+}
+
+/// How log records are written to stdout and the log file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines.
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        LogFormat::Text
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Unknown log format: {}", format)),
+        }
+    }
+}
+
 pub fn init_logger(
     log_level: log::LevelFilter,
     log_file: Option<&PathBuf>,
     output_timestamp: bool,
+    format: LogFormat,
 ) -> Result<(), Error> {
-    let mut top_dispatcher = fern::Dispatch::new().level(log_level);
-    for silenced_crate in WARNING_SILENCED_CRATES {
-        top_dispatcher = top_dispatcher.level_for(*silenced_crate, log::LevelFilter::Error);
-    }
-    for silenced_crate in SILENCED_CRATES {
-        top_dispatcher = top_dispatcher.level_for(*silenced_crate, log::LevelFilter::Warn);
-    }
-    for silenced_crate in SLIGHTLY_SILENCED_CRATES {
-        top_dispatcher = top_dispatcher.level_for(*silenced_crate, one_level_quieter(log_level));
-    }
+    *LOG_FILTER.write() = LogFilter::new(log_level);
+    let mut top_dispatcher =
+        fern::Dispatch::new().filter(|metadata| LOG_FILTER.read().enabled(metadata));
 
     let stdout_formatter = Formatter {
         output_timestamp,
         output_color: true,
+        format,
     };
     let stdout_dispatcher = fern::Dispatch::new()
         .format(move |out, message, record| stdout_formatter.output_msg(out, message, record))
         .chain(io::stdout());
     top_dispatcher = top_dispatcher.chain(stdout_dispatcher);
+    top_dispatcher = top_dispatcher.chain(Output::call(|record| LOG_STREAM.lock().push(record)));
 
     if let Some(ref log_file) = log_file {
         rotate_log(log_file).map_err(Error::RotateLog)?;
         let file_formatter = Formatter {
             output_timestamp: true,
             output_color: false,
+            format,
         };
-        let f = fern::log_file(log_file).map_err(|source| Error::WriteFile {
-            path: log_file.display().to_string(),
-            source,
+        let f = RotatingLogFile::open(log_file, *ROTATION_POLICY).map_err(|source| {
+            Error::WriteFile {
+                path: log_file.display().to_string(),
+                source,
+            }
         })?;
         let file_dispatcher = fern::Dispatch::new()
             .format(move |out, message, record| file_formatter.output_msg(out, message, record))
-            .chain(Output::file(f, LINE_SEPARATOR));
+            .chain(Output::writer(Box::new(f), LINE_SEPARATOR));
         top_dispatcher = top_dispatcher.chain(file_dispatcher);
     }
     #[cfg(all(target_os = "android", debug_assertions))]
@@ -108,9 +158,251 @@ pub fn init_logger(
         top_dispatcher = top_dispatcher.chain(logger);
     }
     top_dispatcher.apply().map_err(Error::SetLoggerError)?;
+    log::set_max_level(LOG_FILTER.read().max_level());
+    log_rotation_policy_errors();
     Ok(())
 }
 
+/// The tunnel state that is included in JSON log records.
+#[derive(Debug, Clone, serde::Serialize)]
+struct TunnelContext {
+    state: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    endpoint: Option<String>,
+}
+
+/// Sets the tunnel state that is included in subsequent JSON log records.
+pub fn set_tunnel_state(tunnel_state: &TunnelState) {
+    let (state, endpoint) = match tunnel_state {
+        TunnelState::Disconnected => ("disconnected", None),
+        TunnelState::Connecting { endpoint, .. } => ("connecting", Some(endpoint.to_string())),
+        TunnelState::Connected { endpoint, .. } => ("connected", Some(endpoint.to_string())),
+        TunnelState::Disconnecting(_) => ("disconnecting", None),
+        TunnelState::Error(_) => ("error", None),
+    };
+    *TUNNEL_CONTEXT.write() = Some(TunnelContext { state, endpoint });
+}
+
+/// A log record sent to log stream subscribers.
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub level: log::Level,
+    /// The module that the record was logged from.
+    pub module: String,
+    pub message: String,
+}
+
+impl LogRecord {
+    /// Returns whether the record is at most as verbose as `level`, and from `module` or one of
+    /// its submodules if a module is given.
+    pub fn matches(&self, level: log::LevelFilter, module: Option<&str>) -> bool {
+        self.level <= level
+            && module
+                .map(|module| is_in_module(&self.module, module))
+                .unwrap_or(true)
+    }
+}
+
+/// Returns a subscription to the most recent log records and all records logged after them.
+pub fn subscribe_to_logs() -> LogSubscription {
+    LOG_STREAM.lock().subscribe()
+}
+
+/// The most recent log records, and a receiver of all records logged after them.
+pub struct LogSubscription {
+    history: Vec<LogRecord>,
+    receiver: broadcast::Receiver<LogRecord>,
+}
+
+impl LogSubscription {
+    /// Sends the records that match `level` and `module` to `tx`, converted using `convert`. The
+    /// recent records are sent first, followed by new ones if `follow` is set. New records are
+    /// dropped while `tx` is full. Returns once all records are sent or `tx` is closed.
+    pub async fn forward<T>(
+        mut self,
+        level: log::LevelFilter,
+        module: Option<String>,
+        follow: bool,
+        tx: mpsc::Sender<T>,
+        convert: impl Fn(LogRecord) -> T,
+    ) {
+        let matches = |record: &LogRecord| record.matches(level, module.as_deref());
+        for record in self.history.into_iter().filter(matches) {
+            if tx.send(convert(record)).await.is_err() {
+                return;
+            }
+        }
+        if !follow {
+            return;
+        }
+        let mut closed = Box::pin(tx.closed().fuse());
+        loop {
+            let mut next_record = Box::pin(self.receiver.recv().fuse());
+            let result = futures::select! {
+                () = closed => return,
+                result = next_record => result,
+            };
+            match result {
+                Ok(record) if matches(&record) => match tx.try_send(convert(record)) {
+                    Ok(()) | Err(mpsc::error::TrySendError::Full(_)) => (),
+                    Err(mpsc::error::TrySendError::Closed(_)) => return,
+                },
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => (),
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+}
+
+/// Recent log records, and the channel that new records are sent on.
+struct LogStream {
+    history: VecDeque<LogRecord>,
+    sender: broadcast::Sender<LogRecord>,
+}
+
+impl LogStream {
+    fn new() -> Self {
+        let (sender, _) = broadcast::channel(LOG_STREAM_CAPACITY);
+        LogStream {
+            history: VecDeque::with_capacity(LOG_HISTORY_LENGTH),
+            sender,
+        }
+    }
+
+    fn subscribe(&self) -> LogSubscription {
+        LogSubscription {
+            history: self.history.iter().cloned().collect(),
+            receiver: self.sender.subscribe(),
+        }
+    }
+
+    fn push(&mut self, record: &log::Record<'_>) {
+        let record = LogRecord {
+            timestamp: chrono::Utc::now(),
+            level: record.level(),
+            module: record.target().to_owned(),
+            message: record.args().to_string(),
+        };
+        if self.history.len() >= LOG_HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(record.clone());
+        // Sending only fails if there are no subscribers
+        let _ = self.sender.send(record);
+    }
+}
+
+/// A change of the log level, made by `set_log_level`.
+#[derive(Debug, Clone)]
+pub struct LogLevelChange {
+    module: Option<String>,
+    previous_level: Option<log::LevelFilter>,
+    generation: u64,
+}
+
+/// Sets the log level of `module` and its submodules, or the global level if `module` is `None`.
+/// A module level takes precedence over the global level, even if it is lower.
+pub fn set_log_level(level: log::LevelFilter, module: Option<String>) -> LogLevelChange {
+    let mut filter = LOG_FILTER.write();
+    let previous_level = match &module {
+        Some(module) => filter.module_levels.insert(module.clone(), level),
+        None => Some(std::mem::replace(&mut filter.level, level)),
+    };
+    let generation = filter.next_generation(&module);
+    log::set_max_level(filter.max_level());
+
+    LogLevelChange {
+        module,
+        previous_level,
+        generation,
+    }
+}
+
+/// Restores the log level that was replaced by `change`, unless the level has been changed again
+/// since. Returns whether the level was restored.
+pub fn revert_log_level(change: LogLevelChange) -> bool {
+    let mut filter = LOG_FILTER.write();
+    if filter.generations.get(&change.module) != Some(&change.generation) {
+        return false;
+    }
+    match (&change.module, change.previous_level) {
+        (Some(module), Some(level)) => {
+            filter.module_levels.insert(module.clone(), level);
+        }
+        (Some(module), None) => {
+            filter.module_levels.remove(module);
+        }
+        (None, level) => filter.level = level.unwrap_or(filter.level),
+    }
+    filter.next_generation(&change.module);
+    log::set_max_level(filter.max_level());
+    true
+}
+
+/// Decides which log records are enabled. The levels can be changed at runtime.
+struct LogFilter {
+    level: log::LevelFilter,
+    /// Levels of specific modules and their submodules, set at runtime.
+    module_levels: BTreeMap<String, log::LevelFilter>,
+    /// Number of changes made to the global level (`None`) and each module level.
+    generations: BTreeMap<Option<String>, u64>,
+}
+
+impl LogFilter {
+    fn new(level: log::LevelFilter) -> Self {
+        LogFilter {
+            level,
+            module_levels: BTreeMap::new(),
+            generations: BTreeMap::new(),
+        }
+    }
+
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn level_for(&self, target: &str) -> log::LevelFilter {
+        let module_level = self
+            .module_levels
+            .iter()
+            .filter(|(module, _)| is_in_module(target, module))
+            .max_by_key(|(module, _)| module.len());
+        if let Some((_, level)) = module_level {
+            return *level;
+        }
+
+        let crate_level = |crates: &[&str], level| {
+            crates
+                .iter()
+                .any(|silenced_crate| is_in_module(target, silenced_crate))
+                .then(|| level)
+        };
+        crate_level(WARNING_SILENCED_CRATES, log::LevelFilter::Error)
+            .or_else(|| crate_level(SILENCED_CRATES, log::LevelFilter::Warn))
+            .or_else(|| crate_level(SLIGHTLY_SILENCED_CRATES, one_level_quieter(self.level)))
+            .unwrap_or(self.level)
+    }
+
+    /// Returns the most verbose level of any module.
+    fn max_level(&self) -> log::LevelFilter {
+        self.module_levels
+            .values()
+            .fold(self.level, |max_level, level| cmp::max(max_level, *level))
+    }
+
+    fn next_generation(&mut self, module: &Option<String>) -> u64 {
+        let generation = self.generations.entry(module.clone()).or_insert(0);
+        *generation += 1;
+        *generation
+    }
+}
+
+/// Returns whether `target` is `module` or one of its submodules.
+fn is_in_module(target: &str, module: &str) -> bool {
+    target == module || (target.starts_with(module) && target[module.len()..].starts_with("::"))
+}
+
 fn one_level_quieter(level: log::LevelFilter) -> log::LevelFilter {
     use log::LevelFilter::*;
     match level {
@@ -127,6 +419,19 @@ fn one_level_quieter(level: log::LevelFilter) -> log::LevelFilter {
 struct Formatter {
     pub output_timestamp: bool,
     pub output_color: bool,
+    pub format: LogFormat,
+}
+
+/// A log record as written in the JSON log format.
+#[derive(serde::Serialize)]
+struct JsonRecord<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<String>,
+    level: &'a str,
+    target: &'a str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tunnel: Option<TunnelContext>,
 }
 
 impl Formatter {
@@ -154,6 +459,23 @@ impl Formatter {
     ) {
         let message = escape_newlines(format!("{}", message));
 
+        if self.format == LogFormat::Json {
+            // Newlines are escaped by the JSON serializer, so every record is a single line
+            let json_record = JsonRecord {
+                timestamp: self.output_timestamp.then(|| {
+                    chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false)
+                }),
+                level: record.level().as_str(),
+                target: record.target(),
+                message: &message,
+                tunnel: TUNNEL_CONTEXT.read().clone(),
+            };
+            // Serialization cannot fail for these types, but fall back to text if it does
+            if let Ok(json) = serde_json::to_string(&json_record) {
+                return out.finish(format_args!("{}", json));
+            }
+        }
+
         out.finish(format_args!(
             "{}[{}][{}] {}",
             chrono::Local::now().format(self.get_timetsamp_fmt()),
@@ -173,3 +495,139 @@ fn escape_newlines(text: String) -> String {
 fn escape_newlines(text: String) -> String {
     text.replace("\n", LINE_SEPARATOR)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_module_levels() {
+        let mut filter = LogFilter::new(log::LevelFilter::Info);
+        filter
+            .module_levels
+            .insert("talpid_core::firewall".to_owned(), log::LevelFilter::Trace);
+
+        assert_eq!(
+            filter.level_for("talpid_core::firewall::linux"),
+            log::LevelFilter::Trace
+        );
+        assert_eq!(
+            filter.level_for("talpid_core::firewall_other"),
+            log::LevelFilter::Info
+        );
+        assert_eq!(filter.level_for("hyper::client"), log::LevelFilter::Warn);
+        assert_eq!(filter.level_for("nftnl"), log::LevelFilter::Warn);
+        assert_eq!(filter.max_level(), log::LevelFilter::Trace);
+    }
+
+    #[test]
+    fn test_log_subscription() {
+        fn push(stream: &mut LogStream, records: &[(log::Level, &str, &str)]) {
+            for (level, module, message) in records {
+                stream.push(
+                    &log::Record::builder()
+                        .level(*level)
+                        .target(module)
+                        .args(format_args!("{}", message))
+                        .build(),
+                );
+            }
+        }
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let mut stream = LogStream::new();
+        push(
+            &mut stream,
+            &[
+                (log::Level::Info, "talpid_core::firewall", "old"),
+                (log::Level::Debug, "talpid_core::firewall", "verbose"),
+                (log::Level::Info, "mullvad_daemon", "other module"),
+            ],
+        );
+        let subscription = stream.subscribe();
+        push(
+            &mut stream,
+            &[
+                (log::Level::Warn, "talpid_core::firewall::linux", "new"),
+                (log::Level::Info, "talpid_core::firewall_other", "other"),
+            ],
+        );
+        drop(stream);
+
+        // New records are dropped while the channel is full, so leave room for all of them
+        let (tx, mut rx) = mpsc::channel(5);
+        let messages = runtime.block_on(async move {
+            tokio::spawn(subscription.forward(
+                log::LevelFilter::Info,
+                Some("talpid_core::firewall".to_owned()),
+                true,
+                tx,
+                |record| record.message,
+            ));
+            let mut messages = vec![];
+            while let Some(message) = rx.recv().await {
+                messages.push(message);
+            }
+            messages
+        });
+        assert_eq!(messages, vec!["old", "new"]);
+    }
+
+    /// Logs a multiline message with a JSON formatter and returns the output.
+    fn log_json_record() -> Vec<String> {
+        let formatter = Formatter {
+            output_timestamp: false,
+            output_color: false,
+            format: LogFormat::Json,
+        };
+        let output = std::sync::Arc::new(Mutex::new(vec![]));
+        let output_clone = output.clone();
+        let (_, logger) = fern::Dispatch::new()
+            .format(move |out, message, record| formatter.output_msg(out, message, record))
+            .chain(Output::call(move |record| {
+                output_clone.lock().push(record.args().to_string())
+            }))
+            .into_log();
+
+        *TUNNEL_CONTEXT.write() = Some(TunnelContext {
+            state: "disconnected",
+            endpoint: None,
+        });
+        logger.log(
+            &log::Record::builder()
+                .level(log::Level::Info)
+                .target("mullvad_daemon")
+                .args(format_args!("first line\nsecond line"))
+                .build(),
+        );
+        let output = output.lock().clone();
+        output
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_json_record() {
+        assert_eq!(
+            log_json_record(),
+            vec![concat!(
+                r#"{"level":"INFO","target":"mullvad_daemon","message":"first line\nsecond line","#,
+                r#""tunnel":{"state":"disconnected"}}"#
+            )]
+        );
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_json_record() {
+        assert_eq!(
+            log_json_record(),
+            vec![concat!(
+                r#"{"level":"INFO","target":"mullvad_daemon","#,
+                r#""message":"first line\r\nsecond line","tunnel":{"state":"disconnected"}}"#
+            )]
+        );
+    }
+}
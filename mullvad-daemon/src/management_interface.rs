@@ -1,4 +1,7 @@
-use crate::{account_history, settings, DaemonCommand, DaemonCommandSender, EventListener};
+use crate::{
+    account_history, logging, settings, version_check, DaemonCommand, DaemonCommandSender,
+    EventListener,
+};
 use futures::channel::oneshot;
 use mullvad_management_interface::{
     types::{self, daemon_event, management_service_server::ManagementService},
@@ -9,11 +12,15 @@ use mullvad_rpc::{rest::Error as RestError, StatusCode};
 #[cfg(not(target_os = "android"))]
 use mullvad_types::settings::DnsOptions;
 use mullvad_types::{
-    account::AccountToken,
+    access_method::{self, ApiAccessMethod},
+    account::{AccountExpiryEvent, AccountToken},
+    network_profile::{self, NetworkProfile},
     relay_constraints::{BridgeSettings, BridgeState, RelaySettingsUpdate},
     relay_list::RelayList,
+    retry_schedule::RetrySchedule,
     settings::Settings,
     states::{TargetState, TunnelState},
+    trusted_network::{self, TrustedNetwork},
     version,
     wireguard::{RotationInterval, RotationIntervalError},
 };
@@ -26,7 +33,10 @@ use std::{
     sync::{mpsc, Arc},
     time::Duration,
 };
-use talpid_types::ErrorExt;
+use talpid_types::{
+    net::{openvpn, Connectivity, ConnectivityProbe},
+    ErrorExt,
+};
 use tokio_stream::wrappers::{ReceiverStream, UnboundedReceiverStream};
 
 #[derive(err_derive::Error, Debug)]
@@ -49,10 +59,14 @@ type EventsListenerSender = tokio::sync::mpsc::UnboundedSender<Result<types::Dae
 const INVALID_VOUCHER_MESSAGE: &str = "This voucher code is invalid";
 const USED_VOUCHER_MESSAGE: &str = "This voucher code has already been used";
 
+/// Number of log records buffered for each log stream client.
+const LOG_STREAM_BUFFER_SIZE: usize = 64;
+
 #[mullvad_management_interface::async_trait]
 impl ManagementService for ManagementServiceImpl {
     type GetRelayLocationsStream = ReceiverStream<Result<types::RelayListCountry, Status>>;
     type GetSplitTunnelProcessesStream = UnboundedReceiverStream<Result<i32, Status>>;
+    type StreamLogsStream = ReceiverStream<Result<types::LogRecord, Status>>;
     type EventsListenStream = EventsListenerReceiver;
 
     // Control and get the tunnel state
@@ -92,6 +106,74 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(types::TunnelState::from(state)))
     }
 
+    async fn get_connectivity(&self, _: Request<()>) -> ServiceResult<types::Connectivity> {
+        log::debug!("get_connectivity");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetConnectivity(tx))?;
+        let connectivity = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::Connectivity::from(connectivity)))
+    }
+
+    async fn set_connectivity_probe(&self, request: Request<String>) -> ServiceResult<()> {
+        let probe = request.into_inner();
+        log::debug!("set_connectivity_probe({})", probe);
+        let probe = if probe.is_empty() {
+            None
+        } else {
+            let probe = probe
+                .parse::<ConnectivityProbe>()
+                .map_err(|error| Status::invalid_argument(error.to_string()))?;
+            Some(probe)
+        };
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetConnectivityProbe(tx, probe))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
+    async fn captive_portal_login(
+        &self,
+        request: Request<types::CaptivePortalLogin>,
+    ) -> ServiceResult<bool> {
+        #[cfg(not(any(target_os = "android", windows)))]
+        {
+            let request = request.into_inner();
+            let duration = request
+                .duration
+                .ok_or_else(|| Status::invalid_argument("missing login duration"))?;
+            let duration = Duration::try_from(duration)
+                .map_err(|_| Status::invalid_argument("unexpected negative login duration"))?;
+            if duration == Duration::ZERO {
+                return Err(Status::invalid_argument("login duration must not be zero"));
+            }
+            let login = talpid_core::tunnel_state_machine::CaptivePortalLogin {
+                duration,
+                #[cfg(target_os = "linux")]
+                browser_cgroup: request.browser_cgroup,
+            };
+
+            log::debug!("captive_portal_login({:?})", login);
+            let (tx, rx) = oneshot::channel();
+            self.send_command_to_daemon(DaemonCommand::CaptivePortalLogin(tx, login))?;
+            let login_issued = self.wait_for_result(rx).await?;
+            Ok(Response::new(login_issued))
+        }
+        #[cfg(target_os = "android")]
+        {
+            let _ = request;
+            Ok(Response::new(false))
+        }
+        #[cfg(windows)]
+        {
+            let _ = request;
+            Err(Status::unimplemented(
+                "captive portal login is not supported on Windows",
+            ))
+        }
+    }
+
     // Control the daemon and receive events
     //
 
@@ -247,6 +329,241 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_settings_error)
     }
 
+    async fn get_retry_schedule(&self, _: Request<()>) -> ServiceResult<types::RetrySchedule> {
+        log::debug!("get_retry_schedule");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetRetrySchedule(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|schedule| Response::new(types::RetrySchedule::from(schedule)))
+            .ok_or_else(|| Status::not_found("no retry schedule is used for custom relays"))
+    }
+
+    async fn set_retry_schedule(
+        &self,
+        request: Request<types::RetrySchedule>,
+    ) -> ServiceResult<()> {
+        let retry_schedule = RetrySchedule::try_from(request.into_inner())?;
+        log::debug!("set_retry_schedule({:?})", retry_schedule);
+        let retry_schedule = if retry_schedule.attempts.is_empty() {
+            None
+        } else {
+            Some(retry_schedule)
+        };
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetRetrySchedule(tx, retry_schedule))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
+    // API access methods
+    //
+
+    async fn add_api_access_method(
+        &self,
+        request: Request<types::ApiAccessMethod>,
+    ) -> ServiceResult<()> {
+        let method = ApiAccessMethod::try_from(request.into_inner())?;
+
+        log::debug!("add_api_access_method({:?})", method);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::AddApiAccessMethod(tx, method))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn remove_api_access_method(&self, request: Request<String>) -> ServiceResult<()> {
+        let name = request.into_inner();
+
+        log::debug!("remove_api_access_method({})", name);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::RemoveApiAccessMethod(tx, name))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn set_api_access_method_enabled(
+        &self,
+        request: Request<types::ApiAccessMethodToggle>,
+    ) -> ServiceResult<()> {
+        let toggle = request.into_inner();
+
+        log::debug!(
+            "set_api_access_method_enabled({}, {})",
+            toggle.name,
+            toggle.enabled
+        );
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetApiAccessMethodEnabled(
+            tx,
+            toggle.name,
+            toggle.enabled,
+        ))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn get_current_api_access_method(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::ApiAccessMethod> {
+        log::debug!("get_current_api_access_method");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetCurrentApiAccessMethod(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|method| Response::new(types::ApiAccessMethod::from(&method)))
+            .ok_or_else(|| Status::not_found("no API access method is in use"))
+    }
+
+    async fn get_api_status(&self, _: Request<()>) -> ServiceResult<types::ApiStatus> {
+        log::debug!("get_api_status");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetApiStatus(tx))?;
+        self.wait_for_result(rx)
+            .await
+            .map(|status| Response::new(types::ApiStatus::from(status)))
+    }
+
+    // Trusted networks
+    //
+
+    async fn add_trusted_network(
+        &self,
+        request: Request<types::TrustedNetwork>,
+    ) -> ServiceResult<()> {
+        let network = TrustedNetwork::try_from(request.into_inner())?;
+
+        log::debug!("add_trusted_network({:?})", network);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::AddTrustedNetwork(tx, network))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn remove_trusted_network(
+        &self,
+        request: Request<types::TrustedNetwork>,
+    ) -> ServiceResult<()> {
+        let network = TrustedNetwork::try_from(request.into_inner())?;
+
+        log::debug!("remove_trusted_network({:?})", network);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::RemoveTrustedNetwork(tx, network))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn get_connected_networks(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::ConnectedNetworks> {
+        log::debug!("get_connected_networks");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetConnectedNetworks(tx))?;
+        let networks = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::ConnectedNetworks {
+            networks: networks
+                .into_iter()
+                .map(types::ConnectedNetwork::from)
+                .collect(),
+        }))
+    }
+
+    // Network profiles
+    //
+
+    async fn add_network_profile(
+        &self,
+        request: Request<types::NetworkProfile>,
+    ) -> ServiceResult<()> {
+        let profile = NetworkProfile::try_from(request.into_inner())?;
+
+        log::debug!("add_network_profile({:?})", profile);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::AddNetworkProfile(tx, profile))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn update_network_profile(
+        &self,
+        request: Request<types::NetworkProfile>,
+    ) -> ServiceResult<()> {
+        let profile = NetworkProfile::try_from(request.into_inner())?;
+
+        log::debug!("update_network_profile({:?})", profile);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::UpdateNetworkProfile(tx, profile))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn update_network_profile_relay_settings(
+        &self,
+        request: Request<types::NetworkProfileRelaySettingsUpdate>,
+    ) -> ServiceResult<()> {
+        let request = request.into_inner();
+        let update = RelaySettingsUpdate::try_from(
+            request
+                .update
+                .ok_or_else(|| Status::invalid_argument("missing relay settings update"))?,
+        )?;
+
+        log::debug!("update_network_profile_relay_settings({})", request.name);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::UpdateNetworkProfileRelaySettings(
+            tx,
+            request.name,
+            update,
+        ))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn remove_network_profile(&self, request: Request<String>) -> ServiceResult<()> {
+        let name = request.into_inner();
+
+        log::debug!("remove_network_profile({})", name);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::RemoveNetworkProfile(tx, name))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn get_active_network_profile(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::ActiveNetworkProfile> {
+        log::debug!("get_active_network_profile");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetActiveNetworkProfile(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|active| Response::new(types::ActiveNetworkProfile::from(active)))
+            .ok_or_else(|| Status::not_found("no network profile applies"))
+    }
+
     // Settings
     //
 
@@ -281,6 +598,30 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_settings_error)
     }
 
+    async fn set_update_channel(
+        &self,
+        request: Request<types::UpdateChannel>,
+    ) -> ServiceResult<()> {
+        let channel = request.into_inner();
+        log::debug!("set_update_channel({})", channel.source);
+        let channel = if channel.source.is_empty() {
+            None
+        } else {
+            Some(version::UpdateChannelSettings {
+                source: channel.source,
+                public_key: channel.public_key,
+            })
+        };
+        version_check::UpdateChannel::parse(channel.as_ref())
+            .map_err(|error| Status::invalid_argument(error.to_string()))?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetUpdateChannel(tx, channel))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
     async fn set_block_when_disconnected(&self, request: Request<bool>) -> ServiceResult<()> {
         let block_when_disconnected = request.into_inner();
         log::debug!("set_block_when_disconnected({})", block_when_disconnected);
@@ -322,6 +663,34 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_settings_error)
     }
 
+    async fn set_openvpn_cipher(
+        &self,
+        request: Request<types::OpenvpnCipher>,
+    ) -> ServiceResult<()> {
+        let cipher = Option::<openvpn::Cipher>::try_from(request.into_inner())?;
+        log::debug!("set_openvpn_cipher({:?})", cipher);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetOpenVpnCipher(tx, cipher))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
+    async fn set_openvpn_tls_version_min(
+        &self,
+        request: Request<types::OpenvpnTlsVersion>,
+    ) -> ServiceResult<()> {
+        let version = openvpn::TlsVersion::try_from(request.into_inner())?;
+        log::debug!("set_openvpn_tls_version_min({})", version);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetOpenVpnTlsVersionMin(tx, version))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
     async fn set_wireguard_mtu(&self, request: Request<u32>) -> ServiceResult<()> {
         let mtu = request.into_inner();
         let mtu = if mtu != 0 { Some(mtu as u16) } else { None };
@@ -417,6 +786,37 @@ impl ManagementService for ManagementServiceImpl {
             })
     }
 
+    async fn get_account_expiry(&self, _: Request<()>) -> ServiceResult<types::AccountData> {
+        log::debug!("get_account_expiry");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetAccountExpiry(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|expiry| {
+                Response::new(types::AccountData {
+                    expiry: Some(types::Timestamp {
+                        seconds: expiry.timestamp(),
+                        nanos: 0,
+                    }),
+                })
+            })
+            .ok_or_else(|| Status::not_found("no account expiry is known"))
+    }
+
+    async fn set_expiry_notification_hours(
+        &self,
+        request: Request<types::ExpiryNotificationHours>,
+    ) -> ServiceResult<()> {
+        let hours = request.into_inner().hours;
+        log::debug!("set_expiry_notification_hours({:?})", hours);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetExpiryNotificationHours(tx, hours))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
     async fn get_account_history(&self, _: Request<()>) -> ServiceResult<types::AccountHistory> {
         log::debug!("get_account_history");
         let (tx, rx) = oneshot::channel();
@@ -544,6 +944,19 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_daemon_error)
     }
 
+    async fn get_wireguard_key_rotation_history(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::KeyRotationHistory> {
+        log::debug!("get_wireguard_key_rotation_history");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetWireguardKeyRotationHistory(tx))?;
+        let history = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::KeyRotationHistory {
+            rotations: history.into_iter().map(types::KeyRotation::from).collect(),
+        }))
+    }
+
     // Split tunneling
     //
 
@@ -704,6 +1117,54 @@ impl ManagementService for ManagementServiceImpl {
     async fn set_use_wireguard_nt(&self, _: Request<bool>) -> ServiceResult<()> {
         Ok(Response::new(()))
     }
+
+    // Debugging
+    //
+
+    async fn set_log_level(&self, request: Request<types::LogLevelChange>) -> ServiceResult<()> {
+        let change = request.into_inner();
+        let level = types::LogLevel::from_i32(change.level)
+            .ok_or_else(|| Status::invalid_argument("unknown log level"))?;
+        let module = Some(change.module).filter(|module| !module.is_empty());
+        log::debug!("set_log_level({:?}, {:?})", level, module);
+
+        let timeout = change
+            .timeout
+            .map(Duration::try_from)
+            .transpose()
+            .map_err(|_| Status::invalid_argument("unexpected negative timeout"))?;
+
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetLogLevel(
+            tx,
+            log::LevelFilter::from(level),
+            module,
+            timeout,
+        ))?;
+        self.wait_for_result(rx).await.map(Response::new)
+    }
+
+    async fn stream_logs(
+        &self,
+        request: Request<types::LogStreamRequest>,
+    ) -> ServiceResult<Self::StreamLogsStream> {
+        log::debug!("stream_logs");
+        let request = request.into_inner();
+        let level = types::LogLevel::from_i32(request.level)
+            .map(log::LevelFilter::from)
+            .ok_or_else(|| Status::invalid_argument("unknown log level"))?;
+        let module = Some(request.module).filter(|module| !module.is_empty());
+
+        let subscription = logging::subscribe_to_logs();
+        let (tx, rx) = tokio::sync::mpsc::channel(LOG_STREAM_BUFFER_SIZE);
+        tokio::spawn(
+            subscription.forward(level, module, request.follow, tx, |record| {
+                Ok(convert_log_record(record))
+            }),
+        );
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
 }
 
 impl ManagementServiceImpl {
@@ -847,6 +1308,24 @@ impl EventListener for ManagementInterfaceEventBroadcaster {
             ))),
         })
     }
+
+    fn notify_connectivity(&self, connectivity: Connectivity) {
+        log::debug!("Broadcasting new connectivity");
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::Connectivity(
+                types::Connectivity::from(connectivity),
+            )),
+        })
+    }
+
+    fn notify_account_expiry(&self, event: AccountExpiryEvent) {
+        log::debug!("Broadcasting account expiry event");
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::AccountExpiry(
+                types::AccountExpiryEvent::from(event),
+            )),
+        })
+    }
 }
 
 impl ManagementInterfaceEventBroadcaster {
@@ -863,6 +1342,18 @@ impl Drop for ManagementInterfaceEventBroadcaster {
     }
 }
 
+fn convert_log_record(record: logging::LogRecord) -> types::LogRecord {
+    types::LogRecord {
+        timestamp: Some(types::Timestamp {
+            seconds: record.timestamp.timestamp(),
+            nanos: record.timestamp.timestamp_subsec_nanos() as i32,
+        }),
+        level: types::LogLevel::from(record.level) as i32,
+        module: record.module,
+        message: record.message,
+    }
+}
+
 /// Converts [`mullvad_daemon::Error`] into a tonic status.
 fn map_daemon_error(error: crate::Error) -> Status {
     use crate::Error as DaemonError;
@@ -873,6 +1364,9 @@ fn map_daemon_error(error: crate::Error) -> Status {
         #[cfg(windows)]
         DaemonError::SplitTunnelError(error) => map_split_tunnel_error(error),
         DaemonError::AccountHistory(error) => map_account_history_error(error),
+        DaemonError::AccessMethodError(error) => map_access_method_error(error),
+        DaemonError::TrustedNetworkError(error) => map_trusted_network_error(error),
+        DaemonError::NetworkProfileError(error) => map_network_profile_error(error),
         DaemonError::NoAccountToken | DaemonError::NoAccountTokenHistory => {
             Status::unauthenticated(error.to_string())
         }
@@ -942,6 +1436,34 @@ fn map_settings_error(error: settings::Error) -> Status {
     }
 }
 
+/// Converts an instance of [`mullvad_types::trusted_network::Error`] into a tonic status.
+fn map_trusted_network_error(error: trusted_network::Error) -> Status {
+    match error {
+        trusted_network::Error::AlreadyTrusted(..) => Status::already_exists(error.to_string()),
+        trusted_network::Error::NotFound(..) => Status::not_found(error.to_string()),
+    }
+}
+
+/// Converts an instance of [`mullvad_types::network_profile::Error`] into a tonic status.
+fn map_network_profile_error(error: network_profile::Error) -> Status {
+    match error {
+        network_profile::Error::DuplicateName(..) => Status::already_exists(error.to_string()),
+        network_profile::Error::NotFound(..) => Status::not_found(error.to_string()),
+    }
+}
+
+/// Converts an instance of [`mullvad_types::access_method::Error`] into a tonic status.
+fn map_access_method_error(error: access_method::Error) -> Status {
+    match error {
+        access_method::Error::NotFound(..) => Status::not_found(error.to_string()),
+        access_method::Error::DuplicateName(..) => Status::already_exists(error.to_string()),
+        access_method::Error::AddBuiltIn => Status::invalid_argument(error.to_string()),
+        access_method::Error::RemoveBuiltIn(..) | access_method::Error::NoneEnabled => {
+            Status::failed_precondition(error.to_string())
+        }
+    }
+}
+
 /// Converts an instance of [`mullvad_daemon::account_history::Error`] into a tonic status.
 fn map_account_history_error(error: account_history::Error) -> Status {
     match error {
@@ -9,15 +9,20 @@ use futures::{
 };
 use ipnetwork::IpNetwork;
 use log::{debug, error, info, warn};
-use mullvad_rpc::{availability::ApiAvailabilityHandle, rest::MullvadRestHandle, RelayListProxy};
+use mullvad_rpc::{
+    availability::ApiAvailabilityHandle, rest::MullvadRestHandle, signature, RelayListProxy,
+    SignedRelayList,
+};
 use mullvad_types::{
     endpoint::MullvadEndpoint,
-    location::Location,
+    location::{GeoIpLocation, Location},
     relay_constraints::{
         BridgeState, Constraint, InternalBridgeConstraints, LocationConstraint, Match,
-        OpenVpnConstraints, Providers, RelayConstraints, Set, TransportPort, WireguardConstraints,
+        OpenVpnConstraints, Providers, RelayConstraints, SelectedObfuscation, Set, TransportPort,
+        WireguardConstraints,
     },
     relay_list::{OpenVpnEndpointData, Relay, RelayList, RelayTunnels, WireguardEndpointData},
+    retry_schedule::RetrySchedule,
 };
 use parking_lot::Mutex;
 use rand::{self, rngs::ThreadRng, seq::SliceRandom, Rng};
@@ -32,12 +37,11 @@ use std::{
 use talpid_core::future_retry::{retry_future, ExponentialBackoff, Jittered};
 use talpid_types::{
     net::{
-        all_of_the_internet, openvpn::ProxySettings, wireguard, IpVersion, TransportProtocol,
-        TunnelType,
+        all_of_the_internet, obfuscation::ObfuscationType, openvpn::ProxySettings, wireguard,
+        Endpoint, IpVersion, TransportProtocol, TunnelType,
     },
     ErrorExt,
 };
-use tokio::fs::File;
 
 const DATE_TIME_FORMAT_STR: &str = "%Y-%m-%d %H:%M:%S%.3f";
 const RELAYS_FILENAME: &str = "relays.json";
@@ -59,8 +63,9 @@ const WIREGUARD_EXIT_CONSTRAINTS: WireguardConstraints = WireguardConstraints {
     }),
     ip_version: Constraint::Only(IpVersion::V4),
     entry_location: None,
+    automatic_entry: None,
+    obfuscation: SelectedObfuscation::Off,
 };
-const WIREGUARD_TCP_PORTS: [(u16, u16); 3] = [(80, 80), (443, 443), (5001, 5001)];
 
 
 #[derive(err_derive::Error, Debug)]
@@ -78,6 +83,9 @@ pub enum Error {
     #[error(display = "Failure in serialization of the relay list")]
     Serialize(#[error(source)] serde_json::Error),
 
+    #[error(display = "Relay list is not signed or its signature is invalid")]
+    VerifySignature(#[error(source)] signature::Error),
+
     #[error(display = "Downloader already shut down")]
     DownloaderShutDown,
 }
@@ -118,15 +126,25 @@ impl ParsedRelays {
                         longitude,
                     });
 
-                    for wg_tunnel in &relay.tunnels.wireguard {
-                        relay_with_location
-                            .tunnels
-                            .wireguard
-                            .push(WireguardEndpointData {
-                                protocol: TransportProtocol::Tcp,
-                                port_ranges: WIREGUARD_TCP_PORTS.to_vec(),
-                                ..wg_tunnel.clone()
-                            });
+                    // WireGuard endpoints can also be reached through the obfuscators
+                    let udp2tcp_ports: Vec<(u16, u16)> = relay
+                        .obfuscators
+                        .udp2tcp
+                        .iter()
+                        .map(|endpoint| (endpoint.port, endpoint.port))
+                        .collect();
+                    if !udp2tcp_ports.is_empty() {
+                        for wg_tunnel in &relay.tunnels.wireguard {
+                            relay_with_location
+                                .tunnels
+                                .wireguard
+                                .push(WireguardEndpointData {
+                                    protocol: ObfuscationType::Udp2Tcp.transport_protocol(),
+                                    port_ranges: udp2tcp_ports.clone(),
+                                    obfuscation: Some(ObfuscationType::Udp2Tcp),
+                                    ..wg_tunnel.clone()
+                                });
+                        }
                     }
 
                     relays.push(relay_with_location);
@@ -140,20 +158,21 @@ impl ParsedRelays {
         }
     }
 
+    /// Reads a relay list from `path`. In builds that require signed relay lists, the signature
+    /// must be stored next to it.
     pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
-        debug!("Reading relays from {}", path.as_ref().display());
-        let (last_modified, file) =
-            Self::open_file(path.as_ref()).map_err(Error::OpenRelayCache)?;
-        let relay_list =
-            serde_json::from_reader(io::BufReader::new(file)).map_err(Error::Serialize)?;
+        let path = path.as_ref();
+        debug!("Reading relays from {}", path.display());
+        let signed_relay_list =
+            SignedRelayList::read_from_file(path).map_err(Error::OpenRelayCache)?;
+        let last_modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(Error::OpenRelayCache)?;
 
-        Ok(Self::from_relay_list(relay_list, last_modified))
-    }
+        signed_relay_list.verify().map_err(Error::VerifySignature)?;
+        let relay_list = signed_relay_list.parse().map_err(Error::Serialize)?;
 
-    fn open_file(path: &Path) -> io::Result<(SystemTime, std::fs::File)> {
-        let file = std::fs::File::open(path)?;
-        let last_modified = file.metadata()?.modified()?;
-        Ok((last_modified, file))
+        Ok(Self::from_relay_list(relay_list, last_modified))
     }
 
     pub fn last_updated(&self) -> SystemTime {
@@ -177,6 +196,8 @@ pub struct RelaySelector {
     parsed_relays: Arc<Mutex<ParsedRelays>>,
     rng: ThreadRng,
     updater: Option<RelayListUpdaterHandle>,
+    retry_schedule: Option<RetrySchedule>,
+    user_location: Option<GeoIpLocation>,
 }
 
 impl RelaySelector {
@@ -220,6 +241,8 @@ impl RelaySelector {
             parsed_relays,
             rng: rand::thread_rng(),
             updater: Some(updater),
+            retry_schedule: None,
+            user_location: None,
         }
     }
 
@@ -234,6 +257,17 @@ impl RelaySelector {
         }
     }
 
+    /// Overrides the default retry schedules. `None` restores them.
+    pub fn set_retry_schedule(&mut self, retry_schedule: Option<RetrySchedule>) {
+        self.retry_schedule = retry_schedule;
+    }
+
+    /// Sets the location of the user, as seen from outside the tunnel. It is used to select
+    /// automatic multihop entry relays.
+    pub fn set_user_location(&mut self, user_location: GeoIpLocation) {
+        self.user_location = Some(user_location);
+    }
+
     pub fn updater_handle(&self) -> RelayListUpdaterHandle {
         self.updater.as_ref().unwrap().clone()
     }
@@ -257,7 +291,12 @@ impl RelaySelector {
         let wg_entry_is_subset = if let Some(entry_location) =
             exit_relay_constraints.wireguard_constraints.entry_location
         {
-            let is_subset = entry_location.is_subset(&exit_relay_constraints.location);
+            // An automatic entry depends on the exit relay, so the exit is selected first
+            let is_subset = exit_relay_constraints
+                .wireguard_constraints
+                .automatic_entry
+                .is_none()
+                && entry_location.is_subset(&exit_relay_constraints.location);
             exit_relay_constraints.wireguard_constraints = WireguardConstraints {
                 entry_location: Some(entry_location),
                 ..WIREGUARD_EXIT_CONSTRAINTS
@@ -299,8 +338,8 @@ impl RelaySelector {
                     .entry_location
                     .is_some()
             {
-                if let MullvadEndpoint::Wireguard { peer, .. } = &endpoint {
-                    self.select_entry_endpoint(Some(peer), &relay_constraints, retry_attempt)
+                if let MullvadEndpoint::Wireguard { .. } = &endpoint {
+                    self.select_entry_endpoint(Some(&exit_relay), &relay_constraints, retry_attempt)
                 } else {
                     None
                 }
@@ -373,23 +412,42 @@ impl RelaySelector {
         retry_attempt: u32,
         wg_key_exists: bool,
     ) -> RelayConstraints {
-        let (preferred_port, preferred_protocol, preferred_tunnel) =
-            if bridge_state != BridgeState::On {
-                self.preferred_tunnel_constraints(
-                    retry_attempt,
-                    &original_constraints.location,
-                    &original_constraints.providers,
-                    wg_key_exists,
-                )
+        let schedule = self.retry_schedule(original_constraints, wg_key_exists);
+        let attempt = *schedule
+            .attempt(retry_attempt)
+            .expect("Retry schedules are never empty");
+        // WireGuard is only relayed through bridges if it has been selected explicitly
+        let preferred_tunnel =
+            if bridge_state == BridgeState::On && original_constraints.tunnel_protocol.is_any() {
+                TunnelType::OpenVpn
             } else {
-                (Constraint::Any, TransportProtocol::Tcp, TunnelType::OpenVpn)
+                attempt.tunnel_type
             };
+        let preferred_port = if bridge_state == BridgeState::On {
+            // Bridged OpenVPN tunnels must use TCP, while WireGuard is relayed over UDP
+            let protocol = match preferred_tunnel {
+                TunnelType::OpenVpn => TransportProtocol::Tcp,
+                TunnelType::Wireguard => TransportProtocol::Udp,
+            };
+            Constraint::Only(TransportPort {
+                protocol,
+                port: Constraint::Any,
+            })
+        } else {
+            attempt.port
+        };
 
+        // The preferred port is not used if the WireGuard obfuscation setting rules it out
+        let wireguard_port_allowed = |constraints: &WireguardConstraints| {
+            preferred_port
+                .map(|port| constraints.obfuscation.allows_transport(port.protocol))
+                .unwrap_or(true)
+        };
 
         let mut relay_constraints = original_constraints.clone();
         relay_constraints.openvpn_constraints = Default::default();
 
-        // Highest priority preference. Where we prefer OpenVPN using UDP. But without changing
+        // Highest priority preference. Where we follow the retry schedule, but without changing
         // any constraints that are explicitly specified.
         match original_constraints.tunnel_protocol {
             // If no tunnel protocol is selected, use preferred constraints
@@ -398,22 +456,18 @@ impl RelaySelector {
                     || bridge_state == BridgeState::On
                 {
                     relay_constraints.openvpn_constraints = OpenVpnConstraints {
-                        port: Constraint::Only(TransportPort {
-                            protocol: preferred_protocol,
-                            port: preferred_port,
-                        }),
+                        port: preferred_port,
+                        ..original_constraints.openvpn_constraints
                     };
                 } else {
                     relay_constraints.openvpn_constraints =
                         original_constraints.openvpn_constraints;
                 }
 
-                if relay_constraints.wireguard_constraints.port.is_any() {
-                    relay_constraints.wireguard_constraints.port =
-                        Constraint::Only(TransportPort {
-                            protocol: preferred_protocol,
-                            port: preferred_port,
-                        });
+                if relay_constraints.wireguard_constraints.port.is_any()
+                    && wireguard_port_allowed(&relay_constraints.wireguard_constraints)
+                {
+                    relay_constraints.wireguard_constraints.port = preferred_port;
                 }
 
                 relay_constraints.tunnel_protocol = Constraint::Only(preferred_tunnel);
@@ -421,33 +475,17 @@ impl RelaySelector {
             Constraint::Only(TunnelType::OpenVpn) => {
                 let openvpn_constraints = &mut relay_constraints.openvpn_constraints;
                 *openvpn_constraints = original_constraints.openvpn_constraints;
-                if bridge_state == BridgeState::On && openvpn_constraints.port.is_any() {
-                    // FIXME: This is temporary while talpid-core only supports TCP proxies
-                    openvpn_constraints.port = Constraint::Only(TransportPort {
-                        protocol: TransportProtocol::Tcp,
-                        port: Constraint::Any,
-                    });
-                } else if openvpn_constraints.port.is_any() {
-                    let (preferred_port, preferred_protocol) =
-                        Self::preferred_openvpn_constraints(retry_attempt);
-                    openvpn_constraints.port = Constraint::Only(TransportPort {
-                        protocol: preferred_protocol,
-                        port: preferred_port,
-                    });
+                if openvpn_constraints.port.is_any() {
+                    openvpn_constraints.port = preferred_port;
                 }
             }
             Constraint::Only(TunnelType::Wireguard) => {
                 relay_constraints.wireguard_constraints =
                     original_constraints.wireguard_constraints.clone();
-                // This ensures that if after the first 2 failed attempts the daemon does not
-                // connect, then afterwards 2 of each 4 successive attempts will try to connect
-                // on port 53.
-                if retry_attempt % 4 > 1 && relay_constraints.wireguard_constraints.port.is_any() {
-                    relay_constraints.wireguard_constraints.port =
-                        Constraint::Only(TransportPort {
-                            protocol: TransportProtocol::Udp,
-                            port: Constraint::Only(53),
-                        });
+                if relay_constraints.wireguard_constraints.port.is_any()
+                    && wireguard_port_allowed(&relay_constraints.wireguard_constraints)
+                {
+                    relay_constraints.wireguard_constraints.port = preferred_port;
                 }
             }
         }
@@ -455,9 +493,11 @@ impl RelaySelector {
         relay_constraints
     }
 
+    /// Selects a WireGuard entry relay for the given exit relay, or for any exit relay if it has
+    /// not been selected yet.
     fn select_entry_endpoint(
         &mut self,
-        exit_peer: Option<&wireguard::PeerConfig>,
+        exit_relay: Option<&Relay>,
         relay_constraints: &RelayConstraints,
         retry_attempt: u32,
     ) -> Option<(Relay, MullvadEndpoint)> {
@@ -473,22 +513,80 @@ impl RelaySelector {
         let entry_constraints =
             self.preferred_constraints(&entry_constraints, BridgeState::Off, retry_attempt, true);
 
-        let matching_relays: Vec<Relay> = self
+        let mut matching_relays: Vec<Relay> = self
             .parsed_relays
             .lock()
             .relays()
             .iter()
             .filter(|relay| relay.active)
-            .filter_map(|relay| Self::matching_relay(relay, &entry_constraints, exit_peer))
+            .filter(|relay| {
+                exit_relay
+                    .map(|exit_relay| exit_relay.hostname != relay.hostname)
+                    .unwrap_or(true)
+            })
+            .filter_map(|relay| Self::matching_relay(relay, &entry_constraints, None))
             .collect();
 
-        let relay = self
-            .pick_random_relay(&matching_relays)
-            .map(|relay| relay.clone())?;
+        let relay = match relay_constraints.wireguard_constraints.automatic_entry {
+            Some(automatic_entry) => {
+                if let Some(exit_relay) = exit_relay {
+                    matching_relays.retain(|relay| automatic_entry.allows(relay, exit_relay));
+                }
+                match self.closest_relay(&matching_relays) {
+                    Some(relay) => relay.clone(),
+                    None => {
+                        debug!("Location of the user is unknown. Selecting a random entry relay");
+                        self.pick_random_relay(&matching_relays)?.clone()
+                    }
+                }
+            }
+            None => self
+                .pick_random_relay(&matching_relays)
+                .map(|relay| relay.clone())?,
+        };
         let endpoint = self.get_random_tunnel(&relay, &entry_constraints)?;
         Some((relay, endpoint))
     }
 
+    /// Selects a WireGuard entry relay for an OpenVPN tunnel to `exit_relay`. The entry peer only
+    /// routes traffic to the OpenVPN relay.
+    pub fn get_openvpn_entry_endpoint(
+        &mut self,
+        relay_constraints: &RelayConstraints,
+        exit_relay: &Relay,
+        exit_endpoint: Endpoint,
+        retry_attempt: u32,
+    ) -> Result<(Relay, MullvadEndpoint), Error> {
+        let mut entry_constraints = relay_constraints.clone();
+        if entry_constraints
+            .wireguard_constraints
+            .entry_location
+            .is_none()
+        {
+            entry_constraints.wireguard_constraints.entry_location = Some(Constraint::Any);
+        }
+        let (entry_relay, mut entry_endpoint) = self
+            .select_entry_endpoint(Some(exit_relay), &entry_constraints, retry_attempt)
+            .ok_or(Error::NoRelay)?;
+        if let MullvadEndpoint::Wireguard { peer, .. } = &mut entry_endpoint {
+            peer.allowed_ips = vec![IpNetwork::from(exit_endpoint.address.ip())];
+        }
+        info!(
+            "Selected entry relay {} at {}",
+            entry_relay.hostname,
+            entry_endpoint.to_endpoint().address.ip()
+        );
+        Ok((entry_relay, entry_endpoint))
+    }
+
+    /// Returns the relay closest to the user, or `None` if the location of the user is unknown.
+    fn closest_relay<'a>(&self, relays: &'a [Relay]) -> Option<&'a Relay> {
+        let user_location = self.user_location.as_ref()?;
+        relays.iter().min_by_key(|relay| {
+            (user_location.distance_from(relay.location.as_ref().unwrap()) * 1000.0) as i64
+        })
+    }
+
     fn set_entry_peers(
         &mut self,
         new_exit_peer: &wireguard::PeerConfig,
@@ -505,32 +603,18 @@ impl RelaySelector {
         }
     }
 
-    pub fn get_auto_proxy_settings(
-        &mut self,
-        bridge_constraints: &InternalBridgeConstraints,
-        location: &Location,
+    /// Returns whether a bridge should be used for the given attempt, if the bridge state is set
+    /// to auto.
+    pub fn should_use_bridge(
+        &self,
+        relay_constraints: &RelayConstraints,
         retry_attempt: u32,
-    ) -> Option<(ProxySettings, Relay)> {
-        if !self.should_use_bridge(retry_attempt) {
-            return None;
-        }
-
-        // For now, only TCP tunnels are supported.
-        if let Constraint::Only(TransportProtocol::Udp) = bridge_constraints.transport_protocol {
-            return None;
-        }
-
-        self.get_proxy_settings(bridge_constraints, location)
-    }
-
-    pub fn should_use_bridge(&self, retry_attempt: u32) -> bool {
-        // shouldn't use a bridge for the first 3 times
-        retry_attempt > 3 &&
-            // i.e. 4th and 5th with bridge, 6th & 7th without
-            // The test is to see whether the current _couple of connections_ is even or not.
-            // | retry_attempt                | 4 | 5 | 6 | 7 | 8 | 9 |
-            // | (retry_attempt % 4) < 2      | t | t | f | f | t | t |
-            (retry_attempt % 4) < 2
+        wg_key_exists: bool,
+    ) -> bool {
+        self.retry_schedule(relay_constraints, wg_key_exists)
+            .attempt(retry_attempt)
+            .map(|attempt| attempt.bridge)
+            .unwrap_or(false)
     }
 
     pub fn get_proxy_settings(
@@ -560,80 +644,92 @@ impl RelaySelector {
         })
     }
 
-    /// Returns preferred constraints
-    #[allow(unused_variables)]
-    fn preferred_tunnel_constraints(
+    /// Returns a random Shadowsocks bridge, which can be used to reach the API when it is
+    /// blocked.
+    #[cfg(not(target_os = "android"))]
+    pub fn get_api_bridge(&mut self) -> Option<ProxySettings> {
+        let bridge_relays: Vec<Relay> = self
+            .parsed_relays
+            .lock()
+            .relays()
+            .iter()
+            .filter(|relay| relay.active && !relay.bridges.shadowsocks.is_empty())
+            .cloned()
+            .collect();
+        let relay = bridge_relays.choose(&mut self.rng)?.clone();
+        self.pick_random_bridge(&relay)
+    }
+
+    /// Returns the retry schedule to follow for the given constraints. A custom schedule is
+    /// limited to the tunnel protocols that can be used, and the default schedule is used if
+    /// none of its attempts can be.
+    pub fn retry_schedule(
         &self,
-        retry_attempt: u32,
-        location_constraint: &Constraint<LocationConstraint>,
-        providers_constraint: &Constraint<Providers>,
+        relay_constraints: &RelayConstraints,
         wg_key_exists: bool,
-    ) -> (Constraint<u16>, TransportProtocol, TunnelType) {
-        #[cfg(target_os = "windows")]
-        {
-            let location_supports_openvpn =
-                self.parsed_relays.lock().relays().iter().any(|relay| {
-                    relay.active
-                        && !relay.tunnels.openvpn.is_empty()
-                        && location_constraint.matches(relay)
-                        && providers_constraint.matches(relay)
-                });
-            if location_supports_openvpn {
-                let (preferred_port, preferred_protocol) =
-                    Self::preferred_openvpn_constraints(retry_attempt);
-                return (preferred_port, preferred_protocol, TunnelType::OpenVpn);
-            }
+    ) -> RetrySchedule {
+        let wireguard_usable = wg_key_exists
+            && self.location_supports_wireguard(
+                &relay_constraints.location,
+                &relay_constraints.providers,
+            );
+        let custom_schedule = self
+            .retry_schedule
+            .as_ref()
+            .filter(|schedule| !schedule.attempts.is_empty())
+            .and_then(|schedule| match relay_constraints.tunnel_protocol {
+                Constraint::Only(tunnel_type) => schedule.only_tunnel_type(tunnel_type),
+                Constraint::Any if wireguard_usable => Some(schedule.clone()),
+                Constraint::Any => schedule.only_tunnel_type(TunnelType::OpenVpn),
+            });
+        if let Some(schedule) = custom_schedule {
+            return schedule;
         }
-
-        let location_supports_wireguard = self.parsed_relays.lock().relays().iter().any(|relay| {
-            relay.active
-                && !relay.tunnels.wireguard.is_empty()
-                && location_constraint.matches(relay)
-                && providers_constraint.matches(relay)
-        });
-        // If location does not support WireGuard, defer to preferred OpenVPN tunnel
-        // constraints
-        if !location_supports_wireguard || !wg_key_exists {
-            let (preferred_port, preferred_protocol) =
-                Self::preferred_openvpn_constraints(retry_attempt);
-            return (preferred_port, preferred_protocol, TunnelType::OpenVpn);
+        if self.retry_schedule.is_some() {
+            warn!("No attempt in the retry schedule can be used. Using the default schedule");
         }
 
-        // Try out WireGuard in the first two connection attempts, first with any port,
-        // afterwards on port 53. Afterwards, connect through OpenVPN alternating between UDP
-        // on any port twice and TCP on port 443 once.
-        match retry_attempt {
-            0 => (
-                Constraint::Any,
-                TransportProtocol::Udp,
-                TunnelType::Wireguard,
-            ),
-            1 => (
-                Constraint::Only(53),
-                TransportProtocol::Udp,
-                TunnelType::Wireguard,
-            ),
-            _ => {
-                let (preferred_port, preferred_protocol) =
-                    Self::preferred_openvpn_constraints(retry_attempt - 2);
-                (preferred_port, preferred_protocol, TunnelType::OpenVpn)
+        match relay_constraints.tunnel_protocol {
+            Constraint::Only(TunnelType::OpenVpn) => RetrySchedule::default_openvpn(),
+            Constraint::Only(TunnelType::Wireguard) => RetrySchedule::default_wireguard(),
+            Constraint::Any => {
+                #[cfg(target_os = "windows")]
+                {
+                    let location_supports_openvpn =
+                        self.parsed_relays.lock().relays().iter().any(|relay| {
+                            relay.active
+                                && !relay.tunnels.openvpn.is_empty()
+                                && relay_constraints.location.matches(relay)
+                                && relay_constraints.providers.matches(relay)
+                        });
+                    if location_supports_openvpn {
+                        return RetrySchedule::default_openvpn();
+                    }
+                }
+
+                // If location does not support WireGuard, defer to the OpenVPN schedule
+                if wireguard_usable {
+                    RetrySchedule::default_wireguard_and_openvpn()
+                } else {
+                    RetrySchedule::default_openvpn()
+                }
             }
         }
     }
 
-    fn preferred_openvpn_constraints(retry_attempt: u32) -> (Constraint<u16>, TransportProtocol) {
-        // Prefer UDP by default. But if that has failed a couple of times, then try TCP port
-        // 443, which works for many with UDP problems. After that, just alternate
-        // between protocols.
-        match retry_attempt {
-            0 | 1 => (Constraint::Any, TransportProtocol::Udp),
-            2 | 3 => (Constraint::Only(443), TransportProtocol::Tcp),
-            attempt if attempt % 2 == 0 => (Constraint::Any, TransportProtocol::Udp),
-            _ => (Constraint::Any, TransportProtocol::Tcp),
-        }
+    fn location_supports_wireguard(
+        &self,
+        location_constraint: &Constraint<LocationConstraint>,
+        providers_constraint: &Constraint<Providers>,
+    ) -> bool {
+        self.parsed_relays.lock().relays().iter().any(|relay| {
+            relay.active
+                && !relay.tunnels.wireguard.is_empty()
+                && location_constraint.matches(relay)
+                && providers_constraint.matches(relay)
+        })
     }
 
-
     /// Returns a random relay endpoint if any is matching the given constraints.
     fn get_tunnel_endpoint_internal(
         &mut self,
@@ -696,7 +792,7 @@ impl RelaySelector {
                         vec![]
                     },
                     openvpn: Self::matching_openvpn_tunnels(
-                        &relay.tunnels,
+                        &relay,
                         constraints.openvpn_constraints,
                     ),
                 };
@@ -722,7 +818,7 @@ impl RelaySelector {
                 let mut relay = relay.clone();
                 relay.tunnels = RelayTunnels {
                     openvpn: Self::matching_openvpn_tunnels(
-                        &relay.tunnels,
+                        &relay,
                         constraints.openvpn_constraints,
                     ),
                     wireguard: vec![],
@@ -771,10 +867,14 @@ impl RelaySelector {
     }
 
     fn matching_openvpn_tunnels(
-        tunnels: &RelayTunnels,
+        relay: &Relay,
         constraints: OpenVpnConstraints,
     ) -> Vec<OpenVpnEndpointData> {
-        tunnels
+        if !constraints.cipher.matches(relay) {
+            return vec![];
+        }
+        relay
+            .tunnels
             .openvpn
             .iter()
             .filter(|endpoint| constraints.matches(*endpoint))
@@ -892,10 +992,7 @@ impl RelaySelector {
             public_key: data.public_key,
             endpoint: SocketAddr::new(host, port),
             allowed_ips: all_of_the_internet(),
-            protocol: constraints
-                .port
-                .map(|port| port.protocol)
-                .unwrap_or(TransportProtocol::Udp),
+            protocol: data.protocol,
         };
         Some(MullvadEndpoint::Wireguard {
             peer: peer_config,
@@ -1092,12 +1189,15 @@ impl RelayListUpdater {
 
     async fn consume_new_relay_list(
         &mut self,
-        result: Result<Option<RelayList>, mullvad_rpc::Error>,
+        result: Result<Option<SignedRelayList>, mullvad_rpc::Error>,
     ) {
         match result {
-            Ok(Some(relay_list)) => {
-                if let Err(err) = self.update_cache(relay_list).await {
-                    log::error!("Failed to update relay list cache: {}", err);
+            Ok(Some(signed_relay_list)) => {
+                if let Err(err) = self.update_cache(signed_relay_list).await {
+                    log::error!(
+                        "{}",
+                        err.display_chain_with_msg("Rejecting downloaded relay list")
+                    );
                 }
             }
             Ok(None) => log::debug!("Relay list is up-to-date"),
@@ -1129,7 +1229,7 @@ impl RelayListUpdater {
         api_handle: ApiAvailabilityHandle,
         rpc_handle: RelayListProxy,
         tag: Option<String>,
-    ) -> impl Future<Output = Result<Option<RelayList>, mullvad_rpc::Error>> + 'static {
+    ) -> impl Future<Output = Result<Option<SignedRelayList>, mullvad_rpc::Error>> + 'static {
         let download_futures = move || {
             let available = api_handle.wait_background();
             let req = rpc_handle.relay_list(tag.clone());
@@ -1151,8 +1251,11 @@ impl RelayListUpdater {
         download_future
     }
 
-    async fn update_cache(&mut self, new_relay_list: RelayList) -> Result<(), Error> {
-        if let Err(error) = Self::cache_relays(&self.cache_path, &new_relay_list).await {
+    async fn update_cache(&mut self, signed_relay_list: SignedRelayList) -> Result<(), Error> {
+        signed_relay_list.verify().map_err(Error::VerifySignature)?;
+        let new_relay_list = signed_relay_list.parse().map_err(Error::Serialize)?;
+
+        if let Err(error) = Self::cache_relays(self.cache_path.clone(), signed_relay_list).await {
             error!(
                 "{}",
                 error.display_chain_with_msg("Failed to update relay cache on disk")
@@ -1171,18 +1274,13 @@ impl RelayListUpdater {
         Ok(())
     }
 
-    /// Write a `RelayList` to the cache file.
-    async fn cache_relays(cache_path: &Path, relays: &RelayList) -> Result<(), Error> {
+    /// Write a signed relay list, its signature and its entity tag to the cache.
+    async fn cache_relays(cache_path: PathBuf, relays: SignedRelayList) -> Result<(), Error> {
         debug!("Writing relays cache to {}", cache_path.display());
-        let mut file = File::create(cache_path)
-            .await
-            .map_err(Error::OpenRelayCache)?;
-        let bytes = serde_json::to_vec_pretty(relays).map_err(Error::Serialize)?;
-        let mut slice: &[u8] = bytes.as_slice();
-        let _ = tokio::io::copy(&mut slice, &mut file)
+        tokio::task::spawn_blocking(move || relays.write_to_file(&cache_path))
             .await
-            .map_err(Error::WriteRelayCache)?;
-        Ok(())
+            .expect("Relay cache writer panicked")
+            .map_err(Error::WriteRelayCache)
     }
 }
 
@@ -1191,13 +1289,14 @@ impl RelayListUpdater {
 mod test {
     use super::*;
     use mullvad_types::{
-        relay_constraints::RelayConstraints,
+        relay_constraints::{AutomaticEntry, RelayConstraints},
         relay_list::{
-            Relay, RelayBridges, RelayListCity, RelayListCountry, RelayTunnels,
-            WireguardEndpointData,
+            Relay, RelayBridges, RelayListCity, RelayListCountry, RelayObfuscators, RelayTunnels,
+            Udp2TcpEndpointData, WireguardEndpointData,
         },
+        retry_schedule::RetryAttempt,
     };
-    use talpid_types::net::wireguard::PublicKey;
+    use talpid_types::net::{openvpn::Cipher, wireguard::PublicKey};
 
     lazy_static::lazy_static! {
         static ref RELAYS: RelayList = RelayList {
@@ -1231,12 +1330,17 @@ mod test {
                                                 ipv6_gateway: "fc00:bbbb:bbbb:bb01::1".parse().unwrap(),
                                                 public_key: PublicKey::from_base64("BLNHNoGO88LjV/wDBa7CUUwUzPq/fO2UwcGLy56hKy4=").unwrap(),
                                                 protocol: TransportProtocol::Udp,
+                                                obfuscation: None,
                                             },
                                         ],
                                     },
                                     bridges: RelayBridges {
                                         shadowsocks: vec![],
                                     },
+                                    obfuscators: RelayObfuscators {
+                                        udp2tcp: vec![Udp2TcpEndpointData { port: 443 }],
+                                    },
+                                    openvpn_ciphers: vec![],
                                     location: None,
                                 },
                                 Relay {
@@ -1257,12 +1361,17 @@ mod test {
                                                 ipv6_gateway: "fc00:bbbb:bbbb:bb01::1".parse().unwrap(),
                                                 public_key: PublicKey::from_base64("veGD6/aEY6sMfN3Ls7YWPmNgu3AheO7nQqsFT47YSws=").unwrap(),
                                                 protocol: TransportProtocol::Udp,
+                                                obfuscation: None,
                                             },
                                         ],
                                     },
                                     bridges: RelayBridges {
                                         shadowsocks: vec![],
                                     },
+                                    obfuscators: RelayObfuscators {
+                                        udp2tcp: vec![Udp2TcpEndpointData { port: 443 }],
+                                    },
+                                    openvpn_ciphers: vec![],
                                     location: None,
                                 },
                                 Relay {
@@ -1294,6 +1403,8 @@ mod test {
                                     bridges: RelayBridges {
                                         shadowsocks: vec![],
                                     },
+                                    obfuscators: RelayObfuscators::default(),
+                                    openvpn_ciphers: vec![Cipher::Aes256Gcm],
                                     location: None,
                                 },
                             ],
@@ -1312,6 +1423,8 @@ mod test {
             ))),
             rng: rand::thread_rng(),
             updater: None,
+            retry_schedule: None,
+            user_location: None,
         }
     }
 
@@ -1398,6 +1511,148 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_bridged_tunnel_constraints() {
+        let relay_selector = new_relay_selector();
+
+        // WireGuard is relayed over UDP through bridges
+        let relay_constraints = RelayConstraints {
+            location: Constraint::Only(LocationConstraint::Hostname(
+                "se".to_string(),
+                "got".to_string(),
+                "se9-wireguard".to_string(),
+            )),
+            tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+            ..RelayConstraints::default()
+        };
+        for attempt in 0..10 {
+            let preferred = relay_selector.preferred_constraints(
+                &relay_constraints,
+                BridgeState::On,
+                attempt,
+                true,
+            );
+            assert_eq!(
+                preferred.tunnel_protocol,
+                Constraint::Only(TunnelType::Wireguard)
+            );
+            assert_eq!(
+                preferred.wireguard_constraints.port,
+                Constraint::Only(TransportPort {
+                    protocol: TransportProtocol::Udp,
+                    port: Constraint::Any,
+                })
+            );
+        }
+
+        // WireGuard is only bridged if it has been selected explicitly. Bridged OpenVPN tunnels
+        // must use TCP
+        let relay_constraints = RelayConstraints {
+            location: Constraint::Only(LocationConstraint::Country("se".to_string())),
+            tunnel_protocol: Constraint::Any,
+            ..RelayConstraints::default()
+        };
+        for attempt in 0..10 {
+            let preferred = relay_selector.preferred_constraints(
+                &relay_constraints,
+                BridgeState::On,
+                attempt,
+                true,
+            );
+            assert_eq!(
+                preferred.tunnel_protocol,
+                Constraint::Only(TunnelType::OpenVpn)
+            );
+            assert_eq!(
+                preferred.openvpn_constraints.port,
+                Constraint::Only(TransportPort {
+                    protocol: TransportProtocol::Tcp,
+                    port: Constraint::Any,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_custom_retry_schedule() {
+        let mut relay_selector = new_relay_selector();
+        let openvpn_attempt = RetryAttempt {
+            tunnel_type: TunnelType::OpenVpn,
+            port: Constraint::Only(TransportPort {
+                protocol: TransportProtocol::Tcp,
+                port: Constraint::Only(443),
+            }),
+            bridge: true,
+        };
+        let wireguard_attempt = RetryAttempt {
+            tunnel_type: TunnelType::Wireguard,
+            port: Constraint::Only(TransportPort {
+                protocol: TransportProtocol::Udp,
+                port: Constraint::Only(53),
+            }),
+            bridge: false,
+        };
+        relay_selector.set_retry_schedule(Some(RetrySchedule {
+            attempts: vec![openvpn_attempt, wireguard_attempt],
+            repeat_from: 0,
+        }));
+
+        let relay_constraints = RelayConstraints::default();
+        for attempt in 0..4 {
+            let expected = if attempt % 2 == 0 {
+                openvpn_attempt
+            } else {
+                wireguard_attempt
+            };
+            let preferred = relay_selector.preferred_constraints(
+                &relay_constraints,
+                BridgeState::Off,
+                attempt,
+                true,
+            );
+            assert_eq!(
+                preferred.tunnel_protocol,
+                Constraint::Only(expected.tunnel_type)
+            );
+            assert_eq!(preferred.wireguard_constraints.port, expected.port);
+            assert_eq!(
+                relay_selector.should_use_bridge(&relay_constraints, attempt, true),
+                expected.bridge
+            );
+        }
+
+        // Only attempts with an allowed tunnel protocol are used
+        let relay_constraints = RelayConstraints {
+            tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+            ..RelayConstraints::default()
+        };
+        for attempt in 0..4 {
+            let preferred = relay_selector.preferred_constraints(
+                &relay_constraints,
+                BridgeState::Off,
+                attempt,
+                true,
+            );
+            assert_eq!(preferred.wireguard_constraints.port, wireguard_attempt.port);
+        }
+
+        // Without a key, the WireGuard attempts are skipped
+        let relay_constraints = RelayConstraints::default();
+        for attempt in 0..4 {
+            let preferred = relay_selector.preferred_constraints(
+                &relay_constraints,
+                BridgeState::Off,
+                attempt,
+                false,
+            );
+            assert_eq!(
+                preferred.tunnel_protocol,
+                Constraint::Only(TunnelType::OpenVpn)
+            );
+            assert_eq!(preferred.openvpn_constraints.port, openvpn_attempt.port);
+        }
+    }
+
     #[test]
     fn test_wg_entry_hostname_collision() {
         let mut relay_selector = new_relay_selector();
@@ -1434,6 +1689,101 @@ mod test {
             .is_ok());
     }
 
+    #[test]
+    fn test_automatic_wg_entry() {
+        let mut relay_selector = new_relay_selector();
+        relay_selector.set_user_location(GeoIpLocation {
+            ipv4: None,
+            ipv6: None,
+            country: "Sweden".to_string(),
+            city: Some("Gothenburg".to_string()),
+            latitude: 57.70887,
+            longitude: 11.97456,
+            mullvad_exit_ip: false,
+            hostname: None,
+            bridge_hostname: None,
+            entry_hostname: None,
+        });
+
+        let mut relay_constraints = RelayConstraints {
+            location: Constraint::Only(LocationConstraint::Hostname(
+                "se".to_string(),
+                "got".to_string(),
+                "se9-wireguard".to_string(),
+            )),
+            tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+            ..RelayConstraints::default()
+        };
+        relay_constraints.wireguard_constraints.entry_location = Some(Constraint::Any);
+        relay_constraints.wireguard_constraints.automatic_entry = Some(AutomaticEntry::default());
+
+        // The only other WireGuard relay is used as the entry
+        let (exit_relay, endpoint) = relay_selector
+            .get_tunnel_endpoint(&relay_constraints, BridgeState::Off, 0, true)
+            .expect("Failed to select an automatic entry");
+        assert_eq!(exit_relay.hostname, "se9-wireguard");
+        match endpoint {
+            MullvadEndpoint::Wireguard { peer, .. } => {
+                assert_eq!(
+                    peer.endpoint.ip(),
+                    "185.213.154.69".parse::<IpAddr>().unwrap()
+                )
+            }
+            _ => panic!("WireGuard endpoint was not selected"),
+        }
+
+        // All relays are in the same country and run by the same provider
+        relay_constraints.wireguard_constraints.automatic_entry = Some(AutomaticEntry {
+            different_country: true,
+            different_provider: false,
+        });
+        assert!(relay_selector
+            .get_tunnel_endpoint(&relay_constraints, BridgeState::Off, 0, true)
+            .is_err());
+
+        relay_constraints.wireguard_constraints.automatic_entry = Some(AutomaticEntry {
+            different_country: false,
+            different_provider: true,
+        });
+        assert!(relay_selector
+            .get_tunnel_endpoint(&relay_constraints, BridgeState::Off, 0, true)
+            .is_err());
+    }
+
+    #[test]
+    fn test_automatic_wg_entry_without_user_location() {
+        let mut relay_selector = new_relay_selector();
+
+        let mut relay_constraints = RelayConstraints {
+            location: Constraint::Only(LocationConstraint::Hostname(
+                "se".to_string(),
+                "got".to_string(),
+                "se9-wireguard".to_string(),
+            )),
+            tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+            ..RelayConstraints::default()
+        };
+        relay_constraints.wireguard_constraints.entry_location = Some(Constraint::Any);
+        relay_constraints.wireguard_constraints.automatic_entry = Some(AutomaticEntry::default());
+
+        // A random entry that is distinct from the exit is selected until the location is known
+        for attempt in 0..10 {
+            let (exit_relay, endpoint) = relay_selector
+                .get_tunnel_endpoint(&relay_constraints, BridgeState::Off, attempt, true)
+                .expect("Failed to select an automatic entry");
+            assert_eq!(exit_relay.hostname, "se9-wireguard");
+            match endpoint {
+                MullvadEndpoint::Wireguard { peer, .. } => {
+                    assert_eq!(
+                        peer.endpoint.ip(),
+                        "185.213.154.69".parse::<IpAddr>().unwrap()
+                    )
+                }
+                _ => panic!("WireGuard endpoint was not selected"),
+            }
+        }
+    }
+
     #[test]
     fn test_wg_entry_filter() -> Result<(), String> {
         let mut relay_selector = new_relay_selector();
@@ -1486,4 +1836,94 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_openvpn_wireguard_entry() {
+        let mut relay_selector = new_relay_selector();
+
+        let mut relay_constraints = RelayConstraints {
+            tunnel_protocol: Constraint::Only(TunnelType::OpenVpn),
+            ..RelayConstraints::default()
+        };
+        relay_constraints.openvpn_constraints.wireguard_entry = true;
+
+        let (exit_relay, exit_endpoint) = relay_selector
+            .get_tunnel_endpoint(&relay_constraints, BridgeState::Off, 0, false)
+            .expect("Failed to select an OpenVPN relay");
+        let exit_endpoint = exit_endpoint.to_endpoint();
+
+        let (entry_relay, entry_endpoint) = relay_selector
+            .get_openvpn_entry_endpoint(&relay_constraints, &exit_relay, exit_endpoint, 0)
+            .expect("Failed to select a WireGuard entry relay");
+        assert_ne!(entry_relay.hostname, exit_relay.hostname);
+        match entry_endpoint {
+            MullvadEndpoint::Wireguard {
+                peer, exit_peer, ..
+            } => {
+                assert!(exit_peer.is_none());
+                assert_eq!(entry_relay.ipv4_addr_in, peer.endpoint.ip());
+                assert_eq!(
+                    peer.allowed_ips,
+                    vec![IpNetwork::from(exit_endpoint.address.ip())]
+                );
+            }
+            MullvadEndpoint::OpenVpn(_) => panic!("WireGuard endpoint was not selected"),
+        }
+    }
+
+    #[test]
+    fn test_openvpn_cipher() {
+        let mut relay_selector = new_relay_selector();
+
+        let mut relay_constraints = RelayConstraints {
+            tunnel_protocol: Constraint::Only(TunnelType::OpenVpn),
+            ..RelayConstraints::default()
+        };
+
+        relay_constraints.openvpn_constraints.cipher = Constraint::Only(Cipher::Aes256Gcm);
+        let (relay, _) = relay_selector
+            .get_tunnel_endpoint(&relay_constraints, BridgeState::Off, 0, false)
+            .expect("Failed to select a relay supporting the cipher");
+        assert_eq!(relay.hostname, "se-got-001");
+
+        relay_constraints.openvpn_constraints.cipher = Constraint::Only(Cipher::Chacha20Poly1305);
+        relay_selector
+            .get_tunnel_endpoint(&relay_constraints, BridgeState::Off, 0, false)
+            .expect_err("Selected a relay that does not support the cipher");
+    }
+
+    #[test]
+    fn test_wireguard_obfuscation() {
+        let mut relay_selector = new_relay_selector();
+
+        let mut relay_constraints = RelayConstraints {
+            tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+            ..RelayConstraints::default()
+        };
+        relay_constraints.wireguard_constraints.obfuscation = SelectedObfuscation::Udp2Tcp;
+
+        // Every attempt uses the UDP-over-TCP servers advertised by the relays
+        for attempt in 0..10 {
+            let (_relay, endpoint) = relay_selector
+                .get_tunnel_endpoint(&relay_constraints, BridgeState::Off, attempt, true)
+                .expect("Failed to select an obfuscated relay");
+            match endpoint {
+                MullvadEndpoint::Wireguard { peer, .. } => {
+                    assert_eq!(peer.protocol, TransportProtocol::Tcp);
+                    assert_eq!(peer.endpoint.port(), 443);
+                }
+                MullvadEndpoint::OpenVpn(_) => panic!("WireGuard endpoint was not selected"),
+            }
+        }
+
+        // Obfuscation cannot be turned off while requiring TCP
+        relay_constraints.wireguard_constraints.obfuscation = SelectedObfuscation::Off;
+        relay_constraints.wireguard_constraints.port = Constraint::Only(TransportPort {
+            protocol: TransportProtocol::Tcp,
+            port: Constraint::Any,
+        });
+        assert!(relay_selector
+            .get_tunnel_endpoint(&relay_constraints, BridgeState::Off, 0, true)
+            .is_err());
+    }
 }
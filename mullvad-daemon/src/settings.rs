@@ -1,8 +1,12 @@
 #[cfg(not(target_os = "android"))]
 use futures::TryFutureExt;
 use mullvad_types::{
+    access_method, network_profile,
     relay_constraints::{BridgeSettings, BridgeState, RelaySettingsUpdate},
+    retry_schedule::RetrySchedule,
     settings::{DnsOptions, Settings},
+    trusted_network,
+    version::UpdateChannelSettings,
     wireguard::{RotationInterval, WireguardData},
 };
 #[cfg(target_os = "windows")]
@@ -11,7 +15,10 @@ use std::{
     ops::Deref,
     path::{Path, PathBuf},
 };
-use talpid_types::ErrorExt;
+use talpid_types::{
+    net::{openvpn, ConnectivityProbe},
+    ErrorExt,
+};
 use tokio::{
     fs,
     io::{self, AsyncWriteExt},
@@ -190,6 +197,19 @@ impl SettingsPersister {
         self.update(should_save).await
     }
 
+    pub async fn set_wireguard_pending(
+        &mut self,
+        wireguard: Option<WireguardData>,
+    ) -> Result<bool, Error> {
+        let should_save = self.settings.set_wireguard_pending(wireguard);
+        self.update(should_save).await
+    }
+
+    pub async fn set_wireguard_pending_attempts(&mut self, attempts: u32) -> Result<bool, Error> {
+        let should_save = self.settings.set_wireguard_pending_attempts(attempts);
+        self.update(should_save).await
+    }
+
     pub async fn update_relay_settings(
         &mut self,
         update: RelaySettingsUpdate,
@@ -227,6 +247,28 @@ impl SettingsPersister {
         self.update(should_save).await
     }
 
+    pub async fn set_openvpn_cipher(
+        &mut self,
+        openvpn_cipher: Option<openvpn::Cipher>,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(
+            &mut self.settings.tunnel_options.openvpn.cipher,
+            openvpn_cipher,
+        );
+        self.update(should_save).await
+    }
+
+    pub async fn set_openvpn_tls_version_min(
+        &mut self,
+        tls_version_min: openvpn::TlsVersion,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(
+            &mut self.settings.tunnel_options.openvpn.tls_version_min,
+            tls_version_min,
+        );
+        self.update(should_save).await
+    }
+
     pub async fn set_enable_ipv6(&mut self, enable_ipv6: bool) -> Result<bool, Error> {
         let should_save = Self::update_field(
             &mut self.settings.tunnel_options.generic.enable_ipv6,
@@ -267,6 +309,24 @@ impl SettingsPersister {
         self.update(should_save).await
     }
 
+    pub async fn set_update_channel(
+        &mut self,
+        update_channel: Option<UpdateChannelSettings>,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.update_channel, update_channel);
+        self.update(should_save).await
+    }
+
+    pub async fn set_expiry_notification_hours(
+        &mut self,
+        mut hours: Vec<u32>,
+    ) -> Result<bool, Error> {
+        hours.sort_unstable_by(|a, b| b.cmp(a));
+        hours.dedup();
+        let should_save = Self::update_field(&mut self.settings.expiry_notification_hours, hours);
+        self.update(should_save).await
+    }
+
     pub async fn set_bridge_settings(
         &mut self,
         bridge_settings: BridgeSettings,
@@ -275,11 +335,53 @@ impl SettingsPersister {
         self.update(should_save).await
     }
 
+    /// Overrides the default retry schedule. An empty schedule restores the default.
+    pub async fn set_retry_schedule(
+        &mut self,
+        retry_schedule: Option<RetrySchedule>,
+    ) -> Result<bool, Error> {
+        let retry_schedule = retry_schedule.filter(|schedule| !schedule.attempts.is_empty());
+        let should_save = Self::update_field(&mut self.settings.retry_schedule, retry_schedule);
+        self.update(should_save).await
+    }
+
+    pub async fn set_connectivity_probe(
+        &mut self,
+        probe: Option<ConnectivityProbe>,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.connectivity_probe, probe);
+        self.update(should_save).await
+    }
+
     pub async fn set_bridge_state(&mut self, bridge_state: BridgeState) -> Result<bool, Error> {
         let should_save = self.settings.set_bridge_state(bridge_state);
         self.update(should_save).await
     }
 
+    pub async fn set_api_access_methods(
+        &mut self,
+        access_methods: access_method::Settings,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.api_access_methods, access_methods);
+        self.update(should_save).await
+    }
+
+    pub async fn set_trusted_networks(
+        &mut self,
+        trusted_networks: trusted_network::Settings,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.trusted_networks, trusted_networks);
+        self.update(should_save).await
+    }
+
+    pub async fn set_network_profiles(
+        &mut self,
+        network_profiles: network_profile::Settings,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.network_profiles, network_profiles);
+        self.update(should_save).await
+    }
+
     #[cfg(windows)]
     pub async fn set_split_tunnel_apps(&mut self, paths: HashSet<PathBuf>) -> Result<bool, Error> {
         let should_save = paths != self.settings.split_tunnel.apps;
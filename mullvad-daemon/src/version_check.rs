@@ -7,13 +7,19 @@ use futures::{
     stream::FusedStream,
     FutureExt, SinkExt, StreamExt, TryFutureExt,
 };
-use mullvad_rpc::{availability::ApiAvailabilityHandle, rest::MullvadRestHandle, AppVersionProxy};
-use mullvad_types::version::{AppVersionInfo, ParsedAppVersion};
+use mullvad_rpc::{
+    availability::ApiAvailabilityHandle,
+    rest::{self, MullvadRestHandle},
+    signature, AppVersionProxy, AppVersionResponse,
+};
+use mullvad_types::version::{AppVersionInfo, ParsedAppVersion, UpdateChannelSettings};
 use serde::{Deserialize, Serialize};
 use std::{
+    fmt,
     future::Future,
     io,
     path::{Path, PathBuf},
+    pin::Pin,
     time::{Duration, Instant},
 };
 use talpid_core::mpsc::Sender;
@@ -56,17 +62,120 @@ struct CachedAppVersionInfo {
     #[serde(flatten)]
     pub version_info: AppVersionInfo,
     pub cached_from_version: String,
+    /// The alternate update channel that the version info was fetched from, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_channel: Option<String>,
 }
 
-impl From<AppVersionInfo> for CachedAppVersionInfo {
-    fn from(version_info: AppVersionInfo) -> CachedAppVersionInfo {
+impl CachedAppVersionInfo {
+    fn new(version_info: AppVersionInfo, channel: &UpdateChannel) -> CachedAppVersionInfo {
         CachedAppVersionInfo {
             version_info,
             cached_from_version: PRODUCT_VERSION.to_owned(),
+            update_channel: channel.cache_key(),
+        }
+    }
+}
+
+/// Where update metadata is fetched from.
+#[derive(Debug, Clone)]
+pub enum UpdateChannel {
+    /// The Mullvad API.
+    Api,
+    /// A self-hosted channel serving metadata with the same schema as the API. The metadata must
+    /// have a detached signature, stored next to it with `.sig` appended, made with the key
+    /// matching `public_key`.
+    Custom {
+        source: UpdateSource,
+        public_key: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum UpdateSource {
+    Url(String),
+    File(PathBuf),
+}
+
+impl fmt::Display for UpdateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateSource::Url(url) => url.fmt(f),
+            UpdateSource::File(path) => path.display().fmt(f),
+        }
+    }
+}
+
+impl UpdateChannel {
+    /// Returns the update channel described by `settings`. The API is used if no channel is set,
+    /// or if the channel cannot be verified because its key is missing or invalid.
+    pub fn from_settings(settings: Option<&UpdateChannelSettings>) -> Self {
+        Self::parse(settings).unwrap_or_else(|error| {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Ignoring the update channel")
+            );
+            UpdateChannel::Api
+        })
+    }
+
+    /// Parses the source and public key of an update channel. `None` selects the API.
+    pub fn parse(settings: Option<&UpdateChannelSettings>) -> Result<Self, Error> {
+        let settings = match settings {
+            Some(settings) => settings,
+            None => return Ok(UpdateChannel::Api),
+        };
+        let source =
+            if settings.source.starts_with("https://") || settings.source.starts_with("http://") {
+                UpdateSource::Url(settings.source.clone())
+            } else {
+                UpdateSource::File(PathBuf::from(&settings.source))
+            };
+        let public_key = settings.public_key.trim();
+        if public_key.is_empty() {
+            return Err(Error::MissingUpdateChannelKey);
+        }
+        let public_key = base64::decode(public_key).map_err(Error::InvalidUpdateChannelKey)?;
+        Ok(UpdateChannel::Custom { source, public_key })
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        match self {
+            UpdateChannel::Api => None,
+            UpdateChannel::Custom { source, .. } => Some(source.to_string()),
         }
     }
 }
 
+/// Fetches update metadata from a self-hosted channel and verifies its signature. The channel is
+/// not part of the API, so it is reached directly rather than through the API access methods.
+async fn fetch_from_channel(
+    source: UpdateSource,
+    public_key: Vec<u8>,
+) -> Result<AppVersionResponse, Error> {
+    let (data, signature) = match &source {
+        UpdateSource::Url(url) => {
+            let data = rest::fetch_external(url, DOWNLOAD_TIMEOUT)
+                .await
+                .map_err(Error::Download)?;
+            let signature = rest::fetch_external(&format!("{}.sig", url), DOWNLOAD_TIMEOUT)
+                .await
+                .map_err(Error::Download)?;
+            (data, String::from_utf8_lossy(&signature).into_owned())
+        }
+        UpdateSource::File(path) => {
+            let data = fs::read(path).await.map_err(Error::ReadUpdateChannel)?;
+            let signature = fs::read_to_string(signature::signature_path(path))
+                .await
+                .map_err(Error::ReadUpdateChannel)?;
+            (data, signature)
+        }
+    };
+    signature::verify_with_key(&public_key, &data, Some(&signature))
+        .map_err(Error::VerifyUpdateChannel)?;
+    serde_json::from_slice(&data).map_err(Error::Deserialize)
+}
+
 #[derive(err_derive::Error, Debug)]
 #[error(no_from)]
 pub enum Error {
@@ -91,6 +200,21 @@ pub enum Error {
     #[error(display = "Clearing version check cache due to a version mismatch")]
     CacheVersionMismatch,
 
+    #[error(display = "Clearing version check cache due to an update channel mismatch")]
+    CacheChannelMismatch,
+
+    #[error(display = "An update channel is set, but no key to verify it with")]
+    MissingUpdateChannelKey,
+
+    #[error(display = "The update channel key is not valid base64")]
+    InvalidUpdateChannelKey(#[error(source)] base64::DecodeError),
+
+    #[error(display = "Failed to read version info from the update channel")]
+    ReadUpdateChannel(#[error(source)] io::Error),
+
+    #[error(display = "Failed to verify version info from the update channel")]
+    VerifyUpdateChannel(#[error(source)] signature::Error),
+
     #[error(display = "Version updater is down")]
     VersionUpdaterDown,
 
@@ -99,8 +223,11 @@ pub enum Error {
 }
 
 
+type VersionCheckFuture = Pin<Box<dyn Future<Output = Result<AppVersionResponse, Error>> + Send>>;
+
 pub(crate) struct VersionUpdater {
     version_proxy: AppVersionProxy,
+    channel: UpdateChannel,
     cache_path: PathBuf,
     update_sender: DaemonEventSender<AppVersionInfo>,
     last_app_version_info: Option<AppVersionInfo>,
@@ -119,6 +246,7 @@ pub(crate) struct VersionUpdaterHandle {
 
 enum VersionUpdaterCommand {
     SetShowBetaReleases(bool),
+    SetUpdateChannel(UpdateChannel),
     RunVersionCheck(oneshot::Sender<AppVersionInfo>),
 }
 
@@ -136,6 +264,17 @@ impl VersionUpdaterHandle {
         }
     }
 
+    pub async fn set_update_channel(&mut self, channel: UpdateChannel) {
+        if self
+            .tx
+            .send(VersionUpdaterCommand::SetUpdateChannel(channel))
+            .await
+            .is_err()
+        {
+            log::error!("Version updater already down, can't send new update channel");
+        }
+    }
+
     pub async fn run_version_check(&mut self) -> Result<AppVersionInfo, Error> {
         let (done_tx, done_rx) = oneshot::channel();
         if self
@@ -159,6 +298,7 @@ impl VersionUpdater {
         update_sender: DaemonEventSender<AppVersionInfo>,
         last_app_version_info: Option<AppVersionInfo>,
         show_beta_releases: bool,
+        channel: UpdateChannel,
     ) -> (Self, VersionUpdaterHandle) {
         rpc_handle.factory.timeout = DOWNLOAD_TIMEOUT;
         let version_proxy = AppVersionProxy::new(rpc_handle);
@@ -169,6 +309,7 @@ impl VersionUpdater {
         (
             Self {
                 version_proxy,
+                channel,
                 cache_path,
                 update_sender,
                 last_app_version_info,
@@ -183,6 +324,29 @@ impl VersionUpdater {
         )
     }
 
+    /// Returns a function that fetches the latest version info from the update channel.
+    fn version_check_factory(&self) -> impl Fn() -> VersionCheckFuture + Send + 'static {
+        let version_proxy = self.version_proxy.clone();
+        let channel = self.channel.clone();
+        let platform_version = self.platform_version.clone();
+        move || -> VersionCheckFuture {
+            match &channel {
+                UpdateChannel::Api => Box::pin(
+                    version_proxy
+                        .version_check(
+                            PRODUCT_VERSION.to_owned(),
+                            PLATFORM,
+                            platform_version.clone(),
+                        )
+                        .map_err(Error::Download),
+                ),
+                UpdateChannel::Custom { source, public_key } => {
+                    Box::pin(fetch_from_channel(source.clone(), public_key.clone()))
+                }
+            }
+        }
+    }
+
     fn create_update_future(
         &mut self,
         done_tx: oneshot::Sender<AppVersionInfo>,
@@ -192,17 +356,7 @@ impl VersionUpdater {
         self.internal_done_tx = Some(done_tx);
 
         let api_handle = self.availability_handle.clone();
-        let version_proxy = self.version_proxy.clone();
-        let platform_version = self.platform_version.clone();
-        let download_future_factory = move || {
-            version_proxy
-                .version_check(
-                    PRODUCT_VERSION.to_owned(),
-                    PLATFORM,
-                    platform_version.clone(),
-                )
-                .map_err(Error::Download)
-        };
+        let download_future_factory = self.version_check_factory();
 
         Box::pin(talpid_core::future_retry::retry_future_n(
             download_future_factory,
@@ -230,18 +384,13 @@ impl VersionUpdater {
         Box<dyn Future<Output = Result<mullvad_rpc::AppVersionResponse, Error>> + Send + 'static>,
     > {
         let api_handle = self.availability_handle.clone();
-        let version_proxy = self.version_proxy.clone();
-        let platform_version = self.platform_version.clone();
+        let version_check = self.version_check_factory();
         let download_future_factory = move || {
             let when_available = api_handle.wait_background();
-            let request = version_proxy.version_check(
-                PRODUCT_VERSION.to_owned(),
-                PLATFORM,
-                platform_version.clone(),
-            );
+            let request = version_check();
             async move {
                 when_available.await.map_err(Error::ApiCheck)?;
-                request.await.map_err(Error::Download)
+                request.await
             }
         };
 
@@ -267,7 +416,8 @@ impl VersionUpdater {
         let mut file = File::create(&self.cache_path)
             .await
             .map_err(Error::WriteVersionCache)?;
-        let cached_app_version = CachedAppVersionInfo::from(last_app_version_info.clone());
+        let cached_app_version =
+            CachedAppVersionInfo::new(last_app_version_info.clone(), &self.channel);
         let mut buf = serde_json::to_vec_pretty(&cached_app_version).map_err(Error::Serialize)?;
         let mut read_buf: &[u8] = buf.as_mut();
 
@@ -380,6 +530,14 @@ impl VersionUpdater {
                                 }).await;
                             }
                         }
+                        Some(VersionUpdaterCommand::SetUpdateChannel(channel)) => {
+                            self.channel = channel;
+                            // Discard any pending result from the previous channel and check the
+                            // new one on the next wakeup
+                            version_check = futures::future::Fuse::terminated();
+                            self.internal_done_tx = None;
+                            self.next_update_time = Instant::now();
+                        }
                         Some(VersionUpdaterCommand::RunVersionCheck(done_tx)) => {
                             if self.update_sender.is_closed() {
                                 return;
@@ -437,7 +595,10 @@ impl VersionUpdater {
     }
 }
 
-async fn try_load_cache(cache_dir: &Path) -> Result<AppVersionInfo, Error> {
+async fn try_load_cache(
+    cache_dir: &Path,
+    channel: &UpdateChannel,
+) -> Result<AppVersionInfo, Error> {
     let path = cache_dir.join(VERSION_INFO_FILENAME);
     log::debug!("Loading version check cache from {}", path.display());
     let content = fs::read_to_string(&path)
@@ -446,15 +607,17 @@ async fn try_load_cache(cache_dir: &Path) -> Result<AppVersionInfo, Error> {
     let version_info: CachedAppVersionInfo =
         serde_json::from_str(&content).map_err(Error::Deserialize)?;
 
-    if version_info.cached_from_version == PRODUCT_VERSION {
-        Ok(version_info.version_info)
-    } else {
+    if version_info.cached_from_version != PRODUCT_VERSION {
         Err(Error::CacheVersionMismatch)
+    } else if version_info.update_channel != channel.cache_key() {
+        Err(Error::CacheChannelMismatch)
+    } else {
+        Ok(version_info.version_info)
     }
 }
 
-pub async fn load_cache(cache_dir: &Path) -> Option<AppVersionInfo> {
-    match try_load_cache(cache_dir).await {
+pub async fn load_cache(cache_dir: &Path, channel: &UpdateChannel) -> Option<AppVersionInfo> {
+    match try_load_cache(cache_dir, channel).await {
         Ok(app_version_info) => Some(app_version_info),
         Err(error) => {
             log::warn!(
@@ -532,4 +695,122 @@ mod test {
             None
         );
     }
+
+    const TEST_SEED: [u8; 32] = [7; 32];
+    const TEST_RESPONSE: &str =
+        r#"{"supported":true,"latest":"2021.5","latest_stable":"2021.5","latest_beta":"2021.5"}"#;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_update_channel() {
+        let key = base64::encode(signature::public_key(&TEST_SEED).unwrap());
+        let settings = |source: &str, public_key: &str| UpdateChannelSettings {
+            source: source.to_owned(),
+            public_key: public_key.to_owned(),
+        };
+
+        assert!(matches!(UpdateChannel::parse(None), Ok(UpdateChannel::Api)));
+
+        assert!(matches!(
+            UpdateChannel::parse(Some(&settings("http://localhost:8080/version", &key))),
+            Ok(UpdateChannel::Custom { source: UpdateSource::Url(url), public_key })
+                if url == "http://localhost:8080/version" && base64::encode(&public_key) == key
+        ));
+
+        assert!(matches!(
+            UpdateChannel::parse(Some(&settings("/tmp/version.json", &key))),
+            Ok(UpdateChannel::Custom { source: UpdateSource::File(path), .. })
+                if path == Path::new("/tmp/version.json")
+        ));
+
+        let invalid_key = settings("/tmp/version.json", "not base64!");
+        assert!(matches!(
+            UpdateChannel::parse(Some(&invalid_key)),
+            Err(Error::InvalidUpdateChannelKey(_))
+        ));
+        assert!(matches!(
+            UpdateChannel::from_settings(Some(&invalid_key)),
+            UpdateChannel::Api
+        ));
+
+        assert!(matches!(
+            UpdateChannel::parse(Some(&settings("/tmp/version.json", ""))),
+            Err(Error::MissingUpdateChannelKey)
+        ));
+    }
+
+    #[test]
+    fn test_cache_is_invalidated_by_channel_change() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let channel = UpdateChannel::Custom {
+            source: UpdateSource::Url("https://example.com/version".to_owned()),
+            public_key: vec![],
+        };
+        let version_info = AppVersionInfo {
+            supported: true,
+            latest_stable: "2021.5".to_owned(),
+            latest_beta: "2021.5".to_owned(),
+            suggested_upgrade: None,
+        };
+        let cached_info = CachedAppVersionInfo::new(version_info.clone(), &channel);
+        std::fs::write(
+            cache_dir.path().join(VERSION_INFO_FILENAME),
+            serde_json::to_vec(&cached_info).unwrap(),
+        )
+        .unwrap();
+
+        let runtime = runtime();
+        assert_eq!(
+            runtime.block_on(load_cache(cache_dir.path(), &channel)),
+            Some(version_info)
+        );
+        assert!(matches!(
+            runtime.block_on(try_load_cache(cache_dir.path(), &UpdateChannel::Api)),
+            Err(Error::CacheChannelMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_channel_signature_is_verified() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("version.json");
+        let public_key = signature::public_key(&TEST_SEED).unwrap();
+        std::fs::write(&path, TEST_RESPONSE).unwrap();
+        std::fs::write(
+            signature::signature_path(&path),
+            signature::sign(&TEST_SEED, TEST_RESPONSE.as_bytes()).unwrap(),
+        )
+        .unwrap();
+
+        let runtime = runtime();
+        let fetch = |public_key: Vec<u8>| {
+            runtime.block_on(fetch_from_channel(
+                UpdateSource::File(path.clone()),
+                public_key,
+            ))
+        };
+
+        let response = fetch(public_key.clone()).unwrap();
+        assert_eq!(response.latest, "2021.5");
+        assert!(matches!(
+            fetch(signature::public_key(&[8; 32]).unwrap()),
+            Err(Error::VerifyUpdateChannel(
+                signature::Error::InvalidSignature
+            ))
+        ));
+
+        std::fs::write(&path, TEST_RESPONSE.replace("true", "false")).unwrap();
+        assert!(matches!(
+            fetch(public_key),
+            Err(Error::VerifyUpdateChannel(
+                signature::Error::InvalidSignature
+            ))
+        ));
+    }
 }
@@ -4,8 +4,8 @@ use mullvad_rpc::{
     availability::ApiAvailabilityHandle,
     rest::{Error as RestError, MullvadRestHandle},
 };
-use mullvad_types::account::AccountToken;
 pub use mullvad_types::wireguard::*;
+use mullvad_types::{account::AccountToken, states::TunnelState};
 use std::{future::Future, pin::Pin, time::Duration};
 
 use futures::future::{abortable, AbortHandle};
@@ -17,9 +17,13 @@ use talpid_core::{
 };
 
 pub use talpid_types::net::wireguard::{
-    ConnectionConfig, PrivateKey, TunnelConfig, TunnelParameters,
+    ConnectionConfig, PeerConfig, PrivateKey, TunnelConfig, TunnelParameters,
+};
+use talpid_types::{
+    net::{TunnelEndpoint, TunnelType},
+    tunnel::ErrorStateCause,
+    ErrorExt,
 };
-use talpid_types::ErrorExt;
 
 /// How long to wait before starting key rotation
 const ROTATION_START_DELAY: Duration = Duration::from_secs(60 * 3);
@@ -49,6 +53,50 @@ pub enum Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// A new key produced by automatic key rotation.
+#[derive(Debug)]
+pub enum RotatedKey {
+    /// The new key was added alongside the old one. Both are valid until a tunnel has been
+    /// established using the new key, or until that has failed.
+    Added {
+        old_key: PublicKey,
+        new_data: WireguardData,
+    },
+    /// The account had no room for an additional key, so the old key was replaced.
+    Replaced {
+        old_key: PublicKey,
+        new_data: WireguardData,
+    },
+}
+
+impl RotatedKey {
+    pub fn new_data(&self) -> &WireguardData {
+        match self {
+            RotatedKey::Added { new_data, .. } | RotatedKey::Replaced { new_data, .. } => new_data,
+        }
+    }
+}
+
+/// Returns whether a tunnel to `endpoint` is set up using the WireGuard key. This is the case for
+/// OpenVPN tunnels that are routed through a WireGuard entry relay as well.
+pub fn uses_wireguard_key(endpoint: &TunnelEndpoint) -> bool {
+    endpoint.tunnel_type == TunnelType::Wireguard || endpoint.entry_endpoint.is_some()
+}
+
+/// Returns whether a tunnel attempt that left the tunnel in `tunnel_state` may have failed because
+/// the relay did not accept its key. A tunnel that is still connecting when the next attempt is
+/// made closed by itself, which is what happens when no handshake completes. Attempts that were
+/// interrupted, e.g. by reconnecting or by going offline, say nothing about the key.
+pub fn is_key_failure(tunnel_state: &TunnelState) -> bool {
+    match tunnel_state {
+        TunnelState::Connecting { .. } => true,
+        TunnelState::Error(error_state) => {
+            matches!(error_state.cause(), ErrorStateCause::AuthFailed(_))
+        }
+        _ => false,
+    }
+}
+
 pub struct KeyManager {
     daemon_tx: DaemonEventSender,
     availability_handle: ApiAvailabilityHandle,
@@ -422,18 +470,46 @@ impl KeyManager {
         old_key: PublicKey,
     ) -> impl Future<Output = Result<PublicKey>> {
         let new_key = PrivateKey::new_from_random();
-        let rpc_result =
-            Self::replace_key_rpc(http_handle, account_token.clone(), old_key, new_key);
+        // The old key is kept on the account, so that it can still be used if no tunnel can be
+        // established using the new key.
+        let push_result = mullvad_rpc::WireguardKeyProxy::new(http_handle.clone()).push_wg_key(
+            account_token.clone(),
+            new_key.public_key(),
+            None,
+        );
 
         async move {
-            match rpc_result.await {
-                Ok(data) => {
-                    // Update account data
-                    let _ = daemon_tx.send(InternalDaemonEvent::WgKeyEvent((
+            let rotated_key = match push_result.await.map_err(Self::map_rpc_error) {
+                Ok(addresses) => Ok(RotatedKey::Added {
+                    old_key,
+                    new_data: WireguardData {
+                        private_key: new_key,
+                        addresses,
+                        created: Utc::now(),
+                    },
+                }),
+                Err(Error::TooManyKeys) => {
+                    log::warn!("Account has no room for another key. Replacing the current key");
+                    Self::replace_key_rpc(
+                        http_handle,
+                        account_token.clone(),
+                        old_key.clone(),
+                        new_key,
+                    )
+                    .await
+                    .map(|new_data| RotatedKey::Replaced { old_key, new_data })
+                }
+                Err(error) => Err(error),
+            };
+
+            match rotated_key {
+                Ok(rotated_key) => {
+                    let new_key = rotated_key.new_data().get_public_key();
+                    let _ = daemon_tx.send(InternalDaemonEvent::WgKeyRotationEvent((
                         account_token,
-                        Ok(data.clone()),
+                        rotated_key,
                     )));
-                    Ok(data.get_public_key())
+                    Ok(new_key)
                 }
                 Err(Error::TooManyKeys) => {
                     let _ = daemon_tx.send(InternalDaemonEvent::WgKeyEvent((
@@ -501,3 +577,52 @@ impl KeyManager {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use talpid_types::{
+        net::{Endpoint, TransportProtocol},
+        tunnel::{ActionAfterDisconnect, ErrorState},
+    };
+
+    fn endpoint(tunnel_type: TunnelType, entry: bool) -> TunnelEndpoint {
+        let entry_endpoint = Endpoint::new([10, 0, 0, 2], 51820, TransportProtocol::Udp);
+        TunnelEndpoint {
+            endpoint: Endpoint::new([10, 0, 0, 1], 1194, TransportProtocol::Udp),
+            tunnel_type,
+            proxy: None,
+            entry_endpoint: if entry { Some(entry_endpoint) } else { None },
+        }
+    }
+
+    #[test]
+    fn test_uses_wireguard_key() {
+        assert!(uses_wireguard_key(&endpoint(TunnelType::Wireguard, false)));
+        assert!(uses_wireguard_key(&endpoint(TunnelType::Wireguard, true)));
+        assert!(uses_wireguard_key(&endpoint(TunnelType::OpenVpn, true)));
+        assert!(!uses_wireguard_key(&endpoint(TunnelType::OpenVpn, false)));
+    }
+
+    #[test]
+    fn test_is_key_failure() {
+        let connecting = TunnelState::Connecting {
+            endpoint: endpoint(TunnelType::Wireguard, false),
+            location: None,
+        };
+        assert!(is_key_failure(&connecting));
+        assert!(is_key_failure(&TunnelState::Error(ErrorState::new(
+            ErrorStateCause::AuthFailed(None),
+            None
+        ))));
+
+        assert!(!is_key_failure(&TunnelState::Error(ErrorState::new(
+            ErrorStateCause::IsOffline,
+            None
+        ))));
+        assert!(!is_key_failure(&TunnelState::Disconnecting(
+            ActionAfterDisconnect::Reconnect
+        )));
+        assert!(!is_key_failure(&TunnelState::Disconnected));
+    }
+}
@@ -7,11 +7,11 @@ use jnix::{
 };
 use mullvad_daemon::EventListener;
 use mullvad_types::{
-    relay_list::RelayList, settings::Settings, states::TunnelState, version::AppVersionInfo,
-    wireguard::KeygenEvent,
+    account::AccountExpiryEvent, relay_list::RelayList, settings::Settings, states::TunnelState,
+    version::AppVersionInfo, wireguard::KeygenEvent,
 };
 use std::{sync::mpsc, thread};
-use talpid_types::ErrorExt;
+use talpid_types::{net::Connectivity, ErrorExt};
 
 #[derive(Debug, err_derive::Error)]
 #[error(no_from)]
@@ -63,6 +63,14 @@ impl EventListener for JniEventListener {
     fn notify_app_version(&self, app_version_info: AppVersionInfo) {
         let _ = self.0.send(Event::AppVersionInfo(app_version_info));
     }
+
+    fn notify_connectivity(&self, _connectivity: Connectivity) {
+        // The Android app monitors connectivity through its own `ConnectivityListener`.
+    }
+
+    fn notify_account_expiry(&self, _event: AccountExpiryEvent) {
+        // The Android app shows expiry notifications based on its own `AccountCache`.
+    }
 }
 
 struct JniEventHandler<'env> {
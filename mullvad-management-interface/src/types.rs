@@ -18,6 +18,7 @@ impl From<mullvad_types::location::GeoIpLocation> for GeoIpLocation {
             mullvad_exit_ip: geoip.mullvad_exit_ip,
             hostname: geoip.hostname.unwrap_or_default(),
             bridge_hostname: geoip.bridge_hostname.unwrap_or_default(),
+            entry_hostname: geoip.entry_hostname.unwrap_or_default(),
         }
     }
 }
@@ -148,6 +149,18 @@ impl From<mullvad_types::states::TunnelState> for TunnelState {
                             talpid_tunnel::ErrorStateCause::SplitTunnelError => {
                                 i32::from(Cause::SplitTunnelError)
                             }
+                            #[cfg(not(target_os = "android"))]
+                            talpid_tunnel::ErrorStateCause::CaptivePortalLogin => {
+                                i32::from(Cause::CaptivePortalLogin)
+                            }
+                            #[cfg(not(target_os = "android"))]
+                            talpid_tunnel::ErrorStateCause::AccountExpired => {
+                                i32::from(Cause::AccountExpired)
+                            }
+                            #[cfg(target_os = "linux")]
+                            talpid_tunnel::ErrorStateCause::CaptivePortal => {
+                                i32::from(Cause::CaptivePortal)
+                            }
                         },
                         blocking_error: error_state.block_failure().map(map_firewall_error),
                         auth_fail_reason: if let talpid_tunnel::ErrorStateCause::AuthFailed(
@@ -228,6 +241,41 @@ impl From<mullvad_types::wireguard::PublicKey> for PublicKey {
     }
 }
 
+impl From<mullvad_types::wireguard::KeyRotation> for KeyRotation {
+    fn from(rotation: mullvad_types::wireguard::KeyRotation) -> Self {
+        use mullvad_types::wireguard::KeyRotationOutcome;
+
+        let outcome = match rotation.outcome {
+            KeyRotationOutcome::Verified => key_rotation::Outcome::Verified,
+            KeyRotationOutcome::Unverified => key_rotation::Outcome::Unverified,
+            KeyRotationOutcome::Replaced => key_rotation::Outcome::Replaced,
+            KeyRotationOutcome::FellBack => key_rotation::Outcome::FellBack,
+        };
+        KeyRotation {
+            old_key: rotation.old_key.as_bytes().to_vec(),
+            new_key: rotation.new_key.as_bytes().to_vec(),
+            finished: Some(Timestamp {
+                seconds: rotation.finished.timestamp(),
+                nanos: 0,
+            }),
+            outcome: i32::from(outcome),
+        }
+    }
+}
+
+impl From<mullvad_types::account::AccountExpiryEvent> for AccountExpiryEvent {
+    fn from(event: mullvad_types::account::AccountExpiryEvent) -> Self {
+        AccountExpiryEvent {
+            expiry: Some(Timestamp {
+                seconds: event.expiry.timestamp(),
+                nanos: 0,
+            }),
+            expired: event.is_expired(),
+            threshold_hours: event.threshold_hours.unwrap_or(0),
+        }
+    }
+}
+
 impl From<mullvad_types::version::AppVersionInfo> for AppVersionInfo {
     fn from(version_info: mullvad_types::version::AppVersionInfo) -> Self {
         Self {
@@ -239,6 +287,21 @@ impl From<mullvad_types::version::AppVersionInfo> for AppVersionInfo {
     }
 }
 
+impl From<talpid_types::net::Connectivity> for Connectivity {
+    fn from(connectivity: talpid_types::net::Connectivity) -> Self {
+        use talpid_types::net::Connectivity as TalpidConnectivity;
+
+        let state = match connectivity {
+            TalpidConnectivity::Online => connectivity::State::Online,
+            TalpidConnectivity::Offline => connectivity::State::Offline,
+            TalpidConnectivity::CaptivePortal => connectivity::State::CaptivePortal,
+        };
+        Self {
+            state: i32::from(state),
+        }
+    }
+}
+
 impl From<mullvad_types::ConnectionConfig> for ConnectionConfig {
     fn from(config: mullvad_types::ConnectionConfig) -> Self {
         Self {
@@ -396,6 +459,134 @@ impl From<&mullvad_types::settings::Settings> for Settings {
             tunnel_options: Some(TunnelOptions::from(&settings.tunnel_options)),
             show_beta_releases: settings.show_beta_releases,
             split_tunnel,
+            api_access_methods: Some(ApiAccessMethods::from(&settings.api_access_methods)),
+            expiry_notification_hours: settings.expiry_notification_hours.clone(),
+            retry_schedule: settings.retry_schedule.clone().map(RetrySchedule::from),
+            trusted_networks: settings
+                .trusted_networks
+                .networks()
+                .iter()
+                .map(TrustedNetwork::from)
+                .collect(),
+            network_profiles: settings
+                .network_profiles
+                .profiles()
+                .iter()
+                .map(NetworkProfile::from)
+                .collect(),
+            connectivity_probe: settings
+                .connectivity_probe
+                .as_ref()
+                .map(|probe| probe.to_string())
+                .unwrap_or_default(),
+            update_channel: settings.update_channel.as_ref().map(UpdateChannel::from),
+        }
+    }
+}
+
+impl From<&mullvad_types::version::UpdateChannelSettings> for UpdateChannel {
+    fn from(channel: &mullvad_types::version::UpdateChannelSettings) -> Self {
+        Self {
+            source: channel.source.clone(),
+            public_key: channel.public_key.clone(),
+        }
+    }
+}
+
+impl From<&mullvad_types::network_profile::NetworkIdentifier> for NetworkIdentifier {
+    fn from(identifier: &mullvad_types::network_profile::NetworkIdentifier) -> Self {
+        use mullvad_types::network_profile::NetworkIdentifier as MullvadNetworkIdentifier;
+
+        let identifier = match identifier {
+            MullvadNetworkIdentifier::Ssid(ssid) => {
+                network_identifier::Identifier::Ssid(ssid.clone())
+            }
+            MullvadNetworkIdentifier::GatewayMac(mac) => {
+                network_identifier::Identifier::GatewayMac(mac.clone())
+            }
+            MullvadNetworkIdentifier::Interface(interface) => {
+                network_identifier::Identifier::Interface(interface.clone())
+            }
+        };
+        Self {
+            identifier: Some(identifier),
+        }
+    }
+}
+
+impl From<&mullvad_types::network_profile::NetworkProfile> for NetworkProfile {
+    fn from(profile: &mullvad_types::network_profile::NetworkProfile) -> Self {
+        Self {
+            name: profile.name.clone(),
+            networks: profile
+                .networks
+                .iter()
+                .map(NetworkIdentifier::from)
+                .collect(),
+            relay_settings: profile.relay_settings.clone().map(RelaySettings::from),
+            bridge_state: profile.bridge_state.map(BridgeState::from),
+        }
+    }
+}
+
+impl From<mullvad_types::network_profile::ActiveNetworkProfile> for ActiveNetworkProfile {
+    fn from(active: mullvad_types::network_profile::ActiveNetworkProfile) -> Self {
+        Self {
+            profile: Some(NetworkProfile::from(&active.profile)),
+            network: Some(ConnectedNetwork::from(active.network)),
+            identifier: Some(NetworkIdentifier::from(&active.identifier)),
+        }
+    }
+}
+
+impl From<&mullvad_types::trusted_network::TrustedNetwork> for TrustedNetwork {
+    fn from(network: &mullvad_types::trusted_network::TrustedNetwork) -> Self {
+        use mullvad_types::trusted_network::TrustedNetwork as MullvadTrustedNetwork;
+
+        let network = match network {
+            MullvadTrustedNetwork::Ssid(ssid) => trusted_network::Network::Ssid(ssid.clone()),
+            MullvadTrustedNetwork::Interface(interface) => {
+                trusted_network::Network::Interface(interface.clone())
+            }
+        };
+        Self {
+            network: Some(network),
+        }
+    }
+}
+
+impl From<talpid_types::net::ConnectedNetwork> for ConnectedNetwork {
+    fn from(network: talpid_types::net::ConnectedNetwork) -> Self {
+        Self {
+            interface: network.interface,
+            ssid: network.ssid.unwrap_or_default(),
+            gateway_mac: network.gateway_mac.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<mullvad_types::retry_schedule::RetrySchedule> for RetrySchedule {
+    fn from(schedule: mullvad_types::retry_schedule::RetrySchedule) -> Self {
+        Self {
+            attempts: schedule
+                .attempts
+                .into_iter()
+                .map(RetryAttempt::from)
+                .collect(),
+            repeat_from: schedule.repeat_from as u32,
+        }
+    }
+}
+
+impl From<mullvad_types::retry_schedule::RetryAttempt> for RetryAttempt {
+    fn from(attempt: mullvad_types::retry_schedule::RetryAttempt) -> Self {
+        Self {
+            tunnel_type: match attempt.tunnel_type {
+                talpid_types::net::TunnelType::Wireguard => i32::from(TunnelType::Wireguard),
+                talpid_types::net::TunnelType::OpenVpn => i32::from(TunnelType::Openvpn),
+            },
+            port: attempt.port.option().map(TransportPort::from),
+            bridge: attempt.bridge,
         }
     }
 }
@@ -454,6 +645,11 @@ impl From<mullvad_types::relay_constraints::BridgeSettings> for BridgeSettings {
                         cipher: proxy_settings.cipher.clone(),
                     })
                 }
+                talpid_net::openvpn::ProxySettings::HttpConnect(proxy_settings) => {
+                    bridge_settings::Type::HttpConnect(
+                        bridge_settings::HttpConnectProxySettings::from(&proxy_settings),
+                    )
+                }
             },
         };
 
@@ -463,6 +659,122 @@ impl From<mullvad_types::relay_constraints::BridgeSettings> for BridgeSettings {
     }
 }
 
+impl From<&talpid_types::net::openvpn::HttpConnectProxySettings>
+    for bridge_settings::HttpConnectProxySettings
+{
+    fn from(proxy_settings: &talpid_types::net::openvpn::HttpConnectProxySettings) -> Self {
+        bridge_settings::HttpConnectProxySettings {
+            address: proxy_settings.address.to_string(),
+            auth: proxy_settings
+                .auth
+                .as_ref()
+                .map(|auth| bridge_settings::RemoteProxyAuth {
+                    username: auth.username.clone(),
+                    password: auth.password.clone(),
+                }),
+        }
+    }
+}
+
+impl From<&mullvad_types::access_method::Settings> for ApiAccessMethods {
+    fn from(settings: &mullvad_types::access_method::Settings) -> Self {
+        ApiAccessMethods {
+            access_methods: settings
+                .access_methods()
+                .iter()
+                .map(ApiAccessMethod::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<&mullvad_types::access_method::ApiAccessMethod> for ApiAccessMethod {
+    fn from(method: &mullvad_types::access_method::ApiAccessMethod) -> Self {
+        use mullvad_types::access_method::AccessMethod;
+
+        let access_method = match method.access_method {
+            AccessMethod::Direct => api_access_method::AccessMethod::Direct(()),
+            AccessMethod::Bridges => api_access_method::AccessMethod::Bridges(()),
+            AccessMethod::Socks5(address) => {
+                api_access_method::AccessMethod::Socks5(api_access_method::Socks5 {
+                    address: address.to_string(),
+                })
+            }
+            AccessMethod::HttpConnect(ref proxy_settings) => {
+                api_access_method::AccessMethod::HttpConnect(
+                    bridge_settings::HttpConnectProxySettings::from(proxy_settings),
+                )
+            }
+        };
+
+        ApiAccessMethod {
+            name: method.name.clone(),
+            enabled: method.enabled,
+            access_method: Some(access_method),
+        }
+    }
+}
+
+impl From<mullvad_types::api_status::ApiStatus> for ApiStatus {
+    fn from(status: mullvad_types::api_status::ApiStatus) -> Self {
+        ApiStatus {
+            endpoints: status
+                .endpoints
+                .into_iter()
+                .map(ApiEndpointStatus::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<mullvad_types::api_status::EndpointStatus> for ApiEndpointStatus {
+    fn from(status: mullvad_types::api_status::EndpointStatus) -> Self {
+        ApiEndpointStatus {
+            endpoint: status.endpoint,
+            connection_mode: status.connection_mode,
+            successes: status.successes,
+            failures: status
+                .failures
+                .into_iter()
+                .map(|failure| api_endpoint_status::FailureCount {
+                    kind: i32::from(RequestErrorKind::from(failure.kind)),
+                    count: failure.count,
+                })
+                .collect(),
+            latency: status
+                .latency
+                .into_iter()
+                .map(|bucket| api_endpoint_status::LatencyBucket {
+                    upper_bound_ms: bucket.upper_bound_ms,
+                    count: bucket.count,
+                })
+                .collect(),
+            last_error: status
+                .last_error
+                .map(|error| api_endpoint_status::RequestError {
+                    kind: i32::from(RequestErrorKind::from(error.kind)),
+                    message: error.message,
+                    timestamp: Some(Timestamp {
+                        seconds: error.timestamp.timestamp(),
+                        nanos: 0,
+                    }),
+                }),
+        }
+    }
+}
+
+impl From<mullvad_types::api_status::RequestErrorKind> for RequestErrorKind {
+    fn from(kind: mullvad_types::api_status::RequestErrorKind) -> Self {
+        use mullvad_types::api_status::RequestErrorKind as MullvadRequestErrorKind;
+        match kind {
+            MullvadRequestErrorKind::Network => RequestErrorKind::Network,
+            MullvadRequestErrorKind::Timeout => RequestErrorKind::Timeout,
+            MullvadRequestErrorKind::ApiStatus => RequestErrorKind::ApiStatus,
+            MullvadRequestErrorKind::Other => RequestErrorKind::Other,
+        }
+    }
+}
+
 impl From<mullvad_types::relay_constraints::RelaySettings> for RelaySettings {
     fn from(settings: mullvad_types::relay_constraints::RelaySettings) -> Self {
         use mullvad_types::relay_constraints::RelaySettings as MullvadRelaySettings;
@@ -508,6 +820,15 @@ impl From<mullvad_types::relay_constraints::RelaySettings> for RelaySettings {
                             .wireguard_constraints
                             .entry_location
                             .map(RelayLocation::from),
+                        automatic_entry: constraints.wireguard_constraints.automatic_entry.map(
+                            |automatic_entry| AutomaticEntry {
+                                different_country: automatic_entry.different_country,
+                                different_provider: automatic_entry.different_provider,
+                            },
+                        ),
+                        obfuscation: i32::from(wireguard_constraints::Obfuscation::from(
+                            constraints.wireguard_constraints.obfuscation,
+                        )),
                     }),
 
                     openvpn_constraints: Some(OpenvpnConstraints {
@@ -516,6 +837,7 @@ impl From<mullvad_types::relay_constraints::RelaySettings> for RelaySettings {
                             .port
                             .option()
                             .map(TransportPort::from),
+                        wireguard_entry: constraints.openvpn_constraints.wireguard_entry,
                     }),
                 })
             }
@@ -555,6 +877,8 @@ impl From<&mullvad_types::settings::TunnelOptions> for TunnelOptions {
         Self {
             openvpn: Some(tunnel_options::OpenvpnOptions {
                 mssfix: u32::from(options.openvpn.mssfix.unwrap_or_default()),
+                cipher: Some(OpenvpnCipher::from(options.openvpn.cipher)),
+                tls_version_min: Some(OpenvpnTlsVersion::from(options.openvpn.tls_version_min)),
             }),
             wireguard: Some(tunnel_options::WireguardOptions {
                 mtu: u32::from(options.wireguard.options.mtu.unwrap_or_default()),
@@ -659,6 +983,16 @@ impl From<mullvad_types::relay_list::Relay> for Relay {
                     })
                     .collect(),
             }),
+            obfuscators: Some(RelayObfuscators {
+                udp2tcp: relay
+                    .obfuscators
+                    .udp2tcp
+                    .iter()
+                    .map(|endpoint| Udp2TcpEndpointData {
+                        port: u32::from(endpoint.port),
+                    })
+                    .collect(),
+            }),
             location: relay.location.map(|location| Location {
                 country: location.country,
                 country_code: location.country_code,
@@ -680,6 +1014,31 @@ impl From<TransportProtocol> for talpid_types::net::TransportProtocol {
     }
 }
 
+impl From<log::Level> for LogLevel {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => LogLevel::Error,
+            log::Level::Warn => LogLevel::Warn,
+            log::Level::Info => LogLevel::Info,
+            log::Level::Debug => LogLevel::Debug,
+            log::Level::Trace => LogLevel::Trace,
+        }
+    }
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => log::LevelFilter::Off,
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum FromProtobufTypeError {
     InvalidArgument(&'static str),
@@ -711,6 +1070,24 @@ impl TryFrom<&WireguardConstraints> for mullvad_types::relay_constraints::Wiregu
             None => None,
         };
 
+        let obfuscation =
+            match wireguard_constraints::Obfuscation::from_i32(constraints.obfuscation) {
+                Some(wireguard_constraints::Obfuscation::Auto) => {
+                    mullvad_constraints::SelectedObfuscation::Auto
+                }
+                Some(wireguard_constraints::Obfuscation::Off) => {
+                    mullvad_constraints::SelectedObfuscation::Off
+                }
+                Some(wireguard_constraints::Obfuscation::Udp2tcp) => {
+                    mullvad_constraints::SelectedObfuscation::Udp2Tcp
+                }
+                None => {
+                    return Err(FromProtobufTypeError::InvalidArgument(
+                        "invalid obfuscation setting",
+                    ))
+                }
+            };
+
         Ok(mullvad_constraints::WireguardConstraints {
             port: Constraint::from(wireguard_transport_port),
             ip_version: Constraint::from(ip_version),
@@ -718,10 +1095,30 @@ impl TryFrom<&WireguardConstraints> for mullvad_types::relay_constraints::Wiregu
                 .entry_location
                 .clone()
                 .map(Constraint::<mullvad_types::relay_constraints::LocationConstraint>::from),
+            automatic_entry: constraints.automatic_entry.as_ref().map(|automatic_entry| {
+                mullvad_constraints::AutomaticEntry {
+                    different_country: automatic_entry.different_country,
+                    different_provider: automatic_entry.different_provider,
+                }
+            }),
+            obfuscation,
         })
     }
 }
 
+impl From<mullvad_types::relay_constraints::SelectedObfuscation>
+    for wireguard_constraints::Obfuscation
+{
+    fn from(obfuscation: mullvad_types::relay_constraints::SelectedObfuscation) -> Self {
+        use mullvad_types::relay_constraints::SelectedObfuscation;
+        match obfuscation {
+            SelectedObfuscation::Auto => wireguard_constraints::Obfuscation::Auto,
+            SelectedObfuscation::Off => wireguard_constraints::Obfuscation::Off,
+            SelectedObfuscation::Udp2Tcp => wireguard_constraints::Obfuscation::Udp2tcp,
+        }
+    }
+}
+
 impl TryFrom<&OpenvpnConstraints> for mullvad_types::relay_constraints::OpenVpnConstraints {
     type Error = FromProtobufTypeError;
 
@@ -735,6 +1132,8 @@ impl TryFrom<&OpenvpnConstraints> for mullvad_types::relay_constraints::OpenVpnC
                 Some(port) => Some(mullvad_constraints::TransportPort::try_from(port.clone())?),
                 None => None,
             }),
+            wireguard_entry: constraints.wireguard_entry,
+            cipher: Constraint::Any,
         })
     }
 }
@@ -1138,10 +1537,72 @@ impl TryFrom<BridgeSettings> for mullvad_types::relay_constraints::BridgeSetting
                 );
                 Ok(mullvad_constraints::BridgeSettings::Custom(proxy_settings))
             }
+            bridge_settings::Type::HttpConnect(proxy_settings) => {
+                let proxy_settings = talpid_net::openvpn::ProxySettings::HttpConnect(
+                    talpid_net::openvpn::HttpConnectProxySettings::try_from(proxy_settings)?,
+                );
+                Ok(mullvad_constraints::BridgeSettings::Custom(proxy_settings))
+            }
         }
     }
 }
 
+impl TryFrom<bridge_settings::HttpConnectProxySettings>
+    for talpid_types::net::openvpn::HttpConnectProxySettings
+{
+    type Error = FromProtobufTypeError;
+
+    fn try_from(
+        proxy_settings: bridge_settings::HttpConnectProxySettings,
+    ) -> Result<Self, Self::Error> {
+        let address = proxy_settings
+            .address
+            .parse()
+            .map_err(|_| FromProtobufTypeError::InvalidArgument("failed to parse proxy address"))?;
+        let auth = proxy_settings
+            .auth
+            .map(|auth| talpid_types::net::openvpn::ProxyAuth {
+                username: auth.username,
+                password: auth.password,
+            });
+        Ok(talpid_types::net::openvpn::HttpConnectProxySettings { address, auth })
+    }
+}
+
+impl TryFrom<ApiAccessMethod> for mullvad_types::access_method::ApiAccessMethod {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(method: ApiAccessMethod) -> Result<Self, Self::Error> {
+        use mullvad_types::access_method::AccessMethod;
+
+        let access_method = method
+            .access_method
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing access method",
+            ))?;
+        let access_method = match access_method {
+            api_access_method::AccessMethod::Direct(()) => AccessMethod::Direct,
+            api_access_method::AccessMethod::Bridges(()) => AccessMethod::Bridges,
+            api_access_method::AccessMethod::Socks5(socks5) => {
+                AccessMethod::Socks5(socks5.address.parse().map_err(|_| {
+                    FromProtobufTypeError::InvalidArgument("failed to parse proxy address")
+                })?)
+            }
+            api_access_method::AccessMethod::HttpConnect(proxy_settings) => {
+                AccessMethod::HttpConnect(
+                    talpid_types::net::openvpn::HttpConnectProxySettings::try_from(proxy_settings)?,
+                )
+            }
+        };
+
+        Ok(mullvad_types::access_method::ApiAccessMethod {
+            name: method.name,
+            enabled: method.enabled,
+            access_method,
+        })
+    }
+}
+
 impl TryFrom<BridgeState> for mullvad_types::relay_constraints::BridgeState {
     type Error = FromProtobufTypeError;
 
@@ -1161,6 +1622,66 @@ impl TryFrom<BridgeState> for mullvad_types::relay_constraints::BridgeState {
     }
 }
 
+impl From<Option<talpid_types::net::openvpn::Cipher>> for OpenvpnCipher {
+    fn from(cipher: Option<talpid_types::net::openvpn::Cipher>) -> Self {
+        use talpid_types::net::openvpn::Cipher;
+
+        OpenvpnCipher {
+            cipher: i32::from(match cipher {
+                None => openvpn_cipher::Cipher::Any,
+                Some(Cipher::Aes256Gcm) => openvpn_cipher::Cipher::Aes256Gcm,
+                Some(Cipher::Chacha20Poly1305) => openvpn_cipher::Cipher::Chacha20Poly1305,
+            }),
+        }
+    }
+}
+
+impl TryFrom<OpenvpnCipher> for Option<talpid_types::net::openvpn::Cipher> {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(cipher: OpenvpnCipher) -> Result<Self, Self::Error> {
+        use talpid_types::net::openvpn::Cipher;
+
+        match openvpn_cipher::Cipher::from_i32(cipher.cipher) {
+            Some(openvpn_cipher::Cipher::Any) => Ok(None),
+            Some(openvpn_cipher::Cipher::Aes256Gcm) => Ok(Some(Cipher::Aes256Gcm)),
+            Some(openvpn_cipher::Cipher::Chacha20Poly1305) => Ok(Some(Cipher::Chacha20Poly1305)),
+            None => Err(FromProtobufTypeError::InvalidArgument(
+                "invalid OpenVPN cipher",
+            )),
+        }
+    }
+}
+
+impl From<talpid_types::net::openvpn::TlsVersion> for OpenvpnTlsVersion {
+    fn from(version: talpid_types::net::openvpn::TlsVersion) -> Self {
+        use talpid_types::net::openvpn::TlsVersion;
+
+        OpenvpnTlsVersion {
+            version: i32::from(match version {
+                TlsVersion::V1_2 => openvpn_tls_version::Version::Tls12,
+                TlsVersion::V1_3 => openvpn_tls_version::Version::Tls13,
+            }),
+        }
+    }
+}
+
+impl TryFrom<OpenvpnTlsVersion> for talpid_types::net::openvpn::TlsVersion {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(version: OpenvpnTlsVersion) -> Result<Self, Self::Error> {
+        use talpid_types::net::openvpn::TlsVersion;
+
+        match openvpn_tls_version::Version::from_i32(version.version) {
+            Some(openvpn_tls_version::Version::Tls12) => Ok(TlsVersion::V1_2),
+            Some(openvpn_tls_version::Version::Tls13) => Ok(TlsVersion::V1_3),
+            None => Err(FromProtobufTypeError::InvalidArgument(
+                "invalid OpenVPN TLS version",
+            )),
+        }
+    }
+}
+
 impl TryFrom<TunnelOptions> for mullvad_types::settings::TunnelOptions {
     type Error = FromProtobufTypeError;
 
@@ -1195,6 +1716,16 @@ impl TryFrom<TunnelOptions> for mullvad_types::settings::TunnelOptions {
                 } else {
                     None
                 },
+                cipher: openvpn_options
+                    .cipher
+                    .map(Option::<net::openvpn::Cipher>::try_from)
+                    .transpose()?
+                    .flatten(),
+                tls_version_min: openvpn_options
+                    .tls_version_min
+                    .map(net::openvpn::TlsVersion::try_from)
+                    .transpose()?
+                    .unwrap_or_default(),
             },
             wireguard: mullvad_types::wireguard::TunnelOptions {
                 options: net::wireguard::TunnelOptions {
@@ -1284,6 +1815,145 @@ impl TryFrom<DnsOptions> for mullvad_types::settings::DnsOptions {
     }
 }
 
+impl TryFrom<TrustedNetwork> for mullvad_types::trusted_network::TrustedNetwork {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(network: TrustedNetwork) -> Result<Self, Self::Error> {
+        use mullvad_types::trusted_network::TrustedNetwork as MullvadTrustedNetwork;
+
+        match network.network {
+            Some(trusted_network::Network::Ssid(ssid)) => Ok(MullvadTrustedNetwork::Ssid(ssid)),
+            Some(trusted_network::Network::Interface(interface)) => {
+                Ok(MullvadTrustedNetwork::Interface(interface))
+            }
+            None => Err(FromProtobufTypeError::InvalidArgument(
+                "missing trusted network",
+            )),
+        }
+    }
+}
+
+impl TryFrom<NetworkIdentifier> for mullvad_types::network_profile::NetworkIdentifier {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(identifier: NetworkIdentifier) -> Result<Self, Self::Error> {
+        use mullvad_types::network_profile::NetworkIdentifier as MullvadNetworkIdentifier;
+
+        match identifier.identifier {
+            Some(network_identifier::Identifier::Ssid(ssid)) => {
+                Ok(MullvadNetworkIdentifier::Ssid(ssid))
+            }
+            Some(network_identifier::Identifier::GatewayMac(mac)) => {
+                Ok(MullvadNetworkIdentifier::GatewayMac(mac))
+            }
+            Some(network_identifier::Identifier::Interface(interface)) => {
+                Ok(MullvadNetworkIdentifier::Interface(interface))
+            }
+            None => Err(FromProtobufTypeError::InvalidArgument(
+                "missing network identifier",
+            )),
+        }
+    }
+}
+
+impl TryFrom<NetworkProfile> for mullvad_types::network_profile::NetworkProfile {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(profile: NetworkProfile) -> Result<Self, Self::Error> {
+        Ok(mullvad_types::network_profile::NetworkProfile {
+            name: profile.name,
+            networks: profile
+                .networks
+                .into_iter()
+                .map(mullvad_types::network_profile::NetworkIdentifier::try_from)
+                .collect::<Result<_, _>>()?,
+            relay_settings: profile
+                .relay_settings
+                .map(mullvad_types::relay_constraints::RelaySettings::try_from)
+                .transpose()?,
+            bridge_state: profile
+                .bridge_state
+                .map(mullvad_types::relay_constraints::BridgeState::try_from)
+                .transpose()?,
+        })
+    }
+}
+
+impl TryFrom<ActiveNetworkProfile> for mullvad_types::network_profile::ActiveNetworkProfile {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(active: ActiveNetworkProfile) -> Result<Self, Self::Error> {
+        Ok(mullvad_types::network_profile::ActiveNetworkProfile {
+            profile: mullvad_types::network_profile::NetworkProfile::try_from(
+                active
+                    .profile
+                    .ok_or(FromProtobufTypeError::InvalidArgument("missing profile"))?,
+            )?,
+            network: talpid_types::net::ConnectedNetwork::from(
+                active
+                    .network
+                    .ok_or(FromProtobufTypeError::InvalidArgument("missing network"))?,
+            ),
+            identifier: mullvad_types::network_profile::NetworkIdentifier::try_from(
+                active
+                    .identifier
+                    .ok_or(FromProtobufTypeError::InvalidArgument("missing identifier"))?,
+            )?,
+        })
+    }
+}
+
+impl From<ConnectedNetwork> for talpid_types::net::ConnectedNetwork {
+    fn from(network: ConnectedNetwork) -> Self {
+        Self {
+            interface: network.interface,
+            ssid: Some(network.ssid).filter(|ssid| !ssid.is_empty()),
+            gateway_mac: Some(network.gateway_mac).filter(|mac| !mac.is_empty()),
+        }
+    }
+}
+
+impl TryFrom<RetrySchedule> for mullvad_types::retry_schedule::RetrySchedule {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(schedule: RetrySchedule) -> Result<Self, Self::Error> {
+        Ok(mullvad_types::retry_schedule::RetrySchedule {
+            attempts: schedule
+                .attempts
+                .into_iter()
+                .map(mullvad_types::retry_schedule::RetryAttempt::try_from)
+                .collect::<Result<_, _>>()?,
+            repeat_from: schedule.repeat_from as usize,
+        })
+    }
+}
+
+impl TryFrom<RetryAttempt> for mullvad_types::retry_schedule::RetryAttempt {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(attempt: RetryAttempt) -> Result<Self, Self::Error> {
+        let tunnel_type = match TunnelType::from_i32(attempt.tunnel_type) {
+            Some(TunnelType::Openvpn) => talpid_types::net::TunnelType::OpenVpn,
+            Some(TunnelType::Wireguard) => talpid_types::net::TunnelType::Wireguard,
+            None => {
+                return Err(FromProtobufTypeError::InvalidArgument(
+                    "invalid tunnel protocol",
+                ))
+            }
+        };
+        Ok(mullvad_types::retry_schedule::RetryAttempt {
+            tunnel_type,
+            port: match attempt.port {
+                Some(port) => Constraint::Only(
+                    mullvad_types::relay_constraints::TransportPort::try_from(port)?,
+                ),
+                None => Constraint::Any,
+            },
+            bridge: attempt.bridge,
+        })
+    }
+}
+
 impl TryFrom<TransportPort> for mullvad_types::relay_constraints::TransportPort {
     type Error = FromProtobufTypeError;
 
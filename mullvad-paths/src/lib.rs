@@ -68,4 +68,4 @@ mod rpc_socket;
 pub use crate::rpc_socket::{get_default_rpc_socket_path, get_rpc_socket_path};
 
 mod settings;
-pub use crate::settings::{get_default_settings_dir, settings_dir};
+pub use crate::settings::{get_default_settings_dir, get_settings_dir, settings_dir};
@@ -7,7 +7,8 @@ pub fn settings_dir() -> Result<PathBuf> {
     crate::create_and_return(get_settings_dir, None)
 }
 
-fn get_settings_dir() -> Result<PathBuf> {
+/// Get the settings directory, but don't try to create it.
+pub fn get_settings_dir() -> Result<PathBuf> {
     match env::var_os("MULLVAD_SETTINGS_DIR") {
         Some(path) => Ok(PathBuf::from(path)),
         None => get_default_settings_dir(),
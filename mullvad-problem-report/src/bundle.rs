@@ -0,0 +1,537 @@
+//! Diagnostic bundles. Unlike the text report written by `collect_report`, a bundle is a gzip
+//! compressed tar archive with one file per log or system snapshot, described by a JSON manifest.
+
+use crate::{
+    build_report,
+    redaction::{RedactOptions, RedactionSummary},
+    write_log, write_metadata, Error, ProblemReport,
+};
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use mullvad_management_interface::{new_rpc_client, types};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::Path,
+    process::Command,
+};
+use talpid_types::ErrorExt;
+
+/// Version of the bundle layout, stored in the manifest.
+const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_PATH: &str = "manifest.json";
+const SETTINGS_PATH: &str = "settings.json";
+const TUNNEL_STATE_PATH: &str = "tunnel-state.txt";
+
+/// Settings whose values are replaced before the settings are added to a bundle. The proxy
+/// credentials are used by bridges and API access methods.
+const SECRET_SETTINGS: &[&str] = &["account_token", "private_key", "username", "password"];
+
+/// Commands whose output is added to bundles, by kind and path in the archive.
+#[cfg(target_os = "linux")]
+const SNAPSHOTS: &[(EntryKind, &str, &[&[&str]])] = &[
+    (
+        EntryKind::Routes,
+        "routes.txt",
+        &[
+            &["ip", "-4", "route", "show", "table", "all"],
+            &["ip", "-6", "route", "show", "table", "all"],
+            &["ip", "rule", "list"],
+        ],
+    ),
+    (
+        EntryKind::Firewall,
+        "nftables.txt",
+        &[&["nft", "list", "ruleset"]],
+    ),
+];
+#[cfg(target_os = "macos")]
+const SNAPSHOTS: &[(EntryKind, &str, &[&[&str]])] = &[
+    (EntryKind::Routes, "routes.txt", &[&["netstat", "-rn"]]),
+    (
+        EntryKind::Firewall,
+        "pf.txt",
+        &[&["pfctl", "-a", "mullvad", "-s", "rules"]],
+    ),
+];
+#[cfg(windows)]
+const SNAPSHOTS: &[(EntryKind, &str, &[&[&str]])] =
+    &[(EntryKind::Routes, "routes.txt", &[&["route", "print"]])];
+
+/// Describes the contents of a bundle.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format_version: u32,
+    pub created: DateTime<Utc>,
+    /// System information, the same as at the start of a text report.
+    pub metadata: BTreeMap<String, String>,
+    /// The tunnel state of the daemon, if it could be reached.
+    pub tunnel_state: Option<String>,
+    /// The DNS backend used by the daemon, if known.
+    pub dns_backend: Option<String>,
+    pub entries: Vec<Entry>,
+}
+
+/// A file in a bundle, or a failure to collect one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub kind: EntryKind,
+    /// Path of the file in the archive. `None` if the contents could not be collected.
+    pub path: Option<String>,
+    /// Where the contents were taken from, such as a redacted file path or a command.
+    pub source: String,
+    /// Why the contents could not be collected.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Log,
+    Settings,
+    TunnelState,
+    Routes,
+    Firewall,
+}
+
+/// The manifest and files of a bundle.
+#[derive(Debug)]
+pub struct Bundle {
+    pub manifest: Manifest,
+    /// Contents of the files in the archive, by path.
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+/// Collects logs, settings and system snapshots into a bundle, which is kept in memory until it is
+/// written with `Bundle::write`. Returns the bundle and a summary of what was redacted from it.
+pub fn collect_bundle(
+    extra_logs: &[&Path],
+    redact_options: RedactOptions,
+) -> Result<(Bundle, RedactionSummary), Error> {
+    // Bundles are not uploaded through the API, so they can contain the logs in full
+    let mut report = build_report(extra_logs, redact_options, usize::MAX);
+    let mut bundle = Bundle::new(report.metadata.clone());
+
+    for (index, log) in report.logs.iter().enumerate() {
+        if log.is_error {
+            bundle.add_error(EntryKind::Log, log.label.clone(), log.content.clone());
+        } else {
+            let file_name = Path::new(&log.label)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            // Rotated logs are stored decompressed
+            let file_name = file_name.trim_end_matches(".gz");
+            bundle.add_file(
+                EntryKind::Log,
+                format!("logs/{:02}-{}", index, file_name),
+                log.label.clone(),
+                log.content.clone().into_bytes(),
+            );
+        }
+    }
+    bundle.manifest.dns_backend = dns_backend(&report);
+
+    bundle.add_settings(&mut report);
+    bundle.add_tunnel_state(&mut report)?;
+    for (kind, path, commands) in SNAPSHOTS {
+        bundle.add_snapshot(&mut report, *kind, path, commands);
+    }
+
+    Ok((bundle, report.redaction_summary))
+}
+
+/// Returns whether the file at `path` looks like a bundle rather than a text report.
+pub fn is_bundle(path: &Path) -> bool {
+    let mut magic = [0u8; 2];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .map(|()| magic == [0x1f, 0x8b])
+        .unwrap_or(false)
+}
+
+impl Bundle {
+    fn new(metadata: BTreeMap<String, String>) -> Self {
+        Bundle {
+            manifest: Manifest {
+                format_version: FORMAT_VERSION,
+                created: Utc::now(),
+                metadata,
+                tunnel_state: None,
+                dns_backend: None,
+                entries: vec![],
+            },
+            files: BTreeMap::new(),
+        }
+    }
+
+    fn add_file(&mut self, kind: EntryKind, path: String, source: String, contents: Vec<u8>) {
+        println!("Adding {}", source);
+        self.manifest.entries.push(Entry {
+            kind,
+            path: Some(path.clone()),
+            source,
+            error: None,
+        });
+        self.files.insert(path, contents);
+    }
+
+    fn add_error(&mut self, kind: EntryKind, source: String, error: String) {
+        self.manifest.entries.push(Entry {
+            kind,
+            path: None,
+            source,
+            error: Some(error),
+        });
+    }
+
+    /// Adds the daemon settings, with account numbers, private keys and credentials removed.
+    fn add_settings(&mut self, report: &mut ProblemReport) {
+        match mullvad_paths::get_settings_dir() {
+            Ok(dir) => self.add_settings_file(report, &dir.join(SETTINGS_PATH)),
+            Err(error) => {
+                let source = SETTINGS_PATH.to_owned();
+                self.add_error(
+                    EntryKind::Settings,
+                    source,
+                    report.redact(&error.display_chain()),
+                );
+            }
+        }
+    }
+
+    fn add_settings_file(&mut self, report: &mut ProblemReport, settings_path: &Path) {
+        let source = report.redact(&settings_path.to_string_lossy());
+
+        let settings = fs::read(settings_path)
+            .and_then(|contents| serde_json::from_slice(&contents).map_err(io::Error::from))
+            .and_then(|mut settings: serde_json::Value| {
+                strip_secrets(&mut settings);
+                serde_json::to_string_pretty(&settings).map_err(io::Error::from)
+            });
+        match settings {
+            Ok(settings) => self.add_file(
+                EntryKind::Settings,
+                SETTINGS_PATH.to_owned(),
+                source,
+                report.redact(&settings).into_bytes(),
+            ),
+            Err(error) => {
+                let error = report.redact(&error.display_chain_with_msg("Failed to read settings"));
+                self.add_error(EntryKind::Settings, source, error)
+            }
+        }
+    }
+
+    /// Adds the tunnel state, as reported by the daemon.
+    fn add_tunnel_state(&mut self, report: &mut ProblemReport) -> Result<(), Error> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(Error::CreateRuntime)?;
+        let tunnel_state = runtime.block_on(async {
+            let mut rpc = new_rpc_client()
+                .await
+                .map_err(|error| error.display_chain_with_msg("Failed to connect to daemon"))?;
+            rpc.get_tunnel_state(())
+                .await
+                .map(|response| response.into_inner())
+                .map_err(|error| error.display_chain_with_msg("Failed to get tunnel state"))
+        });
+
+        let source = "daemon".to_owned();
+        match tunnel_state {
+            Ok(tunnel_state) => {
+                self.manifest.tunnel_state = Some(tunnel_state_name(&tunnel_state).to_owned());
+                self.add_file(
+                    EntryKind::TunnelState,
+                    TUNNEL_STATE_PATH.to_owned(),
+                    source,
+                    report.redact(&format!("{:#?}", tunnel_state)).into_bytes(),
+                );
+            }
+            Err(error) => self.add_error(EntryKind::TunnelState, source, error),
+        }
+        Ok(())
+    }
+
+    /// Adds the output of `commands` as a single file.
+    fn add_snapshot(
+        &mut self,
+        report: &mut ProblemReport,
+        kind: EntryKind,
+        path: &str,
+        commands: &[&[&str]],
+    ) {
+        let source = commands
+            .iter()
+            .map(|command| command.join(" "))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        let mut contents = String::new();
+        for command in commands {
+            match run_command(command) {
+                Ok(output) => {
+                    contents.push_str(&format!("$ {}\n", command.join(" ")));
+                    contents.push_str(&output);
+                    contents.push('\n');
+                }
+                Err(error) => {
+                    self.add_error(kind, source, report.redact(&error));
+                    return;
+                }
+            }
+        }
+        self.add_file(
+            kind,
+            path.to_owned(),
+            source,
+            report.redact(&contents).into_bytes(),
+        );
+    }
+
+    /// Writes the bundle to a new read-only file at `path`.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let write = || -> io::Result<()> {
+            let file = File::create(path)?;
+            let mut permissions = file.metadata()?.permissions();
+            permissions.set_readonly(true);
+            file.set_permissions(permissions)?;
+            self.write_to(file)
+        };
+        write().map_err(|source| Error::WriteBundleError {
+            path: path.display().to_string(),
+            source,
+        })
+    }
+
+    fn write_to<W: Write>(&self, output: W) -> io::Result<()> {
+        let mut archive = tar::Builder::new(GzEncoder::new(output, Compression::default()));
+        let manifest = serde_json::to_vec_pretty(&self.manifest)?;
+        append(&mut archive, MANIFEST_PATH, &manifest)?;
+        for (path, contents) in &self.files {
+            append(&mut archive, path, contents)?;
+        }
+        archive.into_inner()?.finish()?;
+        Ok(())
+    }
+
+    /// Reads a bundle written by `collect_bundle`.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        File::open(path)
+            .and_then(Self::read_from)
+            .map_err(|source| Error::ReadBundleError {
+                path: path.display().to_string(),
+                source,
+            })
+    }
+
+    fn read_from<R: Read>(input: R) -> io::Result<Self> {
+        let mut archive = tar::Archive::new(GzDecoder::new(input));
+        let mut manifest = None;
+        let mut files = BTreeMap::new();
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.to_string_lossy().into_owned();
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents)?;
+            if path == MANIFEST_PATH {
+                manifest = Some(serde_json::from_slice(&contents)?);
+            } else {
+                files.insert(path, contents);
+            }
+        }
+        let manifest = manifest.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "The bundle has no manifest")
+        })?;
+        Ok(Bundle { manifest, files })
+    }
+
+    /// Writes the bundle in the text report format, so that it can be sent to support.
+    pub fn write_report<W: Write>(&self, mut output: W) -> io::Result<()> {
+        write_metadata(&mut output, &self.manifest.metadata)?;
+        for entry in &self.manifest.entries {
+            let contents = match (&entry.path, &entry.error) {
+                (Some(path), _) => self.files.get(path).map(Vec::as_slice).unwrap_or_default(),
+                (None, Some(error)) => error.as_bytes(),
+                (None, None) => continue,
+            };
+            write_log(&mut output, &entry.source, contents)?;
+        }
+        Ok(())
+    }
+}
+
+fn append<W: Write>(archive: &mut tar::Builder<W>, path: &str, contents: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(&mut header, path, contents)
+}
+
+/// Replaces the values of all `SECRET_SETTINGS` that are set.
+fn strip_secrets(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_SETTINGS.contains(&key.as_str()) && !value.is_null() {
+                    *value = serde_json::Value::String("[REDACTED]".to_owned());
+                } else {
+                    strip_secrets(value);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(strip_secrets),
+        _ => (),
+    }
+}
+
+fn tunnel_state_name(tunnel_state: &types::TunnelState) -> &'static str {
+    use types::tunnel_state::State;
+    match tunnel_state.state {
+        Some(State::Disconnected(_)) => "disconnected",
+        Some(State::Connecting(_)) => "connecting",
+        Some(State::Connected(_)) => "connected",
+        Some(State::Disconnecting(_)) => "disconnecting",
+        Some(State::Error(_)) => "error",
+        None => "unknown",
+    }
+}
+
+/// Returns the DNS backend most recently logged by the daemon.
+#[cfg(target_os = "linux")]
+fn dns_backend(report: &ProblemReport) -> Option<String> {
+    const PATTERN: &str = "Managing DNS via ";
+    report
+        .logs
+        .iter()
+        .filter(|log| !log.is_error && log.label.ends_with("daemon.log"))
+        .flat_map(|log| log.content.lines().rev())
+        .find_map(|line| {
+            line.find(PATTERN)
+                .map(|index| line[index + PATTERN.len()..].trim().to_owned())
+        })
+}
+
+#[cfg(target_os = "macos")]
+fn dns_backend(_report: &ProblemReport) -> Option<String> {
+    Some("SystemConfiguration".to_owned())
+}
+
+#[cfg(windows)]
+fn dns_backend(_report: &ProblemReport) -> Option<String> {
+    Some("registry".to_owned())
+}
+
+fn run_command(command: &[&str]) -> Result<String, String> {
+    let output = Command::new(command[0])
+        .args(&command[1..])
+        .output()
+        .map_err(|error| {
+            error.display_chain_with_msg(&format!("Failed to run {}", command.join(" ")))
+        })?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed with {}: {}",
+            command.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_secrets() {
+        let mut settings = serde_json::json!({
+            "account_token": "1234123412341234",
+            "wireguard": { "private_key": "secret", "port": 53 },
+            "allow_lan": true,
+        });
+        strip_secrets(&mut settings);
+        assert_eq!(
+            settings,
+            serde_json::json!({
+                "account_token": "[REDACTED]",
+                "wireguard": { "private_key": "[REDACTED]", "port": 53 },
+                "allow_lan": true,
+            })
+        );
+    }
+
+    #[test]
+    fn bundles_settings_without_secrets() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let settings_path = temp_dir.path().join(SETTINGS_PATH);
+        fs::write(
+            &settings_path,
+            serde_json::json!({
+                "account_token": "1234123412341234",
+                "bridge_settings": {
+                    "http_connect": {
+                        "address": "192.0.2.1:8080",
+                        "auth": { "username": "proxy-user", "password": "proxy-password" },
+                    },
+                },
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let mut report = ProblemReport::new(RedactOptions::default());
+        let mut bundle = Bundle::new(BTreeMap::new());
+        bundle.add_settings_file(&mut report, &settings_path);
+        let mut archive = Vec::new();
+        bundle.write_to(&mut archive).unwrap();
+        let read_bundle = Bundle::read_from(archive.as_slice()).unwrap();
+
+        let settings = String::from_utf8(read_bundle.files[SETTINGS_PATH].clone()).unwrap();
+        for secret in &["1234123412341234", "proxy-user", "proxy-password"] {
+            assert!(!settings.contains(secret), "{} was not removed", secret);
+        }
+        assert!(settings.contains("http_connect"));
+    }
+
+    #[test]
+    fn write_and_read_bundle() {
+        let mut metadata = BTreeMap::new();
+        metadata.insert("os".to_owned(), "test".to_owned());
+        let mut bundle = Bundle::new(metadata);
+        bundle.add_file(
+            EntryKind::Log,
+            "logs/00-daemon.log".to_owned(),
+            "/var/log/mullvad-vpn/daemon.log".to_owned(),
+            b"log line".to_vec(),
+        );
+        bundle.add_error(
+            EntryKind::Routes,
+            "ip route".to_owned(),
+            "Failed".to_owned(),
+        );
+
+        let mut archive = Vec::new();
+        bundle.write_to(&mut archive).unwrap();
+        let read_bundle = Bundle::read_from(archive.as_slice()).unwrap();
+
+        assert_eq!(read_bundle.manifest, bundle.manifest);
+        assert_eq!(read_bundle.files, bundle.files);
+
+        let mut report = Vec::new();
+        read_bundle.write_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert_eq!(
+            ProblemReport::parse_metadata(&report),
+            Some(bundle.manifest.metadata)
+        );
+        assert!(report.contains("log line"));
+    }
+}
@@ -1,7 +1,9 @@
 #![deny(rust_2018_idioms)]
 
+use flate2::read::GzDecoder;
 use lazy_static::lazy_static;
-use regex::Regex;
+use redaction::{RedactOptions, RedactionCategory, RedactionSummary};
+use regex::{Captures, Regex};
 use std::{
     borrow::Cow,
     cmp::min,
@@ -14,15 +16,24 @@ use std::{
 use talpid_types::ErrorExt;
 
 
+#[cfg(not(target_os = "android"))]
+pub mod bundle;
 pub mod metadata;
+pub mod redaction;
 
-/// Maximum number of bytes to read from each log file
+/// Maximum number of bytes to read from each log file, or each decompressed rotated log file
 const LOG_MAX_READ_BYTES: usize = 128 * 1024;
 const EXTRA_BYTES: usize = 32 * 1024;
-/// Fit five logs plus some system information in the report.
-const REPORT_MAX_SIZE: usize = (5 * LOG_MAX_READ_BYTES) + EXTRA_BYTES;
+/// Fit five logs, two rotated logs and some system information in the report.
+const REPORT_MAX_SIZE: usize = (7 * LOG_MAX_READ_BYTES) + EXTRA_BYTES;
 
 
+/// File extensions that are not redacted as top-level domains in strict mode.
+const FILE_EXTENSIONS: &[&str] = &[
+    "conf", "dll", "exe", "gz", "json", "log", "old", "pid", "plist", "rs", "service", "sh", "sig",
+    "so", "sock", "sys", "tar", "txt",
+];
+
 /// Field delimeter in generated problem report
 const LOG_DELIMITER: &str = "====================";
 
@@ -74,6 +85,33 @@ pub enum Error {
 
     #[error(display = "Unable to find cache directory")]
     ObtainCacheDirectory(#[error(source)] mullvad_paths::Error),
+
+    #[error(display = "Failed to write the diagnostic bundle to {}", path)]
+    WriteBundleError {
+        path: String,
+        #[error(source)]
+        source: io::Error,
+    },
+
+    #[error(display = "Failed to read the diagnostic bundle at {}", path)]
+    ReadBundleError {
+        path: String,
+        #[error(source)]
+        source: io::Error,
+    },
+
+    #[error(display = "The diagnostic bundle contains no file at {}", _0)]
+    MissingBundleFile(String),
+
+    #[error(display = "Invalid redaction pattern: {}", pattern)]
+    InvalidRedactPattern {
+        pattern: String,
+        #[error(source)]
+        source: regex::Error,
+    },
+
+    #[error(display = "Failed to read the answer from standard input")]
+    ReadStdinError(#[error(source)] io::Error),
 }
 
 /// These are errors that can happen during problem report collection.
@@ -103,13 +141,58 @@ pub enum LogError {
     NoLocalAppDataDir,
 }
 
+/// Collects a report into `output_path`. Returns a summary of what was redacted from it.
 pub fn collect_report(
     extra_logs: &[&Path],
     output_path: &Path,
-    redact_custom_strings: Vec<String>,
+    redact_options: RedactOptions,
     #[cfg(target_os = "android")] android_log_dir: &Path,
-) -> Result<(), Error> {
-    let mut problem_report = ProblemReport::new(redact_custom_strings);
+) -> Result<RedactionSummary, Error> {
+    let report = TextReport(build_report(
+        extra_logs,
+        redact_options,
+        LOG_MAX_READ_BYTES,
+        #[cfg(target_os = "android")]
+        android_log_dir,
+    ));
+    report.write(output_path)?;
+    Ok(report.0.redaction_summary)
+}
+
+/// A text report that has been collected and redacted, but not written to disk yet.
+pub struct TextReport(ProblemReport);
+
+impl TextReport {
+    /// Collects a report in the same way as `collect_report`, but keeps it in memory.
+    #[cfg(not(target_os = "android"))]
+    pub fn collect(extra_logs: &[&Path], redact_options: RedactOptions) -> Self {
+        TextReport(build_report(extra_logs, redact_options, LOG_MAX_READ_BYTES))
+    }
+
+    /// Returns a summary of what was redacted from the report.
+    pub fn redaction_summary(&self) -> &RedactionSummary {
+        &self.0.redaction_summary
+    }
+
+    /// Writes the report to a new read-only file at `output_path`.
+    pub fn write(&self, output_path: &Path) -> Result<(), Error> {
+        write_problem_report(output_path, &self.0).map_err(|source| Error::WriteReportError {
+            path: output_path.display().to_string(),
+            source,
+        })
+    }
+}
+
+/// Collects system information and redacted logs from the daemon, the frontend and `extra_logs`.
+/// At most `log_max_read_bytes` bytes are read from the end of each log.
+fn build_report(
+    extra_logs: &[&Path],
+    redact_options: RedactOptions,
+    log_max_read_bytes: usize,
+    #[cfg(target_os = "android")] android_log_dir: &Path,
+) -> ProblemReport {
+    let mut problem_report = ProblemReport::new(redact_options);
+    problem_report.log_max_read_bytes = log_max_read_bytes;
 
     let daemon_logs_dir = {
         #[cfg(target_os = "android")]
@@ -167,14 +250,11 @@ pub fn collect_report(
     }
 
     problem_report.add_logs(extra_logs);
-
-    write_problem_report(&output_path, &problem_report).map_err(|source| Error::WriteReportError {
-        path: output_path.display().to_string(),
-        source,
-    })
+    problem_report
 }
 
-/// Returns an iterator over all files in the given directory that has the `.log` extension.
+/// Returns an iterator over all files in the given directory that has the `.log` extension, and
+/// the most recent compressed rotation of each of them.
 fn list_logs(
     log_dir: PathBuf,
 ) -> Result<impl Iterator<Item = Result<PathBuf, LogError>>, LogError> {
@@ -190,7 +270,7 @@ fn list_logs(
                 Ok(dir_entry) => {
                     let path = dir_entry.path();
 
-                    if path.extension() == log_extension {
+                    if path.extension() == log_extension || is_latest_rotated_log(&path) {
                         Some(Ok(path))
                     } else {
                         None
@@ -236,6 +316,14 @@ fn frontend_log_dir() -> Option<Result<PathBuf, LogError>> {
     }
 }
 
+/// Returns whether `path` is the most recent compressed rotation of a log, e.g. `daemon.1.log.gz`.
+fn is_latest_rotated_log(path: &Path) -> bool {
+    match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy().ends_with(".1.log.gz"),
+        None => false,
+    }
+}
+
 fn is_tunnel_log(path: &Path) -> bool {
     match path.file_name() {
         Some(file_name) => file_name.to_string_lossy().contains("openvpn"),
@@ -260,14 +348,7 @@ pub fn send_problem_report(
     report_path: &Path,
     cache_dir: &Path,
 ) -> Result<(), Error> {
-    let report_content = normalize_newlines(
-        read_file_lossy(report_path, REPORT_MAX_SIZE).map_err(|source| {
-            Error::ReadProblemReportError {
-                path: report_path.display().to_string(),
-                source,
-            }
-        })?,
-    );
+    let report_content = read_report(report_path)?;
     let metadata =
         ProblemReport::parse_metadata(&report_content).unwrap_or_else(|| metadata::collect());
 
@@ -314,6 +395,33 @@ pub fn send_problem_report(
     })
 }
 
+/// Reads a report collected by `collect_report`. Diagnostic bundles are converted to the same
+/// format.
+fn read_report(report_path: &Path) -> Result<String, Error> {
+    #[cfg(not(target_os = "android"))]
+    if bundle::is_bundle(report_path) {
+        let bundle = bundle::Bundle::read(report_path)?;
+        let mut report_content = Vec::new();
+        bundle
+            .write_report(&mut report_content)
+            .map_err(|source| Error::ReadBundleError {
+                path: report_path.display().to_string(),
+                source,
+            })?;
+        return Ok(normalize_newlines(
+            String::from_utf8_lossy(&report_content).into_owned(),
+        ));
+    }
+
+    let report_content = read_file_lossy(report_path, REPORT_MAX_SIZE).map_err(|source| {
+        Error::ReadProblemReportError {
+            path: report_path.display().to_string(),
+            source,
+        }
+    })?;
+    Ok(normalize_newlines(report_content))
+}
+
 fn write_problem_report(path: &Path, problem_report: &ProblemReport) -> io::Result<()> {
     let file = File::create(path)?;
     let mut permissions = file.metadata()?.permissions();
@@ -326,22 +434,24 @@ fn write_problem_report(path: &Path, problem_report: &ProblemReport) -> io::Resu
 #[derive(Debug)]
 struct ProblemReport {
     metadata: BTreeMap<String, String>,
-    logs: Vec<(String, String)>,
+    logs: Vec<ReportLog>,
     log_paths: HashSet<PathBuf>,
-    redact_custom_strings: Vec<String>,
+    log_max_read_bytes: usize,
+    redact_options: RedactOptions,
+    redaction_summary: RedactionSummary,
 }
 
 impl ProblemReport {
     /// Creates a new problem report with system information. Logs can be added with `add_log`.
-    /// Logs will have all strings in `redact_custom_strings` removed from them.
-    pub fn new(mut redact_custom_strings: Vec<String>) -> Self {
-        redact_custom_strings.retain(|redact| !redact.is_empty());
-
+    /// Logs will have everything specified by `redact_options` removed from them.
+    pub fn new(redact_options: RedactOptions) -> Self {
         ProblemReport {
             metadata: metadata::collect(),
             logs: Vec::new(),
             log_paths: HashSet::new(),
-            redact_custom_strings,
+            log_max_read_bytes: LOG_MAX_READ_BYTES,
+            redact_options,
+            redaction_summary: RedactionSummary::default(),
         }
     }
 
@@ -363,15 +473,22 @@ impl ProblemReport {
         let expanded_path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
         if self.log_paths.insert(expanded_path.clone()) {
             let redacted_path = self.redact(&expanded_path.to_string_lossy());
-            let content = self.redact(&read_file_lossy(path, LOG_MAX_READ_BYTES).unwrap_or_else(
-                |error| {
-                    error.display_chain_with_msg(&format!(
-                        "Error reading the contents of log file: {}",
-                        expanded_path.display()
-                    ))
-                },
-            ));
-            self.logs.push((redacted_path, content));
+            let content = if path.extension() == Some(OsStr::new("gz")) {
+                read_gzip_file_lossy(path, self.log_max_read_bytes)
+            } else {
+                read_file_lossy(path, self.log_max_read_bytes)
+            };
+            let content = self.redact(&content.unwrap_or_else(|error| {
+                error.display_chain_with_msg(&format!(
+                    "Error reading the contents of log file: {}",
+                    expanded_path.display()
+                ))
+            }));
+            self.logs.push(ReportLog {
+                label: redacted_path,
+                content,
+                is_error: false,
+            });
             println!("Adding {}", expanded_path.display());
         }
     }
@@ -379,32 +496,57 @@ impl ProblemReport {
     /// Attach an error to the report.
     pub fn add_error(&mut self, message: &'static str, error: &impl ErrorExt) {
         let redacted_error = self.redact(&error.display_chain());
-        self.logs.push((message.to_string(), redacted_error));
+        self.logs.push(ReportLog {
+            label: message.to_string(),
+            content: redacted_error,
+            is_error: true,
+        });
     }
 
-    fn redact(&self, input: &str) -> String {
-        let out1 = Self::redact_account_number(input);
-        let out2 = Self::redact_home_dir(&out1);
-        let out3 = Self::redact_network_info(&out2);
-        let out4 = Self::redact_guids(&out3);
-        self.redact_custom_strings(&out4).to_string()
+    /// Redacts `input` and records what was redacted in the redaction summary.
+    fn redact(&mut self, input: &str) -> String {
+        let summary = &mut self.redaction_summary;
+        let out1 = Self::redact_account_number(summary, input);
+        let out2 = Self::redact_home_dir(summary, &out1);
+        let out3 = Self::redact_network_info(summary, &out2);
+        let out4 = Self::redact_guids(summary, &out3);
+        let out5 = if self.redact_options.strict {
+            let out = Self::redact_relay_names(summary, &out4);
+            Cow::from(Self::redact_hostnames(summary, &out).into_owned())
+        } else {
+            out4
+        };
+        Self::redact_custom_patterns(summary, &self.redact_options.custom_patterns, &out5)
+            .to_string()
     }
 
-    fn redact_account_number(input: &str) -> Cow<'_, str> {
+    fn redact_account_number<'a>(summary: &mut RedactionSummary, input: &'a str) -> Cow<'a, str> {
         lazy_static! {
             static ref RE: Regex = Regex::new("\\d{16}").unwrap();
         }
-        RE.replace_all(input, "[REDACTED ACCOUNT NUMBER]")
+        replace_matches(
+            summary,
+            RedactionCategory::AccountNumber,
+            &RE,
+            input,
+            "[REDACTED ACCOUNT NUMBER]",
+        )
     }
 
-    fn redact_home_dir(input: &str) -> Cow<'_, str> {
+    fn redact_home_dir<'a>(summary: &mut RedactionSummary, input: &'a str) -> Cow<'a, str> {
         match dirs_next::home_dir() {
-            Some(home) => Cow::from(input.replace(home.to_string_lossy().as_ref(), "~")),
+            Some(home) => replace_string(
+                summary,
+                RedactionCategory::HomeDir,
+                &home.to_string_lossy(),
+                input,
+                "~",
+            ),
             None => Cow::from(input),
         }
     }
 
-    fn redact_network_info(input: &str) -> Cow<'_, str> {
+    fn redact_network_info<'a>(summary: &mut RedactionSummary, input: &'a str) -> Cow<'a, str> {
         lazy_static! {
             static ref RE: Regex = {
                 let boundary = "[^0-9a-zA-Z.:]";
@@ -418,42 +560,90 @@ impl ProblemReport {
                 Regex::new(&combined_pattern).unwrap()
             };
         }
-        RE.replace_all(input, "$start[REDACTED]")
+        RE.replace_all(input, |captures: &Captures<'_>| {
+            let start = &captures["start"];
+            let matched = captures.get(0).unwrap();
+            summary.record(
+                RedactionCategory::NetworkInfo,
+                input,
+                matched.start() + start.len()..matched.end(),
+            );
+            format!("{}[REDACTED]", start)
+        })
     }
 
-    fn redact_guids(input: &str) -> Cow<'_, str> {
+    fn redact_guids<'a>(summary: &mut RedactionSummary, input: &'a str) -> Cow<'a, str> {
         lazy_static! {
             static ref RE: Regex = Regex::new(
                 r#"(?i)\{?[A-F0-9]{8}-[A-F0-9]{4}-[A-F0-9]{4}-[A-F0-9]{4}-[A-F0-9]{12}\}?"#
             )
             .unwrap();
         }
-        RE.replace_all(input, "[REDACTED]")
+        replace_matches(summary, RedactionCategory::Guid, &RE, input, "[REDACTED]")
+    }
+
+    /// Redacts relay hostnames such as `se-got-wg-001` and `se3-wireguard`.
+    fn redact_relay_names<'a>(summary: &mut RedactionSummary, input: &'a str) -> Cow<'a, str> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(concat!(
+                r#"(?i)\b[a-z]{2}[0-9]*-(?:[a-z]{3}-)?"#,
+                r#"(?:wg|ovpn|br|wireguard|openvpn|bridge)(?:-[0-9]+)?\b"#
+            ))
+            .unwrap();
+        }
+        replace_matches(
+            summary,
+            RedactionCategory::RelayName,
+            &RE,
+            input,
+            "[REDACTED]",
+        )
+    }
+
+    /// Redacts domain names, except for what looks like file names.
+    fn redact_hostnames<'a>(summary: &mut RedactionSummary, input: &'a str) -> Cow<'a, str> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(concat!(
+                r#"(?i)\b(?:[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?\.)+"#,
+                r#"(?P<tld>[a-z][a-z0-9-]{1,62})\b"#
+            ))
+            .unwrap();
+        }
+        RE.replace_all(input, |captures: &Captures<'_>| {
+            let matched = captures.get(0).unwrap();
+            let tld = captures["tld"].to_lowercase();
+            if FILE_EXTENSIONS.contains(&tld.as_str()) {
+                return matched.as_str().to_owned();
+            }
+            summary.record(RedactionCategory::Hostname, input, matched.range());
+            "[REDACTED]".to_owned()
+        })
     }
 
-    fn redact_custom_strings<'a>(&self, input: &'a str) -> Cow<'a, str> {
-        // Can probably me made a lot faster with aho-corasick if optimization is ever needed.
+    fn redact_custom_patterns<'a>(
+        summary: &mut RedactionSummary,
+        custom_patterns: &[Regex],
+        input: &'a str,
+    ) -> Cow<'a, str> {
         let mut out = Cow::from(input);
-        for redact in &self.redact_custom_strings {
-            out = out.replace(redact, "[REDACTED]").into()
+        for pattern in custom_patterns {
+            out = replace_matches(
+                summary,
+                RedactionCategory::CustomPattern,
+                pattern,
+                &out,
+                "[REDACTED]",
+            )
+            .into_owned()
+            .into()
         }
         out
     }
 
     fn write_to<W: Write>(&self, mut output: W) -> io::Result<()> {
-        // IMPORTANT: Make sure this implementation stays in sync with `parse_metadata` below.
-        write_line!(output, "System information:")?;
-        for (key, value) in &self.metadata {
-            write_line!(output, "{}: {}", key, value)?;
-        }
-        // Write empty line to separate metadata from first log
-        write_line!(output)?;
-        for &(ref label, ref content) in &self.logs {
-            write_line!(output, "{}", LOG_DELIMITER)?;
-            write_line!(output, "Log: {}", label)?;
-            write_line!(output, "{}", LOG_DELIMITER)?;
-            output.write_all(content.as_bytes())?;
-            write_line!(output)?;
+        write_metadata(&mut output, &self.metadata)?;
+        for log in &self.logs {
+            write_log(&mut output, &log.label, log.content.as_bytes())?;
         }
         Ok(())
     }
@@ -461,7 +651,7 @@ impl ProblemReport {
     /// Tries to parse out the metadata map from a string that is supposed to be a report written by
     /// this struct.
     pub fn parse_metadata(report: &str) -> Option<BTreeMap<String, String>> {
-        // IMPORTANT: Make sure this implementation stays in sync with `write_to` above.
+        // IMPORTANT: Make sure this implementation stays in sync with `write_metadata` below.
         const PATTERN: &str = ": ";
         let mut lines = report.lines();
         if lines.next() != Some("System information:") {
@@ -483,6 +673,66 @@ impl ProblemReport {
     }
 }
 
+/// Replaces all matches of `regex` with `replacement` and records them in `summary`.
+fn replace_matches<'a>(
+    summary: &mut RedactionSummary,
+    category: RedactionCategory,
+    regex: &Regex,
+    input: &'a str,
+    replacement: &str,
+) -> Cow<'a, str> {
+    regex.replace_all(input, |captures: &Captures<'_>| {
+        summary.record(category, input, captures.get(0).unwrap().range());
+        replacement.to_owned()
+    })
+}
+
+/// Replaces all occurrences of `needle` with `replacement` and records them in `summary`.
+fn replace_string<'a>(
+    summary: &mut RedactionSummary,
+    category: RedactionCategory,
+    needle: &str,
+    input: &'a str,
+    replacement: &str,
+) -> Cow<'a, str> {
+    if needle.is_empty() || !input.contains(needle) {
+        return Cow::from(input);
+    }
+    for (index, _) in input.match_indices(needle) {
+        summary.record(category, input, index..index + needle.len());
+    }
+    Cow::from(input.replace(needle, replacement))
+}
+
+#[derive(Debug)]
+struct ReportLog {
+    /// Redacted path of the log file, or a description of the error.
+    label: String,
+    content: String,
+    /// Whether `content` is an error message rather than the contents of a log file.
+    is_error: bool,
+}
+
+/// Writes the system information at the start of a report.
+fn write_metadata<W: Write>(output: &mut W, metadata: &BTreeMap<String, String>) -> io::Result<()> {
+    // IMPORTANT: Make sure this implementation stays in sync with `parse_metadata` above.
+    write_line!(output, "System information:")?;
+    for (key, value) in metadata {
+        write_line!(output, "{}: {}", key, value)?;
+    }
+    // Write empty line to separate metadata from first log
+    write_line!(output)
+}
+
+/// Writes a log section of a report.
+fn write_log<W: Write>(output: &mut W, label: &str, content: &[u8]) -> io::Result<()> {
+    write_line!(output, "{}", LOG_DELIMITER)?;
+    write_line!(output, "Log: {}", label)?;
+    write_line!(output, "{}", LOG_DELIMITER)?;
+    output.write_all(content)?;
+    write_line!(output)
+}
+
 fn build_mac_regex() -> String {
     let octet = "[[:xdigit:]]{2}"; // 0 - ff
 
@@ -569,6 +819,29 @@ fn read_file_lossy(path: &Path, max_bytes: usize) -> io::Result<String> {
     Ok(String::from_utf8_lossy(&buffer).into_owned())
 }
 
+/// Helper to lossily read a gzip-compressed file to a `String`. If the decompressed size exceeds
+/// the given `max_bytes`, only the last `max_bytes` bytes are kept.
+fn read_gzip_file_lossy(path: &Path, max_bytes: usize) -> io::Result<String> {
+    let mut decoder = GzDecoder::new(File::open(path)?);
+    let mut chunk = [0u8; 8 * 1024];
+    let mut buffer = Vec::new();
+    loop {
+        let read = match decoder.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        buffer.extend_from_slice(&chunk[..read]);
+        // Drop the beginning of the log once it is no longer needed, but not on every read
+        if buffer.len() > max_bytes.saturating_mul(2) {
+            buffer.drain(..buffer.len() - max_bytes);
+        }
+    }
+    let start = buffer.len().saturating_sub(max_bytes);
+    Ok(String::from_utf8_lossy(&buffer[start..]).into_owned())
+}
+
 #[cfg(not(windows))]
 fn normalize_newlines(text: String) -> String {
     text
@@ -639,21 +912,71 @@ mod tests {
         assert_does_not_redact("09:47:59");
     }
 
+    #[test]
+    fn redacts_relay_names_and_hostnames_in_strict_mode() {
+        let mut report = ProblemReport::new(RedactOptions {
+            strict: true,
+            ..RedactOptions::default()
+        });
+        assert_eq!(
+            report.redact("Connecting to se-got-wg-001 via api.mullvad.net, see daemon.log"),
+            "Connecting to [REDACTED] via [REDACTED], see daemon.log"
+        );
+        assert_does_not_redact("se-got-wg-001 api.mullvad.net");
+    }
+
+    #[test]
+    fn redacts_custom_patterns() {
+        let mut report = ProblemReport::new(RedactOptions {
+            custom_patterns: vec![
+                Regex::new(&regex::escape("secret.")).unwrap(),
+                Regex::new("user-[0-9]+").unwrap(),
+            ],
+            ..RedactOptions::default()
+        });
+        assert_eq!(
+            report.redact("secret. secrets user-42 user-7"),
+            "[REDACTED] secrets [REDACTED] [REDACTED]"
+        );
+
+        let categories = &report.redaction_summary.categories;
+        assert_eq!(categories[&RedactionCategory::CustomPattern].count, 3);
+        assert_eq!(
+            categories[&RedactionCategory::CustomPattern].samples[1],
+            "[REDACTED] secrets >>user-42<< user-7"
+        );
+    }
+
+    #[test]
+    fn reads_end_of_gzip_file() {
+        use flate2::{write::GzEncoder, Compression};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("daemon.1.log.gz");
+        let contents: String = (0..10_000).map(|line| format!("line {}\n", line)).collect();
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(contents.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        assert_eq!(read_gzip_file_lossy(&path, 10).unwrap(), "line 9999\n");
+        assert_eq!(read_gzip_file_lossy(&path, usize::MAX).unwrap(), contents);
+    }
+
     fn assert_redacts(input: &str) {
-        let report = ProblemReport::new(vec![]);
+        let mut report = ProblemReport::new(RedactOptions::default());
         let actual = report.redact(&format!("pre {} post", input));
         assert_eq!("pre [REDACTED] post", actual);
     }
 
     fn assert_does_not_redact(input: &str) {
-        let report = ProblemReport::new(vec![]);
+        let mut report = ProblemReport::new(RedactOptions::default());
         let res = report.redact(input);
         assert_eq!(input, res);
     }
 
     #[test]
     fn parse_metadata() {
-        let report = ProblemReport::new(Vec::new());
+        let report = ProblemReport::new(RedactOptions::default());
         let mut report_data = Vec::new();
         report
             .write_to(&mut report_data)
@@ -681,4 +1004,11 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn includes_latest_rotated_log() {
+        assert!(is_latest_rotated_log(Path::new("daemon.1.log.gz")));
+        assert!(!is_latest_rotated_log(Path::new("daemon.2.log.gz")));
+        assert!(!is_latest_rotated_log(Path::new("daemon.log")));
+    }
 }
@@ -1,8 +1,19 @@
 #![deny(rust_2018_idioms)]
 
 use clap::{crate_authors, crate_name};
-use mullvad_problem_report::{collect_report, metadata, send_problem_report, Error};
-use std::{env, path::Path, process};
+use mullvad_problem_report::{
+    bundle::{self, Bundle},
+    metadata,
+    redaction::{RedactOptions, RedactionSummary},
+    send_problem_report, Error, TextReport,
+};
+use regex::Regex;
+use std::{
+    env,
+    io::{self, BufRead, Write},
+    path::Path,
+    process,
+};
 use talpid_types::ErrorExt;
 
 
@@ -49,11 +60,39 @@ fn run() -> Result<(), Error> {
                 )
                 .arg(
                     clap::Arg::with_name("redact")
-                        .help("List of words and expressions to remove from the report")
+                        .help(
+                            "Regular expressions whose matches are removed from the report. \
+                            Characters such as '.' must be escaped with a backslash to be matched \
+                            literally, as done by regex::escape.",
+                        )
                         .long("redact")
-                        .value_name("PHRASE")
+                        .value_name("PATTERN")
                         .multiple(true)
                         .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("strict")
+                        .help("Also remove hostnames and relay names from the report")
+                        .long("strict"),
+                )
+                .arg(
+                    clap::Arg::with_name("preview")
+                        .help(
+                            "Show what was removed from the report and review it before it is \
+                            saved. More expressions to remove can be added interactively.",
+                        )
+                        .long("preview"),
+                )
+                .arg(
+                    clap::Arg::with_name("format")
+                        .help(
+                            "Whether to write a single text file, or a diagnostic bundle with \
+                            separate logs, settings and system snapshots.",
+                        )
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["text", "bundle"])
+                        .default_value("text"),
                 ),
         )
         .subcommand(
@@ -83,20 +122,70 @@ fn run() -> Result<(), Error> {
                         .takes_value(true)
                         .required(false),
                 ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("inspect")
+                .about("Show the contents of a collected diagnostic bundle")
+                .arg(
+                    clap::Arg::with_name("bundle")
+                        .help("The path to a diagnostic bundle.")
+                        .value_name("PATH")
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::with_name("file")
+                        .help("Only print the file at this path in the bundle.")
+                        .long("file")
+                        .short("f")
+                        .takes_value(true),
+                ),
         );
 
     let matches = app.get_matches();
 
     if let Some(collect_matches) = matches.subcommand_matches("collect") {
-        let redact_custom_strings = collect_matches
-            .values_of_lossy("redact")
-            .unwrap_or_else(Vec::new);
+        let redact_custom_patterns = collect_matches
+            .values_of("redact")
+            .into_iter()
+            .flatten()
+            .filter(|pattern| !pattern.is_empty())
+            .map(parse_pattern)
+            .collect::<Result<Vec<_>, Error>>()?;
+        let mut redact_options = RedactOptions {
+            custom_patterns: redact_custom_patterns,
+            strict: collect_matches.is_present("strict"),
+        };
         let extra_logs = collect_matches
             .values_of_os("extra_logs")
             .map(|os_values| os_values.map(Path::new).collect())
             .unwrap_or_else(Vec::new);
         let output_path = Path::new(collect_matches.value_of_os("output").unwrap());
-        collect_report(&extra_logs, output_path, redact_custom_strings)?;
+        let report = loop {
+            let report = if collect_matches.value_of("format") == Some("bundle") {
+                let (bundle, redaction_summary) =
+                    bundle::collect_bundle(&extra_logs, redact_options.clone())?;
+                CollectedReport::Bundle(bundle, redaction_summary)
+            } else {
+                CollectedReport::Text(TextReport::collect(&extra_logs, redact_options.clone()))
+            };
+            if !collect_matches.is_present("preview") {
+                break report;
+            }
+
+            println!();
+            println!("Removed from the problem report:");
+            print!("{}", report.redaction_summary());
+            println!();
+            match review()? {
+                Review::Accept => break report,
+                Review::Discard => {
+                    println!("The problem report was discarded");
+                    return Ok(());
+                }
+                Review::Redact(pattern) => redact_options.custom_patterns.push(pattern),
+            }
+        };
+        report.write(output_path)?;
 
         let expanded_output_path = output_path
             .canonicalize()
@@ -115,7 +204,132 @@ fn run() -> Result<(), Error> {
         let user_message = send_matches.value_of("message").unwrap_or("");
         let cache_dir = mullvad_paths::get_cache_dir()?;
         send_problem_report(user_email, user_message, report_path, &cache_dir)
+    } else if let Some(inspect_matches) = matches.subcommand_matches("inspect") {
+        let bundle_path = Path::new(inspect_matches.value_of_os("bundle").unwrap());
+        inspect_bundle(
+            &Bundle::read(bundle_path)?,
+            inspect_matches.value_of("file"),
+        )
     } else {
         unreachable!("No sub command given");
     }
 }
+
+fn inspect_bundle(bundle: &Bundle, file: Option<&str>) -> Result<(), Error> {
+    if let Some(path) = file {
+        let contents = bundle
+            .files
+            .get(path)
+            .ok_or_else(|| Error::MissingBundleFile(path.to_owned()))?;
+        println!("{}", String::from_utf8_lossy(contents));
+        return Ok(());
+    }
+
+    let manifest = &bundle.manifest;
+    println!("Bundle format version: {}", manifest.format_version);
+    println!("Collected: {}", manifest.created);
+    println!(
+        "Tunnel state: {}",
+        manifest.tunnel_state.as_deref().unwrap_or("unknown")
+    );
+    println!(
+        "DNS backend: {}",
+        manifest.dns_backend.as_deref().unwrap_or("unknown")
+    );
+    println!();
+    println!("System information:");
+    for (key, value) in &manifest.metadata {
+        println!("  {}: {}", key, value);
+    }
+    println!();
+    println!("Contents:");
+    for entry in &manifest.entries {
+        match (&entry.path, &entry.error) {
+            (Some(path), _) => println!(
+                "  {:<12} {} ({} bytes, from {})",
+                format!("{:?}", entry.kind),
+                path,
+                bundle.files.get(path).map(Vec::len).unwrap_or(0),
+                entry.source
+            ),
+            (None, error) => println!(
+                "  {:<12} not collected from {}: {}",
+                format!("{:?}", entry.kind),
+                entry.source,
+                error.as_deref().unwrap_or("unknown error")
+            ),
+        }
+    }
+    println!();
+    println!(
+        "Print a file with: {} inspect <PATH> --file <FILE>",
+        env::args().next().unwrap()
+    );
+    Ok(())
+}
+
+fn parse_pattern(pattern: &str) -> Result<Regex, Error> {
+    Regex::new(pattern).map_err(|source| Error::InvalidRedactPattern {
+        pattern: pattern.to_owned(),
+        source,
+    })
+}
+
+enum Review {
+    Accept,
+    Discard,
+    Redact(Regex),
+}
+
+/// Asks the user whether to keep the report as it is, discard it or remove more from it.
+fn review() -> Result<Review, Error> {
+    let stdin = io::stdin();
+    loop {
+        print!(
+            "Enter a regular expression to also remove, \"discard\" to delete the report, or \
+            nothing to keep it: "
+        );
+        io::stdout().flush().map_err(Error::ReadStdinError)?;
+
+        let mut answer = String::new();
+        if stdin
+            .lock()
+            .read_line(&mut answer)
+            .map_err(Error::ReadStdinError)?
+            == 0
+        {
+            println!();
+            return Ok(Review::Accept);
+        }
+        match answer.trim() {
+            "" => return Ok(Review::Accept),
+            "discard" => return Ok(Review::Discard),
+            pattern => match parse_pattern(pattern) {
+                Ok(pattern) => return Ok(Review::Redact(pattern)),
+                Err(error) => eprintln!("{}", error.display_chain()),
+            },
+        }
+    }
+}
+
+/// A report that has been collected, but not written to disk yet.
+enum CollectedReport {
+    Text(TextReport),
+    Bundle(Bundle, RedactionSummary),
+}
+
+impl CollectedReport {
+    fn redaction_summary(&self) -> &RedactionSummary {
+        match self {
+            CollectedReport::Text(report) => report.redaction_summary(),
+            CollectedReport::Bundle(_, redaction_summary) => redaction_summary,
+        }
+    }
+
+    fn write(&self, output_path: &Path) -> Result<(), Error> {
+        match self {
+            CollectedReport::Text(report) => report.write(output_path),
+            CollectedReport::Bundle(bundle, _) => bundle.write(output_path),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Options for what to redact from problem reports, and a summary of what was redacted so that
+//! users can review it before sending a report.
+
+use regex::Regex;
+use std::{collections::BTreeMap, fmt, ops::Range};
+
+/// Maximum number of sample matches kept per category.
+const MAX_SAMPLES: usize = 3;
+/// Number of characters of context shown on each side of a sample match.
+const SAMPLE_CONTEXT: usize = 30;
+
+/// Marks the start and end of the match in a sample.
+const HIGHLIGHT_START: &str = ">>";
+const HIGHLIGHT_END: &str = "<<";
+
+/// What to redact in addition to account numbers, the home directory, IP and MAC addresses and
+/// GUIDs, which are always redacted.
+#[derive(Debug, Clone, Default)]
+pub struct RedactOptions {
+    /// Regular expressions whose matches are removed. Strings can be matched literally by
+    /// escaping them with `regex::escape`.
+    pub custom_patterns: Vec<Regex>,
+    /// Whether to also remove hostnames and relay names.
+    pub strict: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RedactionCategory {
+    AccountNumber,
+    HomeDir,
+    NetworkInfo,
+    Guid,
+    RelayName,
+    Hostname,
+    CustomPattern,
+}
+
+impl fmt::Display for RedactionCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            RedactionCategory::AccountNumber => "Account numbers",
+            RedactionCategory::HomeDir => "Home directory",
+            RedactionCategory::NetworkInfo => "IP and MAC addresses",
+            RedactionCategory::Guid => "GUIDs",
+            RedactionCategory::RelayName => "Relay names",
+            RedactionCategory::Hostname => "Hostnames",
+            RedactionCategory::CustomPattern => "Custom patterns",
+        };
+        f.write_str(description)
+    }
+}
+
+/// Number of redactions in a category, and some of the redacted text in context.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CategorySummary {
+    pub count: usize,
+    /// Lines containing a match, with the match highlighted.
+    pub samples: Vec<String>,
+}
+
+/// Everything that was redacted from a report, by category.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RedactionSummary {
+    pub categories: BTreeMap<RedactionCategory, CategorySummary>,
+}
+
+impl RedactionSummary {
+    /// Records a redaction of `input[range]`.
+    pub(crate) fn record(&mut self, category: RedactionCategory, input: &str, range: Range<usize>) {
+        let summary = self.categories.entry(category).or_default();
+        summary.count += 1;
+        if summary.samples.len() < MAX_SAMPLES {
+            summary.samples.push(highlight(input, range));
+        }
+    }
+}
+
+impl fmt::Display for RedactionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.categories.is_empty() {
+            return writeln!(f, "Nothing was redacted");
+        }
+        for (category, summary) in &self.categories {
+            writeln!(f, "{}: {}", category, summary.count)?;
+            for sample in &summary.samples {
+                writeln!(f, "    {}", sample)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the line around `input[range]`, shortened and with the match highlighted.
+fn highlight(input: &str, range: Range<usize>) -> String {
+    let line_start = input[..range.start]
+        .rfind('\n')
+        .map_or(0, |index| index + 1);
+    let line_end = input[range.end..]
+        .find('\n')
+        .map_or(input.len(), |index| range.end + index);
+
+    let before = &input[line_start..range.start];
+    let before_chars = before.chars().count();
+    let before: String = before
+        .chars()
+        .skip(before_chars.saturating_sub(SAMPLE_CONTEXT))
+        .collect();
+    let after: String = input[range.end..line_end]
+        .chars()
+        .take(SAMPLE_CONTEXT)
+        .collect();
+
+    format!(
+        "{}{}{}{}{}",
+        before.trim_start(),
+        HIGHLIGHT_START,
+        &input[range],
+        HIGHLIGHT_END,
+        after.trim_end()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_match_in_line() {
+        let input = "first line\nconnecting to 1.2.3.4 now\nlast line";
+        let start = input.find("1.2.3.4").unwrap();
+        assert_eq!(
+            highlight(input, start..start + 7),
+            "connecting to >>1.2.3.4<< now"
+        );
+    }
+}
@@ -1,11 +1,39 @@
 /// Intended to be used to pre-load a relay list when creating an installer for the Mullvad VPN
-/// app.
-use mullvad_rpc::{rest::Error as RestError, MullvadRpcRuntime, RelayListProxy};
-use std::process;
+/// app, and to sign and verify relay lists hosted on mirrors.
+///
+/// Usage:
+///   relay_list fetch <path>                    Download a relay list to <path>
+///   relay_list sign <secret key file> <path>   Sign the relay list at <path>
+///   relay_list verify <path> [public key]      Verify the signature of the relay list at <path>
+///
+/// Signatures are stored in `<path>.sig`. The secret key file contains a base64 encoded 32 byte
+/// Ed25519 seed. Lists are verified against the pinned key unless a base64 encoded public key is
+/// given.
+use mullvad_rpc::{
+    rest::Error as RestError, signature, MullvadRpcRuntime, RelayListProxy, SignedRelayList,
+};
+use std::{env, fs, path::Path, process};
 use talpid_types::ErrorExt;
 
 #[tokio::main]
 async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["fetch", path] => fetch(Path::new(path)).await,
+        ["sign", key_path, path] => sign(Path::new(key_path), Path::new(path)),
+        ["verify", path] => verify(Path::new(path), None),
+        ["verify", path, public_key] => verify(Path::new(path), Some(public_key)),
+        _ => {
+            eprintln!("Usage: relay_list fetch <path>");
+            eprintln!("       relay_list sign <secret key file> <path>");
+            eprintln!("       relay_list verify <path> [public key]");
+            process::exit(1);
+        }
+    }
+}
+
+async fn fetch(path: &Path) {
     let mut runtime =
         MullvadRpcRuntime::new(tokio::runtime::Handle::current()).expect("Failed to load runtime");
 
@@ -14,22 +42,113 @@ async fn main() {
         .await;
 
     let relay_list = match relay_list_request {
-        Ok(relay_list) => relay_list,
+        Ok(Some(relay_list)) => relay_list,
+        Ok(None) => {
+            eprintln!("The API responded that the relay list is unchanged, but none was cached");
+            process::exit(1);
+        }
         Err(RestError::TimeoutError(_)) => {
             eprintln!("Request timed out");
             process::exit(2);
         }
-        Err(e @ RestError::DeserializeError(_)) => {
-            eprintln!(
-                "{}",
-                e.display_chain_with_msg("Failed to deserialize relay list")
-            );
-            process::exit(3);
-        }
         Err(e) => {
             eprintln!("{}", e.display_chain_with_msg("Failed to fetch relay list"));
             process::exit(1);
         }
     };
-    println!("{}", serde_json::to_string_pretty(&relay_list).unwrap());
+
+    if let Err(error) = relay_list.parse() {
+        eprintln!(
+            "{}",
+            error.display_chain_with_msg("Failed to deserialize relay list")
+        );
+        process::exit(3);
+    }
+
+    // Bundled and mirrored lists are not compared against the API by entity tag
+    let relay_list = SignedRelayList {
+        etag: None,
+        ..relay_list
+    };
+    if let Err(error) = relay_list.write_to_file(path) {
+        eprintln!(
+            "{}",
+            error.display_chain_with_msg(&format!("Failed to write {}", path.display()))
+        );
+        process::exit(1);
+    }
+}
+
+fn sign(key_path: &Path, path: &Path) {
+    let seed = String::from_utf8_lossy(&read(key_path)).trim().to_owned();
+    let seed = base64::decode(seed).unwrap_or_else(|error| {
+        eprintln!(
+            "{}",
+            error.display_chain_with_msg("Failed to decode secret key")
+        );
+        process::exit(1);
+    });
+    let signature = signature::sign(&seed, &read(path)).unwrap_or_else(|error| {
+        eprintln!(
+            "{}",
+            error.display_chain_with_msg("Failed to sign relay list")
+        );
+        process::exit(1);
+    });
+    write(&signature::signature_path(path), signature.as_bytes());
+}
+
+fn verify(path: &Path, public_key: Option<&str>) {
+    let relay_list = SignedRelayList::read_from_file(path).unwrap_or_else(|error| {
+        eprintln!(
+            "{}",
+            error.display_chain_with_msg(&format!("Failed to read {}", path.display()))
+        );
+        process::exit(1);
+    });
+
+    let result = match public_key {
+        Some(public_key) => base64::decode(public_key)
+            .map_err(signature::Error::Decode)
+            .and_then(|public_key| {
+                signature::verify_with_key(
+                    &public_key,
+                    &relay_list.data,
+                    relay_list.signature.as_deref(),
+                )
+            }),
+        None => signature::verify(&relay_list.data, relay_list.signature.as_deref()),
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error.display_chain_with_msg("Verification failed"));
+        process::exit(4);
+    }
+    if let Err(error) = relay_list.parse() {
+        eprintln!(
+            "{}",
+            error.display_chain_with_msg("Failed to deserialize relay list")
+        );
+        process::exit(3);
+    }
+    println!("Relay list signature is valid");
+}
+
+fn read(path: &Path) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|error| {
+        eprintln!(
+            "{}",
+            error.display_chain_with_msg(&format!("Failed to read {}", path.display()))
+        );
+        process::exit(1);
+    })
+}
+
+fn write(path: &Path, data: &[u8]) {
+    if let Err(error) = fs::write(path, data) {
+        eprintln!(
+            "{}",
+            error.display_chain_with_msg(&format!("Failed to write {}", path.display()))
+        );
+        process::exit(1);
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{rest::RequestCommand, tcp_stream::TcpStream};
+use crate::{proxy::ApiConnectionMode, rest::RequestCommand, tcp_stream::TcpStream};
 use futures::{
     channel::{mpsc, oneshot},
     sink::SinkExt,
@@ -19,7 +19,7 @@ use std::{
     net::{IpAddr, SocketAddr},
     pin::Pin,
     str::{self, FromStr},
-    sync::Arc,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
     time::Duration,
 };
@@ -42,6 +42,7 @@ pub struct HttpsConnectorWithSni {
     handle: Handle,
     sni_hostname: Option<String>,
     service_tx: Option<mpsc::Sender<RequestCommand>>,
+    connection_mode: Arc<Mutex<ApiConnectionMode>>,
     #[cfg(target_os = "android")]
     socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
     tls: Arc<rustls::ClientConfig>,
@@ -55,18 +56,14 @@ impl HttpsConnectorWithSni {
     ///
     /// Takes number of DNS worker threads.
     ///
-    /// This uses hyper's default `HttpConnector`, and default `TlsConnector`.
-    /// If you wish to use something besides the defaults, use `From::from`.
+    /// This uses hyper's default `HttpConnector`, and the TLS configuration created by
+    /// `create_tls_config`.
     pub fn new(
         handle: Handle,
         sni_hostname: Option<String>,
+        tls: Arc<rustls::ClientConfig>,
         #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
     ) -> Self {
-        let mut config = rustls::ClientConfig::new();
-        config.enable_sni = true;
-        config.root_store = Self::read_cert_store();
-        config.versions = vec![ProtocolVersion::TLSv1_3];
-
         HttpsConnectorWithSni {
             next_socket_id: 0,
             handle,
@@ -74,11 +71,22 @@ impl HttpsConnectorWithSni {
             #[cfg(target_os = "android")]
             socket_bypass_tx,
             service_tx: None,
-            tls: Arc::new(config),
+            connection_mode: Arc::new(Mutex::new(ApiConnectionMode::Direct)),
+            tls,
         }
     }
 
-    fn read_cert_store() -> rustls::RootCertStore {
+    /// Creates the TLS configuration used to connect to the API. Fails if an additional root
+    /// certificate is given but cannot be loaded.
+    pub fn create_tls_config() -> io::Result<Arc<rustls::ClientConfig>> {
+        let mut config = rustls::ClientConfig::new();
+        config.enable_sni = true;
+        config.root_store = Self::read_cert_store()?;
+        config.versions = vec![ProtocolVersion::TLSv1_3];
+        Ok(Arc::new(config))
+    }
+
+    fn read_cert_store() -> io::Result<rustls::RootCertStore> {
         let mut cert_store = rustls::RootCertStore::empty();
 
         let (num_certs_added, num_failures) = cert_store
@@ -88,7 +96,23 @@ impl HttpsConnectorWithSni {
             panic!("Failed to add new root cert");
         }
 
-        cert_store
+        // Trust an additional root certificate, such as the one of a mock API
+        #[cfg(feature = "api-override")]
+        if let Some(path) = std::env::var_os("MULLVAD_API_CA_CERT") {
+            log::debug!("Trusting API root certificate {:?}", path);
+            let cert = std::fs::read(&path)?;
+            match cert_store.add_pem_file(&mut &cert[..]) {
+                Ok((num_certs_added, _)) if num_certs_added > 0 => (),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("No valid certificate in {:?}", path),
+                    ))
+                }
+            }
+        }
+
+        Ok(cert_store)
     }
 
 
@@ -97,6 +121,12 @@ impl HttpsConnectorWithSni {
         self.service_tx = Some(service_tx);
     }
 
+    /// Set the connection mode used by new connections. It can be changed after the connector
+    /// has been constructed by modifying the shared value.
+    pub(crate) fn set_connection_mode(&mut self, connection_mode: Arc<Mutex<ApiConnectionMode>>) {
+        self.connection_mode = connection_mode;
+    }
+
     fn next_id(&mut self) -> usize {
         let next_id = self.next_socket_id;
         self.next_socket_id = self.next_socket_id.wrapping_add(1);
@@ -186,6 +216,11 @@ impl Service<Uri> for HttpsConnectorWithSni {
                 io::Error::new(io::ErrorKind::InvalidInput, "invalid url, missing host")
             });
         let service_tx = self.service_tx.clone();
+        let connection_mode = self
+            .connection_mode
+            .lock()
+            .expect("connection mode lock poisoned")
+            .clone();
 
         let socket_id = self.next_id();
         let handle = self.handle.clone();
@@ -205,12 +240,28 @@ impl Service<Uri> for HttpsConnectorWithSni {
                 .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid hostname"))?;
             let addr = Self::resolve_address(&uri).await?;
 
-            let tokio_connection = Self::open_socket(
-                addr,
-                #[cfg(target_os = "android")]
-                socket_bypass_tx,
-            )
-            .await?;
+            let tokio_connection = match connection_mode {
+                ApiConnectionMode::Direct => {
+                    Self::open_socket(
+                        addr,
+                        #[cfg(target_os = "android")]
+                        socket_bypass_tx,
+                    )
+                    .await?
+                }
+                ApiConnectionMode::Proxied(proxy_config) => {
+                    let mut stream = Self::open_socket(
+                        proxy_config.proxy_address(),
+                        #[cfg(target_os = "android")]
+                        socket_bypass_tx,
+                    )
+                    .await?;
+                    timeout(CONNECT_TIMEOUT, proxy_config.connect(&mut stream, addr))
+                        .await
+                        .map_err(|err| io::Error::new(io::ErrorKind::TimedOut, err))??;
+                    stream
+                }
+            };
 
             let (socket_shutdown_tx, socket_shutdown_rx) = oneshot::channel();
 
@@ -255,6 +306,6 @@ mod test {
 
     #[test]
     fn test_cert_loading() {
-        let _certs = HttpsConnectorWithSni::read_cert_store();
+        let _certs = HttpsConnectorWithSni::read_cert_store().unwrap();
     }
 }
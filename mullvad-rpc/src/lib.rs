@@ -11,16 +11,22 @@ use mullvad_types::{
 use std::{
     collections::BTreeMap,
     future::Future,
+    io,
     net::{IpAddr, Ipv4Addr, SocketAddr},
     path::Path,
     sync::Arc,
 };
 use talpid_types::{net::wireguard, ErrorExt};
+use tokio_rustls::rustls;
 
 
 pub mod availability;
 use availability::{ApiAvailability, ApiAvailabilityHandle};
+pub mod metrics;
+pub mod proxy;
+use proxy::{ConnectionModeProvider, DirectConnectionModeProvider};
 pub mod rest;
+pub mod signature;
 
 mod https_client_with_sni;
 use crate::https_client_with_sni::HttpsConnectorWithSni;
@@ -32,7 +38,7 @@ mod address_cache;
 mod relay_list;
 pub use address_cache::{AddressCache, CurrentAddressChangeListener};
 pub use hyper::StatusCode;
-pub use relay_list::RelayListProxy;
+pub use relay_list::{RelayListProxy, SignedRelayList};
 
 /// Error code returned by the Mullvad API if the voucher has alreaby been used.
 pub const VOUCHER_USED: &str = "VOUCHER_USED";
@@ -85,6 +91,8 @@ pub struct MullvadRpcRuntime {
     handle: tokio::runtime::Handle,
     pub address_cache: AddressCache,
     api_availability: availability::ApiAvailability,
+    metrics: metrics::RequestMetrics,
+    tls_config: Arc<rustls::ClientConfig>,
     #[cfg(target_os = "android")]
     socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
 }
@@ -99,6 +107,9 @@ pub enum Error {
 
     #[error(display = "API availability check failed")]
     ApiCheckError(#[error(source)] availability::Error),
+
+    #[error(display = "Failed to load the API root certificate")]
+    RootCertificateError(#[error(source)] io::Error),
 }
 
 impl MullvadRpcRuntime {
@@ -119,6 +130,9 @@ impl MullvadRpcRuntime {
             handle,
             address_cache: AddressCache::new(vec![API_ADDRESS.clone()], None)?,
             api_availability: ApiAvailability::new(availability::State::default()),
+            metrics: metrics::RequestMetrics::default(),
+            tls_config: HttpsConnectorWithSni::create_tls_config()
+                .map_err(Error::RootCertificateError)?,
             #[cfg(target_os = "android")]
             socket_bypass_tx,
         })
@@ -180,6 +194,9 @@ impl MullvadRpcRuntime {
             handle,
             address_cache,
             api_availability: ApiAvailability::new(availability::State::default()),
+            metrics: metrics::RequestMetrics::default(),
+            tls_config: HttpsConnectorWithSni::create_tls_config()
+                .map_err(Error::RootCertificateError)?,
             #[cfg(target_os = "android")]
             socket_bypass_tx,
         })
@@ -194,10 +211,15 @@ impl MullvadRpcRuntime {
     }
 
     /// Creates a new request service and returns a handle to it.
-    fn new_request_service(&mut self, sni_hostname: Option<String>) -> rest::RequestServiceHandle {
+    fn new_request_service(
+        &mut self,
+        sni_hostname: Option<String>,
+        connection_mode_provider: Box<dyn ConnectionModeProvider>,
+    ) -> rest::RequestServiceHandle {
         let https_connector = HttpsConnectorWithSni::new(
             self.handle.clone(),
             sni_hostname,
+            self.tls_config.clone(),
             #[cfg(target_os = "android")]
             self.socket_bypass_tx.clone(),
         );
@@ -207,6 +229,8 @@ impl MullvadRpcRuntime {
             self.handle.clone(),
             self.api_availability.handle(),
             self.address_cache.clone(),
+            connection_mode_provider,
+            self.metrics.clone(),
         );
         let handle = service.handle();
         self.handle.spawn(service.into_future());
@@ -215,7 +239,17 @@ impl MullvadRpcRuntime {
 
     /// Returns a request factory initialized to create requests for the master API
     pub fn mullvad_rest_handle(&mut self) -> rest::MullvadRestHandle {
-        let service = self.new_request_service(Some(API_HOST.clone()));
+        self.mullvad_rest_handle_with_connection_modes(Box::new(DirectConnectionModeProvider))
+    }
+
+    /// Returns a request factory initialized to create requests for the master API. When requests
+    /// fail due to network errors, the next connection mode is obtained from
+    /// `connection_mode_provider`.
+    pub fn mullvad_rest_handle_with_connection_modes(
+        &mut self,
+        connection_mode_provider: Box<dyn ConnectionModeProvider>,
+    ) -> rest::MullvadRestHandle {
+        let service = self.new_request_service(Some(API_HOST.clone()), connection_mode_provider);
         let factory = rest::RequestFactory::new(
             API_HOST.clone(),
             Box::new(self.address_cache.clone()),
@@ -232,13 +266,18 @@ impl MullvadRpcRuntime {
 
     /// Returns a new request service handle
     pub fn rest_handle(&mut self) -> rest::RequestServiceHandle {
-        self.new_request_service(None)
+        self.new_request_service(None, Box::new(DirectConnectionModeProvider))
     }
 
     pub fn handle(&mut self) -> &mut tokio::runtime::Handle {
         &mut self.handle
     }
 
+    /// Returns the statistics on requests made by all request services created by this runtime.
+    pub fn metrics(&self) -> metrics::RequestMetrics {
+        self.metrics.clone()
+    }
+
     pub fn availability_handle(&self) -> ApiAvailabilityHandle {
         self.api_availability.handle()
     }
@@ -0,0 +1,162 @@
+//! Statistics on the requests made by the request services.
+
+use crate::rest::Error;
+use chrono::Utc;
+use hyper::StatusCode;
+use mullvad_types::api_status::{
+    ApiStatus, EndpointStatus, FailureCount, RequestError, RequestErrorKind,
+    LATENCY_BUCKET_BOUNDS_MS,
+};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use talpid_types::ErrorExt;
+
+/// Collects request statistics per endpoint and connection mode. Clones share the same
+/// statistics.
+#[derive(Clone, Default)]
+pub struct RequestMetrics {
+    endpoints: Arc<Mutex<BTreeMap<(String, String), EndpointStatus>>>,
+}
+
+impl RequestMetrics {
+    /// Records a response, which counts as a failure if its status code is not successful.
+    pub(crate) fn record_response(
+        &self,
+        endpoint: &str,
+        connection_mode: &str,
+        status: StatusCode,
+        latency: Duration,
+    ) {
+        self.update(endpoint, connection_mode, |endpoint_status| {
+            let latency_ms = latency.as_millis();
+            let bucket = LATENCY_BUCKET_BOUNDS_MS
+                .iter()
+                .position(|bound| latency_ms <= u128::from(*bound))
+                .unwrap_or(LATENCY_BUCKET_BOUNDS_MS.len());
+            endpoint_status.latency[bucket].count += 1;
+
+            if status.is_success() {
+                endpoint_status.successes += 1;
+            } else {
+                record_failure(
+                    endpoint_status,
+                    RequestErrorKind::ApiStatus,
+                    format!("Unexpected response status code {}", status),
+                );
+            }
+        });
+    }
+
+    /// Records a request that failed without a response. Cancelled requests are ignored.
+    pub(crate) fn record_error(&self, endpoint: &str, connection_mode: &str, error: &Error) {
+        let kind = match error {
+            Error::Aborted(_) => return,
+            Error::HyperError(_) => RequestErrorKind::Network,
+            Error::TimeoutError(_) => RequestErrorKind::Timeout,
+            Error::ApiError(..) => RequestErrorKind::ApiStatus,
+            _ => RequestErrorKind::Other,
+        };
+        let message = error.display_chain();
+        self.update(endpoint, connection_mode, |endpoint_status| {
+            record_failure(endpoint_status, kind, message)
+        });
+    }
+
+    fn update(
+        &self,
+        endpoint: &str,
+        connection_mode: &str,
+        update_fn: impl FnOnce(&mut EndpointStatus),
+    ) {
+        let mut endpoints = self.endpoints.lock().expect("metrics lock poisoned");
+        let endpoint_status = endpoints
+            .entry((endpoint.to_owned(), connection_mode.to_owned()))
+            .or_insert_with(|| {
+                EndpointStatus::new(endpoint.to_owned(), connection_mode.to_owned())
+            });
+        update_fn(endpoint_status);
+    }
+
+    /// Returns the statistics collected so far.
+    pub fn status(&self) -> ApiStatus {
+        let endpoints = self.endpoints.lock().expect("metrics lock poisoned");
+        ApiStatus {
+            endpoints: endpoints.values().cloned().collect(),
+        }
+    }
+}
+
+fn record_failure(endpoint_status: &mut EndpointStatus, kind: RequestErrorKind, message: String) {
+    match endpoint_status
+        .failures
+        .iter_mut()
+        .find(|failure| failure.kind == kind)
+    {
+        Some(failure) => failure.count += 1,
+        None => {
+            endpoint_status
+                .failures
+                .push(FailureCount { kind, count: 1 });
+            endpoint_status.failures.sort_by_key(|failure| failure.kind);
+        }
+    }
+    endpoint_status.last_error = Some(RequestError {
+        kind,
+        message,
+        timestamp: Utc::now(),
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let metrics = RequestMetrics::default();
+        metrics.record_response(
+            "1.2.3.4:443",
+            "direct",
+            StatusCode::OK,
+            Duration::from_millis(120),
+        );
+        metrics.record_response(
+            "1.2.3.4:443",
+            "direct",
+            StatusCode::SERVICE_UNAVAILABLE,
+            Duration::from_secs(60),
+        );
+        metrics.record_error(
+            "1.2.3.4:443",
+            "SOCKS5 proxy at 10.0.0.1:1080",
+            &Error::SendError,
+        );
+
+        let status = metrics.status();
+        assert_eq!(status.endpoints.len(), 2);
+
+        let direct = &status.endpoints[1];
+        assert_eq!(direct.connection_mode, "direct");
+        assert_eq!(direct.successes, 1);
+        assert_eq!(direct.total_failures(), 1);
+        assert_eq!(direct.latency[1].count, 1);
+        assert_eq!(direct.latency.last().unwrap().count, 1);
+        assert_eq!(
+            direct.last_error.as_ref().map(|error| error.kind),
+            Some(RequestErrorKind::ApiStatus)
+        );
+
+        let proxied = &status.endpoints[0];
+        assert_eq!(proxied.successes, 0);
+        assert_eq!(
+            proxied.failures,
+            vec![FailureCount {
+                kind: RequestErrorKind::Other,
+                count: 1
+            }]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Ways of reaching the API other than connecting to it directly.
+
+use futures::future;
+use std::{
+    fmt,
+    future::Future,
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    pin::Pin,
+};
+use talpid_types::net::{http_connect::http_connect, openvpn::ProxyAuth};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const SOCKS5_VERSION: u8 = 0x05;
+const SOCKS5_NO_AUTHENTICATION: u8 = 0x00;
+const SOCKS5_CMD_CONNECT: u8 = 0x01;
+const SOCKS5_ADDR_IPV4: u8 = 0x01;
+const SOCKS5_ADDR_DOMAIN: u8 = 0x03;
+const SOCKS5_ADDR_IPV6: u8 = 0x04;
+const SOCKS5_REPLY_SUCCEEDED: u8 = 0x00;
+
+/// Describes how connections to the API are established.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiConnectionMode {
+    /// Connect directly to the API.
+    Direct,
+    /// Connect to the API through a proxy.
+    Proxied(ProxyConfig),
+}
+
+impl ApiConnectionMode {
+    /// Returns the remote endpoint that must be reachable for this mode to work, if it differs
+    /// from the API address.
+    pub fn get_endpoint(&self) -> Option<SocketAddr> {
+        match self {
+            ApiConnectionMode::Direct => None,
+            ApiConnectionMode::Proxied(proxy_config) => Some(proxy_config.get_endpoint()),
+        }
+    }
+}
+
+impl fmt::Display for ApiConnectionMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiConnectionMode::Direct => write!(f, "direct"),
+            ApiConnectionMode::Proxied(proxy_config) => proxy_config.fmt(f),
+        }
+    }
+}
+
+/// A proxy that API connections are tunneled through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxyConfig {
+    /// A proxy client listening on localhost, such as a Shadowsocks client, which forwards
+    /// traffic to `peer`.
+    Socks5Local { port: u16, peer: SocketAddr },
+    /// A SOCKS5 server on another host.
+    Socks5Remote(SocketAddr),
+    /// An HTTP proxy that supports the `CONNECT` method.
+    HttpConnect {
+        address: SocketAddr,
+        auth: Option<ProxyAuth>,
+    },
+}
+
+impl ProxyConfig {
+    /// Returns the address that connections are opened to.
+    pub(crate) fn proxy_address(&self) -> SocketAddr {
+        match self {
+            ProxyConfig::Socks5Local { port, .. } => {
+                SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), *port)
+            }
+            ProxyConfig::Socks5Remote(address) | ProxyConfig::HttpConnect { address, .. } => {
+                *address
+            }
+        }
+    }
+
+    /// Returns the remote endpoint that traffic is sent to.
+    pub fn get_endpoint(&self) -> SocketAddr {
+        match self {
+            ProxyConfig::Socks5Local { peer, .. } => *peer,
+            ProxyConfig::Socks5Remote(address) | ProxyConfig::HttpConnect { address, .. } => {
+                *address
+            }
+        }
+    }
+
+    /// Asks the proxy at the other end of `stream` to connect to `target`.
+    pub(crate) async fn connect<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        stream: &mut S,
+        target: SocketAddr,
+    ) -> io::Result<()> {
+        match self {
+            ProxyConfig::Socks5Local { .. } | ProxyConfig::Socks5Remote(_) => {
+                socks5_connect(stream, target).await
+            }
+            ProxyConfig::HttpConnect { auth, .. } => {
+                http_connect(stream, target, auth.as_ref()).await
+            }
+        }
+    }
+}
+
+impl fmt::Display for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyConfig::Socks5Local { port, peer } => {
+                write!(f, "local proxy on port {} to {}", port, peer)
+            }
+            ProxyConfig::Socks5Remote(address) => write!(f, "SOCKS5 proxy at {}", address),
+            ProxyConfig::HttpConnect { address, .. } => write!(f, "HTTP proxy at {}", address),
+        }
+    }
+}
+
+/// Provides the connection modes that a request service uses to reach the API.
+pub trait ConnectionModeProvider: Send {
+    /// Returns the connection mode to use before any connection has failed.
+    fn initial(&self) -> ApiConnectionMode;
+
+    /// Returns the connection mode to use after the current one has failed.
+    fn rotate(&mut self) -> Pin<Box<dyn Future<Output = ApiConnectionMode> + Send>>;
+
+    /// Called once a request has succeeded using the current connection mode.
+    fn succeeded(&mut self) {}
+}
+
+/// Always connects to the API directly.
+pub struct DirectConnectionModeProvider;
+
+impl ConnectionModeProvider for DirectConnectionModeProvider {
+    fn initial(&self) -> ApiConnectionMode {
+        ApiConnectionMode::Direct
+    }
+
+    fn rotate(&mut self) -> Pin<Box<dyn Future<Output = ApiConnectionMode> + Send>> {
+        Box::pin(future::ready(ApiConnectionMode::Direct))
+    }
+}
+
+/// Asks the SOCKS5 proxy at the other end of `stream` to connect to `target`. No authentication
+/// is supported.
+pub(crate) async fn socks5_connect<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    target: SocketAddr,
+) -> io::Result<()> {
+    stream
+        .write_all(&[SOCKS5_VERSION, 1, SOCKS5_NO_AUTHENTICATION])
+        .await?;
+    let mut method_reply = [0u8; 2];
+    stream.read_exact(&mut method_reply).await?;
+    if method_reply != [SOCKS5_VERSION, SOCKS5_NO_AUTHENTICATION] {
+        return Err(socks5_error("unsupported authentication method"));
+    }
+
+    let mut request = vec![SOCKS5_VERSION, SOCKS5_CMD_CONNECT, 0x00];
+    match target.ip() {
+        IpAddr::V4(address) => {
+            request.push(SOCKS5_ADDR_IPV4);
+            request.extend_from_slice(&address.octets());
+        }
+        IpAddr::V6(address) => {
+            request.push(SOCKS5_ADDR_IPV6);
+            request.extend_from_slice(&address.octets());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS5_VERSION {
+        return Err(socks5_error("invalid reply from proxy"));
+    }
+    if reply[1] != SOCKS5_REPLY_SUCCEEDED {
+        return Err(socks5_error(&format!(
+            "proxy refused to connect, reply code {}",
+            reply[1]
+        )));
+    }
+
+    // Skip the address that the proxy bound to
+    let bound_address_len = match reply[3] {
+        SOCKS5_ADDR_IPV4 => 4,
+        SOCKS5_ADDR_IPV6 => 16,
+        SOCKS5_ADDR_DOMAIN => usize::from(stream.read_u8().await?),
+        _ => return Err(socks5_error("invalid address type in reply from proxy")),
+    };
+    let mut bound_address = vec![0u8; bound_address_len + 2];
+    stream.read_exact(&mut bound_address).await?;
+
+    Ok(())
+}
+
+fn socks5_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("SOCKS5: {}", message))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Accepts a single SOCKS5 client and relays its traffic to the requested target.
+    async fn run_socks5_server(listener: TcpListener) {
+        let (mut client, _) = listener.accept().await.unwrap();
+
+        let mut greeting = [0u8; 3];
+        client.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(greeting, [SOCKS5_VERSION, 1, SOCKS5_NO_AUTHENTICATION]);
+        client
+            .write_all(&[SOCKS5_VERSION, SOCKS5_NO_AUTHENTICATION])
+            .await
+            .unwrap();
+
+        let mut request = [0u8; 10];
+        client.read_exact(&mut request).await.unwrap();
+        assert_eq!(
+            request[..4],
+            [SOCKS5_VERSION, SOCKS5_CMD_CONNECT, 0, SOCKS5_ADDR_IPV4]
+        );
+        let target = SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(
+                request[4], request[5], request[6], request[7],
+            )),
+            u16::from_be_bytes([request[8], request[9]]),
+        );
+        let mut upstream = TcpStream::connect(target).await.unwrap();
+
+        let mut reply = vec![SOCKS5_VERSION, SOCKS5_REPLY_SUCCEEDED, 0, SOCKS5_ADDR_IPV4];
+        reply.extend_from_slice(&request[4..]);
+        client.write_all(&reply).await.unwrap();
+
+        let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect() {
+        let target_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_address = target_listener.local_addr().unwrap();
+        let proxy_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_config = ProxyConfig::Socks5Remote(proxy_listener.local_addr().unwrap());
+
+        tokio::spawn(run_socks5_server(proxy_listener));
+        tokio::spawn(async move {
+            let (mut stream, _) = target_listener.accept().await.unwrap();
+            stream.write_all(b"hello").await.unwrap();
+        });
+
+        let mut stream = TcpStream::connect(proxy_config.proxy_address())
+            .await
+            .unwrap();
+        socks5_connect(&mut stream, target_address)
+            .await
+            .expect("failed to connect through proxy");
+        let mut message = [0u8; 5];
+        stream.read_exact(&mut message).await.unwrap();
+        assert_eq!(&message, b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_socks5_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_config = ProxyConfig::Socks5Remote(listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            let (mut client, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            client.read_exact(&mut greeting).await.unwrap();
+            // Only username/password authentication is accepted
+            client.write_all(&[SOCKS5_VERSION, 0x02]).await.unwrap();
+        });
+
+        let mut stream = TcpStream::connect(proxy_config.proxy_address())
+            .await
+            .unwrap();
+        let result = socks5_connect(&mut stream, "127.0.0.1:443".parse().unwrap()).await;
+        assert!(result.is_err());
+    }
+}
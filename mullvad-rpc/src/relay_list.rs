@@ -1,14 +1,18 @@
 /// A module dedicated to retrieving the relay list from the master API.
-use crate::rest;
+use crate::{rest, signature};
 
 use hyper::{header, Method, StatusCode};
 use mullvad_types::{location, relay_list};
-use talpid_types::net::{wireguard, TransportProtocol};
+use talpid_types::net::{obfuscation::ObfuscationType, openvpn, wireguard, TransportProtocol};
 
 use std::{
     collections::BTreeMap,
+    ffi::OsString,
+    fs,
     future::Future,
+    io,
     net::{Ipv4Addr, Ipv6Addr},
+    path::{Path, PathBuf},
     time::Duration,
 };
 
@@ -20,17 +24,20 @@ pub struct RelayListProxy {
 
 const RELAY_LIST_TIMEOUT: Duration = Duration::from_secs(15);
 
+/// Ports of the UDP-over-TCP servers, for relay lists that do not advertise any obfuscators.
+const DEFAULT_UDP2TCP_PORTS: [u16; 3] = [80, 443, 5001];
+
 impl RelayListProxy {
     /// Construct a new relay list rest client
     pub fn new(handle: rest::MullvadRestHandle) -> Self {
         Self { handle }
     }
 
-    /// Fetch the relay list
+    /// Fetch the relay list, along with its signature. The signature is not verified.
     pub fn relay_list(
         &self,
         etag: Option<String>,
-    ) -> impl Future<Output = Result<Option<relay_list::RelayList>, rest::Error>> {
+    ) -> impl Future<Output = Result<Option<SignedRelayList>, rest::Error>> {
         let service = self.handle.service.clone();
         let request = self.handle.factory.request("/v1/relays", Method::GET);
 
@@ -61,16 +68,115 @@ impl RelayListProxy {
                     }
                 });
 
-            Ok(Some(
-                rest::deserialize_body::<ServerRelayList>(response)
-                    .await?
-                    .into_relay_list(etag),
-            ))
+            let signature = response
+                .headers()
+                .get(signature::SIGNATURE_HEADER)
+                .and_then(|signature| signature.to_str().ok())
+                .map(str::to_owned);
+
+            Ok(Some(SignedRelayList {
+                data: rest::read_body(response).await?,
+                signature,
+                etag,
+            }))
         };
         future
     }
 }
 
+/// A relay list in the format served by the API, along with its detached signature.
+#[derive(Debug, Clone)]
+pub struct SignedRelayList {
+    pub data: Vec<u8>,
+    pub signature: Option<String>,
+    pub etag: Option<String>,
+}
+
+impl SignedRelayList {
+    /// Verifies the signature against the pinned relay list key. Relay lists are only required to
+    /// be signed in builds with the `relay-list-signatures` feature. Otherwise, this always
+    /// succeeds.
+    pub fn verify(&self) -> Result<(), signature::Error> {
+        if cfg!(feature = "relay-list-signatures") {
+            signature::verify(&self.data, self.signature.as_deref())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Parses the relay list. This should only be done once the signature has been verified.
+    pub fn parse(&self) -> Result<relay_list::RelayList, serde_json::Error> {
+        serde_json::from_slice::<ServerRelayList>(&self.data)
+            .map(|relay_list| relay_list.into_relay_list(self.etag.clone()))
+    }
+
+    /// Reads a relay list from `path`, along with the signature and entity tag stored next to it.
+    /// The signature is not verified.
+    pub fn read_from_file(path: &Path) -> io::Result<Self> {
+        Ok(SignedRelayList {
+            data: fs::read(path)?,
+            signature: read_optional(&signature::signature_path(path))?,
+            etag: read_optional(&etag_path(path))?.map(|etag| etag.trim().to_owned()),
+        })
+    }
+
+    /// Writes the relay list to `path`, and its signature and entity tag next to it. Each file is
+    /// replaced atomically. The entity tag is removed first and written last, so that it is never
+    /// sent along with a request while the cached list is incomplete. The signature of a previous
+    /// list is removed if this list is unsigned.
+    pub fn write_to_file(&self, path: &Path) -> io::Result<()> {
+        let etag_path = etag_path(path);
+        remove_if_exists(&etag_path)?;
+
+        write_atomically(path, &self.data)?;
+        let signature_path = signature::signature_path(path);
+        match &self.signature {
+            Some(signature) => write_atomically(&signature_path, signature.as_bytes())?,
+            None => remove_if_exists(&signature_path)?,
+        }
+        if let Some(etag) = &self.etag {
+            write_atomically(&etag_path, etag.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the path of the file that the entity tag of the relay list at `path` is stored in.
+fn etag_path(path: &Path) -> PathBuf {
+    let mut etag_path = OsString::from(path.as_os_str());
+    etag_path.push(".etag");
+    PathBuf::from(etag_path)
+}
+
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Writes `data` to a temporary file that then replaces the file at `path`, so that readers never
+/// see a partially written file.
+fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut temp_path = OsString::from(path.as_os_str());
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    fs::write(&temp_path, data)?;
+    fs::rename(&temp_path, path).map_err(|error| {
+        let _ = fs::remove_file(&temp_path);
+        error
+    })
+}
+
 
 #[derive(Debug, serde::Deserialize)]
 struct ServerRelayList {
@@ -131,8 +237,20 @@ impl ServerRelayList {
         openvpn: OpenVpn,
     ) {
         let openvpn_endpoint_data = openvpn.ports;
-        for mut openvpn_relay in openvpn.relays.into_iter() {
+        for openvpn_relay in openvpn.relays.into_iter() {
+            let OpenVpnRelay {
+                relay: mut openvpn_relay,
+                ciphers,
+            } = openvpn_relay;
             openvpn_relay.to_lower();
+            // Relays that do not list their ciphers support all of them
+            let ciphers = match ciphers {
+                Some(ciphers) => ciphers
+                    .iter()
+                    .filter_map(|cipher| cipher.parse().ok())
+                    .collect(),
+                None => openvpn::Cipher::ALL.to_vec(),
+            };
             if let Some((country_code, city_code)) = split_location_code(&openvpn_relay.location) {
                 if let Some(country) = countries.get_mut(country_code) {
                     if let Some(city) = country
@@ -153,10 +271,14 @@ impl ServerRelayList {
                             .iter_mut()
                             .find(|r| r.hostname == openvpn_relay.hostname)
                         {
-                            Some(relay) => relay.tunnels.openvpn = openvpn_endpoint_data.clone(),
+                            Some(relay) => {
+                                relay.tunnels.openvpn = openvpn_endpoint_data.clone();
+                                relay.openvpn_ciphers = ciphers;
+                            }
                             None => {
                                 let mut relay = relay(openvpn_relay, location);
                                 relay.tunnels.openvpn = openvpn_endpoint_data.clone();
+                                relay.openvpn_ciphers = ciphers;
                                 city.relays.push(relay);
                             }
                         };
@@ -174,6 +296,7 @@ impl ServerRelayList {
             port_ranges,
             ipv4_gateway,
             ipv6_gateway,
+            obfuscators,
             relays,
         } = wireguard;
 
@@ -184,8 +307,21 @@ impl ServerRelayList {
                 ipv6_gateway,
                 public_key,
                 protocol: TransportProtocol::Udp,
+                obfuscation: None,
             };
 
+        // Relays that do not list their obfuscators run all of them
+        let relay_obfuscators = |supported: Option<Vec<ObfuscationType>>| match supported {
+            Some(supported) => relay_list::RelayObfuscators {
+                udp2tcp: if supported.contains(&ObfuscationType::Udp2Tcp) {
+                    obfuscators.udp2tcp.clone()
+                } else {
+                    vec![]
+                },
+            },
+            None => obfuscators.clone(),
+        };
+
         for mut wireguard_relay in relays {
             wireguard_relay.relay.to_lower();
             if let Some((country_code, city_code)) =
@@ -210,15 +346,19 @@ impl ServerRelayList {
                             .iter_mut()
                             .find(|r| r.hostname == wireguard_relay.relay.hostname)
                         {
-                            Some(relay) => relay
-                                .tunnels
-                                .wireguard
-                                .push(wireguard_endpoint_data(wireguard_relay.public_key)),
+                            Some(relay) => {
+                                relay
+                                    .tunnels
+                                    .wireguard
+                                    .push(wireguard_endpoint_data(wireguard_relay.public_key));
+                                relay.obfuscators = relay_obfuscators(wireguard_relay.obfuscators);
+                            }
                             None => {
                                 let mut relay = relay(wireguard_relay.relay, location);
                                 relay.ipv6_addr_in = Some(wireguard_relay.ipv6_addr_in);
                                 relay.tunnels.wireguard =
                                     vec![wireguard_endpoint_data(wireguard_relay.public_key)];
+                                relay.obfuscators = relay_obfuscators(wireguard_relay.obfuscators);
                                 city.relays.push(relay);
                             }
                         };
@@ -317,6 +457,8 @@ fn relay(relay: Relay, location: location::Location) -> relay_list::Relay {
         weight: relay.weight,
         tunnels: Default::default(),
         bridges: Default::default(),
+        obfuscators: Default::default(),
+        openvpn_ciphers: vec![],
         location: Some(location),
     }
 }
@@ -332,7 +474,16 @@ struct Location {
 #[derive(Debug, serde::Deserialize)]
 struct OpenVpn {
     ports: Vec<relay_list::OpenVpnEndpointData>,
-    relays: Vec<Relay>,
+    relays: Vec<OpenVpnRelay>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct OpenVpnRelay {
+    #[serde(flatten)]
+    relay: Relay,
+    /// Data channel ciphers supported by the relay. All of them are supported if this is absent.
+    #[serde(default)]
+    ciphers: Option<Vec<String>>,
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -359,15 +510,30 @@ struct Wireguard {
     port_ranges: Vec<(u16, u16)>,
     ipv4_gateway: Ipv4Addr,
     ipv6_gateway: Ipv6Addr,
+    /// Obfuscation servers that run on the WireGuard relays.
+    #[serde(default = "default_obfuscators")]
+    obfuscators: relay_list::RelayObfuscators,
     relays: Vec<WireGuardRelay>,
 }
 
+fn default_obfuscators() -> relay_list::RelayObfuscators {
+    relay_list::RelayObfuscators {
+        udp2tcp: DEFAULT_UDP2TCP_PORTS
+            .iter()
+            .map(|&port| relay_list::Udp2TcpEndpointData { port })
+            .collect(),
+    }
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct WireGuardRelay {
     #[serde(flatten)]
     relay: Relay,
     ipv6_addr_in: Ipv6Addr,
     public_key: wireguard::PublicKey,
+    /// Obfuscation protocols supported by the relay. All of them are supported if this is absent.
+    #[serde(default)]
+    obfuscators: Option<Vec<ObfuscationType>>,
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -375,3 +541,34 @@ struct Bridges {
     shadowsocks: Vec<relay_list::ShadowsocksEndpointData>,
     relays: Vec<Relay>,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("relays.json");
+        let relay_list = SignedRelayList {
+            data: b"{}".to_vec(),
+            signature: Some("signature".to_owned()),
+            etag: Some("\"tag\"".to_owned()),
+        };
+        relay_list.write_to_file(&path).unwrap();
+
+        let read_relay_list = SignedRelayList::read_from_file(&path).unwrap();
+        assert_eq!(read_relay_list.data, relay_list.data);
+        assert_eq!(read_relay_list.signature, relay_list.signature);
+        assert_eq!(read_relay_list.etag, relay_list.etag);
+        assert!(!temp_dir.path().join("relays.json.tmp").exists());
+
+        // A stale entity tag must not be kept for a list without one
+        let relay_list = SignedRelayList {
+            etag: None,
+            ..relay_list
+        };
+        relay_list.write_to_file(&path).unwrap();
+        assert_eq!(SignedRelayList::read_from_file(&path).unwrap().etag, None);
+    }
+}
@@ -1,6 +1,10 @@
 use crate::{
-    address_cache::AddressCache, availability::ApiAvailabilityHandle,
-    https_client_with_sni::HttpsConnectorWithSni, tcp_stream::TcpStreamHandle,
+    address_cache::AddressCache,
+    availability::ApiAvailabilityHandle,
+    https_client_with_sni::HttpsConnectorWithSni,
+    metrics::RequestMetrics,
+    proxy::{ApiConnectionMode, ConnectionModeProvider},
+    tcp_stream::TcpStreamHandle,
 };
 use futures::{
     channel::{mpsc, oneshot},
@@ -14,12 +18,14 @@ use hyper::{
     header::{self, HeaderValue},
     Method, Uri,
 };
+use hyper_rustls::HttpsConnector;
 use std::{
     collections::BTreeMap,
     future::Future,
     mem,
     net::{IpAddr, SocketAddr},
     str::FromStr,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 use talpid_types::ErrorExt;
@@ -96,6 +102,14 @@ pub(crate) struct RequestService {
     in_flight_requests: BTreeMap<u64, AbortHandle>,
     api_availability: ApiAvailabilityHandle,
     address_cache: AddressCache,
+    connection_mode_provider: Box<dyn ConnectionModeProvider>,
+    connection_mode: Arc<Mutex<ApiConnectionMode>>,
+    /// Incremented every time the connection mode changes, so that failures caused by a previous
+    /// connection mode can be ignored.
+    connection_mode_generation: usize,
+    connection_mode_confirmed: bool,
+    rotating_connection_mode: bool,
+    metrics: RequestMetrics,
 }
 
 impl RequestService {
@@ -105,10 +119,15 @@ impl RequestService {
         handle: Handle,
         api_availability: ApiAvailabilityHandle,
         address_cache: AddressCache,
+        connection_mode_provider: Box<dyn ConnectionModeProvider>,
+        metrics: RequestMetrics,
     ) -> RequestService {
         let (command_tx, command_rx) = mpsc::channel(1);
 
+        let connection_mode = Arc::new(Mutex::new(connection_mode_provider.initial()));
+
         connector.set_service_tx(command_tx.clone());
+        connector.set_connection_mode(connection_mode.clone());
         let client = Client::builder().build(connector);
 
         Self {
@@ -121,6 +140,12 @@ impl RequestService {
             handle,
             api_availability,
             address_cache,
+            connection_mode_provider,
+            connection_mode,
+            connection_mode_generation: 0,
+            connection_mode_confirmed: false,
+            rotating_connection_mode: false,
+            metrics,
         }
     }
 
@@ -141,17 +166,43 @@ impl RequestService {
 
                 let hyper_request = request.into_request();
                 let host_addr = get_request_socket_addr(&hyper_request);
+                let endpoint = hyper_request
+                    .uri()
+                    .authority()
+                    .map(|authority| authority.to_string())
+                    .unwrap_or_default();
+                let connection_mode = self
+                    .connection_mode
+                    .lock()
+                    .expect("connection mode lock poisoned")
+                    .to_string();
 
                 let api_availability = self.api_availability.clone();
                 let suspend_fut = api_availability.wait_for_unsuspend();
                 let request_fut = self.client.request(hyper_request).map_err(Error::from);
 
+                let metrics = self.metrics.clone();
+                let response_metrics = metrics.clone();
+                let response_endpoint = endpoint.clone();
+                let response_connection_mode = connection_mode.clone();
                 let (request_future, abort_handle) = abortable(async move {
                     let _ = suspend_fut.await;
-                    request_fut.await
+                    let start = Instant::now();
+                    let response = request_fut.await;
+                    if let Ok(response) = &response {
+                        response_metrics.record_response(
+                            &response_endpoint,
+                            &response_connection_mode,
+                            response.status(),
+                            start.elapsed(),
+                        );
+                    }
+                    response
                 });
                 let address_cache = self.address_cache.clone();
                 let handle = self.handle.clone();
+                let connection_mode_generation = self.connection_mode_generation;
+                let connection_mode_confirmed = self.connection_mode_confirmed;
 
                 let future = async move {
                     let response =
@@ -160,6 +211,29 @@ impl RequestService {
                             .map_err(Error::TimeoutError);
 
                     let response = flatten_result(flatten_result(response));
+                    if let Err(err) = &response {
+                        metrics.record_error(&endpoint, &connection_mode, err);
+                    }
+                    match &response {
+                        Ok(_) if !connection_mode_confirmed => {
+                            let _ = tx
+                                .send(RequestCommand::ConnectionModeSucceeded(
+                                    connection_mode_generation,
+                                ))
+                                .await;
+                        }
+                        Err(err)
+                            if err.is_network_error()
+                                && !api_availability.get_state().is_offline() =>
+                        {
+                            let _ = tx
+                                .send(RequestCommand::ConnectionModeFailed(
+                                    connection_mode_generation,
+                                ))
+                                .await;
+                        }
+                        _ => (),
+                    }
                     if let Some(host_addr) = host_addr {
                         if let Err(err) = &response {
                             if err.is_network_error() {
@@ -211,6 +285,28 @@ impl RequestService {
                 self.in_flight_requests.remove(&id);
             }
 
+            RequestCommand::ConnectionModeSucceeded(generation) => {
+                if generation == self.connection_mode_generation && !self.connection_mode_confirmed
+                {
+                    self.connection_mode_confirmed = true;
+                    self.connection_mode_provider.succeeded();
+                }
+            }
+            RequestCommand::ConnectionModeFailed(generation) => {
+                if generation == self.connection_mode_generation && !self.rotating_connection_mode {
+                    self.rotating_connection_mode = true;
+                    let next_mode = self.connection_mode_provider.rotate();
+                    let mut tx = self.command_tx.clone();
+                    self.handle.spawn(async move {
+                        let next_mode = next_mode.await;
+                        let _ = tx.send(RequestCommand::SetConnectionMode(next_mode)).await;
+                    });
+                }
+            }
+            RequestCommand::SetConnectionMode(connection_mode) => {
+                self.set_connection_mode(connection_mode);
+            }
+
             RequestCommand::Reset(tx) => {
                 self.reset();
                 let _ = tx.send(());
@@ -218,16 +314,40 @@ impl RequestService {
         }
     }
 
-    fn reset(&mut self) {
-        let old_requests = mem::replace(&mut self.in_flight_requests, BTreeMap::new());
-        for (_, abort_handle) in old_requests.into_iter() {
-            abort_handle.abort();
+    fn set_connection_mode(&mut self, new_mode: ApiConnectionMode) {
+        self.rotating_connection_mode = false;
+        self.connection_mode_generation = self.connection_mode_generation.wrapping_add(1);
+        self.connection_mode_confirmed = false;
+
+        {
+            let mut connection_mode = self
+                .connection_mode
+                .lock()
+                .expect("connection mode lock poisoned");
+            if *connection_mode == new_mode {
+                return;
+            }
+            log::info!("Connecting to the API using connection mode: {}", new_mode);
+            *connection_mode = new_mode;
         }
+        // Pooled connections must not be reused, since they use the previous mode
+        self.close_sockets();
+    }
 
+    fn close_sockets(&mut self) {
         let old_sockets = mem::replace(&mut self.sockets, BTreeMap::new());
         for (_, socket) in old_sockets.into_iter() {
             socket.close();
         }
+    }
+
+    fn reset(&mut self) {
+        let old_requests = mem::replace(&mut self.in_flight_requests, BTreeMap::new());
+        for (_, abort_handle) in old_requests.into_iter() {
+            abort_handle.abort();
+        }
+
+        self.close_sockets();
 
         self.next_id = 0;
     }
@@ -276,6 +396,14 @@ impl RequestServiceHandle {
         let _ = done_rx.await;
     }
 
+    /// Switches to a different connection mode, closing any connections that use the current one.
+    pub async fn set_connection_mode(&self, connection_mode: ApiConnectionMode) {
+        let mut tx = self.tx.clone();
+        let _ = tx
+            .send(RequestCommand::SetConnectionMode(connection_mode))
+            .await;
+    }
+
     /// Submits a `RestRequest` for exectuion to the request service.
     pub async fn request(&self, request: RestRequest) -> Result<Response> {
         let (completion_tx, completion_rx) = oneshot::channel();
@@ -301,6 +429,9 @@ pub(crate) enum RequestCommand {
         oneshot::Sender<std::result::Result<Response, Error>>,
     ),
     RequestFinished(u64),
+    ConnectionModeSucceeded(usize),
+    ConnectionModeFailed(usize),
+    SetConnectionMode(ApiConnectionMode),
     SocketOpened(usize, TcpStreamHandle),
     SocketClosed(usize),
     Reset(oneshot::Sender<()>),
@@ -568,7 +699,13 @@ pub fn post_request_with_json<B: serde::Serialize>(
 }
 
 
-pub async fn deserialize_body<T: serde::de::DeserializeOwned>(mut response: Response) -> Result<T> {
+pub async fn deserialize_body<T: serde::de::DeserializeOwned>(response: Response) -> Result<T> {
+    let body = read_body(response).await?;
+    serde_json::from_slice(&body).map_err(Error::DeserializeError)
+}
+
+/// Reads the entire body of a response.
+pub async fn read_body(mut response: Response) -> Result<Vec<u8>> {
     let body_length: usize = response
         .headers()
         .get(header::CONTENT_LENGTH)
@@ -580,8 +717,27 @@ pub async fn deserialize_body<T: serde::de::DeserializeOwned>(mut response: Resp
     while let Some(chunk) = response.body_mut().next().await {
         body.extend(&chunk?);
     }
+    Ok(body)
+}
 
-    serde_json::from_slice(&body).map_err(Error::DeserializeError)
+/// Fetches the body of `uri` from a server other than the API, such as a self-hosted update
+/// channel. Unlike requests executed by the `RequestService`, this does not use the API access
+/// methods, and the server certificate is verified against the public web PKI roots instead of the
+/// root certificate of the API. Both `http` and `https` URIs are accepted.
+pub async fn fetch_external(uri: &str, timeout: Duration) -> Result<Vec<u8>> {
+    let request = RestRequest::get(uri)?.request;
+    let client = Client::builder().build::<_, hyper::Body>(HttpsConnector::with_webpki_roots());
+    let fetch = async move {
+        let response = client.request(request).await.map_err(Error::HyperError)?;
+        let status = response.status();
+        if status != StatusCode::OK {
+            return Err(Error::ApiError(status, status.to_string()));
+        }
+        read_body(response).await
+    };
+    tokio::time::timeout(timeout, fetch)
+        .await
+        .map_err(Error::TimeoutError)?
 }
 
 pub async fn parse_rest_response(
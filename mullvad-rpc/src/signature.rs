@@ -0,0 +1,143 @@
+//! Detached Ed25519 signatures for relay lists. Relay lists are signed with `relay_list sign`,
+//! and the signature is stored next to the list, in a file with the `.sig` extension appended.
+
+use ring::signature::{self, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+/// Response header that is read for the base64 encoded signature of a downloaded relay list.
+/// Relay lists downloaded without it are unsigned.
+pub const SIGNATURE_HEADER: &str = "x-mullvad-signature";
+
+/// Public half of the relay list signing key. Relay lists are only verified against it in builds
+/// with the `relay-list-signatures` feature. This is a placeholder, which must be replaced with the
+/// real key before that feature is enabled. Builds with the `api-override` feature can replace it
+/// using `MULLVAD_RELAY_LIST_KEY`.
+const RELAY_LIST_PUBLIC_KEY: [u8; 32] = [
+    0x81, 0xff, 0xa4, 0x4d, 0x40, 0x9c, 0x90, 0xe1, 0x3f, 0xd7, 0xf8, 0x5d, 0x9b, 0xb1, 0x95, 0x72,
+    0x8a, 0x80, 0x2b, 0x06, 0xa4, 0x52, 0x85, 0x92, 0x19, 0xe5, 0x3a, 0x88, 0xb3, 0x6b, 0x7a, 0x85,
+];
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// No signature was provided.
+    #[error(display = "The relay list is not signed")]
+    Unsigned,
+
+    /// The signature or key is not valid base64.
+    #[error(display = "Failed to decode signature or key")]
+    Decode(#[error(source)] base64::DecodeError),
+
+    /// The signature does not match the data and the public key.
+    #[error(display = "The relay list signature is invalid")]
+    InvalidSignature,
+
+    /// The secret key seed is not 32 bytes long.
+    #[error(display = "Invalid secret key")]
+    InvalidKey,
+}
+
+// Override the key that relay lists must be signed with, e.g. when using a mock API.
+#[cfg(feature = "api-override")]
+lazy_static::lazy_static! {
+    static ref RELAY_LIST_KEY_OVERRIDE: Option<Vec<u8>> = std::env::var("MULLVAD_RELAY_LIST_KEY")
+        .ok()
+        .and_then(|key| {
+            let key = base64::decode(key.trim()).ok();
+            if key.is_some() {
+                log::debug!("Overriding relay list key");
+            }
+            key
+        });
+}
+
+/// Verifies a base64 encoded signature against the pinned relay list key.
+pub fn verify(data: &[u8], signature: Option<&str>) -> Result<(), Error> {
+    #[cfg(feature = "api-override")]
+    if let Some(key) = &*RELAY_LIST_KEY_OVERRIDE {
+        return verify_with_key(key, data, signature);
+    }
+    verify_with_key(&RELAY_LIST_PUBLIC_KEY, data, signature)
+}
+
+/// Verifies a base64 encoded signature against the given public key.
+pub fn verify_with_key(
+    public_key: &[u8],
+    data: &[u8],
+    signature: Option<&str>,
+) -> Result<(), Error> {
+    let signature =
+        base64::decode(signature.ok_or(Error::Unsigned)?.trim()).map_err(Error::Decode)?;
+    UnparsedPublicKey::new(&signature::ED25519, public_key)
+        .verify(data, &signature)
+        .map_err(|_| Error::InvalidSignature)
+}
+
+/// Signs `data` using the secret key derived from a 32 byte seed. Returns the base64 encoded
+/// signature.
+pub fn sign(seed: &[u8], data: &[u8]) -> Result<String, Error> {
+    let key_pair = Ed25519KeyPair::from_seed_unchecked(seed).map_err(|_| Error::InvalidKey)?;
+    Ok(base64::encode(key_pair.sign(data).as_ref()))
+}
+
+/// Returns the public key corresponding to a 32 byte seed.
+pub fn public_key(seed: &[u8]) -> Result<Vec<u8>, Error> {
+    let key_pair = Ed25519KeyPair::from_seed_unchecked(seed).map_err(|_| Error::InvalidKey)?;
+    Ok(key_pair.public_key().as_ref().to_vec())
+}
+
+/// Returns the path of the signature file for `path`.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut signature_path = OsString::from(path.as_os_str());
+    signature_path.push(".sig");
+    PathBuf::from(signature_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEST_SEED: [u8; 32] = [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+        25, 26, 27, 28, 29, 30, 31,
+    ];
+    const TEST_DATA: &[u8] = br#"{"locations":{}}"#;
+    const TEST_SIGNATURE: &str =
+        "27lfxyXb4TKfR7oVpyfriSLD1yyULfs1p6gQ/0C1ibpq1NTXUT59xCRJ0S91keslpbqjOGvCVCo8n3GO+5UcDQ==";
+
+    #[test]
+    fn test_sign_and_verify() {
+        let public_key = public_key(&TEST_SEED).unwrap();
+        let signature = sign(&TEST_SEED, TEST_DATA).unwrap();
+        assert_eq!(signature, TEST_SIGNATURE);
+        verify_with_key(&public_key, TEST_DATA, Some(&signature)).unwrap();
+    }
+
+    #[test]
+    fn test_reject_tampered() {
+        let public_key = public_key(&TEST_SEED).unwrap();
+        assert!(matches!(
+            verify_with_key(&public_key, br#"{"locations":[]}"#, Some(TEST_SIGNATURE)),
+            Err(Error::InvalidSignature)
+        ));
+        assert!(matches!(
+            verify_with_key(&public_key, TEST_DATA, None),
+            Err(Error::Unsigned)
+        ));
+        assert!(matches!(
+            verify(TEST_DATA, Some(TEST_SIGNATURE)),
+            Err(Error::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_signature_path() {
+        assert_eq!(
+            signature_path(Path::new("/cache/relays.json")),
+            Path::new("/cache/relays.json.sig")
+        );
+    }
+}
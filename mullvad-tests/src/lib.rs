@@ -1,8 +1,8 @@
 #![cfg(not(target_os = "android"))]
 
-use self::{mock_openvpn::MOCK_OPENVPN_ARGS_FILE, platform_specific::*};
-use jsonrpc_client_ipc::IpcTransport;
-use mullvad_ipc_client::DaemonRpcClient;
+use self::{mock_api::MockApi, mock_openvpn::MOCK_OPENVPN_ARGS_FILE, platform_specific::*};
+use futures::Future;
+use mullvad_management_interface::ManagementServiceClient;
 use mullvad_rpc::API_IP_CACHE_FILENAME;
 use notify::{RawEvent, RecommendedWatcher, RecursiveMode, Watcher};
 use std::{
@@ -30,7 +30,9 @@ mod openvpn_proto {
 }
 use openvpn_proto::openvpn_event_proxy_client::OpenvpnEventProxyClient;
 
+pub mod mock_api;
 pub mod mock_openvpn;
+pub mod mock_socks;
 
 type Result<T> = std::result::Result<T, String>;
 
@@ -203,7 +205,7 @@ pub fn wait_for_file<P: AsRef<Path>>(file_path: P) {
     watcher.wait_for_burst_of_events(Duration::from_secs(1));
 }
 
-fn prepare_test_dirs() -> (TempDir, PathBuf, PathBuf, PathBuf) {
+fn prepare_test_dirs(api: &MockApi) -> (TempDir, PathBuf, PathBuf, PathBuf) {
     let temp_dir = TempDir::new().expect("Failed to create temporary daemon directory");
     let cache_dir = temp_dir.path().join("cache");
     let resource_dir = temp_dir.path().join("resource-dir");
@@ -214,7 +216,7 @@ fn prepare_test_dirs() -> (TempDir, PathBuf, PathBuf, PathBuf) {
     fs::create_dir(&settings_dir).expect("Failed to create settings directory");
 
     prepare_resource_dir(&resource_dir);
-    prepare_cache_dir(&cache_dir);
+    prepare_cache_dir(&cache_dir, api);
 
     (temp_dir, cache_dir, resource_dir, settings_dir)
 }
@@ -228,105 +230,109 @@ fn prepare_resource_dir(resource_dir: &Path) {
     File::create(talpid_openvpn_plugin).expect("Failed to create mock Talpid OpenVPN plugin");
 }
 
-fn prepare_cache_dir(cache_dir: &Path) {
-    prepare_relay_list(cache_dir.join("relays.json"));
+fn prepare_cache_dir(cache_dir: &Path, api: &MockApi) {
+    api.write_relay_list(&cache_dir.join("relays.json"));
 
-    fs::write(cache_dir.join(API_IP_CACHE_FILENAME), "192.168.0.123")
-        .expect("Failed to cache API IP");
-}
-
-fn prepare_relay_list<T: AsRef<Path>>(path: T) {
     fs::write(
-        path,
-        r#"{
-            "countries": [{
-                "name": "Sweden",
-                "code": "se",
-                "cities": [{
-                    "name": "Gothenburg",
-                    "code": "got",
-                    "latitude": 57.70887,
-                    "longitude": 11.97456,
-                    "relays": [{
-                        "hostname": "fakehost",
-                        "ipv4_addr_in": "192.168.0.100",
-                        "ipv6_addr_in": null,
-                        "include_in_country": true,
-                        "active": true,
-                        "owned": false,
-                        "provider": "M247",
-                        "weight": 100,
-                        "tunnels": {
-                            "openvpn": [ { "port": 1000, "protocol": "udp" } ],
-                            "wireguard": []
-                        }
-                    }],
-                    "location": {
-                      "country": "Sweden",
-                      "country_code": "se",
-                      "city": "Gothenburg",
-                      "city_code": "got",
-                      "latitude": 57.70887,
-                      "longitude": 11.97456
-                    }
-                }]
-            }]
-        }"#,
+        cache_dir.join(API_IP_CACHE_FILENAME),
+        api.address().to_string(),
     )
-    .expect("Failed to create mock relay list file");
+    .expect("Failed to cache API IP");
 }
 
+/// Runs a daemon that talks to a `MockApi`. The daemon must have been built with the
+/// `api-override` feature, e.g. using `cargo build -p mullvad-daemon --features
+/// mullvad-rpc/api-override`.
 pub struct DaemonRunner {
     process: Option<duct::Handle>,
     mock_openvpn_args_file: PathBuf,
     rpc_socket_path: PathBuf,
+    cache_dir: PathBuf,
+    resource_dir: PathBuf,
+    settings_dir: PathBuf,
+    runtime: tokio::runtime::Runtime,
+    api: MockApi,
     _temp_dir: TempDir,
 }
 
 impl DaemonRunner {
     pub fn spawn() -> Self {
-        Self::spawn_internal()
+        Self::spawn_with_api(MockApi::start())
     }
 
-    fn spawn_internal() -> Self {
-        let (temp_dir, cache_dir, resource_dir, settings_dir) = prepare_test_dirs();
+    /// Spawns a daemon using an already configured mock API.
+    pub fn spawn_with_api(api: MockApi) -> Self {
+        let (temp_dir, cache_dir, resource_dir, settings_dir) = prepare_test_dirs(&api);
         let mock_openvpn_args_file = temp_dir.path().join(MOCK_OPENVPN_ARGS_FILE);
 
         let rpc_socket_path = temp_dir.path().join("rpc_socket");
 
-        let expression = duct::cmd!(DAEMON_EXECUTABLE_PATH, "-v", "--disable-log-to-file")
+        let mut runner = DaemonRunner {
+            process: None,
+            mock_openvpn_args_file,
+            rpc_socket_path,
+            cache_dir,
+            resource_dir,
+            settings_dir,
+            runtime: tokio::runtime::Runtime::new().expect("Failed to create runtime"),
+            api,
+            _temp_dir: temp_dir,
+        };
+        runner.start_process();
+        runner
+    }
+
+    /// Stops the daemon and starts it again, keeping its settings and cache.
+    pub fn restart(&mut self) {
+        self.stop_process();
+        let _ = fs::remove_file(&self.rpc_socket_path);
+        self.start_process();
+    }
+
+    fn start_process(&mut self) {
+        let mut expression = duct::cmd!(DAEMON_EXECUTABLE_PATH, "-v", "--disable-log-to-file")
             .dir("..")
-            .env("MULLVAD_CACHE_DIR", cache_dir)
-            .env("MULLVAD_RPC_SOCKET_PATH", rpc_socket_path.clone())
-            .env("MULLVAD_RESOURCE_DIR", resource_dir)
-            .env("MULLVAD_SETTINGS_DIR", settings_dir)
-            .env("MOCK_OPENVPN_ARGS_FILE", mock_openvpn_args_file.clone())
+            .env("MULLVAD_CACHE_DIR", &self.cache_dir)
+            .env("MULLVAD_RPC_SOCKET_PATH", &self.rpc_socket_path)
+            .env("MULLVAD_RESOURCE_DIR", &self.resource_dir)
+            .env("MULLVAD_SETTINGS_DIR", &self.settings_dir)
+            .env("MOCK_OPENVPN_ARGS_FILE", &self.mock_openvpn_args_file)
             .stdout_null()
             .stderr_null();
+        for (key, value) in self.api.daemon_env() {
+            expression = expression.env(key, value);
+        }
 
+        self.process = Some(expression.start().expect("Failed to start daemon"));
+    }
 
-        let process = expression.start().expect("Failed to start daemon");
-
-        DaemonRunner {
-            process: Some(process),
-            mock_openvpn_args_file,
-            rpc_socket_path,
-            _temp_dir: temp_dir,
-        }
+    pub fn api(&self) -> &MockApi {
+        &self.api
     }
 
     pub fn mock_openvpn_args_file(&self) -> &Path {
         &self.mock_openvpn_args_file
     }
 
-    pub fn rpc_client(&mut self) -> Result<DaemonRpcClient> {
+    pub fn rpc_client(&mut self) -> Result<ManagementServiceClient> {
         wait_for_file(&self.rpc_socket_path);
-        let socket_path: String = self.rpc_socket_path.to_string_lossy().to_string();
-        mullvad_ipc_client::new_standalone_transport(socket_path, |path| {
-            IpcTransport::new(&path, &tokio01::reactor::Handle::default())
+        let socket_path = self.rpc_socket_path.to_string_lossy().to_string();
+        self.runtime.block_on(async move {
+            // The URI will be ignored
+            let channel = Endpoint::from_static("lttp://[::]:50051")
+                .connect_with_connector(service_fn(move |_: Uri| {
+                    IpcEndpoint::connect(socket_path.clone())
+                }))
+                .await
+                .map_err(|e| format!("Failed to construct an RPC client - {}", e))?;
+            Ok(ManagementServiceClient::new(channel))
         })
-        .map_err(|e| e.to_string())
-        .map_err(|e| format!("Failed to construct an RPC client - {}", e))
+    }
+
+    /// Runs a future, such as a request made using the client returned by `rpc_client`, to
+    /// completion.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
     }
 
     #[cfg(unix)]
@@ -339,15 +345,13 @@ impl DaemonRunner {
     #[cfg(not(unix))]
     fn request_clean_shutdown(&mut self, _: &mut duct::Handle) -> bool {
         if let Ok(mut rpc_client) = self.rpc_client() {
-            rpc_client.shutdown().is_ok()
+            self.block_on(rpc_client.shutdown(())).is_ok()
         } else {
             false
         }
     }
-}
 
-impl Drop for DaemonRunner {
-    fn drop(&mut self) {
+    fn stop_process(&mut self) {
         if let Some(mut process) = self.process.take() {
             if self.request_clean_shutdown(&mut process) {
                 let process = Arc::new(process);
@@ -372,6 +376,12 @@ impl Drop for DaemonRunner {
     }
 }
 
+impl Drop for DaemonRunner {
+    fn drop(&mut self) {
+        self.stop_process();
+    }
+}
+
 pub struct MockOpenVpnPluginRpcClient {
     runtime: tokio::runtime::Runtime,
     rpc: OpenvpnEventProxyClient<tonic::transport::Channel>,
@@ -0,0 +1,607 @@
+//! A local mock of the Mullvad API. The daemon is pointed at it using the environment returned by
+//! `MockApi::daemon_env`, which requires the daemon to be built with the `api-override` feature.
+//!
+//! Responses can be made to fail in order to test how the daemon handles API errors, server
+//! errors and timeouts.
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use hyper::{
+    header, server::conn::Http, service::service_fn, Body, Method, Request, Response, StatusCode,
+};
+use mullvad_rpc::signature;
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+use tokio_rustls::{rustls, TlsAcceptor};
+
+/// Hostname that the mock API certificate is issued for.
+pub const MOCK_API_HOST: &str = "api.mullvad.net";
+
+/// Maximum number of WireGuard keys per account, like the real API.
+pub const MAX_WIREGUARD_KEYS: usize = 5;
+
+/// Seed of the key that the mock relay list is signed with.
+const RELAY_LIST_KEY_SEED: [u8; 32] = [
+    0x6d, 0x6f, 0x63, 0x6b, 0x2d, 0x72, 0x65, 0x6c, 0x61, 0x79, 0x2d, 0x6c, 0x69, 0x73, 0x74, 0x2d,
+    0x6b, 0x65, 0x79, 0x2d, 0x66, 0x6f, 0x72, 0x2d, 0x74, 0x65, 0x73, 0x74, 0x69, 0x6e, 0x67, 0x21,
+];
+
+const CA_CERT_FILENAME: &str = "mock-api-ca.pem";
+
+/// An API endpoint that failures can be scripted for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    CreateAccount,
+    GetAccount,
+    SubmitVoucher,
+    WwwAuthToken,
+    PushWireguardKey,
+    ReplaceWireguardKey,
+    GetWireguardKey,
+    RemoveWireguardKey,
+    Relays,
+    VersionCheck,
+    ProblemReport,
+    ApiAddrs,
+}
+
+impl Endpoint {
+    fn from_request(method: &Method, path: &str) -> Option<Self> {
+        let endpoint = match (method, path) {
+            (&Method::POST, "/v1/accounts") => Endpoint::CreateAccount,
+            (&Method::GET, "/v1/me") => Endpoint::GetAccount,
+            (&Method::POST, "/v1/submit-voucher") => Endpoint::SubmitVoucher,
+            (&Method::POST, "/v1/www-auth-token") => Endpoint::WwwAuthToken,
+            (&Method::POST, "/v1/wireguard-keys") => Endpoint::PushWireguardKey,
+            (&Method::POST, "/v1/replace-wireguard-key") => Endpoint::ReplaceWireguardKey,
+            (&Method::GET, path) if path.starts_with("/v1/wireguard-keys/") => {
+                Endpoint::GetWireguardKey
+            }
+            (&Method::DELETE, path) if path.starts_with("/v1/wireguard-keys/") => {
+                Endpoint::RemoveWireguardKey
+            }
+            (&Method::GET, "/v1/relays") => Endpoint::Relays,
+            (&Method::GET, path) if path.starts_with("/v1/releases/") => Endpoint::VersionCheck,
+            (&Method::POST, "/v1/problem-report") => Endpoint::ProblemReport,
+            (&Method::GET, "/v1/api-addrs") => Endpoint::ApiAddrs,
+            _ => return None,
+        };
+        Some(endpoint)
+    }
+}
+
+/// A scripted failure of a single request.
+#[derive(Debug, Clone)]
+pub enum Failure {
+    /// Respond with an API error code, such as `KEY_LIMIT_REACHED`.
+    ApiError(StatusCode, &'static str),
+    /// Respond with a status code and no body, such as a 5xx error.
+    Status(StatusCode),
+    /// Wait this long before responding with `504 Gateway Timeout`. The daemon gives up on most
+    /// requests long before this if the delay is long enough.
+    Timeout(Duration),
+}
+
+impl Failure {
+    /// The error returned when an account already has `MAX_WIREGUARD_KEYS` keys.
+    pub fn too_many_keys() -> Self {
+        Failure::ApiError(StatusCode::BAD_REQUEST, mullvad_rpc::KEY_LIMIT_REACHED)
+    }
+
+    pub fn server_error() -> Self {
+        Failure::Status(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    pub fn timeout() -> Self {
+        Failure::Timeout(Duration::from_secs(60))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Account {
+    expiry: DateTime<Utc>,
+    wireguard_keys: Vec<String>,
+}
+
+struct State {
+    accounts: HashMap<String, Account>,
+    next_account_number: u64,
+    relay_list: Vec<u8>,
+    relay_list_signature: String,
+    version_info: serde_json::Value,
+    problem_reports: Vec<serde_json::Value>,
+    failures: HashMap<Endpoint, VecDeque<Failure>>,
+    request_counts: HashMap<Endpoint, usize>,
+    address: Option<SocketAddr>,
+}
+
+/// A mock API server running on a runtime of its own, so that it keeps serving requests while
+/// tests block.
+pub struct MockApi {
+    state: Arc<Mutex<State>>,
+    address: SocketAddr,
+    ca_cert_path: PathBuf,
+    _runtime: tokio::runtime::Runtime,
+    _temp_dir: TempDir,
+}
+
+impl MockApi {
+    /// Starts the mock API on a random local port.
+    pub fn start() -> Self {
+        let temp_dir = TempDir::new().expect("Failed to create mock API directory");
+        let (ca_cert, acceptor) = create_tls_acceptor();
+        let ca_cert_path = temp_dir.path().join(CA_CERT_FILENAME);
+        fs::write(&ca_cert_path, ca_cert).expect("Failed to write mock API CA certificate");
+
+        let relay_list =
+            serde_json::to_vec(&default_relay_list()).expect("Failed to serialize mock relay list");
+        let relay_list_signature = signature::sign(&RELAY_LIST_KEY_SEED, &relay_list)
+            .expect("Failed to sign mock relay list");
+        let state = Arc::new(Mutex::new(State {
+            accounts: HashMap::new(),
+            next_account_number: 1000_0000_0000_0000,
+            relay_list,
+            relay_list_signature,
+            version_info: serde_json::json!({
+                "supported": true,
+                "latest": "2021.5",
+                "latest_stable": "2021.5",
+                "latest_beta": "2021.5",
+            }),
+            problem_reports: Vec::new(),
+            failures: HashMap::new(),
+            request_counts: HashMap::new(),
+            address: None,
+        }));
+
+        let runtime = tokio::runtime::Runtime::new().expect("Failed to create mock API runtime");
+        let listener = runtime
+            .block_on(TcpListener::bind("127.0.0.1:0"))
+            .expect("Failed to bind mock API listener");
+        let address = listener
+            .local_addr()
+            .expect("Failed to get mock API address");
+        state.lock().unwrap().address = Some(address);
+        runtime.spawn(serve(listener, acceptor, state.clone()));
+
+        MockApi {
+            state,
+            address,
+            ca_cert_path,
+            _runtime: runtime,
+            _temp_dir: temp_dir,
+        }
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Environment variables that make a daemon built with `api-override` use this mock API.
+    pub fn daemon_env(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("MULLVAD_API_HOST", MOCK_API_HOST.to_owned()),
+            ("MULLVAD_API_ADDRESS", self.address.to_string()),
+            (
+                "MULLVAD_API_CA_CERT",
+                self.ca_cert_path.to_string_lossy().into_owned(),
+            ),
+            ("MULLVAD_RELAY_LIST_KEY", self.relay_list_key()),
+        ]
+    }
+
+    /// Returns the base64 encoded public key that the relay list is signed with.
+    pub fn relay_list_key(&self) -> String {
+        let public_key =
+            signature::public_key(&RELAY_LIST_KEY_SEED).expect("Invalid relay list key seed");
+        base64::encode(&public_key)
+    }
+
+    /// Writes the relay list and its signature to `path`, as the daemon caches them.
+    pub fn write_relay_list(&self, path: &Path) {
+        let state = self.state.lock().unwrap();
+        fs::write(path, &state.relay_list).expect("Failed to write mock relay list");
+        fs::write(signature::signature_path(path), &state.relay_list_signature)
+            .expect("Failed to write mock relay list signature");
+    }
+
+    /// Replaces the relay list served by the mock API. It is given in the format served by the
+    /// real API, and is signed with the mock relay list key.
+    pub fn set_relay_list(&self, relay_list: serde_json::Value) {
+        let relay_list = serde_json::to_vec(&relay_list).expect("Failed to serialize relay list");
+        let mut state = self.state.lock().unwrap();
+        state.relay_list_signature = signature::sign(&RELAY_LIST_KEY_SEED, &relay_list)
+            .expect("Failed to sign mock relay list");
+        state.relay_list = relay_list;
+    }
+
+    /// Replaces the response to version checks. It has the same format as the real API response.
+    pub fn set_version_info(&self, version_info: serde_json::Value) {
+        self.state.lock().unwrap().version_info = version_info;
+    }
+
+    /// Adds an account that expires at `expiry`.
+    pub fn add_account(&self, account_token: &str, expiry: DateTime<Utc>) {
+        self.state.lock().unwrap().accounts.insert(
+            account_token.to_owned(),
+            Account {
+                expiry,
+                wireguard_keys: Vec::new(),
+            },
+        );
+    }
+
+    /// Changes the expiry of an existing account.
+    pub fn set_account_expiry(&self, account_token: &str, expiry: DateTime<Utc>) {
+        if let Some(account) = self.state.lock().unwrap().accounts.get_mut(account_token) {
+            account.expiry = expiry;
+        }
+    }
+
+    /// Registers a base64 encoded WireGuard key for an existing account.
+    pub fn add_wireguard_key(&self, account_token: &str, key: &str) {
+        if let Some(account) = self.state.lock().unwrap().accounts.get_mut(account_token) {
+            account.wireguard_keys.push(key.to_owned());
+        }
+    }
+
+    /// Returns whether an account exists, e.g. after it has been created through the daemon.
+    pub fn has_account(&self, account_token: &str) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .accounts
+            .contains_key(account_token)
+    }
+
+    /// Returns the base64 encoded WireGuard keys registered for an account.
+    pub fn wireguard_keys(&self, account_token: &str) -> Vec<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .accounts
+            .get(account_token)
+            .map(|account| account.wireguard_keys.clone())
+            .unwrap_or_default()
+    }
+
+    /// Makes the next request to `endpoint` fail. Failures are used in the order they are added.
+    pub fn push_failure(&self, endpoint: Endpoint, failure: Failure) {
+        self.state
+            .lock()
+            .unwrap()
+            .failures
+            .entry(endpoint)
+            .or_default()
+            .push_back(failure);
+    }
+
+    /// Returns the number of requests that have been made to `endpoint`, including failed ones.
+    pub fn request_count(&self, endpoint: Endpoint) -> usize {
+        self.state
+            .lock()
+            .unwrap()
+            .request_counts
+            .get(&endpoint)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns the bodies of all problem reports that have been sent.
+    pub fn problem_reports(&self) -> Vec<serde_json::Value> {
+        self.state.lock().unwrap().problem_reports.clone()
+    }
+}
+
+/// Generates a CA and a certificate for `MOCK_API_HOST` signed by it. Returns the PEM encoded CA
+/// certificate and an acceptor using the host certificate.
+fn create_tls_acceptor() -> (String, TlsAcceptor) {
+    let mut ca_params = rcgen::CertificateParams::new(vec![]);
+    ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    ca_params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, "Mock API CA");
+    let ca = rcgen::Certificate::from_params(ca_params).expect("Failed to generate mock API CA");
+
+    let host = rcgen::generate_simple_self_signed(vec![MOCK_API_HOST.to_owned()])
+        .expect("Failed to generate mock API certificate");
+    let host_cert = host
+        .serialize_der_with_signer(&ca)
+        .expect("Failed to sign mock API certificate");
+
+    let mut config = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+    config
+        .set_single_cert(
+            vec![rustls::Certificate(host_cert)],
+            rustls::PrivateKey(host.serialize_private_key_der()),
+        )
+        .expect("Failed to use mock API certificate");
+
+    let ca_cert = ca.serialize_pem().expect("Failed to serialize mock API CA");
+    (ca_cert, TlsAcceptor::from(Arc::new(config)))
+}
+
+async fn serve(listener: TcpListener, acceptor: TlsAcceptor, state: Arc<Mutex<State>>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(error) => {
+                eprintln!("Mock API failed to accept connection: {}", error);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let state = state.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(_) => return,
+            };
+            let service = service_fn(move |request| handle_request(state.clone(), request));
+            let _ = Http::new().serve_connection(stream, service).await;
+        });
+    }
+}
+
+async fn handle_request(
+    state: Arc<Mutex<State>>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let endpoint = match Endpoint::from_request(request.method(), request.uri().path()) {
+        Some(endpoint) => endpoint,
+        None => return Ok(status_response(StatusCode::NOT_FOUND)),
+    };
+
+    let failure = {
+        let mut state = state.lock().unwrap();
+        *state.request_counts.entry(endpoint).or_insert(0) += 1;
+        state
+            .failures
+            .get_mut(&endpoint)
+            .and_then(|failures| failures.pop_front())
+    };
+    match failure {
+        Some(Failure::ApiError(status, code)) => return Ok(error_response(status, code)),
+        Some(Failure::Status(status)) => return Ok(status_response(status)),
+        Some(Failure::Timeout(delay)) => {
+            tokio::time::sleep(delay).await;
+            return Ok(status_response(StatusCode::GATEWAY_TIMEOUT));
+        }
+        None => (),
+    }
+
+    let account_token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Token "))
+        .map(str::to_owned);
+    let path = request.uri().path().to_owned();
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(_) => return Ok(status_response(StatusCode::BAD_REQUEST)),
+    };
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+
+    let mut state = state.lock().unwrap();
+    let response = match endpoint {
+        Endpoint::CreateAccount => {
+            let account_token = state.next_account_number.to_string();
+            state.next_account_number += 1;
+            let expiry = Utc::now();
+            state.accounts.insert(
+                account_token.clone(),
+                Account {
+                    expiry,
+                    wireguard_keys: Vec::new(),
+                },
+            );
+            json_response(
+                StatusCode::CREATED,
+                serde_json::json!({ "token": account_token, "expires": expiry }),
+            )
+        }
+        Endpoint::GetAccount => match state.account(&account_token) {
+            Ok((account_token, account)) => json_response(
+                StatusCode::OK,
+                serde_json::json!({ "token": account_token, "expires": account.expiry }),
+            ),
+            Err(response) => response,
+        },
+        Endpoint::SubmitVoucher => match state.account_mut(&account_token) {
+            Ok(account) => {
+                let time_added = ChronoDuration::days(30);
+                account.expiry = std::cmp::max(account.expiry, Utc::now()) + time_added;
+                json_response(
+                    StatusCode::OK,
+                    serde_json::json!({
+                        "time_added": time_added.num_seconds(),
+                        "new_expiry": account.expiry,
+                    }),
+                )
+            }
+            Err(response) => response,
+        },
+        Endpoint::WwwAuthToken => match state.account(&account_token) {
+            Ok(_) => json_response(
+                StatusCode::OK,
+                serde_json::json!({ "auth_token": "mock-auth-token" }),
+            ),
+            Err(response) => response,
+        },
+        Endpoint::PushWireguardKey => {
+            let key = body["pubkey"].as_str().unwrap_or_default().to_owned();
+            match state.account_mut(&account_token) {
+                Ok(account) if account.wireguard_keys.len() >= MAX_WIREGUARD_KEYS => {
+                    error_response(StatusCode::BAD_REQUEST, mullvad_rpc::KEY_LIMIT_REACHED)
+                }
+                Ok(account) => {
+                    account.wireguard_keys.push(key);
+                    addresses_response(StatusCode::CREATED, account.wireguard_keys.len())
+                }
+                Err(response) => response,
+            }
+        }
+        Endpoint::ReplaceWireguardKey => {
+            let old = body["old"].as_str().unwrap_or_default();
+            let new = body["new"].as_str().unwrap_or_default().to_owned();
+            match state.account_mut(&account_token) {
+                Ok(account) => {
+                    account.wireguard_keys.retain(|key| key != old);
+                    if account.wireguard_keys.len() >= MAX_WIREGUARD_KEYS {
+                        error_response(StatusCode::BAD_REQUEST, mullvad_rpc::KEY_LIMIT_REACHED)
+                    } else {
+                        account.wireguard_keys.push(new);
+                        addresses_response(StatusCode::CREATED, account.wireguard_keys.len())
+                    }
+                }
+                Err(response) => response,
+            }
+        }
+        Endpoint::GetWireguardKey => {
+            let key = percent_decode(path.trim_start_matches("/v1/wireguard-keys/"));
+            match state.account_mut(&account_token) {
+                Ok(account) => match account.wireguard_keys.iter().position(|k| *k == key) {
+                    Some(index) => addresses_response(StatusCode::OK, index + 1),
+                    None => status_response(StatusCode::NOT_FOUND),
+                },
+                Err(response) => response,
+            }
+        }
+        Endpoint::RemoveWireguardKey => {
+            let key = percent_decode(path.trim_start_matches("/v1/wireguard-keys/"));
+            match state.account_mut(&account_token) {
+                Ok(account) => {
+                    account.wireguard_keys.retain(|k| *k != key);
+                    status_response(StatusCode::NO_CONTENT)
+                }
+                Err(response) => response,
+            }
+        }
+        Endpoint::Relays => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(
+                signature::SIGNATURE_HEADER,
+                state.relay_list_signature.clone(),
+            )
+            .body(Body::from(state.relay_list.clone()))
+            .unwrap(),
+        Endpoint::VersionCheck => json_response(StatusCode::OK, state.version_info.clone()),
+        Endpoint::ProblemReport => {
+            state.problem_reports.push(body);
+            status_response(StatusCode::NO_CONTENT)
+        }
+        Endpoint::ApiAddrs => json_response(StatusCode::OK, serde_json::json!([state.address])),
+    };
+    Ok(response)
+}
+
+impl State {
+    fn account(
+        &self,
+        account_token: &Option<String>,
+    ) -> Result<(&String, &Account), Response<Body>> {
+        account_token
+            .as_ref()
+            .and_then(|account_token| self.accounts.get_key_value(account_token))
+            .ok_or_else(invalid_account_response)
+    }
+
+    fn account_mut(
+        &mut self,
+        account_token: &Option<String>,
+    ) -> Result<&mut Account, Response<Body>> {
+        account_token
+            .as_ref()
+            .and_then(move |account_token| self.accounts.get_mut(account_token))
+            .ok_or_else(invalid_account_response)
+    }
+}
+
+fn invalid_account_response() -> Response<Body> {
+    error_response(StatusCode::UNAUTHORIZED, mullvad_rpc::INVALID_ACCOUNT)
+}
+
+fn addresses_response(status: StatusCode, index: usize) -> Response<Body> {
+    json_response(
+        status,
+        serde_json::json!({
+            "ipv4_address": format!("10.64.{}.{}/32", index / 256, index % 256),
+            "ipv6_address": format!("fc00:bbbb:bbbb:bb01::{:x}/128", index),
+        }),
+    )
+}
+
+fn error_response(status: StatusCode, code: &str) -> Response<Body> {
+    json_response(status, serde_json::json!({ "code": code, "error": code }))
+}
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Decodes the percent-encoded characters that occur in base64 encoded keys.
+fn percent_decode(input: &str) -> String {
+    input
+        .replace("%2B", "+")
+        .replace("%2F", "/")
+        .replace("%3D", "=")
+}
+
+/// The relay list served by default, with a single OpenVPN relay. It is in the format served by
+/// the API.
+pub fn default_relay_list() -> serde_json::Value {
+    serde_json::json!({
+        "locations": {
+            "se-got": {
+                "city": "Gothenburg",
+                "country": "Sweden",
+                "latitude": 57.70887,
+                "longitude": 11.97456
+            }
+        },
+        "openvpn": {
+            "ports": [ { "port": 1000, "protocol": "udp" } ],
+            "relays": [{
+                "hostname": "fakehost",
+                "active": true,
+                "owned": false,
+                "location": "se-got",
+                "provider": "M247",
+                "ipv4_addr_in": "192.168.0.100",
+                "weight": 100,
+                "include_in_country": true
+            }]
+        },
+        "wireguard": {
+            "port_ranges": [ [51820, 51820] ],
+            "ipv4_gateway": "10.64.0.1",
+            "ipv6_gateway": "fc00:bbbb:bbbb:bb01::1",
+            "relays": []
+        },
+        "bridge": {
+            "shadowsocks": [],
+            "relays": []
+        }
+    })
+}
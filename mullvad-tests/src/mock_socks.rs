@@ -0,0 +1,154 @@
+//! A minimal SOCKS5 proxy that forwards connections to their requested targets. It only supports
+//! the `CONNECT` command without authentication, which is what the daemon uses to reach the API.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+const SOCKS_VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const CONNECT_COMMAND: u8 = 1;
+const ADDRESS_TYPE_IPV4: u8 = 1;
+const ADDRESS_TYPE_IPV6: u8 = 4;
+const REPLY_SUCCEEDED: u8 = 0;
+const REPLY_HOST_UNREACHABLE: u8 = 4;
+
+/// A SOCKS5 proxy running on a runtime of its own, so that it keeps forwarding traffic while
+/// tests block.
+pub struct MockSocksProxy {
+    address: SocketAddr,
+    connection_count: Arc<AtomicUsize>,
+    _runtime: tokio::runtime::Runtime,
+}
+
+impl MockSocksProxy {
+    /// Starts the proxy on a random local port.
+    pub fn start() -> Self {
+        let runtime = tokio::runtime::Runtime::new().expect("Failed to create SOCKS5 runtime");
+        let listener = runtime
+            .block_on(TcpListener::bind("127.0.0.1:0"))
+            .expect("Failed to bind SOCKS5 listener");
+        let address = listener
+            .local_addr()
+            .expect("Failed to get SOCKS5 proxy address");
+        let connection_count = Arc::new(AtomicUsize::new(0));
+        runtime.spawn(serve(listener, connection_count.clone()));
+
+        MockSocksProxy {
+            address,
+            connection_count,
+            _runtime: runtime,
+        }
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Returns the number of connections that have been forwarded through the proxy.
+    pub fn connection_count(&self) -> usize {
+        self.connection_count.load(Ordering::SeqCst)
+    }
+}
+
+async fn serve(listener: TcpListener, connection_count: Arc<AtomicUsize>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(error) => {
+                eprintln!("SOCKS5 proxy failed to accept connection: {}", error);
+                continue;
+            }
+        };
+        let connection_count = connection_count.clone();
+        tokio::spawn(async move {
+            let _ = forward(stream, &connection_count).await;
+        });
+    }
+}
+
+async fn forward(mut client: TcpStream, connection_count: &AtomicUsize) -> io::Result<()> {
+    let mut header = [0u8; 2];
+    client.read_exact(&mut header).await?;
+    let mut methods = vec![0u8; usize::from(header[1])];
+    client.read_exact(&mut methods).await?;
+    if header[0] != SOCKS_VERSION || !methods.contains(&NO_AUTHENTICATION) {
+        client
+            .write_all(&[SOCKS_VERSION, NO_ACCEPTABLE_METHODS])
+            .await?;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unsupported SOCKS5 handshake",
+        ));
+    }
+    client
+        .write_all(&[SOCKS_VERSION, NO_AUTHENTICATION])
+        .await?;
+
+    let mut request = [0u8; 4];
+    client.read_exact(&mut request).await?;
+    let ip = match request[3] {
+        ADDRESS_TYPE_IPV4 => {
+            let mut octets = [0u8; 4];
+            client.read_exact(&mut octets).await?;
+            IpAddr::V4(Ipv4Addr::from(octets))
+        }
+        ADDRESS_TYPE_IPV6 => {
+            let mut octets = [0u8; 16];
+            client.read_exact(&mut octets).await?;
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unsupported SOCKS5 address type",
+            ))
+        }
+    };
+    let port = client.read_u16().await?;
+    if request[1] != CONNECT_COMMAND {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unsupported SOCKS5 command",
+        ));
+    }
+
+    let mut target = match TcpStream::connect(SocketAddr::new(ip, port)).await {
+        Ok(target) => target,
+        Err(error) => {
+            client.write_all(&reply(REPLY_HOST_UNREACHABLE)).await?;
+            return Err(error);
+        }
+    };
+    client.write_all(&reply(REPLY_SUCCEEDED)).await?;
+    connection_count.fetch_add(1, Ordering::SeqCst);
+
+    tokio::io::copy_bidirectional(&mut client, &mut target).await?;
+    Ok(())
+}
+
+/// Builds a reply with an unspecified bound address, which clients ignore.
+fn reply(status: u8) -> [u8; 10] {
+    [
+        SOCKS_VERSION,
+        status,
+        0,
+        ADDRESS_TYPE_IPV4,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    ]
+}
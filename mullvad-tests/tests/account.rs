@@ -1,5 +1,6 @@
 #![cfg(feature = "integration-tests")]
 
+use chrono::{Duration, Utc};
 use mullvad_tests::{mock_openvpn::search_openvpn_args, watch_event, DaemonRunner, PathWatcher};
 use std::{
     fs::File,
@@ -15,10 +16,11 @@ fn uses_account_token() {
     let mut openvpn_args_file_events = PathWatcher::watch(&openvpn_args_file).unwrap();
 
     let specified_account = "123456";
-    rpc_client
-        .set_account(Some(specified_account.to_owned()))
+    add_account(&daemon, specified_account);
+    daemon
+        .block_on(rpc_client.set_account(specified_account.to_owned()))
         .unwrap();
-    rpc_client.connect().unwrap();
+    daemon.block_on(rpc_client.connect_tunnel(())).unwrap();
 
     openvpn_args_file_events.assert_create_write_close_sequence();
 
@@ -35,16 +37,18 @@ fn uses_updated_account_token() {
     let mut openvpn_args_file_events = PathWatcher::watch(&openvpn_args_file).unwrap();
 
     let first_account_specified = "123456";
-    rpc_client
-        .set_account(Some(first_account_specified.to_owned()))
+    add_account(&daemon, first_account_specified);
+    daemon
+        .block_on(rpc_client.set_account(first_account_specified.to_owned()))
         .unwrap();
-    rpc_client.connect().unwrap();
+    daemon.block_on(rpc_client.connect_tunnel(())).unwrap();
 
     openvpn_args_file_events.assert_create_write_close_sequence();
 
     let second_account_specified = "654321";
-    rpc_client
-        .set_account(Some(second_account_specified.to_owned()))
+    add_account(&daemon, second_account_specified);
+    daemon
+        .block_on(rpc_client.set_account(second_account_specified.to_owned()))
         .unwrap();
 
     assert_eq!(openvpn_args_file_events.next(), Some(watch_event::REMOVE));
@@ -55,6 +59,12 @@ fn uses_updated_account_token() {
     assert_eq!(account_token_sent_to_plugin, second_account_specified);
 }
 
+fn add_account(daemon: &DaemonRunner, account_token: &str) {
+    daemon
+        .api()
+        .add_account(account_token, Utc::now() + Duration::days(30));
+}
+
 fn read_account_token<P: AsRef<Path>>(openvpn_args_file_path: P) -> Result<String, String> {
     let account_token_file_path = search_openvpn_args(openvpn_args_file_path, "--auth-user-pass")
         .skip(1)
@@ -0,0 +1,367 @@
+#![cfg(feature = "integration-tests")]
+
+use chrono::{Duration, Utc};
+use mullvad_management_interface::types::{
+    api_access_method, keygen_event::KeygenEvent, ApiAccessMethod, ApiAccessMethodToggle,
+};
+use mullvad_tests::{
+    mock_api::{default_relay_list, Endpoint, Failure, MockApi, MAX_WIREGUARD_KEYS},
+    mock_socks::MockSocksProxy,
+    DaemonRunner,
+};
+use mullvad_types::access_method::BRIDGES_NAME;
+use std::{thread, time::Instant};
+
+#[test]
+fn creates_account() {
+    let mut daemon = DaemonRunner::spawn();
+    let mut rpc_client = daemon.rpc_client().unwrap();
+
+    let account_token = daemon
+        .block_on(rpc_client.create_new_account(()))
+        .expect("Failed to create account")
+        .into_inner();
+
+    assert!(daemon.api().has_account(&account_token));
+    assert_eq!(daemon.api().request_count(Endpoint::CreateAccount), 1);
+}
+
+#[test]
+fn reports_too_many_wireguard_keys() {
+    let api = MockApi::start();
+    let account_token = "123456";
+    api.add_account(account_token, Utc::now() + Duration::days(30));
+    for key in 0..MAX_WIREGUARD_KEYS {
+        api.add_wireguard_key(account_token, &key.to_string());
+    }
+    let mut daemon = DaemonRunner::spawn_with_api(api);
+    let mut rpc_client = daemon.rpc_client().unwrap();
+
+    daemon
+        .block_on(rpc_client.set_account(account_token.to_owned()))
+        .unwrap();
+    let keygen_event = daemon
+        .block_on(rpc_client.generate_wireguard_key(()))
+        .expect("Failed to generate WireGuard key")
+        .into_inner();
+
+    assert_eq!(keygen_event.event, KeygenEvent::TooManyKeys as i32);
+    assert_eq!(
+        daemon.api().wireguard_keys(account_token).len(),
+        MAX_WIREGUARD_KEYS
+    );
+}
+
+#[test]
+fn replaces_wireguard_key() {
+    let api = MockApi::start();
+    let account_token = "123456";
+    api.add_account(account_token, Utc::now() + Duration::days(30));
+    let mut daemon = DaemonRunner::spawn_with_api(api);
+    let mut rpc_client = daemon.rpc_client().unwrap();
+    daemon
+        .block_on(rpc_client.set_account(account_token.to_owned()))
+        .unwrap();
+
+    let first_key = generate_wireguard_key(&daemon, &mut rpc_client);
+    let second_key = generate_wireguard_key(&daemon, &mut rpc_client);
+
+    assert_ne!(first_key, second_key);
+    assert_eq!(
+        daemon.api().wireguard_keys(account_token),
+        vec![base64::encode(&second_key)]
+    );
+    assert!(daemon.api().request_count(Endpoint::ReplaceWireguardKey) >= 1);
+}
+
+#[test]
+fn keeps_wireguard_key_when_replacement_fails() {
+    let api = MockApi::start();
+    let account_token = "123456";
+    api.add_account(account_token, Utc::now() + Duration::days(30));
+    let mut daemon = DaemonRunner::spawn_with_api(api);
+    let mut rpc_client = daemon.rpc_client().unwrap();
+    daemon
+        .block_on(rpc_client.set_account(account_token.to_owned()))
+        .unwrap();
+    let key = generate_wireguard_key(&daemon, &mut rpc_client);
+
+    daemon
+        .api()
+        .push_failure(Endpoint::ReplaceWireguardKey, Failure::server_error());
+    assert!(daemon
+        .block_on(rpc_client.generate_wireguard_key(()))
+        .is_err());
+
+    let current_key = daemon
+        .block_on(rpc_client.get_wireguard_key(()))
+        .expect("Failed to get WireGuard key")
+        .into_inner()
+        .key;
+    assert_eq!(current_key, key);
+    assert_eq!(
+        daemon.api().wireguard_keys(account_token),
+        vec![base64::encode(&key)]
+    );
+}
+
+#[test]
+fn reports_api_server_errors() {
+    let api = MockApi::start();
+    let account_token = "123456";
+    api.add_account(account_token, Utc::now() + Duration::days(30));
+    api.push_failure(Endpoint::GetAccount, Failure::server_error());
+    let mut daemon = DaemonRunner::spawn_with_api(api);
+    let mut rpc_client = daemon.rpc_client().unwrap();
+
+    assert!(daemon
+        .block_on(rpc_client.get_account_data(account_token.to_owned()))
+        .is_err());
+    // Only the scripted request fails
+    daemon
+        .block_on(rpc_client.get_account_data(account_token.to_owned()))
+        .expect("Failed to get account data after a server error");
+    assert_eq!(daemon.api().request_count(Endpoint::GetAccount), 2);
+}
+
+#[test]
+fn gives_up_on_api_timeouts() {
+    let api = MockApi::start();
+    let account_token = "123456";
+    api.add_account(account_token, Utc::now() + Duration::days(30));
+    api.push_failure(Endpoint::GetAccount, Failure::timeout());
+    let mut daemon = DaemonRunner::spawn_with_api(api);
+    let mut rpc_client = daemon.rpc_client().unwrap();
+
+    let start = Instant::now();
+    assert!(daemon
+        .block_on(rpc_client.get_account_data(account_token.to_owned()))
+        .is_err());
+    assert!(
+        start.elapsed() < std::time::Duration::from_secs(30),
+        "The daemon waited for the API to respond instead of timing out"
+    );
+}
+
+#[test]
+fn reports_account_expiry() {
+    let api = MockApi::start();
+    let account_token = "123456";
+    let expired = Utc::now() - Duration::days(1);
+    api.add_account(account_token, expired);
+    let mut daemon = DaemonRunner::spawn_with_api(api);
+    let mut rpc_client = daemon.rpc_client().unwrap();
+
+    assert_eq!(
+        get_account_expiry(&daemon, &mut rpc_client, account_token),
+        expired.timestamp()
+    );
+
+    let renewed = Utc::now() + Duration::days(30);
+    daemon.api().set_account_expiry(account_token, renewed);
+    assert_eq!(
+        get_account_expiry(&daemon, &mut rpc_client, account_token),
+        renewed.timestamp()
+    );
+}
+
+#[test]
+fn extends_account_expiry_with_voucher() {
+    let api = MockApi::start();
+    let account_token = "123456";
+    let expired = Utc::now() - Duration::days(1);
+    api.add_account(account_token, expired);
+    let mut daemon = DaemonRunner::spawn_with_api(api);
+    let mut rpc_client = daemon.rpc_client().unwrap();
+    daemon
+        .block_on(rpc_client.set_account(account_token.to_owned()))
+        .unwrap();
+
+    let submission = daemon
+        .block_on(rpc_client.submit_voucher("voucher".to_owned()))
+        .expect("Failed to submit voucher")
+        .into_inner();
+
+    // Time is added from now, since the account has already expired
+    let new_expiry = submission.new_expiry.unwrap().seconds;
+    assert_eq!(
+        submission.seconds_added,
+        Duration::days(30).num_seconds() as u64
+    );
+    assert!(new_expiry >= (Utc::now() + Duration::days(29)).timestamp());
+    assert_eq!(
+        get_account_expiry(&daemon, &mut rpc_client, account_token),
+        new_expiry
+    );
+}
+
+#[test]
+fn updates_relay_list() {
+    let mut daemon = DaemonRunner::spawn();
+    let mut rpc_client = daemon.rpc_client().unwrap();
+
+    let mut relay_list = default_relay_list();
+    relay_list["openvpn"]["relays"][0]["hostname"] = "newhost".into();
+    daemon.api().set_relay_list(relay_list);
+
+    daemon
+        .block_on(rpc_client.update_relay_locations(()))
+        .unwrap();
+
+    let start = Instant::now();
+    while get_relay_hostnames(&daemon, &mut rpc_client) != ["newhost"] {
+        assert!(
+            start.elapsed() < std::time::Duration::from_secs(10),
+            "Timed out waiting for the relay list to be updated"
+        );
+        thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert!(daemon.api().request_count(Endpoint::Relays) >= 1);
+}
+
+#[test]
+fn rotates_to_working_api_access_method() {
+    let proxy = MockSocksProxy::start();
+    let mut daemon = DaemonRunner::spawn();
+    let mut rpc_client = daemon.rpc_client().unwrap();
+    add_socks_access_method(&daemon, &mut rpc_client, &proxy);
+
+    // The direct request times out, which makes the daemon try the next access method
+    daemon
+        .api()
+        .push_failure(Endpoint::CreateAccount, Failure::timeout());
+    create_account_eventually(&daemon, &mut rpc_client);
+
+    assert!(proxy.connection_count() >= 1);
+    assert_eq!(
+        get_current_access_method(&daemon, &mut rpc_client),
+        SOCKS_ACCESS_METHOD_NAME
+    );
+}
+
+#[test]
+fn remembers_working_api_access_method() {
+    let proxy = MockSocksProxy::start();
+    let mut daemon = DaemonRunner::spawn();
+    let mut rpc_client = daemon.rpc_client().unwrap();
+    add_socks_access_method(&daemon, &mut rpc_client, &proxy);
+    daemon
+        .api()
+        .push_failure(Endpoint::CreateAccount, Failure::timeout());
+    create_account_eventually(&daemon, &mut rpc_client);
+
+    daemon.restart();
+    let mut rpc_client = daemon.rpc_client().unwrap();
+
+    // The remembered access method is used from the start, before any request has failed
+    assert_eq!(
+        get_current_access_method(&daemon, &mut rpc_client),
+        SOCKS_ACCESS_METHOD_NAME
+    );
+    let connection_count = proxy.connection_count();
+    daemon
+        .block_on(rpc_client.create_new_account(()))
+        .expect("Failed to create account");
+    assert!(proxy.connection_count() > connection_count);
+}
+
+const SOCKS_ACCESS_METHOD_NAME: &str = "mock-socks";
+
+/// Adds an access method using `proxy`, and disables the bridges so that it is the only method
+/// besides connecting directly.
+fn add_socks_access_method(
+    daemon: &DaemonRunner,
+    rpc_client: &mut mullvad_management_interface::ManagementServiceClient,
+    proxy: &MockSocksProxy,
+) {
+    daemon
+        .block_on(
+            rpc_client.set_api_access_method_enabled(ApiAccessMethodToggle {
+                name: BRIDGES_NAME.to_owned(),
+                enabled: false,
+            }),
+        )
+        .expect("Failed to disable bridges access method");
+    daemon
+        .block_on(rpc_client.add_api_access_method(ApiAccessMethod {
+            name: SOCKS_ACCESS_METHOD_NAME.to_owned(),
+            enabled: true,
+            access_method: Some(api_access_method::AccessMethod::Socks5(
+                api_access_method::Socks5 {
+                    address: proxy.address().to_string(),
+                },
+            )),
+        }))
+        .expect("Failed to add SOCKS5 access method");
+}
+
+/// Creates an account, retrying if a request fails while the daemon switches access methods.
+fn create_account_eventually(
+    daemon: &DaemonRunner,
+    rpc_client: &mut mullvad_management_interface::ManagementServiceClient,
+) {
+    let start = Instant::now();
+    while daemon.block_on(rpc_client.create_new_account(())).is_err() {
+        assert!(
+            start.elapsed() < std::time::Duration::from_secs(30),
+            "Timed out waiting for an account to be created"
+        );
+    }
+}
+
+fn generate_wireguard_key(
+    daemon: &DaemonRunner,
+    rpc_client: &mut mullvad_management_interface::ManagementServiceClient,
+) -> Vec<u8> {
+    let keygen_event = daemon
+        .block_on(rpc_client.generate_wireguard_key(()))
+        .expect("Failed to generate WireGuard key")
+        .into_inner();
+    assert_eq!(keygen_event.event, KeygenEvent::NewKey as i32);
+    keygen_event.new_key.expect("Missing new key").key
+}
+
+fn get_account_expiry(
+    daemon: &DaemonRunner,
+    rpc_client: &mut mullvad_management_interface::ManagementServiceClient,
+    account_token: &str,
+) -> i64 {
+    daemon
+        .block_on(rpc_client.get_account_data(account_token.to_owned()))
+        .expect("Failed to get account data")
+        .into_inner()
+        .expiry
+        .expect("Missing account expiry")
+        .seconds
+}
+
+fn get_current_access_method(
+    daemon: &DaemonRunner,
+    rpc_client: &mut mullvad_management_interface::ManagementServiceClient,
+) -> String {
+    daemon
+        .block_on(rpc_client.get_current_api_access_method(()))
+        .expect("Failed to get current access method")
+        .into_inner()
+        .name
+}
+
+fn get_relay_hostnames(
+    daemon: &DaemonRunner,
+    rpc_client: &mut mullvad_management_interface::ManagementServiceClient,
+) -> Vec<String> {
+    daemon.block_on(async {
+        let mut countries = rpc_client
+            .get_relay_locations(())
+            .await
+            .unwrap()
+            .into_inner();
+        let mut hostnames = vec![];
+        while let Some(country) = countries.message().await.unwrap() {
+            for city in country.cities {
+                hostnames.extend(city.relays.into_iter().map(|relay| relay.hostname));
+            }
+        }
+        hostnames
+    })
+}
@@ -1,11 +1,15 @@
 #![cfg(feature = "integration-tests")]
 
-use futures::{stream::Stream, Future};
+use chrono::Utc;
+use mullvad_management_interface::{
+    types::{self, daemon_event::Event},
+    ManagementServiceClient,
+};
 use mullvad_tests::{
     mock_openvpn::search_openvpn_args, watch_event, DaemonRunner, MockOpenVpnPluginRpcClient,
     PathWatcher,
 };
-use mullvad_types::{location::GeoIpLocation, states::TunnelState, DaemonEvent};
+use mullvad_types::{location::GeoIpLocation, states::TunnelState};
 use std::{fs, path::Path, time::Duration};
 use talpid_types::{
     net::{Endpoint, TransportProtocol, TunnelEndpoint, TunnelType},
@@ -27,8 +31,8 @@ fn spawns_openvpn() {
 
     assert!(!openvpn_args_file.exists());
 
-    rpc_client.set_account(Some("123456".to_owned())).unwrap();
-    rpc_client.connect().unwrap();
+    set_account(&daemon, &mut rpc_client, "123456");
+    daemon.block_on(rpc_client.connect_tunnel(())).unwrap();
 
     openvpn_args_file_events.assert_create_write_close_sequence();
 }
@@ -44,8 +48,8 @@ fn respawns_openvpn_if_it_crashes() {
 
     assert!(!openvpn_args_file.exists());
 
-    rpc_client.set_account(Some("123456".to_owned())).unwrap();
-    rpc_client.connect().unwrap();
+    set_account(&daemon, &mut rpc_client, "123456");
+    daemon.block_on(rpc_client.connect_tunnel(())).unwrap();
 
     openvpn_args_file_events.assert_create_write_close_sequence();
 
@@ -60,24 +64,28 @@ fn respawns_openvpn_if_it_crashes() {
 fn changes_to_connecting_state() {
     let mut daemon = DaemonRunner::spawn();
     let mut rpc_client = daemon.rpc_client().unwrap();
-    let state_events = rpc_client.daemon_event_subscribe().wait().unwrap();
+    let mut state_events = daemon
+        .block_on(rpc_client.events_listen(()))
+        .unwrap()
+        .into_inner();
 
-    rpc_client.set_account(Some("123456".to_owned())).unwrap();
-    rpc_client.connect().unwrap();
+    set_account(&daemon, &mut rpc_client, "123456");
+    daemon.block_on(rpc_client.connect_tunnel(())).unwrap();
 
-    let _ = assert_state_event(
-        state_events,
+    assert_state_event(
+        &daemon,
+        &mut state_events,
         TunnelState::Connecting {
             endpoint: get_default_endpoint(),
             location: get_default_location(),
         },
     );
     assert_eq!(
-        rpc_client.get_state().unwrap(),
-        TunnelState::Connecting {
+        get_tunnel_state(&daemon, &mut rpc_client),
+        types::TunnelState::from(TunnelState::Connecting {
             endpoint: get_default_endpoint(),
             location: get_default_location(),
-        },
+        })
     );
 }
 
@@ -87,13 +95,17 @@ fn changes_to_connected_state() {
     let mut rpc_client = daemon.rpc_client().unwrap();
     let openvpn_args_file = daemon.mock_openvpn_args_file();
     let mut openvpn_args_file_events = PathWatcher::watch(&openvpn_args_file).unwrap();
-    let state_events = rpc_client.daemon_event_subscribe().wait().unwrap();
+    let mut state_events = daemon
+        .block_on(rpc_client.events_listen(()))
+        .unwrap()
+        .into_inner();
 
-    rpc_client.set_account(Some("123456".to_owned())).unwrap();
-    rpc_client.connect().unwrap();
+    set_account(&daemon, &mut rpc_client, "123456");
+    daemon.block_on(rpc_client.connect_tunnel(())).unwrap();
 
-    let state_events = assert_state_event(
-        state_events,
+    assert_state_event(
+        &daemon,
+        &mut state_events,
         TunnelState::Connecting {
             endpoint: get_default_endpoint(),
             location: get_default_location(),
@@ -106,18 +118,19 @@ fn changes_to_connected_state() {
     mock_plugin_client.up().unwrap();
 
     assert_state_event(
-        state_events,
+        &daemon,
+        &mut state_events,
         TunnelState::Connected {
             endpoint: get_default_endpoint(),
             location: get_default_location(),
         },
     );
     assert_eq!(
-        rpc_client.get_state().unwrap(),
-        TunnelState::Connected {
+        get_tunnel_state(&daemon, &mut rpc_client),
+        types::TunnelState::from(TunnelState::Connected {
             endpoint: get_default_endpoint(),
             location: get_default_location(),
-        }
+        })
     );
 }
 
@@ -127,13 +140,17 @@ fn returns_to_connecting_state() {
     let mut rpc_client = daemon.rpc_client().unwrap();
     let openvpn_args_file = daemon.mock_openvpn_args_file();
     let mut openvpn_args_file_events = PathWatcher::watch(&openvpn_args_file).unwrap();
-    let state_events = rpc_client.daemon_event_subscribe().wait().unwrap();
+    let mut state_events = daemon
+        .block_on(rpc_client.events_listen(()))
+        .unwrap()
+        .into_inner();
 
-    rpc_client.set_account(Some("123456".to_owned())).unwrap();
-    rpc_client.connect().unwrap();
+    set_account(&daemon, &mut rpc_client, "123456");
+    daemon.block_on(rpc_client.connect_tunnel(())).unwrap();
 
-    let state_events = assert_state_event(
-        state_events,
+    assert_state_event(
+        &daemon,
+        &mut state_events,
         TunnelState::Connecting {
             endpoint: get_default_endpoint(),
             location: get_default_location(),
@@ -145,8 +162,9 @@ fn returns_to_connecting_state() {
 
     mock_plugin_client.up().unwrap();
 
-    let state_events = assert_state_event(
-        state_events,
+    assert_state_event(
+        &daemon,
+        &mut state_events,
         TunnelState::Connected {
             endpoint: get_default_endpoint(),
             location: get_default_location(),
@@ -159,8 +177,9 @@ fn returns_to_connecting_state() {
     assert_eq!(openvpn_args_file_events.next(), Some(watch_event::REMOVE));
     openvpn_args_file_events.assert_create_write_close_sequence();
 
-    let _ = assert_state_event(
-        state_events,
+    assert_state_event(
+        &daemon,
+        &mut state_events,
         TunnelState::Disconnecting(ActionAfterDisconnect::Reconnect),
     );
 }
@@ -171,13 +190,17 @@ fn disconnects() {
     let mut rpc_client = daemon.rpc_client().unwrap();
     let openvpn_args_file = daemon.mock_openvpn_args_file();
     let mut openvpn_args_file_events = PathWatcher::watch(&openvpn_args_file).unwrap();
-    let state_events = rpc_client.daemon_event_subscribe().wait().unwrap();
+    let mut state_events = daemon
+        .block_on(rpc_client.events_listen(()))
+        .unwrap()
+        .into_inner();
 
-    rpc_client.set_account(Some("123456".to_owned())).unwrap();
-    rpc_client.connect().unwrap();
+    set_account(&daemon, &mut rpc_client, "123456");
+    daemon.block_on(rpc_client.connect_tunnel(())).unwrap();
 
-    let state_events = assert_state_event(
-        state_events,
+    assert_state_event(
+        &daemon,
+        &mut state_events,
         TunnelState::Connecting {
             endpoint: get_default_endpoint(),
             location: get_default_location(),
@@ -189,21 +212,23 @@ fn disconnects() {
 
     mock_plugin_client.up().unwrap();
 
-    let state_events = assert_state_event(
-        state_events,
+    assert_state_event(
+        &daemon,
+        &mut state_events,
         TunnelState::Connected {
             endpoint: get_default_endpoint(),
             location: get_default_location(),
         },
     );
 
-    rpc_client.disconnect().unwrap();
+    daemon.block_on(rpc_client.disconnect_tunnel(())).unwrap();
 
-    let state_events = assert_state_event(
-        state_events,
+    assert_state_event(
+        &daemon,
+        &mut state_events,
         TunnelState::Disconnecting(ActionAfterDisconnect::Nothing),
     );
-    let _ = assert_state_event(state_events, TunnelState::Disconnected);
+    assert_state_event(&daemon, &mut state_events, TunnelState::Disconnected);
 }
 
 fn get_default_endpoint() -> TunnelEndpoint {
@@ -228,33 +253,52 @@ fn get_default_location() -> Option<GeoIpLocation> {
         mullvad_exit_ip: true,
         hostname: Some("fakehost".to_string()),
         bridge_hostname: None,
+        entry_hostname: None,
     })
 }
 
-fn assert_state_event<
-    S: Stream<Item = DaemonEvent, Error = jsonrpc_client_core::Error> + std::fmt::Debug,
->(
-    mut receiver: S,
+fn set_account(
+    daemon: &DaemonRunner,
+    rpc_client: &mut ManagementServiceClient,
+    account_token: &str,
+) {
+    daemon
+        .api()
+        .add_account(account_token, Utc::now() + chrono::Duration::days(30));
+    daemon
+        .block_on(rpc_client.set_account(account_token.to_owned()))
+        .unwrap();
+}
+
+fn get_tunnel_state(
+    daemon: &DaemonRunner,
+    rpc_client: &mut ManagementServiceClient,
+) -> types::TunnelState {
+    daemon
+        .block_on(rpc_client.get_tunnel_state(()))
+        .unwrap()
+        .into_inner()
+}
+
+fn assert_state_event(
+    daemon: &DaemonRunner,
+    events: &mut tonic::Streaming<types::DaemonEvent>,
     expected_state: TunnelState,
-) -> S {
-    use futures::future::Either;
-
-    let mut transition = None;
-    while transition.is_none() {
-        let timer = tokio_timer::Timer::default();
-        let timeout = timer.sleep(Duration::from_secs(3));
-        let (event, receiver2) = match receiver.into_future().select2(timeout).wait() {
-            Ok(Either::A((stream_result, _timer))) => stream_result,
-            _ => panic!("Timed out waiting for tunnel state transition"),
-        };
-        receiver = receiver2;
-        if let DaemonEvent::TunnelState(new_state) = event.unwrap() {
-            transition = Some(new_state);
+) {
+    let transition = daemon.block_on(async {
+        loop {
+            let event = tokio::time::timeout(Duration::from_secs(3), events.message())
+                .await
+                .expect("Timed out waiting for tunnel state transition")
+                .expect("Failed to receive daemon event")
+                .expect("Daemon event stream ended");
+            if let Some(Event::TunnelState(new_state)) = event.event {
+                break new_state;
+            }
         }
-    }
+    });
 
-    assert_eq!(transition.unwrap(), expected_state);
-    receiver
+    assert_eq!(transition, types::TunnelState::from(expected_state));
 }
 
 fn create_mock_openvpn_plugin_client<P: AsRef<Path>>(
@@ -1,5 +1,6 @@
 #![cfg(feature = "integration-tests")]
 
+use mullvad_management_interface::types;
 use mullvad_tests::DaemonRunner;
 use mullvad_types::states::TunnelState;
 
@@ -8,7 +9,10 @@ fn starts_in_disconnected_state() {
     let mut daemon = DaemonRunner::spawn();
     let mut rpc_client = daemon.rpc_client().expect("Failed to create RPC client");
 
-    let state = rpc_client.get_state().expect("Failed to read daemon state");
+    let state = daemon
+        .block_on(rpc_client.get_tunnel_state(()))
+        .expect("Failed to read daemon state")
+        .into_inner();
 
-    assert_eq!(state, TunnelState::Disconnected);
+    assert_eq!(state, types::TunnelState::from(TunnelState::Disconnected));
 }
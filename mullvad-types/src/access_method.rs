@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, net::SocketAddr};
+use talpid_types::net::openvpn::HttpConnectProxySettings;
+
+/// Name of the built-in access method that connects to the API directly.
+pub const DIRECT_NAME: &str = "Direct";
+/// Name of the built-in access method that connects to the API through a Mullvad bridge.
+pub const BRIDGES_NAME: &str = "Mullvad Bridges";
+
+#[derive(err_derive::Error, Debug, Clone, PartialEq)]
+#[error(no_from)]
+pub enum Error {
+    /// An access method with the same name already exists.
+    #[error(display = "An API access method named \"{}\" already exists", _0)]
+    DuplicateName(String),
+
+    /// No access method with the given name exists.
+    #[error(display = "There is no API access method named \"{}\"", _0)]
+    NotFound(String),
+
+    /// Built-in access methods are always present and cannot be added again.
+    #[error(display = "Only custom API access methods can be added")]
+    AddBuiltIn,
+
+    /// Built-in access methods cannot be removed, only disabled.
+    #[error(display = "Built-in API access method \"{}\" cannot be removed", _0)]
+    RemoveBuiltIn(String),
+
+    /// At least one access method must remain enabled.
+    #[error(display = "At least one API access method must be enabled")]
+    NoneEnabled,
+}
+
+/// A way of reaching the Mullvad API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessMethod {
+    /// Connect to the API directly.
+    Direct,
+    /// Connect to the API through a Shadowsocks bridge from the relay list.
+    Bridges,
+    /// Connect to the API through a SOCKS5 proxy.
+    Socks5(SocketAddr),
+    /// Connect to the API through an HTTP proxy, using the `CONNECT` method.
+    HttpConnect(HttpConnectProxySettings),
+}
+
+impl AccessMethod {
+    /// Returns whether this method is built in, i.e. always present in the settings.
+    pub fn is_builtin(&self) -> bool {
+        match self {
+            AccessMethod::Direct | AccessMethod::Bridges => true,
+            AccessMethod::Socks5(_) | AccessMethod::HttpConnect(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for AccessMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessMethod::Direct => write!(f, "direct"),
+            AccessMethod::Bridges => write!(f, "Shadowsocks bridge"),
+            AccessMethod::Socks5(address) => write!(f, "SOCKS5 proxy at {}", address),
+            AccessMethod::HttpConnect(proxy) => write!(f, "HTTP proxy at {}", proxy.address),
+        }
+    }
+}
+
+/// A named access method that can be enabled or disabled by the user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiAccessMethod {
+    pub name: String,
+    pub enabled: bool,
+    pub access_method: AccessMethod,
+}
+
+impl ApiAccessMethod {
+    pub fn new(name: String, access_method: AccessMethod) -> Self {
+        ApiAccessMethod {
+            name,
+            enabled: true,
+            access_method,
+        }
+    }
+}
+
+/// The API access methods, in the order in which they are tried.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    access_methods: Vec<ApiAccessMethod>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            access_methods: vec![
+                ApiAccessMethod::new(DIRECT_NAME.to_owned(), AccessMethod::Direct),
+                ApiAccessMethod::new(BRIDGES_NAME.to_owned(), AccessMethod::Bridges),
+            ],
+        }
+    }
+}
+
+impl Settings {
+    /// Returns all access methods, including disabled ones.
+    pub fn access_methods(&self) -> &[ApiAccessMethod] {
+        &self.access_methods
+    }
+
+    /// Returns the access methods that may be used to reach the API.
+    pub fn enabled(&self) -> impl Iterator<Item = &ApiAccessMethod> {
+        self.access_methods.iter().filter(|method| method.enabled)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&ApiAccessMethod> {
+        self.access_methods
+            .iter()
+            .find(|method| method.name == name)
+    }
+
+    /// Appends a new access method. Names must be unique.
+    pub fn add(&mut self, method: ApiAccessMethod) -> Result<(), Error> {
+        if method.access_method.is_builtin() {
+            return Err(Error::AddBuiltIn);
+        }
+        if self.find(&method.name).is_some() {
+            return Err(Error::DuplicateName(method.name));
+        }
+        self.access_methods.push(method);
+        Ok(())
+    }
+
+    /// Removes a custom access method.
+    pub fn remove(&mut self, name: &str) -> Result<(), Error> {
+        let index = self.position(name)?;
+        if self.access_methods[index].access_method.is_builtin() {
+            return Err(Error::RemoveBuiltIn(name.to_owned()));
+        }
+        let removed = self.access_methods.remove(index);
+        if self.enabled().next().is_none() {
+            self.access_methods.insert(index, removed);
+            return Err(Error::NoneEnabled);
+        }
+        Ok(())
+    }
+
+    /// Enables or disables an access method. Returns whether the settings changed.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<bool, Error> {
+        let index = self.position(name)?;
+        if self.access_methods[index].enabled == enabled {
+            return Ok(false);
+        }
+        if !enabled && self.enabled().count() == 1 {
+            return Err(Error::NoneEnabled);
+        }
+        self.access_methods[index].enabled = enabled;
+        Ok(true)
+    }
+
+    fn position(&self, name: &str) -> Result<usize, Error> {
+        self.access_methods
+            .iter()
+            .position(|method| method.name == name)
+            .ok_or_else(|| Error::NotFound(name.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn socks5_method(name: &str) -> ApiAccessMethod {
+        ApiAccessMethod::new(
+            name.to_owned(),
+            AccessMethod::Socks5("127.0.0.1:1080".parse().unwrap()),
+        )
+    }
+
+    #[test]
+    fn test_add_remove() {
+        let mut settings = Settings::default();
+        settings.add(socks5_method("proxy")).unwrap();
+        assert_eq!(
+            settings.add(socks5_method("proxy")),
+            Err(Error::DuplicateName("proxy".to_owned()))
+        );
+        assert_eq!(settings.enabled().count(), 3);
+
+        settings.remove("proxy").unwrap();
+        assert_eq!(
+            settings.remove(DIRECT_NAME),
+            Err(Error::RemoveBuiltIn(DIRECT_NAME.to_owned()))
+        );
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn test_keep_one_enabled() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.set_enabled(BRIDGES_NAME, false), Ok(true));
+        assert_eq!(settings.set_enabled(BRIDGES_NAME, false), Ok(false));
+        assert_eq!(
+            settings.set_enabled(DIRECT_NAME, false),
+            Err(Error::NoneEnabled)
+        );
+
+        settings.add(socks5_method("proxy")).unwrap();
+        assert_eq!(settings.set_enabled(DIRECT_NAME, false), Ok(true));
+        assert_eq!(settings.remove("proxy"), Err(Error::NoneEnabled));
+        assert_eq!(settings.access_methods().len(), 3);
+    }
+}
@@ -22,6 +22,23 @@ impl AccountData {
     }
 }
 
+/// Emitted when the account is about to run out of time, or has already done so.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct AccountExpiryEvent {
+    /// The last known expiry of the account.
+    pub expiry: DateTime<Utc>,
+    /// The notification threshold, in hours before `expiry`, that was crossed. `None` if the
+    /// account has expired.
+    pub threshold_hours: Option<u32>,
+}
+
+impl AccountExpiryEvent {
+    /// Return true if the account has no time left.
+    pub fn is_expired(&self) -> bool {
+        self.threshold_hours.is_none()
+    }
+}
+
 /// Data structure that's returned from successful invocation of the mullvad API's
 /// `/v1/submit-voucher` RPC.
 #[derive(Deserialize, Serialize, Debug)]
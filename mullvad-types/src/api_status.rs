@@ -0,0 +1,104 @@
+use chrono::{offset::Utc, DateTime};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Upper bounds of the request latency histogram buckets, in milliseconds. Requests slower than
+/// the last bound are counted in an additional bucket.
+pub const LATENCY_BUCKET_BOUNDS_MS: [u64; 7] = [100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// Request statistics for every endpoint that the API has been reached through.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiStatus {
+    pub endpoints: Vec<EndpointStatus>,
+}
+
+/// Request statistics for an endpoint, reached using a particular connection mode.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EndpointStatus {
+    /// Host and port that requests were sent to.
+    pub endpoint: String,
+    /// How connections to the endpoint were established, e.g. directly or through a proxy.
+    pub connection_mode: String,
+    pub successes: u64,
+    pub failures: Vec<FailureCount>,
+    pub latency: Vec<LatencyBucket>,
+    pub last_error: Option<RequestError>,
+}
+
+impl EndpointStatus {
+    pub fn new(endpoint: String, connection_mode: String) -> Self {
+        let mut latency: Vec<_> = LATENCY_BUCKET_BOUNDS_MS
+            .iter()
+            .map(|bound| LatencyBucket {
+                upper_bound_ms: Some(*bound),
+                count: 0,
+            })
+            .collect();
+        latency.push(LatencyBucket {
+            upper_bound_ms: None,
+            count: 0,
+        });
+
+        EndpointStatus {
+            endpoint,
+            connection_mode,
+            successes: 0,
+            failures: vec![],
+            latency,
+            last_error: None,
+        }
+    }
+
+    /// Returns the total number of failed requests.
+    pub fn total_failures(&self) -> u64 {
+        self.failures.iter().map(|failure| failure.count).sum()
+    }
+}
+
+/// Number of requests that failed for a given reason.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailureCount {
+    pub kind: RequestErrorKind,
+    pub count: u64,
+}
+
+/// Number of responses received within a latency range.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyBucket {
+    /// Inclusive upper bound of the bucket. `None` for the bucket of the slowest responses.
+    pub upper_bound_ms: Option<u64>,
+    pub count: u64,
+}
+
+/// The most recent request failure.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestError {
+    pub kind: RequestErrorKind,
+    pub message: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Reasons why a request to the API can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestErrorKind {
+    /// No connection could be established, or the connection was lost.
+    Network,
+    /// No response was received in time.
+    Timeout,
+    /// A response with an unsuccessful status code was received.
+    ApiStatus,
+    /// The request could not be sent for some other reason.
+    Other,
+}
+
+impl fmt::Display for RequestErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestErrorKind::Network => write!(f, "network"),
+            RequestErrorKind::Timeout => write!(f, "timeout"),
+            RequestErrorKind::ApiStatus => write!(f, "API status"),
+            RequestErrorKind::Other => write!(f, "other"),
+        }
+    }
+}
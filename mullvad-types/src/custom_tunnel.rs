@@ -6,7 +6,9 @@ use std::{
     fmt, io,
     net::{IpAddr, SocketAddr, ToSocketAddrs},
 };
-use talpid_types::net::{openvpn, wireguard, Endpoint, TunnelParameters};
+use talpid_types::net::{
+    obfuscation::ObfuscatorConfig, openvpn, wireguard, Endpoint, TunnelParameters,
+};
 
 
 #[derive(err_derive::Error, Debug)]
@@ -56,12 +58,15 @@ impl CustomTunnelEndpoint {
                 options: tunnel_options.openvpn.clone(),
                 generic_options: tunnel_options.generic.clone(),
                 proxy,
+                wireguard_entry: None,
             }
             .into(),
             ConnectionConfig::Wireguard(connection) => wireguard::TunnelParameters {
+                obfuscation: ObfuscatorConfig::for_peer(&connection.peer, None),
                 connection,
                 options: tunnel_options.wireguard.options.clone(),
                 generic_options: tunnel_options.generic.clone(),
+                proxy: None,
             }
             .into(),
         };
@@ -1,13 +1,18 @@
 #![deny(rust_2018_idioms)]
 
+pub mod access_method;
 pub mod account;
+pub mod api_status;
 pub mod auth_failed;
 pub mod endpoint;
 pub mod location;
+pub mod network_profile;
 pub mod relay_constraints;
 pub mod relay_list;
+pub mod retry_schedule;
 pub mod settings;
 pub mod states;
+pub mod trusted_network;
 pub mod version;
 pub mod wireguard;
 
@@ -83,6 +83,19 @@ pub struct GeoIpLocation {
     pub hostname: Option<String>,
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub bridge_hostname: Option<String>,
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub entry_hostname: Option<String>,
+}
+
+impl GeoIpLocation {
+    pub fn distance_from(&self, other: &Location) -> f64 {
+        haversine_dist_deg(
+            self.latitude,
+            self.longitude,
+            other.latitude,
+            other.longitude,
+        )
+    }
 }
 
 impl From<AmIMullvad> for GeoIpLocation {
@@ -102,6 +115,7 @@ impl From<AmIMullvad> for GeoIpLocation {
             mullvad_exit_ip: location.mullvad_exit_ip,
             hostname: None,
             bridge_hostname: None,
+            entry_hostname: None,
         }
     }
 }
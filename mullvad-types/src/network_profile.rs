@@ -0,0 +1,333 @@
+use crate::relay_constraints::{BridgeState, RelaySettings, RelaySettingsUpdate};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use talpid_types::net::ConnectedNetwork;
+
+#[derive(err_derive::Error, Debug, Clone, PartialEq)]
+#[error(no_from)]
+pub enum Error {
+    /// A profile with the same name already exists.
+    #[error(display = "A network profile named \"{}\" already exists", _0)]
+    DuplicateName(String),
+
+    /// There is no profile with the given name.
+    #[error(display = "No network profile is named \"{}\"", _0)]
+    NotFound(String),
+}
+
+/// Identifies a network that a profile applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkIdentifier {
+    /// A Wi-Fi network with the given SSID.
+    Ssid(String),
+    /// Any network whose default gateway has the given MAC address.
+    GatewayMac(String),
+    /// Any network reached through the given interface.
+    Interface(String),
+}
+
+impl NetworkIdentifier {
+    pub fn matches(&self, network: &ConnectedNetwork) -> bool {
+        match self {
+            NetworkIdentifier::Ssid(ssid) => network.ssid.as_ref() == Some(ssid),
+            NetworkIdentifier::GatewayMac(mac) => network
+                .gateway_mac
+                .as_ref()
+                .map(|gateway_mac| gateway_mac.eq_ignore_ascii_case(mac))
+                .unwrap_or(false),
+            NetworkIdentifier::Interface(interface) => network.interface == *interface,
+        }
+    }
+}
+
+impl fmt::Display for NetworkIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkIdentifier::Ssid(ssid) => write!(f, "Wi-Fi network \"{}\"", ssid),
+            NetworkIdentifier::GatewayMac(mac) => write!(f, "gateway {}", mac),
+            NetworkIdentifier::Interface(interface) => write!(f, "interface {}", interface),
+        }
+    }
+}
+
+/// Relay settings and bridge state that replace the regular settings while the host is
+/// connected to one of the given networks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkProfile {
+    pub name: String,
+    pub networks: Vec<NetworkIdentifier>,
+    /// Replaces the regular relay settings, unless `None`.
+    pub relay_settings: Option<RelaySettings>,
+    /// Replaces the regular bridge state, unless `None`.
+    pub bridge_state: Option<BridgeState>,
+}
+
+impl NetworkProfile {
+    pub fn new(name: String) -> Self {
+        NetworkProfile {
+            name,
+            networks: vec![],
+            relay_settings: None,
+            bridge_state: None,
+        }
+    }
+}
+
+/// The network profile that is in effect, and the reason for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveNetworkProfile {
+    pub profile: NetworkProfile,
+    /// The connected network that the profile applies to.
+    pub network: ConnectedNetwork,
+    /// The identifier of the profile that matched `network`.
+    pub identifier: NetworkIdentifier,
+}
+
+/// Network profiles, in order of precedence.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    profiles: Vec<NetworkProfile>,
+}
+
+impl Settings {
+    pub fn profiles(&self) -> &[NetworkProfile] {
+        &self.profiles
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NetworkProfile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    pub fn add(&mut self, profile: NetworkProfile) -> Result<(), Error> {
+        if self.get(&profile.name).is_some() {
+            return Err(Error::DuplicateName(profile.name));
+        }
+        self.profiles.push(profile);
+        Ok(())
+    }
+
+    /// Replaces the profile with the same name as `profile`.
+    pub fn replace(&mut self, profile: NetworkProfile) -> Result<(), Error> {
+        let existing = self
+            .profiles
+            .iter_mut()
+            .find(|existing| existing.name == profile.name)
+            .ok_or_else(|| Error::NotFound(profile.name.clone()))?;
+        *existing = profile;
+        Ok(())
+    }
+
+    /// Applies `update` to the relay settings of the profile named `name`. If the profile has no
+    /// relay settings of its own, the update is applied to `regular_relay_settings`.
+    pub fn update_relay_settings(
+        &mut self,
+        name: &str,
+        regular_relay_settings: &RelaySettings,
+        update: RelaySettingsUpdate,
+    ) -> Result<(), Error> {
+        let profile = self
+            .profiles
+            .iter_mut()
+            .find(|profile| profile.name == name)
+            .ok_or_else(|| Error::NotFound(name.to_owned()))?;
+        profile.bridge_state = profile
+            .bridge_state
+            .map(|bridge_state| update.compatible_bridge_state(bridge_state));
+        let mut relay_settings = profile
+            .relay_settings
+            .take()
+            .unwrap_or_else(|| regular_relay_settings.clone());
+        profile.relay_settings = Some(relay_settings.merge(update));
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), Error> {
+        let index = self
+            .profiles
+            .iter()
+            .position(|profile| profile.name == name)
+            .ok_or_else(|| Error::NotFound(name.to_owned()))?;
+        self.profiles.remove(index);
+        Ok(())
+    }
+
+    /// Returns the first profile that applies to any of the `connected` networks.
+    pub fn active_profile(&self, connected: &[ConnectedNetwork]) -> Option<ActiveNetworkProfile> {
+        self.profiles.iter().find_map(|profile| {
+            profile.networks.iter().find_map(|identifier| {
+                connected
+                    .iter()
+                    .find(|network| identifier.matches(network))
+                    .map(|network| ActiveNetworkProfile {
+                        profile: profile.clone(),
+                        network: network.clone(),
+                        identifier: identifier.clone(),
+                    })
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relay_constraints::{
+        Constraint, OpenVpnConstraints, RelayConstraints, RelayConstraintsUpdate, TransportPort,
+    };
+    use talpid_types::net::{TransportProtocol, TunnelType};
+
+    fn network(interface: &str, ssid: Option<&str>, gateway_mac: Option<&str>) -> ConnectedNetwork {
+        ConnectedNetwork {
+            interface: interface.to_owned(),
+            ssid: ssid.map(str::to_owned),
+            gateway_mac: gateway_mac.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn test_add_replace_remove() {
+        let mut settings = Settings::default();
+        settings
+            .add(NetworkProfile::new("home".to_owned()))
+            .unwrap();
+        assert_eq!(
+            settings.add(NetworkProfile::new("home".to_owned())),
+            Err(Error::DuplicateName("home".to_owned()))
+        );
+
+        let mut profile = NetworkProfile::new("home".to_owned());
+        profile.bridge_state = Some(BridgeState::Off);
+        settings.replace(profile.clone()).unwrap();
+        assert_eq!(settings.get("home"), Some(&profile));
+        assert_eq!(
+            settings.replace(NetworkProfile::new("office".to_owned())),
+            Err(Error::NotFound("office".to_owned()))
+        );
+
+        settings.remove("home").unwrap();
+        assert_eq!(
+            settings.remove("home"),
+            Err(Error::NotFound("home".to_owned()))
+        );
+        assert!(settings.profiles().is_empty());
+    }
+
+    #[test]
+    fn test_update_relay_settings() {
+        let mut settings = Settings::default();
+        let mut profile = NetworkProfile::new("home".to_owned());
+        profile.bridge_state = Some(BridgeState::On);
+        settings.add(profile).unwrap();
+
+        let regular = RelaySettings::Normal(RelayConstraints::default());
+        let update = RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
+            tunnel_protocol: Some(Constraint::Only(TunnelType::Wireguard)),
+            ..Default::default()
+        });
+        settings
+            .update_relay_settings("home", &regular, update)
+            .unwrap();
+        let profile = settings.get("home").unwrap();
+        assert_eq!(
+            profile.relay_settings,
+            Some(RelaySettings::Normal(RelayConstraints {
+                tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+                ..RelayConstraints::default()
+            }))
+        );
+        assert_eq!(profile.bridge_state, Some(BridgeState::On));
+
+        // Bridges cannot be used over UDP, so they stop being required
+        let update = RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
+            openvpn_constraints: Some(OpenVpnConstraints {
+                port: Constraint::Only(TransportPort {
+                    protocol: TransportProtocol::Udp,
+                    port: Constraint::Any,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        settings
+            .update_relay_settings("home", &regular, update)
+            .unwrap();
+        assert_eq!(
+            settings.get("home").unwrap().bridge_state,
+            Some(BridgeState::Auto)
+        );
+
+        assert_eq!(
+            settings.update_relay_settings(
+                "office",
+                &regular,
+                RelaySettingsUpdate::Normal(RelayConstraintsUpdate::default())
+            ),
+            Err(Error::NotFound("office".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_active_profile() {
+        let mut client_site = NetworkProfile::new("client site".to_owned());
+        client_site.networks.push(NetworkIdentifier::GatewayMac(
+            "AA:BB:CC:DD:EE:FF".to_owned(),
+        ));
+        client_site.relay_settings = Some(RelaySettings::Normal(RelayConstraints::default()));
+        let mut home = NetworkProfile::new("home".to_owned());
+        home.networks
+            .push(NetworkIdentifier::Ssid("Home network".to_owned()));
+        home.networks
+            .push(NetworkIdentifier::Interface("eth0".to_owned()));
+
+        let mut settings = Settings::default();
+        settings.add(client_site).unwrap();
+        settings.add(home).unwrap();
+
+        let wifi = network("wlan0", Some("Home network"), None);
+        let active = settings.active_profile(&[wifi.clone()]).unwrap();
+        assert_eq!(active.profile.name, "home");
+        assert_eq!(active.network, wifi);
+        assert_eq!(
+            active.identifier,
+            NetworkIdentifier::Ssid("Home network".to_owned())
+        );
+
+        // Gateway MAC addresses are compared without regard to case
+        let ethernet = network("eth0", None, Some("aa:bb:cc:dd:ee:ff"));
+        let active = settings.active_profile(&[wifi, ethernet]).unwrap();
+        assert_eq!(active.profile.name, "client site");
+
+        assert_eq!(
+            settings.active_profile(&[network("eth1", None, None)]),
+            None
+        );
+        assert_eq!(settings.active_profile(&[]), None);
+    }
+
+    #[test]
+    fn test_effective_settings() {
+        let mut settings = crate::settings::Settings::default();
+        let mut profile = NetworkProfile::new("client site".to_owned());
+        profile
+            .networks
+            .push(NetworkIdentifier::Interface("eth0".to_owned()));
+        profile.bridge_state = Some(BridgeState::On);
+        settings.network_profiles.add(profile).unwrap();
+
+        let office = [network("eth0", None, None)];
+        let home = [network("wlan0", Some("Home network"), None)];
+        assert_eq!(
+            settings.get_effective_bridge_state(&office),
+            BridgeState::On
+        );
+        assert_eq!(
+            settings.get_effective_bridge_state(&home),
+            settings.get_bridge_state()
+        );
+        assert_eq!(
+            settings.get_effective_relay_settings(&home),
+            settings.get_relay_settings()
+        );
+    }
+}
@@ -10,7 +10,11 @@ use crate::{
 use jnix::{FromJava, IntoJava};
 use serde::{Deserialize, Serialize};
 use std::{collections::HashSet, fmt};
-use talpid_types::net::{openvpn::ProxySettings, IpVersion, TransportProtocol, TunnelType};
+use talpid_types::net::{
+    obfuscation::ObfuscationType,
+    openvpn::{Cipher, ProxySettings},
+    IpVersion, TransportProtocol, TunnelType,
+};
 
 
 pub trait Match<T> {
@@ -177,8 +181,13 @@ impl RelaySettings {
     pub(crate) fn ensure_bridge_compatibility(&mut self) {
         match self {
             RelaySettings::Normal(ref mut constraints) => {
-                if constraints.tunnel_protocol == Constraint::Only(TunnelType::Wireguard) {
-                    constraints.tunnel_protocol = Constraint::Any;
+                // WireGuard is relayed over UDP through bridges
+                if let Constraint::Only(TransportPort {
+                    protocol: TransportProtocol::Tcp,
+                    ..
+                }) = constraints.wireguard_constraints.port
+                {
+                    constraints.wireguard_constraints.port = Constraint::Any;
                 }
                 if let Constraint::Only(TransportPort {
                     protocol: TransportProtocol::Udp,
@@ -458,20 +467,32 @@ pub struct TransportPort {
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
 pub struct OpenVpnConstraints {
     pub port: Constraint<TransportPort>,
+    /// Connect to the OpenVPN relay through a WireGuard entry relay. The entry relay is
+    /// selected using the multihop constraints in [`WireguardConstraints`].
+    #[serde(default)]
+    pub wireguard_entry: bool,
+    /// Data channel cipher that the relay must support. This is taken from the OpenVPN tunnel
+    /// options when selecting a relay and is never stored.
+    #[serde(skip)]
+    pub cipher: Constraint<Cipher>,
 }
 
 impl fmt::Display for OpenVpnConstraints {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match self.port {
-            Constraint::Any => write!(f, "any port"),
+            Constraint::Any => write!(f, "any port")?,
             Constraint::Only(port) => {
                 match port.port {
                     Constraint::Any => write!(f, "any port")?,
                     Constraint::Only(port) => write!(f, "port {}", port)?,
                 }
-                write!(f, " over {}", port.protocol)
+                write!(f, " over {}", port.protocol)?;
             }
         }
+        if self.wireguard_entry {
+            write!(f, " via a WireGuard entry relay")?;
+        }
+        Ok(())
     }
 }
 
@@ -490,6 +511,12 @@ impl Match<OpenVpnEndpointData> for OpenVpnConstraints {
     }
 }
 
+impl Match<Relay> for Cipher {
+    fn matches(&self, relay: &Relay) -> bool {
+        relay.openvpn_ciphers.contains(self)
+    }
+}
+
 /// [`Constraint`]s applicable to WireGuard relay servers.
 #[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(default)]
@@ -497,6 +524,10 @@ pub struct WireguardConstraints {
     pub port: Constraint<TransportPort>,
     pub ip_version: Constraint<IpVersion>,
     pub entry_location: Option<Constraint<LocationConstraint>>,
+    /// Selects the entry relay closest to the user, among those matching `entry_location`.
+    pub automatic_entry: Option<AutomaticEntry>,
+    /// Obfuscation protocol that traffic to the relay is wrapped in.
+    pub obfuscation: SelectedObfuscation,
 }
 
 impl fmt::Display for WireguardConstraints {
@@ -516,7 +547,21 @@ impl fmt::Display for WireguardConstraints {
             Constraint::Any => write!(f, "IPv4 or IPv6")?,
             Constraint::Only(protocol) => write!(f, "{}", protocol)?,
         }
-        if let Some(Constraint::Only(ref entry)) = self.entry_location {
+        match self.obfuscation {
+            SelectedObfuscation::Auto => (),
+            SelectedObfuscation::Off => write!(f, " without obfuscation")?,
+            SelectedObfuscation::Udp2Tcp => write!(f, " obfuscated with UDP-over-TCP")?,
+        }
+        if let Some(automatic_entry) = self.automatic_entry {
+            write!(f, " (via closest entry")?;
+            if automatic_entry.different_country {
+                write!(f, ", in a different country")?;
+            }
+            if automatic_entry.different_provider {
+                write!(f, ", from a different provider")?;
+            }
+            write!(f, ")")
+        } else if let Some(Constraint::Only(ref entry)) = self.entry_location {
             write!(f, " (via {})", entry)
         } else {
             Ok(())
@@ -524,8 +569,86 @@ impl fmt::Display for WireguardConstraints {
     }
 }
 
+/// Requirements on an automatically selected multihop entry relay.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AutomaticEntry {
+    /// Requires the entry relay to be in a different country than the exit relay.
+    pub different_country: bool,
+    /// Requires the entry relay to be run by a different provider than the exit relay.
+    pub different_provider: bool,
+}
+
+impl AutomaticEntry {
+    /// Returns whether `entry` may be used as the entry relay for `exit`.
+    pub fn allows(&self, entry: &Relay, exit: &Relay) -> bool {
+        let country_code = |relay: &Relay| {
+            relay
+                .location
+                .as_ref()
+                .map(|location| location.country_code.clone())
+        };
+        !(self.different_country && country_code(entry) == country_code(exit))
+            && !(self.different_provider && entry.provider == exit.provider)
+    }
+}
+
+/// Obfuscation protocol to use for WireGuard.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectedObfuscation {
+    /// Obfuscate traffic only if the port constraint or the retry schedule requires it.
+    Auto,
+    Off,
+    #[serde(rename = "udp2tcp")]
+    Udp2Tcp,
+}
+
+impl Default for SelectedObfuscation {
+    fn default() -> Self {
+        SelectedObfuscation::Auto
+    }
+}
+
+impl SelectedObfuscation {
+    /// Returns the obfuscation protocol that must be used, if any.
+    pub fn get_type(&self) -> Option<ObfuscationType> {
+        match self {
+            SelectedObfuscation::Auto | SelectedObfuscation::Off => None,
+            SelectedObfuscation::Udp2Tcp => Some(ObfuscationType::Udp2Tcp),
+        }
+    }
+
+    /// Returns whether WireGuard endpoints reached over `protocol` can be used.
+    pub fn allows_transport(&self, protocol: TransportProtocol) -> bool {
+        match self {
+            SelectedObfuscation::Auto => true,
+            SelectedObfuscation::Off => protocol == TransportProtocol::Udp,
+            SelectedObfuscation::Udp2Tcp => protocol == TransportProtocol::Tcp,
+        }
+    }
+}
+
+impl fmt::Display for SelectedObfuscation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectedObfuscation::Auto => write!(f, "auto"),
+            SelectedObfuscation::Off => write!(f, "off"),
+            SelectedObfuscation::Udp2Tcp => write!(f, "udp2tcp"),
+        }
+    }
+}
+
 impl Match<WireguardEndpointData> for WireguardConstraints {
     fn matches(&self, endpoint: &WireguardEndpointData) -> bool {
+        let obfuscation_matches = match self.obfuscation {
+            // Obfuscated endpoints are only used if the port constraint requires it
+            SelectedObfuscation::Auto => endpoint.obfuscation.is_none() || self.port.is_only(),
+            selected => selected.get_type() == endpoint.obfuscation,
+        };
+        if !obfuscation_matches {
+            return false;
+        }
         match self.port {
             Constraint::Any => true,
             Constraint::Only(transport_port) => {
@@ -627,7 +750,15 @@ impl RelaySettingsUpdate {
                 endpoint.endpoint().protocol == TransportProtocol::Tcp
             }
             RelaySettingsUpdate::Normal(update) => {
-                if let Some(Constraint::Only(TunnelType::Wireguard)) = &update.tunnel_protocol {
+                if let Some(WireguardConstraints {
+                    port:
+                        Constraint::Only(TransportPort {
+                            protocol: TransportProtocol::Tcp,
+                            ..
+                        }),
+                    ..
+                }) = &update.wireguard_constraints
+                {
                     false
                 } else if let Some(constraints) = &update.openvpn_constraints {
                     if let Constraint::Only(TransportPort {
@@ -645,6 +776,16 @@ impl RelaySettingsUpdate {
             }
         }
     }
+
+    /// Returns the bridge state to use together with the relay settings resulting from this
+    /// update. Bridges stop being required if the new relay settings cannot use them.
+    pub fn compatible_bridge_state(&self, bridge_state: BridgeState) -> BridgeState {
+        if bridge_state == BridgeState::On && !self.supports_bridge() {
+            BridgeState::Auto
+        } else {
+            bridge_state
+        }
+    }
 }
 
 /// Used in [`RelaySettings`] to change relay constraints in the daemon.
@@ -10,7 +10,8 @@ use std::{
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
 };
 use talpid_types::net::{
-    openvpn::{ProxySettings, ShadowsocksProxySettings},
+    obfuscation::ObfuscationType,
+    openvpn::{Cipher, ProxySettings, ShadowsocksProxySettings},
     wireguard, Endpoint, TransportProtocol,
 };
 
@@ -84,6 +85,13 @@ pub struct Relay {
     #[serde(skip_serializing_if = "RelayBridges::is_empty", default)]
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub bridges: RelayBridges,
+    #[serde(skip_serializing_if = "RelayObfuscators::is_empty", default)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub obfuscators: RelayObfuscators,
+    /// OpenVPN data channel ciphers supported by the relay.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub openvpn_ciphers: Vec<Cipher>,
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub location: Option<Location>,
 }
@@ -145,6 +153,9 @@ pub struct WireguardEndpointData {
     #[serde(default = "default_wg_transport")]
     #[serde(skip)]
     pub protocol: TransportProtocol,
+    /// Obfuscation protocol that traffic to the endpoint is wrapped in
+    #[serde(skip)]
+    pub obfuscation: Option<ObfuscationType>,
 }
 
 fn default_wg_transport() -> TransportProtocol {
@@ -204,3 +215,33 @@ impl ShadowsocksEndpointData {
         })
     }
 }
+
+/// Obfuscation servers that run on a [`Relay`], in front of its WireGuard endpoints.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RelayObfuscators {
+    pub udp2tcp: Vec<Udp2TcpEndpointData>,
+}
+
+impl RelayObfuscators {
+    pub fn is_empty(&self) -> bool {
+        self.udp2tcp.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.udp2tcp.clear();
+    }
+
+    /// Returns whether the relay runs an obfuscation server of the given type.
+    pub fn supports(&self, obfuscation: ObfuscationType) -> bool {
+        match obfuscation {
+            ObfuscationType::Udp2Tcp => !self.udp2tcp.is_empty(),
+        }
+    }
+}
+
+/// Data needed to connect to a UDP-over-TCP server at a [`Relay`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub struct Udp2TcpEndpointData {
+    pub port: u16,
+}
@@ -0,0 +1,302 @@
+use crate::relay_constraints::{Constraint, TransportPort};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use talpid_types::net::{TransportProtocol, TunnelType};
+
+/// A single connection attempt in a [`RetrySchedule`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RetryAttempt {
+    pub tunnel_type: TunnelType,
+    pub port: Constraint<TransportPort>,
+    /// Whether to connect through a bridge, if the bridge state is set to auto.
+    pub bridge: bool,
+}
+
+impl RetryAttempt {
+    const fn new(tunnel_type: TunnelType, port: Constraint<TransportPort>, bridge: bool) -> Self {
+        RetryAttempt {
+            tunnel_type,
+            port,
+            bridge,
+        }
+    }
+}
+
+impl fmt::Display for RetryAttempt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.tunnel_type)?;
+        match self.port {
+            Constraint::Any => write!(f, " over any protocol")?,
+            Constraint::Only(port) => {
+                write!(f, " over {}", port.protocol)?;
+                if let Constraint::Only(port) = port.port {
+                    write!(f, " port {}", port)?;
+                }
+            }
+        }
+        if self.bridge {
+            write!(f, " via bridge")?;
+        }
+        Ok(())
+    }
+}
+
+/// An ordered list of connection attempts. The first attempt is used when connecting, and every
+/// failed attempt moves on to the next one. Once every attempt has been made, the schedule
+/// continues from `repeat_from`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RetrySchedule {
+    pub attempts: Vec<RetryAttempt>,
+    pub repeat_from: usize,
+}
+
+const fn any_port(protocol: TransportProtocol) -> Constraint<TransportPort> {
+    Constraint::Only(TransportPort {
+        protocol,
+        port: Constraint::Any,
+    })
+}
+
+const fn port(protocol: TransportProtocol, port: u16) -> Constraint<TransportPort> {
+    Constraint::Only(TransportPort {
+        protocol,
+        port: Constraint::Only(port),
+    })
+}
+
+impl RetrySchedule {
+    /// The default schedule when both WireGuard and OpenVPN can be used. WireGuard is tried
+    /// twice, first on any port and then on port 53. Afterwards, OpenVPN is used, alternating
+    /// between UDP and TCP, and every other pair of attempts uses a bridge.
+    pub fn default_wireguard_and_openvpn() -> Self {
+        use TransportProtocol::*;
+        use TunnelType::*;
+
+        RetrySchedule {
+            attempts: vec![
+                RetryAttempt::new(Wireguard, any_port(Udp), false),
+                RetryAttempt::new(Wireguard, port(Udp, 53), false),
+                RetryAttempt::new(OpenVpn, any_port(Udp), false),
+                RetryAttempt::new(OpenVpn, any_port(Udp), false),
+                RetryAttempt::new(OpenVpn, port(Tcp, 443), true),
+                RetryAttempt::new(OpenVpn, port(Tcp, 443), true),
+                RetryAttempt::new(OpenVpn, any_port(Udp), false),
+                RetryAttempt::new(OpenVpn, any_port(Tcp), false),
+                RetryAttempt::new(OpenVpn, any_port(Udp), true),
+                RetryAttempt::new(OpenVpn, any_port(Tcp), true),
+            ],
+            repeat_from: 6,
+        }
+    }
+
+    /// The default schedule when only OpenVPN can be used. UDP is preferred, but TCP port 443 is
+    /// tried if that fails, after which the protocols alternate.
+    pub fn default_openvpn() -> Self {
+        use TransportProtocol::*;
+        use TunnelType::*;
+
+        RetrySchedule {
+            attempts: vec![
+                RetryAttempt::new(OpenVpn, any_port(Udp), false),
+                RetryAttempt::new(OpenVpn, any_port(Udp), false),
+                RetryAttempt::new(OpenVpn, port(Tcp, 443), false),
+                RetryAttempt::new(OpenVpn, port(Tcp, 443), false),
+                RetryAttempt::new(OpenVpn, any_port(Udp), true),
+                RetryAttempt::new(OpenVpn, any_port(Tcp), true),
+                RetryAttempt::new(OpenVpn, any_port(Udp), false),
+                RetryAttempt::new(OpenVpn, any_port(Tcp), false),
+            ],
+            repeat_from: 4,
+        }
+    }
+
+    /// The default schedule when only WireGuard can be used. Two of every four attempts use
+    /// port 53.
+    pub fn default_wireguard() -> Self {
+        use TransportProtocol::*;
+        use TunnelType::*;
+
+        RetrySchedule {
+            attempts: vec![
+                RetryAttempt::new(Wireguard, Constraint::Any, false),
+                RetryAttempt::new(Wireguard, Constraint::Any, false),
+                RetryAttempt::new(Wireguard, port(Udp, 53), false),
+                RetryAttempt::new(Wireguard, port(Udp, 53), false),
+                RetryAttempt::new(Wireguard, Constraint::Any, true),
+                RetryAttempt::new(Wireguard, Constraint::Any, true),
+                RetryAttempt::new(Wireguard, port(Udp, 53), false),
+                RetryAttempt::new(Wireguard, port(Udp, 53), false),
+            ],
+            repeat_from: 4,
+        }
+    }
+
+    /// Returns the attempt to use for the given retry attempt, or `None` if the schedule is
+    /// empty.
+    pub fn attempt(&self, retry_attempt: u32) -> Option<&RetryAttempt> {
+        let index = retry_attempt as usize;
+        let len = self.attempts.len();
+        if index < len {
+            return self.attempts.get(index);
+        }
+        if len == 0 {
+            return None;
+        }
+        let repeat_from = if self.repeat_from < len {
+            self.repeat_from
+        } else {
+            0
+        };
+        self.attempts
+            .get(repeat_from + (index - len) % (len - repeat_from))
+    }
+
+    /// Returns a schedule containing only the attempts that use `tunnel_type`, or `None` if no
+    /// attempt does.
+    pub fn only_tunnel_type(&self, tunnel_type: TunnelType) -> Option<RetrySchedule> {
+        let attempts: Vec<RetryAttempt> = self
+            .attempts
+            .iter()
+            .filter(|attempt| attempt.tunnel_type == tunnel_type)
+            .cloned()
+            .collect();
+        if attempts.is_empty() {
+            return None;
+        }
+        let repeated = self
+            .attempts
+            .iter()
+            .skip(self.repeat_from)
+            .filter(|attempt| attempt.tunnel_type == tunnel_type)
+            .count();
+        Some(RetrySchedule {
+            repeat_from: attempts.len() - repeated,
+            attempts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TESTED_ATTEMPTS: u32 = 100;
+
+    // The hard-coded retry behavior that the default schedules replace
+    fn should_use_bridge(retry_attempt: u32) -> bool {
+        retry_attempt > 3 && (retry_attempt % 4) < 2
+    }
+
+    fn preferred_openvpn_constraints(retry_attempt: u32) -> (Constraint<u16>, TransportProtocol) {
+        match retry_attempt {
+            0 | 1 => (Constraint::Any, TransportProtocol::Udp),
+            2 | 3 => (Constraint::Only(443), TransportProtocol::Tcp),
+            attempt if attempt % 2 == 0 => (Constraint::Any, TransportProtocol::Udp),
+            _ => (Constraint::Any, TransportProtocol::Tcp),
+        }
+    }
+
+    fn preferred_tunnel_constraints(
+        retry_attempt: u32,
+    ) -> (Constraint<u16>, TransportProtocol, TunnelType) {
+        match retry_attempt {
+            0 => (
+                Constraint::Any,
+                TransportProtocol::Udp,
+                TunnelType::Wireguard,
+            ),
+            1 => (
+                Constraint::Only(53),
+                TransportProtocol::Udp,
+                TunnelType::Wireguard,
+            ),
+            _ => {
+                let (port, protocol) = preferred_openvpn_constraints(retry_attempt - 2);
+                (port, protocol, TunnelType::OpenVpn)
+            }
+        }
+    }
+
+    fn preferred_wireguard_port(retry_attempt: u32) -> Constraint<TransportPort> {
+        if retry_attempt % 4 > 1 {
+            port(TransportProtocol::Udp, 53)
+        } else {
+            Constraint::Any
+        }
+    }
+
+    #[test]
+    fn test_default_wireguard_and_openvpn_schedule() {
+        let schedule = RetrySchedule::default_wireguard_and_openvpn();
+        for retry_attempt in 0..TESTED_ATTEMPTS {
+            let (port, protocol, tunnel_type) = preferred_tunnel_constraints(retry_attempt);
+            let expected = RetryAttempt::new(
+                tunnel_type,
+                Constraint::Only(TransportPort { protocol, port }),
+                should_use_bridge(retry_attempt),
+            );
+            assert_eq!(
+                schedule.attempt(retry_attempt),
+                Some(&expected),
+                "retry attempt {}",
+                retry_attempt
+            );
+        }
+    }
+
+    #[test]
+    fn test_default_openvpn_schedule() {
+        let schedule = RetrySchedule::default_openvpn();
+        for retry_attempt in 0..TESTED_ATTEMPTS {
+            let (port, protocol) = preferred_openvpn_constraints(retry_attempt);
+            let expected = RetryAttempt::new(
+                TunnelType::OpenVpn,
+                Constraint::Only(TransportPort { protocol, port }),
+                should_use_bridge(retry_attempt),
+            );
+            assert_eq!(
+                schedule.attempt(retry_attempt),
+                Some(&expected),
+                "retry attempt {}",
+                retry_attempt
+            );
+        }
+    }
+
+    #[test]
+    fn test_default_wireguard_schedule() {
+        let schedule = RetrySchedule::default_wireguard();
+        for retry_attempt in 0..TESTED_ATTEMPTS {
+            let expected = RetryAttempt::new(
+                TunnelType::Wireguard,
+                preferred_wireguard_port(retry_attempt),
+                should_use_bridge(retry_attempt),
+            );
+            assert_eq!(
+                schedule.attempt(retry_attempt),
+                Some(&expected),
+                "retry attempt {}",
+                retry_attempt
+            );
+        }
+    }
+
+    #[test]
+    fn test_only_tunnel_type() {
+        let schedule = RetrySchedule::default_wireguard_and_openvpn();
+
+        let wireguard = schedule.only_tunnel_type(TunnelType::Wireguard).unwrap();
+        assert_eq!(wireguard.attempts, schedule.attempts[..2].to_vec());
+        // No WireGuard attempts are repeated, so the whole schedule is
+        assert_eq!(wireguard.attempt(2), wireguard.attempt(0));
+        assert_eq!(wireguard.attempt(3), wireguard.attempt(1));
+
+        let openvpn = schedule.only_tunnel_type(TunnelType::OpenVpn).unwrap();
+        assert_eq!(openvpn.attempts, schedule.attempts[2..].to_vec());
+        assert_eq!(openvpn.repeat_from, 4);
+
+        assert!(RetrySchedule::default_openvpn()
+            .only_tunnel_type(TunnelType::Wireguard)
+            .is_none());
+    }
+}
@@ -1,9 +1,11 @@
 use crate::{
+    access_method, network_profile,
     relay_constraints::{
         BridgeConstraints, BridgeSettings, BridgeState, Constraint, LocationConstraint,
         RelayConstraints, RelaySettings, RelaySettingsUpdate,
     },
-    wireguard,
+    retry_schedule::RetrySchedule,
+    trusted_network, version, wireguard,
 };
 #[cfg(target_os = "android")]
 use jnix::{jni::objects::JObject, FromJava, IntoJava, JnixEnv};
@@ -12,7 +14,7 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::net::IpAddr;
 #[cfg(target_os = "windows")]
 use std::{collections::HashSet, path::PathBuf};
-use talpid_types::net::{self, openvpn, GenericTunnelOptions};
+use talpid_types::net::{self, openvpn, ConnectedNetwork, GenericTunnelOptions};
 
 pub const CURRENT_SETTINGS_VERSION: SettingsVersion = SettingsVersion::V5;
 
@@ -62,6 +64,13 @@ pub struct Settings {
     account_token: Option<String>,
     #[cfg_attr(target_os = "android", jnix(skip))]
     wireguard: Option<wireguard::WireguardData>,
+    /// A key that has been added to the account during key rotation, but that no tunnel has been
+    /// established with yet. `wireguard` remains in use until it has.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    wireguard_pending: Option<wireguard::WireguardData>,
+    /// Number of tunnels that have failed to come up using `wireguard_pending`.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    wireguard_pending_attempts: u32,
     relay_settings: RelaySettings,
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub bridge_settings: BridgeSettings,
@@ -83,6 +92,27 @@ pub struct Settings {
     /// Split tunneling settings
     #[cfg(windows)]
     pub split_tunnel: SplitTunnelSettings,
+    /// Ways of reaching the API, tried in order
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub api_access_methods: access_method::Settings,
+    /// How many hours before the account expires that clients should be notified about it
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub expiry_notification_hours: Vec<u32>,
+    /// Overrides the default order in which tunnel protocols, ports and bridges are tried
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub retry_schedule: Option<RetrySchedule>,
+    /// Networks on which the tunnel is disconnected automatically
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub trusted_networks: trusted_network::Settings,
+    /// Relay settings and bridge states that apply on specific networks
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub network_profiles: network_profile::Settings,
+    /// HTTP server used to detect captive portals. Captive portals are only detected on Linux
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub connectivity_probe: Option<net::ConnectivityProbe>,
+    /// Self-hosted channel that version checks use instead of the API
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub update_channel: Option<version::UpdateChannelSettings>,
     /// Specifies settings schema version
     #[cfg_attr(target_os = "android", jnix(skip))]
     settings_version: SettingsVersion,
@@ -102,6 +132,8 @@ impl Default for Settings {
         Settings {
             account_token: None,
             wireguard: None,
+            wireguard_pending: None,
+            wireguard_pending_attempts: 0,
             relay_settings: RelaySettings::Normal(RelayConstraints {
                 location: Constraint::Only(LocationConstraint::Country("se".to_owned())),
                 ..Default::default()
@@ -115,6 +147,13 @@ impl Default for Settings {
             show_beta_releases: false,
             #[cfg(windows)]
             split_tunnel: SplitTunnelSettings::default(),
+            api_access_methods: access_method::Settings::default(),
+            expiry_notification_hours: vec![72, 24],
+            retry_schedule: None,
+            trusted_networks: trusted_network::Settings::default(),
+            network_profiles: network_profile::Settings::default(),
+            connectivity_probe: None,
+            update_channel: None,
             settings_version: CURRENT_SETTINGS_VERSION,
         }
     }
@@ -160,17 +199,42 @@ impl Settings {
         }
     }
 
+    pub fn get_wireguard_pending(&self) -> Option<wireguard::WireguardData> {
+        self.wireguard_pending.clone()
+    }
+
+    pub fn set_wireguard_pending(&mut self, wireguard: Option<wireguard::WireguardData>) -> bool {
+        if wireguard != self.wireguard_pending {
+            self.wireguard_pending = wireguard;
+            self.wireguard_pending_attempts = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn get_wireguard_pending_attempts(&self) -> u32 {
+        self.wireguard_pending_attempts
+    }
+
+    pub fn set_wireguard_pending_attempts(&mut self, attempts: u32) -> bool {
+        if attempts != self.wireguard_pending_attempts {
+            self.wireguard_pending_attempts = attempts;
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn get_relay_settings(&self) -> RelaySettings {
         self.relay_settings.clone()
     }
 
     pub fn update_relay_settings(&mut self, update: RelaySettingsUpdate) -> bool {
-        let update_supports_bridge = update.supports_bridge();
+        let bridge_state = update.compatible_bridge_state(self.bridge_state);
         let new_settings = self.relay_settings.merge(update);
         if self.relay_settings != new_settings {
-            if !update_supports_bridge && BridgeState::On == self.bridge_state {
-                self.bridge_state = BridgeState::Auto;
-            }
+            self.bridge_state = bridge_state;
             debug!(
                 "Changing relay settings:\n\tfrom: {}\n\tto: {}",
                 self.relay_settings, new_settings
@@ -199,6 +263,34 @@ impl Settings {
         }
     }
 
+    /// Returns the relay settings that apply while the host is connected to `networks`. These are
+    /// the regular relay settings unless a network profile replaces them.
+    pub fn get_effective_relay_settings(&self, networks: &[ConnectedNetwork]) -> RelaySettings {
+        match self.network_profiles.active_profile(networks) {
+            Some(active) => {
+                let bridge_state = active.profile.bridge_state.unwrap_or(self.bridge_state);
+                let mut relay_settings = active
+                    .profile
+                    .relay_settings
+                    .unwrap_or_else(|| self.relay_settings.clone());
+                if bridge_state == BridgeState::On {
+                    relay_settings.ensure_bridge_compatibility();
+                }
+                relay_settings
+            }
+            None => self.relay_settings.clone(),
+        }
+    }
+
+    /// Returns the bridge state that applies while the host is connected to `networks`. This is
+    /// the regular bridge state unless a network profile replaces it.
+    pub fn get_effective_bridge_state(&self, networks: &[ConnectedNetwork]) -> BridgeState {
+        self.network_profiles
+            .active_profile(networks)
+            .and_then(|active| active.profile.bridge_state)
+            .unwrap_or(self.bridge_state)
+    }
+
     pub fn get_settings_version(&self) -> SettingsVersion {
         self.settings_version
     }
@@ -318,3 +410,44 @@ impl Default for TunnelOptions {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wireguard_data() -> wireguard::WireguardData {
+        wireguard::WireguardData {
+            private_key: net::wireguard::PrivateKey::new_from_random(),
+            addresses: wireguard::AssociatedAddresses {
+                ipv4_address: "10.64.0.2/32".parse().unwrap(),
+                ipv6_address: "fc00:bbbb:bbbb:bb01::2/128".parse().unwrap(),
+            },
+            created: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_pending_attempts_reset_with_pending_key() {
+        let mut settings = Settings::default();
+        let pending = wireguard_data();
+        assert!(settings.set_wireguard_pending(Some(pending.clone())));
+        assert!(settings.set_wireguard_pending_attempts(2));
+        assert!(!settings.set_wireguard_pending_attempts(2));
+
+        assert!(!settings.set_wireguard_pending(Some(pending)));
+        assert_eq!(settings.get_wireguard_pending_attempts(), 2);
+
+        assert!(settings.set_wireguard_pending(Some(wireguard_data())));
+        assert_eq!(settings.get_wireguard_pending_attempts(), 0);
+
+        settings.set_wireguard_pending_attempts(1);
+        assert!(settings.set_wireguard_pending(None));
+        assert_eq!(settings.get_wireguard_pending_attempts(), 0);
+    }
+
+    #[test]
+    fn test_pending_attempts_default_when_missing() {
+        let settings: Settings = serde_json::from_str(r#"{"settings_version": 5}"#).unwrap();
+        assert_eq!(settings.get_wireguard_pending_attempts(), 0);
+    }
+}
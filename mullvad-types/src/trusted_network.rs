@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use talpid_types::net::ConnectedNetwork;
+
+#[derive(err_derive::Error, Debug, Clone, PartialEq)]
+#[error(no_from)]
+pub enum Error {
+    /// The network is already trusted.
+    #[error(display = "{} is already trusted", _0)]
+    AlreadyTrusted(TrustedNetwork),
+
+    /// The network is not trusted.
+    #[error(display = "{} is not trusted", _0)]
+    NotFound(TrustedNetwork),
+}
+
+/// A network on which the tunnel is not needed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrustedNetwork {
+    /// A Wi-Fi network with the given SSID.
+    Ssid(String),
+    /// Any network reached through the given interface, such as an office Ethernet port.
+    Interface(String),
+}
+
+impl TrustedNetwork {
+    pub fn matches(&self, network: &ConnectedNetwork) -> bool {
+        match self {
+            TrustedNetwork::Ssid(ssid) => network.ssid.as_ref() == Some(ssid),
+            TrustedNetwork::Interface(interface) => network.interface == *interface,
+        }
+    }
+}
+
+impl fmt::Display for TrustedNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrustedNetwork::Ssid(ssid) => write!(f, "Wi-Fi network \"{}\"", ssid),
+            TrustedNetwork::Interface(interface) => write!(f, "interface {}", interface),
+        }
+    }
+}
+
+/// The networks on which the daemon disconnects automatically.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    networks: Vec<TrustedNetwork>,
+}
+
+impl Settings {
+    pub fn networks(&self) -> &[TrustedNetwork] {
+        &self.networks
+    }
+
+    pub fn add(&mut self, network: TrustedNetwork) -> Result<(), Error> {
+        if self.networks.contains(&network) {
+            return Err(Error::AlreadyTrusted(network));
+        }
+        self.networks.push(network);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, network: &TrustedNetwork) -> Result<(), Error> {
+        let index = self
+            .networks
+            .iter()
+            .position(|trusted| trusted == network)
+            .ok_or_else(|| Error::NotFound(network.clone()))?;
+        self.networks.remove(index);
+        Ok(())
+    }
+
+    /// Returns whether every network in `connected` is trusted. The host is never considered to
+    /// be on a trusted network while it is not connected to any known network.
+    pub fn is_trusted(&self, connected: &[ConnectedNetwork]) -> bool {
+        !connected.is_empty()
+            && connected
+                .iter()
+                .all(|network| self.networks.iter().any(|trusted| trusted.matches(network)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ethernet(interface: &str) -> ConnectedNetwork {
+        ConnectedNetwork {
+            interface: interface.to_owned(),
+            ssid: None,
+            gateway_mac: None,
+        }
+    }
+
+    fn wifi(ssid: &str) -> ConnectedNetwork {
+        ConnectedNetwork {
+            interface: "wlan0".to_owned(),
+            ssid: Some(ssid.to_owned()),
+            gateway_mac: None,
+        }
+    }
+
+    #[test]
+    fn test_add_remove() {
+        let mut settings = Settings::default();
+        let office = TrustedNetwork::Interface("eth0".to_owned());
+        settings.add(office.clone()).unwrap();
+        assert_eq!(
+            settings.add(office.clone()),
+            Err(Error::AlreadyTrusted(office.clone()))
+        );
+        settings.remove(&office).unwrap();
+        assert_eq!(settings.remove(&office), Err(Error::NotFound(office)));
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn test_is_trusted() {
+        let mut settings = Settings::default();
+        settings
+            .add(TrustedNetwork::Interface("eth0".to_owned()))
+            .unwrap();
+        settings
+            .add(TrustedNetwork::Ssid("Home network".to_owned()))
+            .unwrap();
+
+        assert!(settings.is_trusted(&[ethernet("eth0")]));
+        assert!(settings.is_trusted(&[wifi("Home network")]));
+        assert!(settings.is_trusted(&[ethernet("eth0"), wifi("Home network")]));
+
+        assert!(!settings.is_trusted(&[]));
+        assert!(!settings.is_trusted(&[ethernet("eth1")]));
+        assert!(!settings.is_trusted(&[wifi("Cafe")]));
+        // Every network must be trusted, since traffic may leave through any of them
+        assert!(!settings.is_trusted(&[ethernet("eth0"), wifi("Cafe")]));
+        assert!(!Settings::default().is_trusted(&[ethernet("eth0")]));
+    }
+}
@@ -37,6 +37,15 @@ pub struct AppVersionInfo {
 
 pub type AppVersion = String;
 
+/// A self-hosted source of update metadata, used for version checks instead of the Mullvad API.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct UpdateChannelSettings {
+    /// HTTP(S) URL or local file path of metadata with the same schema as the API.
+    pub source: String,
+    /// Base64 encoded Ed25519 public key that the metadata is signed with.
+    pub public_key: String,
+}
+
 
 /// Parses a version string into a type that can be used for comparisons.
 #[derive(Eq, PartialEq, Debug, Clone)]
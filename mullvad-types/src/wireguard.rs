@@ -166,3 +166,39 @@ impl fmt::Display for KeygenEvent {
         }
     }
 }
+
+/// How an automatic key rotation ended.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyRotationOutcome {
+    /// A tunnel was established using the new key, after which the old key was removed.
+    Verified,
+    /// The new key replaced the old key without being verified, since no WireGuard tunnel was in
+    /// use.
+    Unverified,
+    /// The account had no room for an additional key, so the old key was replaced right away.
+    Replaced,
+    /// No tunnel could be established using the new key. The new key was removed and the old key
+    /// is still used.
+    FellBack,
+}
+
+impl fmt::Display for KeyRotationOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            KeyRotationOutcome::Verified => write!(f, "verified"),
+            KeyRotationOutcome::Unverified => write!(f, "applied without verification"),
+            KeyRotationOutcome::Replaced => write!(f, "replaced"),
+            KeyRotationOutcome::FellBack => write!(f, "fell back to the old key"),
+        }
+    }
+}
+
+/// A finished automatic key rotation.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct KeyRotation {
+    pub old_key: wireguard::PublicKey,
+    pub new_key: wireguard::PublicKey,
+    pub finished: DateTime<Utc>,
+    pub outcome: KeyRotationOutcome,
+}
@@ -13,6 +13,8 @@ use std::{env, fmt, net::IpAddr, path::Path};
 
 
 const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+/// resolv.conf that systemd-resolved keeps up to date with the upstream DNS servers.
+const RESOLVED_UPSTREAM_RESOLV_CONF_PATH: &str = "/run/systemd/resolve/resolv.conf";
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -168,8 +170,52 @@ impl DnsMonitorHolder {
     }
 }
 
+/// Returns the DNS servers that the host uses when the DNS is not managed by `DnsMonitor`,
+/// excluding local stub resolvers.
+pub fn get_system_resolvers() -> Vec<IpAddr> {
+    for path in &[RESOLVED_UPSTREAM_RESOLV_CONF_PATH, RESOLV_CONF_PATH] {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => continue,
+        };
+        match parse_resolvers(&contents) {
+            Ok(resolvers) if !resolvers.is_empty() => return resolvers,
+            Ok(_) => (),
+            Err(error) => log::warn!("Failed to parse {}: {}", path, error),
+        }
+    }
+    vec![]
+}
+
+fn parse_resolvers(contents: &str) -> std::result::Result<Vec<IpAddr>, resolv_conf::ParseError> {
+    let config = resolv_conf::Config::parse(contents)?;
+    Ok(config
+        .nameservers
+        .iter()
+        .map(|address| address.into())
+        .filter(|address: &IpAddr| !address.is_loopback())
+        .collect())
+}
+
 /// Returns true if DnsMonitor will use NetworkManager to manage DNS.
 pub fn will_use_nm() -> bool {
     crate::dns::imp::SystemdResolved::new().is_err()
         && crate::dns::imp::NetworkManager::new().is_ok()
 }
+
+#[cfg(test)]
+mod test {
+    use super::parse_resolvers;
+
+    #[test]
+    fn test_parse_resolvers() {
+        let contents = "nameserver 127.0.0.53\nnameserver 9.9.9.9\nnameserver fd00::1\n";
+        assert_eq!(
+            parse_resolvers(contents).unwrap(),
+            vec!["9.9.9.9".parse().unwrap(), "fd00::1".parse().unwrap()]
+        );
+        assert!(parse_resolvers("nameserver 127.0.0.53\n")
+            .unwrap()
+            .is_empty());
+    }
+}
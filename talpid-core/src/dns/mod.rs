@@ -11,7 +11,7 @@ mod imp;
 mod imp;
 
 #[cfg(target_os = "linux")]
-pub use imp::will_use_nm;
+pub use imp::{get_system_resolvers, will_use_nm};
 
 #[cfg(windows)]
 #[path = "windows/mod.rs"]
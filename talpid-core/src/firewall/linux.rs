@@ -19,6 +19,12 @@ use talpid_types::net::{Endpoint, TransportProtocol};
 /// Priority for rules that tag split tunneling packets. Equals NF_IP_PRI_MANGLE.
 const MANGLE_CHAIN_PRIORITY: i32 = libc::NF_IP_PRI_MANGLE;
 const PREROUTING_CHAIN_PRIORITY: i32 = libc::NF_IP_PRI_CONNTRACK + 1;
+/// Value used to mark connections from the captive portal browser cgroup.
+/// This should be an arbitrary but unique integer.
+const CAPTIVE_PORTAL_MARK: i32 = 0xf42;
+/// Value used to mark connections from the connectivity probe.
+/// This should be an arbitrary but unique integer.
+const CONNECTIVITY_PROBE_MARK: i32 = 0xf43;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -553,14 +559,42 @@ impl<'a> PolicyBatch<'a> {
     }
 
     fn add_policy_specific_rules(&mut self, policy: &FirewallPolicy) -> Result<()> {
+        match policy {
+            FirewallPolicy::Connecting {
+                connectivity_probe_port,
+                ..
+            }
+            | FirewallPolicy::Connected {
+                connectivity_probe_port,
+                ..
+            }
+            | FirewallPolicy::Blocked {
+                connectivity_probe_port,
+                ..
+            }
+            | FirewallPolicy::CaptivePortalLogin {
+                connectivity_probe_port,
+                ..
+            } => {
+                if let Some(port) = connectivity_probe_port {
+                    self.add_allow_connectivity_probe_rules(*port);
+                }
+            }
+        }
+
         let allow_lan = match policy {
             FirewallPolicy::Connecting {
                 peer_endpoint,
                 tunnel,
+                entry_tunnel,
                 allow_lan,
                 allowed_endpoint,
+                ..
             } => {
                 self.add_allow_tunnel_endpoint_rules(peer_endpoint);
+                if let Some(entry_tunnel) = entry_tunnel {
+                    self.add_allow_tunnel_rules(&entry_tunnel.interface)?;
+                }
                 self.add_allow_endpoint_rules(allowed_endpoint);
 
                 // Important to block DNS after allow relay rule (so the relay can operate
@@ -578,10 +612,15 @@ impl<'a> PolicyBatch<'a> {
             FirewallPolicy::Connected {
                 peer_endpoint,
                 tunnel,
+                entry_tunnel,
                 allow_lan,
                 dns_servers,
+                ..
             } => {
                 self.add_allow_tunnel_endpoint_rules(peer_endpoint);
+                if let Some(entry_tunnel) = entry_tunnel {
+                    self.add_allow_tunnel_rules(&entry_tunnel.interface)?;
+                }
                 self.add_allow_dns_rules(tunnel, &dns_servers, TransportProtocol::Udp)?;
                 self.add_allow_dns_rules(tunnel, &dns_servers, TransportProtocol::Tcp)?;
                 // Important to block DNS *before* we allow the tunnel and allow LAN. So DNS
@@ -596,6 +635,7 @@ impl<'a> PolicyBatch<'a> {
             FirewallPolicy::Blocked {
                 allow_lan,
                 allowed_endpoint,
+                ..
             } => {
                 self.add_allow_endpoint_rules(allowed_endpoint);
 
@@ -603,6 +643,30 @@ impl<'a> PolicyBatch<'a> {
                 self.add_drop_dns_rule();
                 *allow_lan
             }
+            FirewallPolicy::CaptivePortalLogin {
+                allow_lan,
+                allowed_endpoint,
+                allowed_networks,
+                allowed_resolvers,
+                browser_cgroup,
+                ..
+            } => {
+                self.add_allow_endpoint_rules(allowed_endpoint);
+                if let Some(classid) = browser_cgroup {
+                    self.add_allow_cgroup_rules(*classid);
+                }
+                for net in allowed_networks {
+                    self.add_allow_captive_portal_rules(*net);
+                }
+                for resolver in allowed_resolvers {
+                    self.add_allow_resolver_rules(*resolver);
+                }
+
+                // Important to drop DNS after allowing DNS to the gateway-assigned networks, but
+                // before allowing LAN (to stop DNS leaking to the LAN)
+                self.add_drop_dns_rule();
+                *allow_lan
+            }
         };
 
         if allow_lan {
@@ -668,6 +732,91 @@ impl<'a> PolicyBatch<'a> {
         self.batch.add(&out_rule, nftnl::MsgType::Add);
     }
 
+    /// Allows HTTP, HTTPS and DNS traffic to any host from processes in the given net_cls cgroup,
+    /// as well as the responses.
+    fn add_allow_cgroup_rules(&mut self, classid: u32) {
+        for chain in &[&self.mangle_chain_v4, &self.mangle_chain_v6] {
+            for (protocol, port) in &super::CAPTIVE_PORTAL_PORTS {
+                let mut rule = Rule::new(chain);
+                rule.add_expr(&nft_expr!(meta cgroup));
+                rule.add_expr(&nft_expr!(cmp == classid));
+                check_port(&mut rule, *protocol, End::Dst, *port);
+                rule.add_expr(&nft_expr!(immediate data CAPTIVE_PORTAL_MARK));
+                rule.add_expr(&nft_expr!(ct mark set));
+                self.batch.add(&rule, nftnl::MsgType::Add);
+            }
+        }
+
+        for chain in &[&self.in_chain, &self.out_chain] {
+            let mut rule = Rule::new(chain);
+            rule.add_expr(&nft_expr!(ct mark));
+            rule.add_expr(&nft_expr!(cmp == CAPTIVE_PORTAL_MARK));
+            add_verdict(&mut rule, &Verdict::Accept);
+            self.batch.add(&rule, nftnl::MsgType::Add);
+        }
+    }
+
+    /// Allows the connectivity probe, which marks its packets like the tunnel does, to reach the
+    /// probe server, as well as the responses.
+    fn add_allow_connectivity_probe_rules(&mut self, port: u16) {
+        let mut out_rule = Rule::new(&self.out_chain);
+        check_port(&mut out_rule, TransportProtocol::Tcp, End::Dst, port);
+        out_rule.add_expr(&nft_expr!(meta mark));
+        out_rule.add_expr(&nft_expr!(cmp == crate::linux::TUNNEL_FW_MARK));
+        out_rule.add_expr(&nft_expr!(immediate data CONNECTIVITY_PROBE_MARK));
+        out_rule.add_expr(&nft_expr!(ct mark set));
+        add_verdict(&mut out_rule, &Verdict::Accept);
+        self.batch.add(&out_rule, nftnl::MsgType::Add);
+
+        let mut in_rule = Rule::new(&self.in_chain);
+        in_rule.add_expr(&nft_expr!(ct mark));
+        in_rule.add_expr(&nft_expr!(cmp == CONNECTIVITY_PROBE_MARK));
+        add_verdict(&mut in_rule, &Verdict::Accept);
+        self.batch.add(&in_rule, nftnl::MsgType::Add);
+    }
+
+    /// Allows DNS traffic to a resolver that the host uses outside the tunnel.
+    fn add_allow_resolver_rules(&mut self, resolver: IpAddr) {
+        for protocol in &[TransportProtocol::Udp, TransportProtocol::Tcp] {
+            let mut out_rule = Rule::new(&self.out_chain);
+            check_ip(&mut out_rule, End::Dst, resolver);
+            check_port(&mut out_rule, *protocol, End::Dst, 53);
+            add_verdict(&mut out_rule, &Verdict::Accept);
+            self.batch.add(&out_rule, nftnl::MsgType::Add);
+
+            let mut in_rule = Rule::new(&self.in_chain);
+            check_ip(&mut in_rule, End::Src, resolver);
+            check_port(&mut in_rule, *protocol, End::Src, 53);
+            in_rule.add_expr(&nft_expr!(ct state));
+            let allowed_states = nftnl::expr::ct::States::ESTABLISHED.bits();
+            in_rule.add_expr(&nft_expr!(bitwise mask allowed_states, xor 0u32));
+            in_rule.add_expr(&nft_expr!(cmp != 0u32));
+            add_verdict(&mut in_rule, &Verdict::Accept);
+            self.batch.add(&in_rule, nftnl::MsgType::Add);
+        }
+    }
+
+    /// Allows HTTP, HTTPS and DNS traffic to a network assigned by the gateway.
+    fn add_allow_captive_portal_rules(&mut self, net: IpNetwork) {
+        for (protocol, port) in &super::CAPTIVE_PORTAL_PORTS {
+            let mut out_rule = Rule::new(&self.out_chain);
+            check_net(&mut out_rule, End::Dst, net);
+            check_port(&mut out_rule, *protocol, End::Dst, *port);
+            add_verdict(&mut out_rule, &Verdict::Accept);
+            self.batch.add(&out_rule, nftnl::MsgType::Add);
+
+            let mut in_rule = Rule::new(&self.in_chain);
+            check_net(&mut in_rule, End::Src, net);
+            check_port(&mut in_rule, *protocol, End::Src, *port);
+            in_rule.add_expr(&nft_expr!(ct state));
+            let allowed_states = nftnl::expr::ct::States::ESTABLISHED.bits();
+            in_rule.add_expr(&nft_expr!(bitwise mask allowed_states, xor 0u32));
+            in_rule.add_expr(&nft_expr!(cmp != 0u32));
+            add_verdict(&mut in_rule, &Verdict::Accept);
+            self.batch.add(&in_rule, nftnl::MsgType::Add);
+        }
+    }
+
     fn add_allow_dns_rules(
         &mut self,
         tunnel: &tunnel::TunnelMetadata,
@@ -100,10 +100,14 @@ impl Firewall {
             FirewallPolicy::Connecting {
                 peer_endpoint,
                 tunnel,
+                entry_tunnel,
                 allow_lan,
                 allowed_endpoint,
             } => {
                 let mut rules = vec![self.get_allow_relay_rule(peer_endpoint)?];
+                if let Some(entry_tunnel) = entry_tunnel {
+                    rules.push(self.get_allow_tunnel_rule(&entry_tunnel.interface)?);
+                }
                 rules.push(self.get_allowed_endpoint_rule(allowed_endpoint)?);
 
                 // Important to block DNS after allow relay rule (so the relay can operate
@@ -122,6 +126,7 @@ impl Firewall {
             FirewallPolicy::Connected {
                 peer_endpoint,
                 tunnel,
+                entry_tunnel,
                 allow_lan,
                 dns_servers,
             } => {
@@ -132,6 +137,9 @@ impl Firewall {
                 }
 
                 rules.push(self.get_allow_relay_rule(peer_endpoint)?);
+                if let Some(entry_tunnel) = entry_tunnel {
+                    rules.push(self.get_allow_tunnel_rule(&entry_tunnel.interface)?);
+                }
 
                 // Important to block DNS *before* we allow the tunnel and allow LAN. So DNS
                 // can't leak to the wrong IPs in the tunnel or on the LAN.
@@ -158,9 +166,45 @@ impl Firewall {
                 }
                 Ok(rules)
             }
+            FirewallPolicy::CaptivePortalLogin {
+                allow_lan,
+                allowed_endpoint,
+                allowed_networks,
+            } => {
+                let mut rules = vec![self.get_allowed_endpoint_rule(allowed_endpoint)?];
+                for net in allowed_networks {
+                    rules.append(&mut self.get_allow_captive_portal_rules(net)?);
+                }
+                // Important to block DNS after allowing DNS to the gateway-assigned networks, but
+                // before allow LAN (so DNS does not leak to the LAN)
+                rules.append(&mut self.get_block_dns_rules()?);
+                if allow_lan {
+                    rules.append(&mut self.get_allow_lan_rules()?);
+                }
+                Ok(rules)
+            }
         }
     }
 
+    /// Allows HTTP, HTTPS and DNS traffic to a network assigned by the gateway.
+    fn get_allow_captive_portal_rules(&self, net: IpNetwork) -> Result<Vec<pfctl::FilterRule>> {
+        let mut rules = vec![];
+        for (protocol, port) in &super::CAPTIVE_PORTAL_PORTS {
+            let mut rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
+            rule_builder
+                .direction(pfctl::Direction::Out)
+                .quick(true)
+                .proto(as_pfctl_proto(*protocol))
+                .keep_state(pfctl::StatePolicy::Keep)
+                .to(pfctl::Endpoint::new(net, pfctl::Port::from(*port)));
+            if *protocol == net::TransportProtocol::Tcp {
+                rule_builder.tcp_flags(Self::get_tcp_flags());
+            }
+            rules.push(rule_builder.build()?);
+        }
+        Ok(rules)
+    }
+
     fn get_allow_dns_rules(
         &self,
         tunnel: &crate::tunnel::TunnelMetadata,
@@ -1,5 +1,6 @@
+use ipnetwork::IpNetwork;
 #[cfg(unix)]
-use ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
+use ipnetwork::{Ipv4Network, Ipv6Network};
 #[cfg(unix)]
 use lazy_static::lazy_static;
 use std::fmt;
@@ -10,6 +11,8 @@ use std::net::{Ipv4Addr, Ipv6Addr};
 #[cfg(windows)]
 use std::path::PathBuf;
 use talpid_types::net::Endpoint;
+#[cfg(all(unix, not(target_os = "android")))]
+use talpid_types::net::TransportProtocol;
 
 
 #[cfg(target_os = "macos")]
@@ -81,6 +84,14 @@ const DHCPV4_CLIENT_PORT: u16 = 68;
 const DHCPV6_SERVER_PORT: u16 = 547;
 #[cfg(all(unix, not(target_os = "android")))]
 const DHCPV6_CLIENT_PORT: u16 = 546;
+/// Ports that may be used to reach the gateway-assigned networks during captive portal login.
+#[cfg(all(unix, not(target_os = "android")))]
+const CAPTIVE_PORTAL_PORTS: [(TransportProtocol, u16); 4] = [
+    (TransportProtocol::Tcp, 80),
+    (TransportProtocol::Tcp, 443),
+    (TransportProtocol::Udp, 53),
+    (TransportProtocol::Tcp, 53),
+];
 
 
 #[cfg(all(unix, not(target_os = "android")))]
@@ -93,6 +104,37 @@ pub fn is_local_address(address: &IpAddr) -> bool {
         .any(|net| net.contains(address))
 }
 
+/// Returns the networks that the host's network interfaces are on, excluding loopback.
+#[cfg(all(unix, not(target_os = "android")))]
+pub fn get_interface_networks() -> Result<Vec<IpNetwork>, nix::Error> {
+    use nix::{ifaddrs::getifaddrs, net::if_::InterfaceFlags, sys::socket::SockAddr};
+
+    let mut networks = vec![];
+    for interface in getifaddrs()? {
+        if !interface.flags.contains(InterfaceFlags::IFF_UP)
+            || interface.flags.contains(InterfaceFlags::IFF_LOOPBACK)
+        {
+            continue;
+        }
+        if let (Some(SockAddr::Inet(address)), Some(SockAddr::Inet(netmask))) =
+            (interface.address, interface.netmask)
+        {
+            let network = IpNetwork::with_netmask(address.ip().to_std(), netmask.ip().to_std())
+                .and_then(|network| IpNetwork::new(network.network(), network.prefix()));
+            match network {
+                Ok(network) if !networks.contains(&network) => networks.push(network),
+                Ok(_) => (),
+                Err(error) => log::warn!(
+                    "Ignoring address on interface {}: {}",
+                    interface.interface_name,
+                    error
+                ),
+            }
+        }
+    }
+    Ok(networks)
+}
+
 /// A enum that describes network security strategy
 ///
 /// # Firewall block/allow specification.
@@ -107,6 +149,9 @@ pub enum FirewallPolicy {
         peer_endpoint: Endpoint,
         /// Metadata about the tunnel and tunnel interface.
         tunnel: Option<crate::tunnel::TunnelMetadata>,
+        /// Metadata about the entry tunnel that traffic to the peer is routed through, if any.
+        /// All traffic on its interface is allowed.
+        entry_tunnel: Option<crate::tunnel::TunnelMetadata>,
         /// Flag setting if communication with LAN networks should be possible.
         allow_lan: bool,
         /// Host that should be reachable by the tunnel client while connecting.
@@ -114,6 +159,10 @@ pub enum FirewallPolicy {
         /// A process that is allowed to send packets to the relay.
         #[cfg(windows)]
         relay_client: PathBuf,
+        /// Port of the connectivity probe server, which traffic from the connectivity probe is
+        /// allowed to.
+        #[cfg(target_os = "linux")]
+        connectivity_probe_port: Option<u16>,
     },
 
     /// Allow traffic only to server and over tunnel interface
@@ -122,6 +171,9 @@ pub enum FirewallPolicy {
         peer_endpoint: Endpoint,
         /// Metadata about the tunnel and tunnel interface.
         tunnel: crate::tunnel::TunnelMetadata,
+        /// Metadata about the entry tunnel that traffic to the peer is routed through, if any.
+        /// All traffic on its interface is allowed.
+        entry_tunnel: Option<crate::tunnel::TunnelMetadata>,
         /// Flag setting if communication with LAN networks should be possible.
         allow_lan: bool,
         /// Servers that are allowed to respond to DNS requests.
@@ -130,6 +182,10 @@ pub enum FirewallPolicy {
         /// A process that is allowed to send packets to the relay.
         #[cfg(windows)]
         relay_client: PathBuf,
+        /// Port of the connectivity probe server, which traffic from the connectivity probe is
+        /// allowed to.
+        #[cfg(target_os = "linux")]
+        connectivity_probe_port: Option<u16>,
     },
 
     /// Block all network traffic in and out from the computer.
@@ -138,6 +194,34 @@ pub enum FirewallPolicy {
         allow_lan: bool,
         /// Host that should be reachable while in the blocked state.
         allowed_endpoint: Endpoint,
+        /// Port of the connectivity probe server, which traffic from the connectivity probe is
+        /// allowed to.
+        #[cfg(target_os = "linux")]
+        connectivity_probe_port: Option<u16>,
+    },
+
+    /// Block all network traffic, except what is needed to log in to a captive portal.
+    CaptivePortalLogin {
+        /// Flag setting if communication with LAN networks should be possible.
+        allow_lan: bool,
+        /// Host that should be reachable while logging in.
+        allowed_endpoint: Endpoint,
+        /// Networks assigned to the host by the gateway. HTTP, HTTPS and DNS traffic to these
+        /// networks is allowed.
+        allowed_networks: Vec<IpNetwork>,
+        /// DNS servers that the host uses outside the tunnel. DNS traffic to these is allowed,
+        /// even if they are not on the gateway-assigned networks.
+        #[cfg(target_os = "linux")]
+        allowed_resolvers: Vec<IpAddr>,
+        /// Class ID of a net_cls cgroup whose HTTP, HTTPS and DNS traffic to any host is allowed.
+        /// This is meant for a browser that is used to log in to a portal that is not hosted on
+        /// the local network.
+        #[cfg(target_os = "linux")]
+        browser_cgroup: Option<u32>,
+        /// Port of the connectivity probe server, which traffic from the connectivity probe is
+        /// allowed to.
+        #[cfg(target_os = "linux")]
+        connectivity_probe_port: Option<u16>,
     },
 }
 
@@ -198,12 +282,29 @@ impl fmt::Display for FirewallPolicy {
             FirewallPolicy::Blocked {
                 allow_lan,
                 allowed_endpoint,
+                ..
             } => write!(
                 f,
                 "Blocked. {} LAN. Allowing endpoint {}",
                 if *allow_lan { "Allowing" } else { "Blocking" },
                 allowed_endpoint,
             ),
+            FirewallPolicy::CaptivePortalLogin {
+                allow_lan,
+                allowed_endpoint,
+                allowed_networks,
+                ..
+            } => write!(
+                f,
+                "Captive portal login. {} LAN. Allowing endpoint {}, web traffic to {}",
+                if *allow_lan { "Allowing" } else { "Blocking" },
+                allowed_endpoint,
+                allowed_networks
+                    .iter()
+                    .map(|net| net.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
         }
     }
 }
@@ -91,9 +91,11 @@ impl FirewallT for Firewall {
 
     fn apply_policy(&mut self, policy: FirewallPolicy) -> Result<(), Self::Error> {
         match policy {
+            // Entry tunnels are not supported on Windows
             FirewallPolicy::Connecting {
                 peer_endpoint,
                 tunnel,
+                entry_tunnel: _,
                 allow_lan,
                 allowed_endpoint,
                 relay_client,
@@ -110,6 +112,7 @@ impl FirewallT for Firewall {
             FirewallPolicy::Connected {
                 peer_endpoint,
                 tunnel,
+                entry_tunnel: _,
                 allow_lan,
                 dns_servers,
                 relay_client,
@@ -124,6 +127,16 @@ impl FirewallT for Firewall {
                 let cfg = &WinFwSettings::new(allow_lan);
                 self.set_blocked_state(&cfg, &allowed_endpoint)
             }
+            FirewallPolicy::CaptivePortalLogin {
+                allow_lan,
+                allowed_endpoint,
+                ..
+            } => {
+                // There are no captive portal rules in winfw, so the daemon rejects captive portal
+                // logins on Windows. Should this policy be applied anyway, traffic is blocked.
+                let cfg = &WinFwSettings::new(allow_lan);
+                self.set_blocked_state(&cfg, &allowed_endpoint)
+            }
         }
     }
 
@@ -53,8 +53,8 @@ pub mod tunnel_state_machine;
 pub mod future_retry;
 
 #[cfg(not(target_os = "android"))]
-/// Internal code for managing bundled proxy software.
-mod proxy;
+/// Managing bundled proxy software.
+pub mod proxy;
 
 #[cfg(not(target_os = "android"))]
 mod mktemp;
@@ -1,29 +1,417 @@
-use std::{fs, io, path::Path};
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
+    time::{Duration, SystemTime},
+};
 
 /// Types/implementations for logging through a callback.
 #[cfg(windows)]
 pub mod windows;
 
+lazy_static::lazy_static! {
+    /// The rotation policy read from the environment, and the errors that occurred while reading
+    /// it.
+    static ref ROTATION_POLICY_FROM_ENV: (RotationPolicy, Vec<String>) = RotationPolicy::from_env();
+
+    /// The rotation policy used for all logs. It can be changed using the `TALPID_LOG_MAX_SIZE_MB`,
+    /// `TALPID_LOG_MAX_AGE_HOURS` and `TALPID_LOG_RETAINED_GENERATIONS` environment variables.
+    pub static ref ROTATION_POLICY: RotationPolicy = ROTATION_POLICY_FROM_ENV.0;
+}
+
+/// Logs the invalid environment variables that were ignored when reading `ROTATION_POLICY`. The
+/// policy is needed before a logger can be set up, so this must be called once one has been.
+pub fn log_rotation_policy_errors() {
+    for error in &ROTATION_POLICY_FROM_ENV.1 {
+        log::error!("{}", error);
+    }
+}
+
 /// Unable to create new log file
 #[derive(err_derive::Error, Debug)]
 #[error(display = "Unable to create new log file")]
 pub struct RotateLogError(#[error(source)] io::Error);
 
+/// Decides when a log file is rotated and how many previous versions of it are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RotationPolicy {
+    /// Rotate the log once it has grown to this many bytes.
+    pub max_size: u64,
+    /// Rotate the log once it has been written to for this long. `None` disables age-based
+    /// rotation.
+    pub max_age: Option<Duration>,
+    /// Number of gzip-compressed previous versions to keep.
+    pub retained_generations: usize,
+}
+
+impl Default for RotationPolicy {
+    fn default() -> Self {
+        RotationPolicy {
+            max_size: 10 * 1024 * 1024,
+            max_age: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+            retained_generations: 3,
+        }
+    }
+}
+
+impl RotationPolicy {
+    fn from_env() -> (Self, Vec<String>) {
+        let default = Self::default();
+        let mut errors = vec![];
+        let max_age = match parse_env_var::<u64>("TALPID_LOG_MAX_AGE_HOURS", &mut errors) {
+            Some(0) => None,
+            Some(hours) => Some(Duration::from_secs(hours * 60 * 60)),
+            None => default.max_age,
+        };
+        let policy = RotationPolicy {
+            max_size: parse_env_var::<u64>("TALPID_LOG_MAX_SIZE_MB", &mut errors)
+                .map(|megabytes| megabytes * 1024 * 1024)
+                .unwrap_or(default.max_size),
+            max_age,
+            retained_generations: parse_env_var("TALPID_LOG_RETAINED_GENERATIONS", &mut errors)
+                .unwrap_or(default.retained_generations),
+        };
+        (policy, errors)
+    }
+}
+
+fn parse_env_var<T: FromStr>(name: &str, errors: &mut Vec<String>) -> Option<T> {
+    let value = env::var(name).ok()?;
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            errors.push(format!("Ignoring invalid value for {}: {}", name, value));
+            None
+        }
+    }
+}
+
 /// Create a new log file while backing up a previous version of it.
 ///
-/// A new log file is created with the given file name, but if a file with that name already exists
-/// it is backed up with the extension changed to `.old.log`.
+/// A new log file is created with the given file name. If a file with that name already exists,
+/// it is compressed and kept as a previous generation according to `ROTATION_POLICY`. See
+/// `rotated_log_path` for how previous generations are named.
 pub fn rotate_log(file: &Path) -> Result<(), RotateLogError> {
-    let backup = file.with_extension("old.log");
-    if let Err(error) = fs::rename(file, &backup) {
-        if error.kind() != io::ErrorKind::NotFound {
-            log::warn!(
-                "Failed to rotate log file to {}: {}",
-                backup.display(),
+    if let Err(error) = rotate_generations(file, &ROTATION_POLICY) {
+        log::warn!("Failed to rotate log file {}: {}", file.display(), error);
+    }
+
+    fs::File::create(file).map(|_| ()).map_err(RotateLogError)
+}
+
+/// Returns the path of the given generation of a rotated log file. Generation 1 is the most
+/// recent one. For example, the first generation of `daemon.log` is `daemon.1.log.gz`.
+pub fn rotated_log_path(file: &Path, generation: usize) -> PathBuf {
+    let mut file_name = OsString::from(file.file_stem().unwrap_or_default());
+    file_name.push(format!(".{}", generation));
+    if let Some(extension) = file.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    file_name.push(".gz");
+    file.with_file_name(file_name)
+}
+
+/// Returns the path that a log file is moved to while its first generation is being compressed.
+/// For example, `daemon.log` is compressed from `daemon.1.log`.
+fn uncompressed_log_path(file: &Path) -> PathBuf {
+    let compressed = rotated_log_path(file, 1);
+    compressed.with_extension("")
+}
+
+/// Moves `file` to the first generation, shifts all previous generations one step and removes
+/// the ones that are no longer retained.
+fn rotate_generations(file: &Path, policy: &RotationPolicy) -> io::Result<()> {
+    if let Some(uncompressed) = shift_generations(file, policy)? {
+        compress_log(&uncompressed, &rotated_log_path(file, 1))?;
+    }
+    Ok(())
+}
+
+/// Shifts all previous generations of `file` one step, removes the ones that are no longer
+/// retained and moves `file` out of the way. Returns the path that `file` was moved to, which
+/// must be compressed into the first generation.
+fn shift_generations(file: &Path, policy: &RotationPolicy) -> io::Result<Option<PathBuf>> {
+    // Remove backups made before generations were introduced
+    remove_if_exists(&file.with_extension("old.log"))?;
+
+    // Finish compressing a generation that was interrupted, e.g. by the process exiting
+    let uncompressed = uncompressed_log_path(file);
+    if uncompressed.exists() {
+        compress_log(&uncompressed, &rotated_log_path(file, 1))?;
+    }
+
+    let mut generation = policy.retained_generations.max(1);
+    while remove_if_exists(&rotated_log_path(file, generation))? {
+        generation += 1;
+    }
+    for generation in (1..policy.retained_generations).rev() {
+        let source = rotated_log_path(file, generation);
+        match fs::rename(&source, rotated_log_path(file, generation + 1)) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+            _ => (),
+        }
+    }
+
+    if policy.retained_generations == 0 {
+        remove_if_exists(file)?;
+        return Ok(None);
+    }
+    match fs::rename(file, &uncompressed) {
+        Ok(()) => Ok(Some(uncompressed)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Compresses `source` into `destination` and removes `source`.
+fn compress_log(source: &Path, destination: &Path) -> io::Result<()> {
+    let mut source_file = fs::File::open(source)?;
+    let destination = fs::File::create(destination)?;
+    let mut encoder = GzEncoder::new(destination, Compression::default());
+    io::copy(&mut source_file, &mut encoder)?;
+    encoder.finish()?;
+    drop(source_file);
+    fs::remove_file(source)
+}
+
+/// Removes `file` and returns whether it existed.
+fn remove_if_exists(file: &Path) -> io::Result<bool> {
+    match fs::remove_file(file) {
+        Ok(()) => Ok(true),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// A log file that is rotated while it is being written to, once it grows larger or older than
+/// its `RotationPolicy` allows.
+pub struct RotatingLogFile {
+    path: PathBuf,
+    policy: RotationPolicy,
+    file: Option<fs::File>,
+    size: u64,
+    opened: SystemTime,
+    at_line_start: bool,
+    /// Compresses the most recently rotated generation.
+    compressor: Option<thread::JoinHandle<io::Result<()>>>,
+}
+
+impl RotatingLogFile {
+    /// Opens `path` for appending, creating it if it does not exist.
+    pub fn open(path: &Path, policy: RotationPolicy) -> io::Result<Self> {
+        let file = Self::open_file(path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingLogFile {
+            path: path.to_owned(),
+            policy,
+            file: Some(file),
+            size,
+            opened: SystemTime::now(),
+            at_line_start: true,
+            compressor: None,
+        })
+    }
+
+    fn open_file(path: &Path) -> io::Result<fs::File> {
+        fs::OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    fn should_rotate(&self) -> bool {
+        let too_old = self
+            .policy
+            .max_age
+            .map(|max_age| {
+                self.opened
+                    .elapsed()
+                    .map(|age| age >= max_age)
+                    .unwrap_or(false)
+            })
+            .unwrap_or(false);
+        self.size >= self.policy.max_size || too_old
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // The previous generation must be in place before the generations are shifted again
+        let compressed = self.wait_for_compressor();
+        // The file must be closed before it can be moved on Windows
+        self.file = None;
+        let result = shift_generations(&self.path, &self.policy);
+        self.file = Some(Self::open_file(&self.path)?);
+        // If rotation failed, the log is appended to until it has grown by `max_size` again
+        self.size = 0;
+        self.opened = SystemTime::now();
+
+        // Compress in the background, since writing to the log blocks until rotation is done
+        if let Some(uncompressed) = result? {
+            let destination = rotated_log_path(&self.path, 1);
+            self.compressor = Some(thread::spawn(move || {
+                compress_log(&uncompressed, &destination)
+            }));
+        }
+        compressed
+    }
+
+    /// Waits for the previous generation to be compressed and returns the result.
+    fn wait_for_compressor(&mut self) -> io::Result<()> {
+        match self.compressor.take() {
+            Some(compressor) => compressor.join().unwrap_or_else(|_| {
+                Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "Log compression thread panicked",
+                ))
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for RotatingLogFile {
+    fn drop(&mut self) {
+        if let Err(error) = self.wait_for_compressor() {
+            eprintln!(
+                "Failed to compress rotated log file {}: {}",
+                self.path.display(),
                 error
             );
         }
     }
+}
 
-    fs::File::create(file).map(|_| ()).map_err(RotateLogError)
+impl Write for RotatingLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Only rotate between lines, so that a record is never split between two files
+        if self.at_line_start && self.should_rotate() {
+            if let Err(error) = self.rotate() {
+                // Logging the error would end up writing to this file again
+                eprintln!(
+                    "Failed to rotate log file {}: {}",
+                    self.path.display(),
+                    error
+                );
+            }
+        }
+        let file = self.file.as_mut().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::Other, "Log file could not be reopened")
+        })?;
+        let written = file.write(buf)?;
+        self.size += written as u64;
+        if written > 0 {
+            self.at_line_start = buf[written - 1] == b'\n';
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn read_generation(file: &Path, generation: usize) -> String {
+        let mut contents = String::new();
+        GzDecoder::new(fs::File::open(rotated_log_path(file, generation)).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    #[test]
+    fn test_rotated_log_path() {
+        assert_eq!(
+            rotated_log_path(Path::new("/var/log/daemon.log"), 2),
+            Path::new("/var/log/daemon.2.log.gz")
+        );
+        assert_eq!(
+            rotated_log_path(Path::new("/var/log/daemon"), 1),
+            Path::new("/var/log/daemon.1.gz")
+        );
+    }
+
+    #[test]
+    fn test_rotate_generations() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("daemon.log");
+        let policy = RotationPolicy {
+            retained_generations: 2,
+            ..RotationPolicy::default()
+        };
+        fs::write(file.with_extension("old.log"), "legacy").unwrap();
+
+        for contents in &["first", "second", "third"] {
+            fs::write(&file, contents).unwrap();
+            rotate_generations(&file, &policy).unwrap();
+        }
+
+        assert!(!file.exists());
+        assert!(!file.with_extension("old.log").exists());
+        assert_eq!(read_generation(&file, 1), "third");
+        assert_eq!(read_generation(&file, 2), "second");
+        assert!(!rotated_log_path(&file, 3).exists());
+    }
+
+    #[test]
+    fn test_rotate_generations_compresses_pending_generation() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("daemon.log");
+        fs::write(uncompressed_log_path(&file), "interrupted").unwrap();
+        fs::write(&file, "current").unwrap();
+
+        rotate_generations(&file, &RotationPolicy::default()).unwrap();
+
+        assert!(!uncompressed_log_path(&file).exists());
+        assert_eq!(read_generation(&file, 1), "current");
+        assert_eq!(read_generation(&file, 2), "interrupted");
+    }
+
+    #[test]
+    fn test_parse_env_var() {
+        let mut errors = vec![];
+        env::set_var("TALPID_TEST_LOG_VALID", "5");
+        env::set_var("TALPID_TEST_LOG_INVALID", "five");
+
+        let valid: Option<u64> = parse_env_var("TALPID_TEST_LOG_VALID", &mut errors);
+        let invalid: Option<u64> = parse_env_var("TALPID_TEST_LOG_INVALID", &mut errors);
+        let unset: Option<u64> = parse_env_var("TALPID_TEST_LOG_UNSET", &mut errors);
+
+        assert_eq!((valid, invalid, unset), (Some(5), None, None));
+        assert_eq!(
+            errors,
+            vec!["Ignoring invalid value for TALPID_TEST_LOG_INVALID: five".to_owned()]
+        );
+    }
+
+    #[test]
+    fn test_rotating_log_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wireguard.log");
+        let policy = RotationPolicy {
+            max_size: 10,
+            max_age: None,
+            retained_generations: 1,
+        };
+        let mut file = RotatingLogFile::open(&path, policy).unwrap();
+
+        file.write_all(b"first ").unwrap();
+        file.write_all(b"record\n").unwrap();
+        file.write_all(b"second\n").unwrap();
+        drop(file);
+
+        assert!(!uncompressed_log_path(&path).exists());
+        assert_eq!(read_generation(&path, 1), "first record\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+    }
 }
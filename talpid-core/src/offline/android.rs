@@ -10,7 +10,7 @@ use jnix::{
     JnixEnv,
 };
 use std::sync::{Arc, Weak};
-use talpid_types::{android::AndroidContext, ErrorExt};
+use talpid_types::{android::AndroidContext, net::Connectivity, ErrorExt};
 
 #[derive(err_derive::Error, Debug)]
 #[error(no_from)]
@@ -43,13 +43,13 @@ pub struct MonitorHandle {
     jvm: Arc<JavaVM>,
     class: GlobalRef,
     object: GlobalRef,
-    _sender: Arc<UnboundedSender<bool>>,
+    _sender: Arc<UnboundedSender<Connectivity>>,
 }
 
 impl MonitorHandle {
     pub fn new(
         android_context: AndroidContext,
-        sender: Arc<UnboundedSender<bool>>,
+        sender: Arc<UnboundedSender<Connectivity>>,
     ) -> Result<Self, Error> {
         let env = JnixEnv::from(
             android_context
@@ -132,7 +132,7 @@ impl MonitorHandle {
         }
     }
 
-    fn set_sender(&self, sender: Weak<UnboundedSender<bool>>) -> Result<(), Error> {
+    fn set_sender(&self, sender: Weak<UnboundedSender<Connectivity>>) -> Result<(), Error> {
         let sender_ptr = Box::new(sender);
         let sender_address = Box::into_raw(sender_ptr) as jlong;
 
@@ -188,7 +188,10 @@ pub extern "system" fn Java_net_mullvad_talpid_ConnectivityListener_notifyConnec
     let is_offline = is_connected == JNI_FALSE;
 
     if let Some(sender) = sender_ref.upgrade() {
-        if sender.unbounded_send(is_offline).is_err() {
+        if sender
+            .unbounded_send(Connectivity::from(is_offline))
+            .is_err()
+        {
             log::warn!("Failed to send offline change event");
         }
     }
@@ -205,12 +208,12 @@ pub extern "system" fn Java_net_mullvad_talpid_ConnectivityListener_destroySende
     let _ = unsafe { get_sender_from_address(sender_address) };
 }
 
-unsafe fn get_sender_from_address(address: jlong) -> Box<Weak<UnboundedSender<bool>>> {
-    Box::from_raw(address as *mut Weak<UnboundedSender<bool>>)
+unsafe fn get_sender_from_address(address: jlong) -> Box<Weak<UnboundedSender<Connectivity>>> {
+    Box::from_raw(address as *mut Weak<UnboundedSender<Connectivity>>)
 }
 
 pub async fn spawn_monitor(
-    sender: UnboundedSender<bool>,
+    sender: UnboundedSender<Connectivity>,
     android_context: AndroidContext,
 ) -> Result<MonitorHandle, Error> {
     let sender = Arc::new(sender);
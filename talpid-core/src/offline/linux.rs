@@ -1,10 +1,28 @@
 use crate::routing::{self, RouteManagerHandle};
-use futures::{channel::mpsc::UnboundedSender, StreamExt};
+use futures::{channel::mpsc::UnboundedSender, future, FutureExt, StreamExt, TryStreamExt};
+use netlink_packet_route::{
+    constants::{ARPHRD_LOOPBACK, ARPHRD_NONE},
+    link::LinkMessage,
+};
+use rtnetlink::{constants::RTMGRP_NEIGH, sys::SocketAddr as NetlinkSocketAddr};
 use std::{
-    net::{IpAddr, Ipv4Addr, Ipv6Addr},
-    sync::Arc,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use talpid_dbus::network_manager::NetworkManager;
+use talpid_types::{
+    net::{ConnectedNetwork, Connectivity, ConnectivityProbe},
+    ErrorExt,
 };
-use talpid_types::ErrorExt;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpSocket,
+};
+
+/// The kernel's ARP table, used to find the MAC address of the default gateway.
+const ARP_TABLE_PATH: &str = "/proc/net/arp";
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -13,69 +31,193 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     #[error(display = "The route manager returned an error")]
     RouteManagerError(#[error(source)] routing::Error),
-}
 
-pub struct MonitorHandle {
-    route_manager: RouteManagerHandle,
-    _notify_tx: Arc<UnboundedSender<bool>>,
+    #[error(display = "Failed to open a netlink connection")]
+    NetlinkConnectionError(#[error(source)] io::Error),
+
+    #[error(display = "Failed to obtain the link state of network interfaces")]
+    LinkStateError(#[error(source)] rtnetlink::Error),
+
+    #[error(display = "Failed to send the connectivity probe")]
+    ProbeIoError(#[error(source)] io::Error),
+
+    #[error(display = "The connectivity probe host could not be resolved")]
+    ProbeResolveError,
+
+    #[error(display = "Received a malformed response to the connectivity probe")]
+    ProbeResponseError,
 }
 
 const PUBLIC_INTERNET_ADDRESS_V4: IpAddr = IpAddr::V4(Ipv4Addr::new(193, 138, 218, 78));
 const PUBLIC_INTERNET_ADDRESS_V6: IpAddr =
     IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6));
 
+/// Maximum time to wait for a response to the connectivity probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often connectivity is re-evaluated while a captive portal is suspected. Logging in to the
+/// portal does not necessarily change any routes, so this cannot rely on route changes alone.
+const CAPTIVE_PORTAL_RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Status code that a probe server must return for the host to be considered online.
+const PROBE_EXPECTED_STATUS: u16 = 204;
+
+/// A single source of evidence about the connectivity of the host.
+#[async_trait::async_trait]
+pub trait ConnectivityDetector: Send + Sync {
+    /// Short description of the detector, used for logging.
+    fn name(&self) -> &'static str;
+
+    /// Infers the current connectivity of the host.
+    async fn detect(&self) -> Result<Connectivity>;
+}
+
+/// Combines several detectors. The detectors are consulted in order, and the first one that does
+/// not report the host as online decides the result. Detectors that fail are ignored, so the
+/// host is presumed to be online unless there's evidence to the contrary.
+struct Detectors(Vec<Box<dyn ConnectivityDetector>>);
+
+impl Detectors {
+    async fn connectivity(&self) -> Connectivity {
+        for detector in &self.0 {
+            match detector.detect().await {
+                Ok(Connectivity::Online) => (),
+                Ok(connectivity) => {
+                    log::trace!("{} detector reported: {}", detector.name(), connectivity);
+                    return connectivity;
+                }
+                Err(error) => {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg(&format!(
+                            "{} detector failed to infer connectivity. Presuming connectivity",
+                            detector.name()
+                        ))
+                    );
+                }
+            }
+        }
+        Connectivity::Online
+    }
+}
+
+pub struct MonitorHandle {
+    detectors: Arc<Detectors>,
+    probe: Arc<Mutex<ProbeState>>,
+    recheck_tx: UnboundedSender<()>,
+    _notify_tx: Arc<UnboundedSender<Connectivity>>,
+}
+
 impl MonitorHandle {
-    pub async fn is_offline(&mut self) -> bool {
-        match public_ip_unreachable(&self.route_manager).await {
-            Ok(is_offline) => is_offline,
-            Err(err) => {
-                log::error!(
-                    "Failed to verify offline state: {}. Presuming connectivity",
-                    err
-                );
-                false
+    pub async fn connectivity(&mut self) -> Connectivity {
+        self.detectors.connectivity().await
+    }
+
+    /// Replaces the connectivity probe, and re-evaluates the connectivity using it.
+    pub fn set_connectivity_probe(&mut self, target: Option<ConnectivityProbe>) {
+        let mut probe = self.probe.lock().unwrap();
+        if probe.target != target {
+            match &target {
+                Some(target) => log::debug!("Using connectivity probe {}", target),
+                None => log::debug!("Disabling connectivity probe"),
             }
+            *probe = ProbeState::new(target);
+            let _ = self.recheck_tx.unbounded_send(());
         }
     }
 }
 
 pub async fn spawn_monitor(
-    notify_tx: UnboundedSender<bool>,
+    notify_tx: UnboundedSender<Connectivity>,
+    network_tx: UnboundedSender<Vec<ConnectedNetwork>>,
     route_manager: RouteManagerHandle,
+    connectivity_probe: Option<ConnectivityProbe>,
 ) -> Result<MonitorHandle> {
-    let mut is_offline = public_ip_unreachable(&route_manager).await?;
+    if let Some(target) = &connectivity_probe {
+        log::debug!("Using connectivity probe {}", target);
+    }
+    let probe = Arc::new(Mutex::new(ProbeState::new(connectivity_probe)));
+    let detectors = Arc::new(Detectors(vec![
+        Box::new(LinkStateDetector::new()?),
+        Box::new(RouteDetector {
+            route_manager: route_manager.clone(),
+        }),
+        Box::new(ProbeDetector {
+            state: probe.clone(),
+        }),
+    ]));
+
+    let mut connectivity = detectors.connectivity().await;
+    let network_manager = Arc::new(Mutex::new(None));
+    let mut networks = connected_networks(network_manager.clone()).await;
+    let _ = network_tx.unbounded_send(networks.clone());
 
     let mut listener = route_manager
         .change_listener()
         .await
-        .map_err(Error::RouteManagerError)?;
+        .map_err(Error::RouteManagerError)?
+        .fuse();
+
+    // The gateway of a new network is usually not in the neighbour table yet when the routes
+    // change, so neighbour changes are used to look up its MAC address again
+    let (mut neighbour_connection, _, neighbour_events) =
+        rtnetlink::new_connection().map_err(Error::NetlinkConnectionError)?;
+    neighbour_connection
+        .socket_mut()
+        .bind(&NetlinkSocketAddr::new(0, RTMGRP_NEIGH))
+        .map_err(Error::NetlinkConnectionError)?;
+    tokio::spawn(neighbour_connection);
+    let mut neighbour_events = neighbour_events.fuse();
+
+    let (recheck_tx, recheck_rx) = futures::channel::mpsc::unbounded();
+    let mut recheck_rx = recheck_rx.fuse();
 
     let notify_tx = Arc::new(notify_tx);
     let sender = Arc::downgrade(&notify_tx);
     let monitor_handle = MonitorHandle {
-        route_manager: route_manager.clone(),
+        detectors: detectors.clone(),
+        probe,
+        recheck_tx,
         _notify_tx: notify_tx,
     };
 
     tokio::spawn(async move {
-        while let Some(_event) = listener.next().await {
-            match sender.upgrade() {
-                Some(sender) => {
-                    let new_offline_state = public_ip_unreachable(&route_manager)
-                        .await
-                        .unwrap_or_else(|err| {
-                            log::error!(
-                                "{}",
-                                err.display_chain_with_msg("Failed to infer offline state")
-                            );
-                            false
-                        });
-                    if new_offline_state != is_offline {
-                        is_offline = new_offline_state;
-                        let _ = sender.unbounded_send(is_offline);
+        loop {
+            let recheck_timer = if connectivity == Connectivity::CaptivePortal {
+                tokio::time::sleep(CAPTIVE_PORTAL_RECHECK_INTERVAL).boxed()
+            } else {
+                future::pending().boxed()
+            };
+            let neighbour_changed = futures::select! {
+                event = listener.next() => {
+                    if event.is_none() {
+                        return;
                     }
+                    false
                 }
+                _ = recheck_timer.fuse() => false,
+                _ = recheck_rx.next() => false,
+                _ = neighbour_events.select_next_some() => true,
+            };
+
+            let sender = match sender.upgrade() {
+                Some(sender) => sender,
                 None => return,
+            };
+
+            if !neighbour_changed {
+                let new_connectivity = detectors.connectivity().await;
+                if new_connectivity != connectivity {
+                    log::info!("Connectivity changed: {}", new_connectivity);
+                    connectivity = new_connectivity;
+                    let _ = sender.unbounded_send(connectivity);
+                }
+            }
+
+            if !neighbour_changed || networks.iter().any(|network| network.gateway_mac.is_none()) {
+                let new_networks = connected_networks(network_manager.clone()).await;
+                if new_networks != networks {
+                    networks = new_networks;
+                    let _ = network_tx.unbounded_send(networks.clone());
+                }
             }
         }
     });
@@ -83,16 +225,327 @@ pub async fn spawn_monitor(
     Ok(monitor_handle)
 }
 
+/// Returns the Ethernet and Wi-Fi networks that NetworkManager reports as active. The connection
+/// to NetworkManager is created on first use and then reused. Without NetworkManager, the host is
+/// presumed not to be connected to any known network.
+async fn connected_networks(
+    network_manager: Arc<Mutex<Option<NetworkManager>>>,
+) -> Vec<ConnectedNetwork> {
+    let result = tokio::task::spawn_blocking(move || {
+        let mut network_manager = network_manager
+            .lock()
+            .expect("NetworkManager lock poisoned");
+        let network_manager = match &mut *network_manager {
+            Some(network_manager) => network_manager,
+            none => none.insert(NetworkManager::new()?),
+        };
+        let networks = network_manager.active_networks()?;
+        let arp_table = std::fs::read_to_string(ARP_TABLE_PATH).unwrap_or_else(|error| {
+            log::debug!("Failed to read {}: {}", ARP_TABLE_PATH, error);
+            String::new()
+        });
+        Ok::<_, talpid_dbus::network_manager::Error>(
+            networks
+                .into_iter()
+                .map(|network| ConnectedNetwork {
+                    gateway_mac: network.gateway.and_then(|gateway| {
+                        find_mac_address(&arp_table, gateway, &network.interface)
+                    }),
+                    interface: network.interface,
+                    ssid: network.ssid,
+                })
+                .collect(),
+        )
+    })
+    .await;
+    match result {
+        Ok(Ok(networks)) => networks,
+        Ok(Err(error)) => {
+            log::debug!(
+                "{}",
+                error.display_chain_with_msg("Failed to obtain the active networks")
+            );
+            vec![]
+        }
+        Err(error) => {
+            log::error!("Failed to obtain the active networks: {}", error);
+            vec![]
+        }
+    }
+}
+
+/// Looks up the MAC address of `address` on `interface` in the contents of `/proc/net/arp`.
+/// Incomplete entries, which have an all-zero address, are ignored.
+fn find_mac_address(arp_table: &str, address: IpAddr, interface: &str) -> Option<String> {
+    arp_table.lines().skip(1).find_map(|line| {
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        match columns.as_slice() {
+            [ip, _hw_type, _flags, mac, _mask, device]
+                if ip.parse::<IpAddr>().ok() == Some(address)
+                    && *device == interface
+                    && *mac != "00:00:00:00:00:00" =>
+            {
+                Some(mac.to_lowercase())
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Reports the host as offline if no physical network interface is up and has a carrier.
+struct LinkStateDetector {
+    handle: rtnetlink::Handle,
+}
+
+impl LinkStateDetector {
+    fn new() -> Result<Self> {
+        let (connection, handle, _) =
+            rtnetlink::new_connection().map_err(Error::NetlinkConnectionError)?;
+        tokio::spawn(connection);
+        Ok(Self { handle })
+    }
+
+    fn is_usable_link(link: &LinkMessage) -> bool {
+        let flags = link.header.flags;
+        let link_layer_type = link.header.link_layer_type;
+        // Tunnel devices have no link layer and cannot provide connectivity on their own
+        link_layer_type != ARPHRD_LOOPBACK
+            && link_layer_type != ARPHRD_NONE
+            && flags & (libc::IFF_UP as u32) != 0
+            && flags & (libc::IFF_LOWER_UP as u32) != 0
+    }
+}
+
+#[async_trait::async_trait]
+impl ConnectivityDetector for LinkStateDetector {
+    fn name(&self) -> &'static str {
+        "Link state"
+    }
 
-async fn public_ip_unreachable(handle: &RouteManagerHandle) -> Result<bool> {
-    Ok(handle
-        .get_destination_route(PUBLIC_INTERNET_ADDRESS_V4, true)
+    async fn detect(&self) -> Result<Connectivity> {
+        let mut links = self.handle.link().get().execute();
+        while let Some(link) = links.try_next().await.map_err(Error::LinkStateError)? {
+            if Self::is_usable_link(&link) {
+                return Ok(Connectivity::Online);
+            }
+        }
+        Ok(Connectivity::Offline)
+    }
+}
+
+/// Reports the host as offline if there is no route to the public internet.
+struct RouteDetector {
+    route_manager: RouteManagerHandle,
+}
+
+#[async_trait::async_trait]
+impl ConnectivityDetector for RouteDetector {
+    fn name(&self) -> &'static str {
+        "Route"
+    }
+
+    async fn detect(&self) -> Result<Connectivity> {
+        let unreachable = self
+            .route_manager
+            .get_destination_route(PUBLIC_INTERNET_ADDRESS_V4, true)
+            .await
+            .map_err(Error::RouteManagerError)?
+            .is_none()
+            && self
+                .route_manager
+                .get_destination_route(PUBLIC_INTERNET_ADDRESS_V6, true)
+                .await
+                .unwrap_or(None)
+                .is_none();
+        Ok(Connectivity::from(unreachable))
+    }
+}
+
+/// Sends an HTTP request to a known server and reports a suspected captive portal if the response
+/// is not the expected one.
+///
+/// The probe is sent with the same firewall mark as the tunnel traffic. It is therefore routed
+/// outside the tunnel, and the firewall lets it through in every state. A probe that fails to get
+/// any response at all is inconclusive, in which case the result of the last conclusive probe is
+/// kept. The probe never reports the host as offline.
+struct ProbeDetector {
+    state: Arc<Mutex<ProbeState>>,
+}
+
+struct ProbeState {
+    target: Option<ConnectivityProbe>,
+    /// The address that the host of the target was last resolved to. DNS is blocked in the
+    /// blocking states, so this address is used if the host cannot be resolved.
+    address: Option<SocketAddr>,
+    /// The result of the last conclusive probe.
+    last_result: Connectivity,
+}
+
+impl ProbeState {
+    fn new(target: Option<ConnectivityProbe>) -> Self {
+        Self {
+            target,
+            address: None,
+            last_result: Connectivity::Online,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ConnectivityDetector for ProbeDetector {
+    fn name(&self) -> &'static str {
+        "Probe"
+    }
+
+    async fn detect(&self) -> Result<Connectivity> {
+        let (target, cached_address) = {
+            let state = self.state.lock().unwrap();
+            match &state.target {
+                Some(target) => (target.clone(), state.address),
+                None => return Ok(Connectivity::Online),
+            }
+        };
+
+        let address = match resolve_probe_target(&target).await {
+            Ok(address) => address,
+            Err(error) => match cached_address {
+                Some(address) => {
+                    log::trace!(
+                        "{}",
+                        error.display_chain_with_msg(&format!(
+                            "Failed to resolve connectivity probe host. Using {}",
+                            address
+                        ))
+                    );
+                    address
+                }
+                None => return Err(error),
+            },
+        };
+
+        let result = match tokio::time::timeout(PROBE_TIMEOUT, probe(&target, address)).await {
+            Ok(Ok(PROBE_EXPECTED_STATUS)) => Some(Connectivity::Online),
+            Ok(Ok(status)) => {
+                log::debug!(
+                    "Connectivity probe {} returned unexpected status {}",
+                    target,
+                    status
+                );
+                Some(Connectivity::CaptivePortal)
+            }
+            Ok(Err(error)) => {
+                log::debug!(
+                    "{}",
+                    error.display_chain_with_msg("Connectivity probe was inconclusive")
+                );
+                None
+            }
+            Err(_) => {
+                log::debug!("Connectivity probe timed out");
+                None
+            }
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if state.target.as_ref() != Some(&target) {
+            // The probe was replaced while this one was in flight
+            return Ok(state.last_result);
+        }
+        state.address = Some(address);
+        if let Some(result) = result {
+            state.last_result = result;
+        }
+        Ok(state.last_result)
+    }
+}
+
+async fn resolve_probe_target(target: &ConnectivityProbe) -> Result<SocketAddr> {
+    tokio::net::lookup_host((target.host.as_str(), target.port))
         .await
-        .map_err(Error::RouteManagerError)?
-        .is_none()
-        && handle
-            .get_destination_route(PUBLIC_INTERNET_ADDRESS_V6, true)
+        .map_err(Error::ProbeIoError)?
+        .next()
+        .ok_or(Error::ProbeResolveError)
+}
+
+/// Sends a request to the probe server and returns the HTTP status code of the response.
+async fn probe(target: &ConnectivityProbe, address: SocketAddr) -> Result<u16> {
+    let socket = match address {
+        SocketAddr::V4(_) => TcpSocket::new_v4(),
+        SocketAddr::V6(_) => TcpSocket::new_v6(),
+    }
+    .map_err(Error::ProbeIoError)?;
+    socket2::SockRef::from(&socket)
+        .set_mark(crate::linux::TUNNEL_FW_MARK)
+        .map_err(Error::ProbeIoError)?;
+    let mut stream = socket.connect(address).await.map_err(Error::ProbeIoError)?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        target.path, target.host
+    );
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(Error::ProbeIoError)?;
+
+    let mut response = vec![0u8; 256];
+    let mut len = 0;
+    while len < response.len() && !response[..len].contains(&b'\n') {
+        let read = stream
+            .read(&mut response[len..])
             .await
-            .unwrap_or(None)
-            .is_none())
+            .map_err(Error::ProbeIoError)?;
+        if read == 0 {
+            break;
+        }
+        len += read;
+    }
+    parse_status(&response[..len]).ok_or(Error::ProbeResponseError)
+}
+
+fn parse_status(response: &[u8]) -> Option<u16> {
+    let status_line = std::str::from_utf8(response).ok()?.lines().next()?;
+    let mut parts = status_line.split_whitespace();
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    parts.next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe_status() {
+        assert_eq!(parse_status(b"HTTP/1.1 204 No Content\r\n"), Some(204));
+        assert_eq!(
+            parse_status(b"HTTP/1.0 302 Found\r\nLocation: /login\r\n"),
+            Some(302)
+        );
+        assert_eq!(parse_status(b"<html>"), None);
+    }
+
+    #[test]
+    fn test_find_mac_address() {
+        let arp_table = "\
+IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.1      0x1         0x2         AA:BB:CC:DD:EE:FF     *        eth0
+192.168.1.1      0x1         0x2         11:22:33:44:55:66     *        wlan0
+10.0.0.1         0x1         0x0         00:00:00:00:00:00     *        eth0
+";
+        let gateway = "192.168.1.1".parse().unwrap();
+        assert_eq!(
+            find_mac_address(arp_table, gateway, "eth0"),
+            Some("aa:bb:cc:dd:ee:ff".to_owned())
+        );
+        assert_eq!(
+            find_mac_address(arp_table, gateway, "wlan0"),
+            Some("11:22:33:44:55:66".to_owned())
+        );
+        assert_eq!(find_mac_address(arp_table, gateway, "eth1"), None);
+        assert_eq!(
+            find_mac_address(arp_table, "10.0.0.1".parse().unwrap(), "eth0"),
+            None
+        );
+    }
 }
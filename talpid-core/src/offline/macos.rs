@@ -22,6 +22,7 @@ use system_configuration::{
         ReachabilityFlags, SCNetworkReachability, SchedulingError, SetCallbackError,
     },
 };
+use talpid_types::net::Connectivity;
 
 
 const PRIMARY_INTERFACE_KEY: &str = "State:/Network/Global/IPv4";
@@ -39,7 +40,7 @@ pub enum Error {
 }
 
 pub struct MonitorHandle {
-    _notify_tx: Arc<UnboundedSender<bool>>,
+    _notify_tx: Arc<UnboundedSender<Connectivity>>,
 }
 
 impl MonitorHandle {
@@ -55,7 +56,9 @@ impl MonitorHandle {
     }
 }
 
-pub async fn spawn_monitor(notify_tx: UnboundedSender<bool>) -> Result<MonitorHandle, Error> {
+pub async fn spawn_monitor(
+    notify_tx: UnboundedSender<Connectivity>,
+) -> Result<MonitorHandle, Error> {
     let (result_tx, result_rx) = mpsc::channel();
     let notify_tx = Arc::new(notify_tx);
     let sender = Arc::downgrade(&notify_tx);
@@ -173,7 +176,7 @@ fn iface_is_physical(iface: &SCNetworkInterface) -> bool {
 
 #[derive(Clone)]
 struct OfflineStateContext {
-    sender: Weak<UnboundedSender<bool>>,
+    sender: Weak<UnboundedSender<Connectivity>>,
     is_offline: Arc<AtomicBool>,
 }
 
@@ -185,7 +188,7 @@ impl OfflineStateContext {
     fn new_state(&self, is_offline: bool) {
         if self.is_offline.swap(is_offline, Ordering::SeqCst) != is_offline {
             if let Some(sender) = self.sender.upgrade() {
-                let _ = sender.unbounded_send(is_offline);
+                let _ = sender.unbounded_send(Connectivity::from(is_offline));
             }
         }
     }
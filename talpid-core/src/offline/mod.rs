@@ -3,6 +3,9 @@ use crate::routing::RouteManagerHandle;
 use futures::channel::mpsc::UnboundedSender;
 #[cfg(target_os = "android")]
 use talpid_types::android::AndroidContext;
+use talpid_types::net::Connectivity;
+#[cfg(target_os = "linux")]
+use talpid_types::net::{ConnectedNetwork, ConnectivityProbe};
 
 #[cfg(target_os = "macos")]
 #[path = "macos.rs"]
@@ -32,17 +35,34 @@ pub use self::imp::Error;
 pub struct MonitorHandle(Option<imp::MonitorHandle>);
 
 impl MonitorHandle {
-    pub async fn is_offline(&mut self) -> bool {
+    pub async fn connectivity(&mut self) -> Connectivity {
         match self.0.as_mut() {
-            Some(monitor) => monitor.is_offline().await,
-            None => false,
+            #[cfg(target_os = "linux")]
+            Some(monitor) => monitor.connectivity().await,
+            #[cfg(not(target_os = "linux"))]
+            Some(monitor) => Connectivity::from(monitor.is_offline().await),
+            None => Connectivity::Online,
+        }
+    }
+
+    /// Sets the HTTP server used to detect captive portals. Detection is disabled if this is
+    /// `None`.
+    #[cfg(target_os = "linux")]
+    pub fn set_connectivity_probe(&mut self, probe: Option<ConnectivityProbe>) {
+        if let Some(monitor) = self.0.as_mut() {
+            monitor.set_connectivity_probe(probe);
         }
     }
 }
 
+/// Spawns a monitor that sends the connectivity of the host on `sender` whenever it changes. On
+/// Linux, the physical networks that the host is connected to are also sent on `network_sender`,
+/// and captive portals are detected using `connectivity_probe`.
 pub async fn spawn_monitor(
-    sender: UnboundedSender<bool>,
+    sender: UnboundedSender<Connectivity>,
+    #[cfg(target_os = "linux")] network_sender: UnboundedSender<Vec<ConnectedNetwork>>,
     #[cfg(target_os = "linux")] route_manager: RouteManagerHandle,
+    #[cfg(target_os = "linux")] connectivity_probe: Option<ConnectivityProbe>,
     #[cfg(target_os = "android")] android_context: AndroidContext,
 ) -> Result<MonitorHandle, Error> {
     let monitor = if !*FORCE_DISABLE_OFFLINE_MONITOR {
@@ -50,7 +70,11 @@ pub async fn spawn_monitor(
             imp::spawn_monitor(
                 sender,
                 #[cfg(target_os = "linux")]
+                network_sender,
+                #[cfg(target_os = "linux")]
                 route_manager,
+                #[cfg(target_os = "linux")]
+                connectivity_probe,
                 #[cfg(target_os = "android")]
                 android_context,
             )
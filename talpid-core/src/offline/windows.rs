@@ -11,7 +11,7 @@ use std::{
     thread,
     time::Duration,
 };
-use talpid_types::ErrorExt;
+use talpid_types::{net::Connectivity, ErrorExt};
 use winapi::{
     shared::{
         basetsd::LONG_PTR,
@@ -51,13 +51,13 @@ pub struct BroadcastListener {
     thread_id: DWORD,
     system_state: Arc<Mutex<SystemState>>,
     _callback_handle: winnet::WinNetCallbackHandle,
-    _notify_tx: Arc<UnboundedSender<bool>>,
+    _notify_tx: Arc<UnboundedSender<Connectivity>>,
 }
 
 unsafe impl Send for BroadcastListener {}
 
 impl BroadcastListener {
-    pub fn start(notify_tx: UnboundedSender<bool>) -> Result<Self, Error> {
+    pub fn start(notify_tx: UnboundedSender<Connectivity>) -> Result<Self, Error> {
         let notify_tx = Arc::new(notify_tx);
         let (v4_connectivity, v6_connectivity) = Self::check_initial_connectivity();
         let system_state = Arc::new(Mutex::new(SystemState {
@@ -273,7 +273,7 @@ struct SystemState {
     v4_connectivity: bool,
     v6_connectivity: bool,
     suspended: bool,
-    notify_tx: Weak<UnboundedSender<bool>>,
+    notify_tx: Weak<UnboundedSender<Connectivity>>,
 }
 
 impl SystemState {
@@ -297,7 +297,7 @@ impl SystemState {
         if old_state != new_state {
             log::info!("Connectivity changed: {}", is_offline_str(new_state));
             if let Some(notify_tx) = self.notify_tx.upgrade() {
-                if let Err(e) = notify_tx.unbounded_send(new_state) {
+                if let Err(e) = notify_tx.unbounded_send(Connectivity::from(new_state)) {
                     log::error!("Failed to send new offline state to daemon: {}", e);
                 }
             }
@@ -320,7 +320,7 @@ fn is_offline_str(offline: bool) -> &'static str {
 
 pub type MonitorHandle = BroadcastListener;
 
-pub async fn spawn_monitor(sender: UnboundedSender<bool>) -> Result<MonitorHandle, Error> {
+pub async fn spawn_monitor(sender: UnboundedSender<Connectivity>) -> Result<MonitorHandle, Error> {
     BroadcastListener::start(sender)
 }
 
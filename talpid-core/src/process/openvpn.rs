@@ -1,14 +1,17 @@
 use duct;
 
 use super::stoppable_process::StoppableProcess;
+use crate::logging::{RotatingLogFile, ROTATION_POLICY};
 use atty;
-use os_pipe::{pipe, PipeWriter};
+use os_pipe::{pipe, PipeReader, PipeWriter};
 use parking_lot::Mutex;
 use shell_escape;
 use std::{
     ffi::{OsStr, OsString},
-    fmt, io,
+    fmt,
+    io::{self, BufRead, BufReader, Write},
     path::{Path, PathBuf},
+    thread,
 };
 use talpid_types::net;
 
@@ -29,8 +32,6 @@ static BASE_ARGUMENTS: &[&[&str]] = &[
     &["--rcvbuf", "1048576"],
     &["--sndbuf", "1048576"],
     &["--fast-io"],
-    &["--data-ciphers-fallback", "AES-256-GCM"],
-    &["--tls-version-min", "1.3"],
     &["--verb", "3"],
     #[cfg(windows)]
     &[
@@ -52,6 +53,15 @@ static BASE_ARGUMENTS: &[&[&str]] = &[
 static ALLOWED_TLS1_3_CIPHERS: &[&str] =
     &["TLS_AES_256_GCM_SHA384", "TLS_CHACHA20_POLY1305_SHA256"];
 
+/// TLS 1.2 cipher suites, only offered if the minimum TLS version has been lowered to 1.2.
+static ALLOWED_TLS1_2_CIPHERS: &[&str] = &[
+    "TLS-ECDHE-RSA-WITH-AES-256-GCM-SHA384",
+    "TLS-ECDHE-RSA-WITH-CHACHA20-POLY1305-SHA256",
+];
+
+/// Data channel cipher used if the server does not support cipher negotiation.
+const DEFAULT_DATA_CIPHER: net::openvpn::Cipher = net::openvpn::Cipher::Aes256Gcm;
+
 /// Tun driver to use, specified using `--windows-driver`.
 #[derive(Clone)]
 pub enum WindowsDriver {
@@ -92,6 +102,8 @@ pub struct OpenVpnCommand {
     tunnel_alias: Option<OsString>,
     enable_ipv6: bool,
     proxy_port: Option<u16>,
+    #[cfg(target_os = "linux")]
+    fwmark: Option<u32>,
 }
 
 impl OpenVpnCommand {
@@ -116,6 +128,8 @@ impl OpenVpnCommand {
             tunnel_alias: None,
             enable_ipv6: true,
             proxy_port: None,
+            #[cfg(target_os = "linux")]
+            fwmark: Some(crate::linux::TUNNEL_FW_MARK),
         }
     }
 
@@ -169,12 +183,18 @@ impl OpenVpnCommand {
         self
     }
 
-    /// Sets a log file path.
+    /// Sets a log file path. The output of OpenVPN is written to it by `OpenVpnProcHandle`, so
+    /// that the log can be rotated while OpenVPN is running.
     pub fn log(&mut self, path: impl AsRef<Path>) -> &mut Self {
         self.log = Some(path.as_ref().to_path_buf());
         self
     }
 
+    /// Returns the log file path, if one is set.
+    pub fn log_path(&self) -> Option<&Path> {
+        self.log.as_deref()
+    }
+
     /// Sets extra options
     pub fn tunnel_options(&mut self, tunnel_options: &net::openvpn::TunnelOptions) -> &mut Self {
         self.tunnel_options = tunnel_options.clone();
@@ -214,6 +234,15 @@ impl OpenVpnCommand {
         self
     }
 
+    /// Sets the mark of the packets sent by OpenVPN. By default, they are marked so that they
+    /// are routed outside of any tunnel. `None` routes them like any other traffic, which is
+    /// needed to reach the server through an entry tunnel.
+    #[cfg(target_os = "linux")]
+    pub fn fwmark(&mut self, fwmark: Option<u32>) -> &mut Self {
+        self.fwmark = fwmark;
+        self
+    }
+
     /// Build a runnable expression from the current state of the command.
     pub fn build(&self) -> duct::Expression {
         log::debug!("Building expression: {}", &self);
@@ -252,11 +281,6 @@ impl OpenVpnCommand {
             args.extend(plugin_args.iter().map(OsString::from));
         }
 
-        if let Some(ref path) = self.log {
-            args.push(OsString::from("--log"));
-            args.push(OsString::from(path))
-        }
-
         if let Some(mssfix) = self.tunnel_options.mssfix {
             args.push(OsString::from("--mssfix"));
             args.push(OsString::from(mssfix.to_string()));
@@ -283,15 +307,14 @@ impl OpenVpnCommand {
             args.push(OsString::from(windows_driver.as_str()));
         }
 
-        args.extend(Self::tls_cipher_arguments().iter().map(OsString::from));
+        args.extend(self.data_cipher_arguments().iter().map(OsString::from));
+        args.extend(self.tls_cipher_arguments().iter().map(OsString::from));
         args.extend(self.proxy_arguments().iter().map(OsString::from));
 
         #[cfg(target_os = "linux")]
-        args.extend(
-            ["--mark", &crate::linux::TUNNEL_FW_MARK.to_string()]
-                .iter()
-                .map(OsString::from),
-        );
+        if let Some(fwmark) = self.fwmark {
+            args.extend(["--mark", &fwmark.to_string()].iter().map(OsString::from));
+        }
 
         args
     }
@@ -306,10 +329,34 @@ impl OpenVpnCommand {
         args
     }
 
-    fn tls_cipher_arguments() -> Vec<String> {
+    fn data_cipher_arguments(&self) -> Vec<String> {
+        let mut args = vec![];
+        match self.tunnel_options.cipher {
+            Some(cipher) => {
+                args.push("--data-ciphers".to_owned());
+                args.push(cipher.openvpn_name().to_owned());
+                args.push("--data-ciphers-fallback".to_owned());
+                args.push(cipher.openvpn_name().to_owned());
+            }
+            None => {
+                args.push("--data-ciphers-fallback".to_owned());
+                args.push(DEFAULT_DATA_CIPHER.openvpn_name().to_owned());
+            }
+        }
+        args
+    }
+
+    fn tls_cipher_arguments(&self) -> Vec<String> {
+        let tls_version_min = self.tunnel_options.tls_version_min;
         let mut args = vec![];
+        args.push("--tls-version-min".to_owned());
+        args.push(tls_version_min.to_string());
         args.push("--tls-ciphersuites".to_owned());
         args.push(ALLOWED_TLS1_3_CIPHERS.join(":"));
+        if tls_version_min < net::openvpn::TlsVersion::V1_3 {
+            args.push("--tls-cipher".to_owned());
+            args.push(ALLOWED_TLS1_2_CIPHERS.join(":"));
+        }
         args
     }
 
@@ -382,6 +429,25 @@ impl OpenVpnCommand {
                 args.push("255.255.255.255".to_owned());
                 args.push("net_gateway".to_owned());
             }
+            Some(net::openvpn::ProxySettings::HttpConnect(ref http_proxy)) => {
+                args.push("--http-proxy".to_owned());
+                args.push(http_proxy.address.ip().to_string());
+                args.push(http_proxy.address.port().to_string());
+
+                if let Some(ref _auth) = http_proxy.auth {
+                    if let Some(ref auth_file) = self.proxy_auth_path {
+                        args.push(auth_file.to_string_lossy().to_string());
+                        args.push("basic".to_owned());
+                    } else {
+                        log::error!("Proxy credentials present but credentials file missing");
+                    }
+                }
+
+                args.push("--route".to_owned());
+                args.push(http_proxy.address.ip().to_string());
+                args.push("255.255.255.255".to_owned());
+                args.push("net_gateway".to_owned());
+            }
             None => {}
         };
         args
@@ -411,18 +477,33 @@ pub struct OpenVpnProcHandle {
 
 /// Impl for proc handle
 impl OpenVpnProcHandle {
-    /// Constructor for a new openvpn proc handle
-    pub fn new(mut cmd: duct::Expression) -> io::Result<Self> {
-        if !atty::is(atty::Stream::Stdout) {
-            cmd = cmd.stdout_null();
-        }
+    /// Constructor for a new openvpn proc handle. The output of the process is written to
+    /// `log_path` if one is given.
+    pub fn new(mut cmd: duct::Expression, log_path: Option<&Path>) -> io::Result<Self> {
+        let mut log_writer = None;
+        if let Some(log_path) = log_path {
+            let log_file = RotatingLogFile::open(log_path, *ROTATION_POLICY)?;
+            let (reader, writer) = pipe()?;
+            cmd = cmd.stderr_to_stdout().stdout_file(writer);
+            log_writer = Some((reader, log_file));
+        } else {
+            if !atty::is(atty::Stream::Stdout) {
+                cmd = cmd.stdout_null();
+            }
 
-        if !atty::is(atty::Stream::Stderr) {
-            cmd = cmd.stderr_null();
+            if !atty::is(atty::Stream::Stderr) {
+                cmd = cmd.stderr_null();
+            }
         }
 
         let (reader, writer) = pipe()?;
         let proc_handle = cmd.stdin_file(reader).start()?;
+        // Release the write end of the log pipe, so that the log writer stops once OpenVPN exits
+        drop(cmd);
+
+        if let Some((reader, log_file)) = log_writer {
+            thread::spawn(move || write_log(reader, log_file));
+        }
 
         Ok(Self {
             inner: proc_handle,
@@ -431,6 +512,28 @@ impl OpenVpnProcHandle {
     }
 }
 
+/// Copies the output of OpenVPN to its log file, line by line, until the process exits.
+fn write_log(output: PipeReader, mut log_file: RotatingLogFile) {
+    let mut output = BufReader::new(output);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match output.read_until(b'\n', &mut line) {
+            Ok(0) => break,
+            Ok(_) => {
+                if let Err(error) = log_file.write_all(&line) {
+                    log::error!("Failed to write to OpenVPN log: {}", error);
+                    break;
+                }
+            }
+            Err(error) => {
+                log::error!("Failed to read OpenVPN output: {}", error);
+                break;
+            }
+        }
+    }
+}
+
 impl StoppableProcess for OpenVpnProcHandle {
     /// Closes STDIN to stop the openvpn process
     fn stop(&self) {
@@ -462,7 +565,7 @@ impl StoppableProcess for OpenVpnProcHandle {
 mod tests {
     use super::OpenVpnCommand;
     use std::{ffi::OsString, net::Ipv4Addr};
-    use talpid_types::net::{Endpoint, TransportProtocol};
+    use talpid_types::net::{openvpn, Endpoint, TransportProtocol};
 
     #[test]
     fn passes_one_remote() {
@@ -489,4 +592,67 @@ mod tests {
         assert!(testee_args.contains(&OsString::from("123")));
         assert!(testee_args.contains(&OsString::from("cde")));
     }
+
+    #[test]
+    fn passes_cipher_and_tls_options() {
+        let testee_args = OpenVpnCommand::new("").get_arguments();
+        assert!(!testee_args.contains(&OsString::from("--data-ciphers")));
+        assert!(!testee_args.contains(&OsString::from("--tls-cipher")));
+        assert!(testee_args.contains(&OsString::from("1.3")));
+
+        let options = openvpn::TunnelOptions {
+            cipher: Some(openvpn::Cipher::Chacha20Poly1305),
+            tls_version_min: openvpn::TlsVersion::V1_2,
+            ..Default::default()
+        };
+        let testee_args = OpenVpnCommand::new("")
+            .tunnel_options(&options)
+            .get_arguments();
+        let position = testee_args
+            .iter()
+            .position(|arg| arg == "--data-ciphers")
+            .unwrap();
+        assert_eq!(testee_args[position + 1], "CHACHA20-POLY1305");
+        assert!(!testee_args.contains(&OsString::from("AES-256-GCM")));
+        assert!(testee_args.contains(&OsString::from("--tls-cipher")));
+        assert!(testee_args.contains(&OsString::from("1.2")));
+    }
+
+    #[test]
+    fn passes_http_proxy() {
+        let proxy = openvpn::ProxySettings::HttpConnect(openvpn::HttpConnectProxySettings {
+            address: "10.0.0.1:3128".parse().unwrap(),
+            auth: Some(openvpn::ProxyAuth {
+                username: "user".to_owned(),
+                password: "pass".to_owned(),
+            }),
+        });
+        let testee_args = OpenVpnCommand::new("")
+            .proxy_settings(proxy)
+            .proxy_auth("./auth")
+            .get_arguments();
+        let position = testee_args
+            .iter()
+            .position(|arg| arg == "--http-proxy")
+            .unwrap();
+        let expected: Vec<OsString> = ["10.0.0.1", "3128", "./auth", "basic"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        assert_eq!(testee_args[position + 1..position + 5], expected[..]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn passes_fwmark() {
+        let testee_args = OpenVpnCommand::new("").get_arguments();
+        let position = testee_args.iter().position(|arg| arg == "--mark").unwrap();
+        assert_eq!(
+            testee_args[position + 1],
+            OsString::from(crate::linux::TUNNEL_FW_MARK.to_string())
+        );
+
+        let testee_args = OpenVpnCommand::new("").fwmark(None).get_arguments();
+        assert!(!testee_args.contains(&OsString::from("--mark")));
+    }
 }
@@ -3,14 +3,18 @@ mod shadowsocks;
 pub use std::io::Result;
 
 use self::shadowsocks::ShadowsocksProxyMonitor;
-use std::{fmt, path::PathBuf, sync::mpsc};
+use std::{fmt, net::SocketAddr, path::PathBuf, sync::mpsc};
 use talpid_types::net::openvpn;
 
+/// How a proxy service exited.
 pub enum WaitResult {
+    /// The proxy exited without being asked to.
     UnexpectedExit(String),
+    /// The proxy exited after being closed.
     ProperShutdown,
 }
 
+/// Monitors a running proxy service.
 pub trait ProxyMonitor: Send {
     /// Create a handle than can be used to ask the proxy service to shut down.
     fn close_handle(&mut self) -> Box<dyn ProxyMonitorCloseHandle>;
@@ -28,7 +32,9 @@ impl fmt::Debug for dyn ProxyMonitor {
     }
 }
 
+/// Handle used to shut down a proxy service.
 pub trait ProxyMonitorCloseHandle: Send {
+    /// Ask the proxy service to shut down.
     fn close(self: Box<Self>) -> Result<()>;
 }
 
@@ -77,10 +83,13 @@ impl ProxyMonitorCloseHandle for NoopProxyMonitorCloseHandle {
 /// proxy implementations find their way around.
 /// TODO: Move struct to wider scope and use more generic name.
 pub struct ProxyResourceData {
+    /// Directory containing bundled proxy binaries.
     pub resource_dir: PathBuf,
+    /// Directory to write proxy logs to.
     pub log_dir: Option<PathBuf>,
 }
 
+/// Start a proxy service, or monitor an unmanaged one, depending on `settings`.
 pub fn start_proxy(
     settings: &openvpn::ProxySettings,
     resource_data: &ProxyResourceData,
@@ -99,5 +108,25 @@ pub fn start_proxy(
         openvpn::ProxySettings::Shadowsocks(ss_settings) => Ok(Box::new(
             ShadowsocksProxyMonitor::start(ss_settings, resource_data)?,
         )),
+        openvpn::ProxySettings::HttpConnect(http_settings) => {
+            // OpenVPN connects to the HTTP proxy by itself.
+            Ok(Box::new(NoopProxyMonitor::start(
+                http_settings.address.port(),
+            )?))
+        }
     }
 }
+
+/// Start a local UDP relay that forwards datagrams to `forward_address` through a Shadowsocks
+/// server. The relay listens on localhost, on the port returned by [`ProxyMonitor::port`].
+pub fn start_udp_relay(
+    settings: &openvpn::ShadowsocksProxySettings,
+    forward_address: SocketAddr,
+    resource_data: &ProxyResourceData,
+) -> Result<Box<dyn ProxyMonitor>> {
+    Ok(Box::new(ShadowsocksProxyMonitor::start_udp_relay(
+        settings,
+        forward_address,
+        resource_data,
+    )?))
+}
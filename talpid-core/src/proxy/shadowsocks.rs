@@ -10,7 +10,7 @@ use std::{
     fmt,
     fs::File,
     io::{BufRead, Error, ErrorKind},
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
     path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -21,7 +21,7 @@ use std::{
 };
 
 use super::{ProxyMonitor, ProxyMonitorCloseHandle, ProxyResourceData, WaitResult};
-use talpid_types::net::openvpn::ShadowsocksProxySettings;
+use talpid_types::net::{openvpn::ShadowsocksProxySettings, TransportProtocol};
 #[cfg(target_os = "linux")]
 use talpid_types::ErrorExt;
 
@@ -32,6 +32,9 @@ struct ShadowsocksCommand {
     peer_password: Option<String>,
     // This should map to the shadowsocks-rust `CipherType` type.
     cipher: Option<String>,
+    udp_forward: Option<SocketAddr>,
+    #[cfg(target_os = "linux")]
+    fwmark: Option<u32>,
 }
 
 impl ShadowsocksCommand {
@@ -42,6 +45,9 @@ impl ShadowsocksCommand {
             peer: None,
             peer_password: None,
             cipher: None,
+            udp_forward: None,
+            #[cfg(target_os = "linux")]
+            fwmark: None,
         }
     }
 
@@ -65,6 +71,20 @@ impl ShadowsocksCommand {
         self
     }
 
+    /// Relay UDP datagrams received on the local address to `forward`, instead of running a
+    /// SOCKS proxy.
+    pub fn udp_forward(&mut self, forward: SocketAddr) -> &mut Self {
+        self.udp_forward = Some(forward);
+        self
+    }
+
+    /// Mark outgoing packets with `fwmark`, so that they are routed outside the tunnel.
+    #[cfg(target_os = "linux")]
+    pub fn fwmark(&mut self, fwmark: u32) -> &mut Self {
+        self.fwmark = Some(fwmark);
+        self
+    }
+
     pub fn build(&self) -> duct::Expression {
         log::debug!("Building expression: {}", &self);
         duct::cmd(&self.shadowsocks_bin, self.get_arguments()).unchecked()
@@ -96,6 +116,20 @@ impl ShadowsocksCommand {
             args.push(cipher.to_string());
         }
 
+        if let Some(ref forward) = self.udp_forward {
+            args.push("--protocol".to_owned());
+            args.push("tunnel".to_owned());
+            args.push("--forward-addr".to_owned());
+            args.push(format!("{}", forward));
+            args.push("-U".to_owned());
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(fwmark) = self.fwmark {
+            args.push("--outbound-fwmark".to_owned());
+            args.push(fwmark.to_string());
+        }
+
         args
     }
 }
@@ -125,23 +159,99 @@ const SHADOWSOCKS_BIN_FILENAME: &str = "sslocal";
 #[cfg(windows)]
 const SHADOWSOCKS_BIN_FILENAME: &str = "sslocal.exe";
 
+/// Number of times to try starting a UDP relay, in case the port picked for it is taken before
+/// `sslocal` binds to it.
+const UDP_RELAY_START_ATTEMPTS: usize = 3;
+
 impl ShadowsocksProxyMonitor {
     pub fn start(
         settings: &ShadowsocksProxySettings,
         resource_data: &ProxyResourceData,
     ) -> Result<Self> {
-        let binary = resource_data
-            .resource_dir
-            .join(SHADOWSOCKS_BIN_FILENAME)
-            .into_os_string();
-
-        let mut cmd = ShadowsocksCommand::new(binary)
+        let cmd = ShadowsocksCommand::new(Self::binary(resource_data))
             .local(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0))
             .peer(settings.peer)
             .peer_password(settings.password.clone())
             .cipher(settings.cipher.clone())
             .build();
 
+        let (subproc, logfile) = Self::spawn(cmd, resource_data)?;
+
+        match Self::get_bound_port(File::open(&logfile)?, &subproc, TransportProtocol::Tcp) {
+            Ok(port) => Ok(Self {
+                subproc: Arc::new(subproc),
+                closed: Arc::new(AtomicBool::new(false)),
+                port,
+            }),
+            Err(err) => {
+                let _ = subproc.kill();
+                Err(err)
+            }
+        }
+    }
+
+    /// Start a local UDP relay that forwards datagrams to `forward_address` through the
+    /// Shadowsocks server.
+    pub fn start_udp_relay(
+        settings: &ShadowsocksProxySettings,
+        forward_address: SocketAddr,
+        resource_data: &ProxyResourceData,
+    ) -> Result<Self> {
+        let mut last_error = None;
+        for _ in 0..UDP_RELAY_START_ATTEMPTS {
+            // sslocal cannot be asked to pick a port for a UDP relay, so pick a free one up front.
+            // Another process may bind to it before sslocal does, in which case sslocal exits.
+            let port = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))?
+                .local_addr()?
+                .port();
+
+            let mut cmd = ShadowsocksCommand::new(Self::binary(resource_data));
+            cmd.local(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port))
+                .peer(settings.peer)
+                .peer_password(settings.password.clone())
+                .cipher(settings.cipher.clone())
+                .udp_forward(forward_address);
+            #[cfg(target_os = "linux")]
+            cmd.fwmark(crate::linux::TUNNEL_FW_MARK);
+
+            let (subproc, logfile) = Self::spawn(cmd.build(), resource_data)?;
+
+            match Self::get_bound_port(File::open(&logfile)?, &subproc, TransportProtocol::Udp) {
+                Ok(bound_port) if bound_port == port => {
+                    return Ok(Self {
+                        subproc: Arc::new(subproc),
+                        closed: Arc::new(AtomicBool::new(false)),
+                        port,
+                    });
+                }
+                Ok(bound_port) => {
+                    let _ = subproc.kill();
+                    last_error = Some(Error::new(
+                        ErrorKind::Other,
+                        format!("Shadowsocks bound to unexpected port {}", bound_port),
+                    ));
+                }
+                Err(error) => {
+                    let _ = subproc.kill();
+                    log::warn!("Failed to start Shadowsocks UDP relay on port {}", port);
+                    last_error = Some(error);
+                }
+            }
+        }
+        Err(last_error.expect("No attempts were made to start the UDP relay"))
+    }
+
+    fn binary(resource_data: &ProxyResourceData) -> OsString {
+        resource_data
+            .resource_dir
+            .join(SHADOWSOCKS_BIN_FILENAME)
+            .into_os_string()
+    }
+
+    fn spawn(
+        mut cmd: duct::Expression,
+        resource_data: &ProxyResourceData,
+    ) -> Result<(duct::Handle, PathBuf)> {
         let log_dir: PathBuf = if let Some(ref log_dir) = resource_data.log_dir {
             log_dir.clone()
         } else {
@@ -173,28 +283,23 @@ impl ShadowsocksProxyMonitor {
                 .iter()
                 .map(|pid| *pid as i32)
                 .collect::<Vec<_>>();
-            excluded_pids.add_list(&i32_pids).map_err(|error| {
-                Error::new(
+            if let Err(error) = excluded_pids.add_list(&i32_pids) {
+                let _ = subproc.kill();
+                return Err(Error::new(
                     ErrorKind::Other,
                     error.display_chain_with_msg("Failed to exclude Shadowsocks process"),
-                )
-            })?;
-        }
-
-        match Self::get_bound_port(File::open(&logfile)?, &subproc) {
-            Ok(port) => Ok(Self {
-                subproc: Arc::new(subproc),
-                closed: Arc::new(AtomicBool::new(false)),
-                port,
-            }),
-            Err(err) => {
-                let _ = subproc.kill();
-                Err(err)
+                ));
             }
         }
+
+        Ok((subproc, logfile))
     }
 
-    fn get_bound_port(logfile: File, subproc: &duct::Handle) -> Result<u16> {
+    fn get_bound_port(
+        logfile: File,
+        subproc: &duct::Handle,
+        protocol: TransportProtocol,
+    ) -> Result<u16> {
         let mut buffered_reader = std::io::BufReader::new(logfile);
 
         for _tries in 0..5 {
@@ -207,7 +312,7 @@ impl ShadowsocksProxyMonitor {
                             break;
                         }
                         // `read_line` includes the line break in the returned line.
-                        if let Ok(port) = Self::parse_port(line.trim_end()) {
+                        if let Ok(port) = Self::parse_port(line.trim_end(), protocol) {
                             return Ok(port);
                         }
                     }
@@ -228,9 +333,16 @@ impl ShadowsocksProxyMonitor {
         ))
     }
 
-    fn parse_port(logline: &str) -> Result<u16> {
+    fn parse_port(logline: &str, protocol: TransportProtocol) -> Result<u16> {
         // TODO: Compile once and reuse.
-        let re = Regex::new(r"(?:TCP listening on \d+\.\d+\.\d+\.\d+:)(\d+$)").unwrap();
+        let re = match protocol {
+            TransportProtocol::Tcp => {
+                Regex::new(r"(?:TCP listening on \d+\.\d+\.\d+\.\d+:)(\d+$)").unwrap()
+            }
+            TransportProtocol::Udp => {
+                Regex::new(r"(?:UDP (?:tunnel )?listening on \d+\.\d+\.\d+\.\d+:)(\d+$)").unwrap()
+            }
+        };
 
         if let Some(captures) = re.captures(logline) {
             return Ok(captures[1].parse().map_err(|_| {
@@ -8,6 +8,8 @@ use std::{
 #[cfg(not(target_os = "android"))]
 use talpid_types::net::openvpn as openvpn_types;
 use talpid_types::net::{wireguard as wireguard_types, TunnelParameters};
+#[cfg(target_os = "linux")]
+use talpid_types::ErrorExt;
 
 #[cfg(target_os = "android")]
 pub use self::tun_provider::TunConfig;
@@ -70,6 +72,9 @@ pub enum TunnelEvent {
     AuthFailed(Option<String>),
     /// Sent when the tunnel interface has been created, before routes are set up.
     InterfaceUp(TunnelMetadata),
+    /// Sent when the interface of an entry tunnel has been created. Traffic to the tunnel
+    /// endpoint is routed through it.
+    EntryInterfaceUp(TunnelMetadata),
     /// Sent when the tunnel comes up and is ready for traffic.
     Up(TunnelMetadata),
     /// Sent when the tunnel goes down.
@@ -119,6 +124,26 @@ impl TunnelMonitor {
         let log_file = Self::prepare_tunnel_log_file(&tunnel_parameters, log_dir)?;
 
         match tunnel_parameters {
+            #[cfg(target_os = "linux")]
+            TunnelParameters::OpenVpn(config) if config.wireguard_entry.is_some() => {
+                Self::start_openvpn_tunnel_via_wireguard(
+                    runtime,
+                    &config,
+                    log_file,
+                    log_dir
+                        .as_ref()
+                        .map(|log_dir| log_dir.join(WIREGUARD_LOG_FILENAME)),
+                    resource_dir,
+                    on_event,
+                    tun_provider,
+                    route_manager,
+                )
+            }
+            // Routes to the OpenVPN server are managed by OpenVPN itself on other platforms
+            #[cfg(any(target_os = "macos", windows))]
+            TunnelParameters::OpenVpn(config) if config.wireguard_entry.is_some() => {
+                Err(Error::UnsupportedPlatform)
+            }
             #[cfg(not(target_os = "android"))]
             TunnelParameters::OpenVpn(config) => {
                 Self::start_openvpn_tunnel(&config, log_file, resource_dir, on_event, route_manager)
@@ -153,6 +178,7 @@ impl TunnelMonitor {
                     "openvpn.exe"
                 }
             }
+            TunnelParameters::Wireguard(params) if params.proxy.is_some() => "sslocal.exe",
             _ => return std::env::current_exe().unwrap(),
         };
         resource_dir.join(process_string)
@@ -178,6 +204,8 @@ impl TunnelMonitor {
         let monitor = wireguard::WireguardMonitor::start(
             runtime,
             config,
+            params.proxy.as_ref(),
+            params.obfuscation.as_ref(),
             log.as_ref().map(|p| p.as_path()),
             resource_dir,
             on_event,
@@ -210,6 +238,69 @@ impl TunnelMonitor {
         })
     }
 
+    /// Starts an OpenVPN tunnel whose traffic is routed through a WireGuard entry tunnel. Only
+    /// the interface events of the entry tunnel are passed on, as `EntryInterfaceUp`.
+    #[cfg(target_os = "linux")]
+    fn start_openvpn_tunnel_via_wireguard<L>(
+        runtime: tokio::runtime::Handle,
+        config: &openvpn_types::TunnelParameters,
+        log: Option<PathBuf>,
+        entry_log: Option<PathBuf>,
+        resource_dir: &Path,
+        on_event: L,
+        tun_provider: &mut TunProvider,
+        route_manager: &mut RouteManager,
+    ) -> Result<Self>
+    where
+        L: (Fn(TunnelEvent) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>)
+            + Send
+            + Sync
+            + Clone
+            + 'static,
+    {
+        let entry_params = config
+            .wireguard_entry
+            .as_ref()
+            .expect("Missing WireGuard entry parameters");
+        let on_entry_event = on_event.clone();
+        let on_entry_event =
+            move |event| -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> {
+                match event {
+                    TunnelEvent::InterfaceUp(metadata) => {
+                        (on_entry_event)(TunnelEvent::EntryInterfaceUp(metadata))
+                    }
+                    _ => Box::pin(async {}),
+                }
+            };
+        let entry = wireguard::WireguardMonitor::start(
+            runtime,
+            wireguard::config::Config::from_parameters(entry_params)?,
+            entry_params.proxy.as_ref(),
+            entry_params.obfuscation.as_ref(),
+            entry_log.as_ref().map(|p| p.as_path()),
+            resource_dir,
+            on_entry_event,
+            tun_provider,
+            route_manager,
+        )?;
+
+        match openvpn::OpenVpnMonitor::start(on_event, config, log, resource_dir, route_manager) {
+            Ok(openvpn) => Ok(TunnelMonitor {
+                monitor: InternalTunnelMonitor::OpenVpnViaWireguard { openvpn, entry },
+            }),
+            Err(error) => {
+                entry.close_handle().close();
+                if let Err(error) = entry.wait() {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to stop WireGuard entry tunnel")
+                    );
+                }
+                Err(error.into())
+            }
+        }
+    }
+
     fn ensure_ipv6_can_be_used_if_enabled(tunnel_parameters: &TunnelParameters) -> Result<()> {
         let options = tunnel_parameters.get_generic_options();
         if options.enable_ipv6 {
@@ -281,6 +372,9 @@ pub enum CloseHandle {
     OpenVpn(openvpn::OpenVpnCloseHandle),
     /// Wireguard close handle
     Wireguard(wireguard::CloseHandle),
+    /// Close handles for an OpenVPN tunnel and the WireGuard entry tunnel it is routed through
+    #[cfg(target_os = "linux")]
+    OpenVpnViaWireguard(openvpn::OpenVpnCloseHandle, wireguard::CloseHandle),
 }
 
 impl CloseHandle {
@@ -293,6 +387,12 @@ impl CloseHandle {
                 handle.close();
                 Ok(())
             }
+            #[cfg(target_os = "linux")]
+            CloseHandle::OpenVpnViaWireguard(openvpn_handle, mut entry_handle) => {
+                let result = openvpn_handle.close();
+                entry_handle.close();
+                result
+            }
         }
     }
 }
@@ -301,6 +401,11 @@ enum InternalTunnelMonitor {
     #[cfg(not(target_os = "android"))]
     OpenVpn(openvpn::OpenVpnMonitor),
     Wireguard(wireguard::WireguardMonitor),
+    #[cfg(target_os = "linux")]
+    OpenVpnViaWireguard {
+        openvpn: openvpn::OpenVpnMonitor,
+        entry: wireguard::WireguardMonitor,
+    },
 }
 
 impl InternalTunnelMonitor {
@@ -309,6 +414,10 @@ impl InternalTunnelMonitor {
             #[cfg(not(target_os = "android"))]
             InternalTunnelMonitor::OpenVpn(tun) => CloseHandle::OpenVpn(tun.close_handle()),
             InternalTunnelMonitor::Wireguard(tun) => CloseHandle::Wireguard(tun.close_handle()),
+            #[cfg(target_os = "linux")]
+            InternalTunnelMonitor::OpenVpnViaWireguard { openvpn, entry } => {
+                CloseHandle::OpenVpnViaWireguard(openvpn.close_handle(), entry.close_handle())
+            }
         }
     }
 
@@ -317,6 +426,30 @@ impl InternalTunnelMonitor {
             #[cfg(not(target_os = "android"))]
             InternalTunnelMonitor::OpenVpn(tun) => tun.wait()?,
             InternalTunnelMonitor::Wireguard(tun) => tun.wait()?,
+            #[cfg(target_os = "linux")]
+            InternalTunnelMonitor::OpenVpnViaWireguard { openvpn, entry } => {
+                // The OpenVPN tunnel cannot work without the entry tunnel, so it is closed if the
+                // entry tunnel goes down
+                let openvpn_close_handle = openvpn.close_handle();
+                let mut entry_close_handle = entry.close_handle();
+                let entry_thread = std::thread::spawn(move || {
+                    let result = entry.wait();
+                    if let Err(error) = openvpn_close_handle.close() {
+                        log::error!(
+                            "{}",
+                            error.display_chain_with_msg("Failed to close OpenVPN tunnel")
+                        );
+                    }
+                    result
+                });
+                let result = openvpn.wait();
+                entry_close_handle.close();
+                let entry_result = entry_thread
+                    .join()
+                    .expect("WireGuard entry tunnel thread panicked");
+                result?;
+                entry_result?;
+            }
         }
 
         Ok(())
@@ -342,6 +342,7 @@ impl OpenVpnMonitor<OpenVpnCommand> {
                 user_pass_file_path: user_pass_file_path.clone(),
                 proxy_auth_file_path: proxy_auth_file_path.clone(),
                 abort_server_tx: event_server_abort_tx,
+                statistics: Arc::new(Mutex::new(event_server::Statistics::default())),
                 #[cfg(target_os = "linux")]
                 route_manager_handle,
                 #[cfg(target_os = "linux")]
@@ -612,13 +613,16 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
     fn create_proxy_auth_file(
         proxy_settings: &Option<openvpn::ProxySettings>,
     ) -> std::result::Result<Option<mktemp::TempFile>, io::Error> {
-        if let Some(openvpn::ProxySettings::Remote(ref remote_proxy)) = proxy_settings {
-            if let Some(ref proxy_auth) = remote_proxy.auth {
-                return Ok(Some(Self::create_credentials_file(
-                    &proxy_auth.username,
-                    &proxy_auth.password,
-                )?));
-            }
+        let proxy_auth = match proxy_settings {
+            Some(openvpn::ProxySettings::Remote(ref remote_proxy)) => remote_proxy.auth.as_ref(),
+            Some(openvpn::ProxySettings::HttpConnect(ref http_proxy)) => http_proxy.auth.as_ref(),
+            _ => None,
+        };
+        if let Some(proxy_auth) = proxy_auth {
+            return Ok(Some(Self::create_credentials_file(
+                &proxy_auth.username,
+                &proxy_auth.password,
+            )?));
         }
         Ok(None)
     }
@@ -701,6 +705,11 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
         if let Some(proxy) = proxy_monitor {
             cmd.proxy_port(proxy.port());
         }
+        // Traffic to the server must be routed through the entry tunnel
+        #[cfg(target_os = "linux")]
+        if params.wireguard_entry.is_some() {
+            cmd.fwmark(None);
+        }
 
         Ok(cmd)
     }
@@ -797,7 +806,7 @@ impl OpenVpnBuilder for OpenVpnCommand {
     }
 
     fn start(&self) -> io::Result<OpenVpnProcHandle> {
-        OpenVpnProcHandle::new(self.build())
+        OpenVpnProcHandle::new(self.build(), self.log_path())
     }
 }
 
@@ -835,7 +844,7 @@ mod event_server {
     }
     pub use proto::{
         openvpn_event_proxy_server::{OpenvpnEventProxy, OpenvpnEventProxyServer},
-        EventDetails,
+        ByteCountDetails, EventDetails, TlsVerifyDetails,
     };
 
     #[derive(err_derive::Error, Debug)]
@@ -849,6 +858,33 @@ mod event_server {
         TonicError(#[error(source)] tonic::transport::Error),
     }
 
+    /// Traffic and TLS session statistics reported by the OpenVPN plugin. They are logged when
+    /// the tunnel goes down, which is also when the byte counts are reported.
+    #[derive(Default, Debug, PartialEq, Clone, Copy)]
+    pub struct Statistics {
+        /// Bytes sent through the tunnel.
+        pub tx_bytes: u64,
+        /// Bytes received through the tunnel.
+        pub rx_bytes: u64,
+        /// Number of completed TLS handshakes, including the initial one.
+        pub tls_handshakes: u32,
+    }
+
+    impl Statistics {
+        /// Returns the number of TLS renegotiations since the tunnel was established.
+        pub fn renegotiations(&self) -> u32 {
+            self.tls_handshakes.saturating_sub(1)
+        }
+
+        /// Records a verified certificate. A handshake is complete once the peer certificate at
+        /// depth 0 has been verified.
+        pub fn record_tls_verify(&mut self, certificate_depth: u32) {
+            if certificate_depth == 0 {
+                self.tls_handshakes += 1;
+            }
+        }
+    }
+
     /// Implements a gRPC service used to process events sent to by OpenVPN.
     pub struct OpenvpnEventProxyImpl<
         L: (Fn(
@@ -862,6 +898,7 @@ mod event_server {
         pub user_pass_file_path: super::PathBuf,
         pub proxy_auth_file_path: Option<super::PathBuf>,
         pub abort_server_tx: triggered::Trigger,
+        pub statistics: std::sync::Arc<std::sync::Mutex<Statistics>>,
         #[cfg(target_os = "linux")]
         pub route_manager_handle: super::routing::RouteManagerHandle,
         #[cfg(target_os = "linux")]
@@ -1045,9 +1082,44 @@ mod event_server {
             &self,
             _request: Request<EventDetails>,
         ) -> std::result::Result<Response<()>, tonic::Status> {
+            let statistics = *self.statistics.lock().expect("statistics lock poisoned");
+            log::debug!(
+                "OpenVPN tunnel going down after sending {} bytes, receiving {} bytes and {} \
+                 TLS renegotiations",
+                statistics.tx_bytes,
+                statistics.rx_bytes,
+                statistics.renegotiations()
+            );
             (self.on_event)(super::TunnelEvent::Down).await;
             Ok(Response::new(()))
         }
+
+        async fn tls_verify(
+            &self,
+            request: Request<TlsVerifyDetails>,
+        ) -> std::result::Result<Response<()>, tonic::Status> {
+            let certificate_depth = request.into_inner().certificate_depth;
+            let mut statistics = self.statistics.lock().expect("statistics lock poisoned");
+            statistics.record_tls_verify(certificate_depth);
+            if certificate_depth == 0 && statistics.renegotiations() > 0 {
+                log::debug!(
+                    "TLS session renegotiated ({} renegotiations)",
+                    statistics.renegotiations()
+                );
+            }
+            Ok(Response::new(()))
+        }
+
+        async fn byte_count(
+            &self,
+            request: Request<ByteCountDetails>,
+        ) -> std::result::Result<Response<()>, tonic::Status> {
+            let details = request.into_inner();
+            let mut statistics = self.statistics.lock().expect("statistics lock poisoned");
+            statistics.tx_bytes = details.bytes_sent;
+            statistics.rx_bytes = details.bytes_received;
+            Ok(Response::new(()))
+        }
     }
 
     pub async fn start<L>(
@@ -1167,6 +1239,18 @@ mod tests {
         ) -> std::result::Result<tonic::Response<()>, tonic::Status> {
             Ok(tonic::Response::new(()))
         }
+        async fn tls_verify(
+            &self,
+            _request: tonic::Request<event_server::TlsVerifyDetails>,
+        ) -> std::result::Result<tonic::Response<()>, tonic::Status> {
+            Ok(tonic::Response::new(()))
+        }
+        async fn byte_count(
+            &self,
+            _request: tonic::Request<event_server::ByteCountDetails>,
+        ) -> std::result::Result<tonic::Response<()>, tonic::Status> {
+            Ok(tonic::Response::new(()))
+        }
     }
 
     #[derive(Debug, Default, Clone)]
@@ -1352,4 +1436,20 @@ mod tests {
             _ => panic!("Wrong error"),
         }
     }
+
+    #[test]
+    fn counts_tls_renegotiations() {
+        let mut statistics = event_server::Statistics::default();
+        assert_eq!(statistics.renegotiations(), 0);
+
+        statistics.record_tls_verify(1);
+        statistics.record_tls_verify(0);
+        assert_eq!(statistics.tls_handshakes, 1);
+        assert_eq!(statistics.renegotiations(), 0);
+
+        statistics.record_tls_verify(1);
+        statistics.record_tls_verify(0);
+        assert_eq!(statistics.tls_handshakes, 2);
+        assert_eq!(statistics.renegotiations(), 1);
+    }
 }
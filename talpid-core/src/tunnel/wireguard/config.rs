@@ -29,6 +29,8 @@ pub struct Config {
 }
 
 const DEFAULT_MTU: u16 = 1380;
+/// Bytes added to each packet by a Shadowsocks UDP relay: salt, AEAD tag and target address.
+const SHADOWSOCKS_OVERHEAD: u16 = 32 + 16 + 19;
 
 /// Configuration errors
 #[derive(err_derive::Error, Debug)]
@@ -54,13 +56,17 @@ impl Config {
         if let Some(exit_peer) = &params.connection.exit_peer {
             peers.push(exit_peer.clone());
         }
-        Self::new(
+        let mut config = Self::new(
             tunnel,
             peers,
             &params.connection,
             &params.options,
             &params.generic_options,
-        )
+        )?;
+        if params.proxy.is_some() && params.options.mtu.is_none() {
+            config.mtu -= SHADOWSOCKS_OVERHEAD;
+        }
+        Ok(config)
     }
 
     /// Constructs a new Config struct
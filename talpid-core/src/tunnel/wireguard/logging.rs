@@ -1,8 +1,9 @@
+use crate::logging::{RotatingLogFile, ROTATION_POLICY};
 use parking_lot::Mutex;
 use std::{collections::HashMap, fmt, fs, io::Write, path::Path};
 
 lazy_static::lazy_static! {
-    static ref LOG_MUTEX: Mutex<HashMap<u32, fs::File>> = Mutex::new(HashMap::new());
+    static ref LOG_MUTEX: Mutex<HashMap<u32, Box<dyn Write + Send>>> = Mutex::new(HashMap::new());
 }
 
 static mut LOG_CONTEXT_NEXT_ORDINAL: u32 = 0;
@@ -35,8 +36,13 @@ static NULL_DEVICE: &str = "NUL";
 #[cfg(not(target_os = "windows"))]
 static NULL_DEVICE: &str = "/dev/null";
 
-fn create_log_file(log_path: Option<&Path>) -> Result<fs::File, Error> {
-    fs::File::create(log_path.unwrap_or(NULL_DEVICE.as_ref())).map_err(Error::PrepareLogFileError)
+fn create_log_file(log_path: Option<&Path>) -> Result<Box<dyn Write + Send>, Error> {
+    match log_path {
+        Some(log_path) => RotatingLogFile::open(log_path, *ROTATION_POLICY)
+            .map(|file| Box::new(file) as Box<dyn Write + Send>),
+        None => fs::File::create(NULL_DEVICE).map(|file| Box::new(file) as Box<dyn Write + Send>),
+    }
+    .map_err(Error::PrepareLogFileError)
 }
 
 pub fn clean_up_logging(ordinal: u32) {
@@ -77,7 +83,7 @@ pub fn log(context: u32, level: LogLevel, tag: &str, msg: &str) {
     }
 }
 
-fn log_inner(logfile: &mut fs::File, level: LogLevel, tag: &str, msg: &str) {
+fn log_inner(logfile: &mut dyn Write, level: LogLevel, tag: &str, msg: &str) {
     let _ = write!(
         logfile,
         "{}[{}][{}] {}",
@@ -3,7 +3,6 @@ use self::config::Config;
 use super::tun_provider;
 use super::{tun_provider::TunProvider, TunnelEvent, TunnelMetadata};
 use crate::routing::{self, RequiredRoute};
-use futures::future::abortable;
 #[cfg(target_os = "linux")]
 use lazy_static::lazy_static;
 #[cfg(target_os = "linux")]
@@ -11,17 +10,23 @@ use std::env;
 #[cfg(windows)]
 use std::io;
 use std::{
-    net::{IpAddr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     path::Path,
     sync::{mpsc, Arc, Mutex},
 };
-use talpid_types::{net::TransportProtocol, ErrorExt};
-use udp_over_tcp::{TcpOptions, Udp2Tcp};
+use talpid_types::{
+    net::{
+        obfuscation::ObfuscatorConfig, openvpn::ShadowsocksProxySettings, wireguard,
+        TransportProtocol,
+    },
+    ErrorExt,
+};
 
 /// WireGuard config data-types
 pub mod config;
 mod connectivity_check;
 mod logging;
+pub mod obfuscation;
 mod stats;
 mod wireguard_go;
 #[cfg(target_os = "linux")]
@@ -49,13 +54,18 @@ pub enum Error {
     #[error(display = "Tunnel failed")]
     TunnelError(#[error(source)] TunnelError),
 
-    /// Failed to set up Udp2Tcp
-    #[error(display = "Failed to start UDP-over-TCP proxy")]
-    Udp2TcpError(#[error(source)] udp_over_tcp::udp2tcp::ConnectError),
+    /// Failed to start the obfuscator
+    #[error(display = "Failed to start obfuscator")]
+    ObfuscatorError(#[error(source)] obfuscation::Error),
+
+    /// The entry peer uses TCP, but no obfuscator was given
+    #[error(display = "No obfuscator was given for a WireGuard peer that uses TCP")]
+    MissingObfuscator,
 
-    /// Failed to obtain the local UDP socket address
-    #[error(display = "Failed obtain local address for the UDP socket in Udp2Tcp")]
-    GetLocalUdpAddress(#[error(source)] std::io::Error),
+    /// Failed to start the Shadowsocks UDP relay
+    #[cfg(not(target_os = "android"))]
+    #[error(display = "Failed to start Shadowsocks UDP relay")]
+    ShadowsocksRelayError(#[error(source)] std::io::Error),
 
     /// Failed to set up connectivity monitor
     #[error(display = "Connectivity monitor failed")]
@@ -90,7 +100,9 @@ pub struct WireguardMonitor {
     #[cfg(target_os = "windows")]
     stop_setup_tx: Option<futures::channel::oneshot::Sender<()>>,
     pinger_stop_sender: mpsc::Sender<()>,
-    _tcp_proxies: Vec<TcpProxy>,
+    _obfuscator: Option<Box<dyn obfuscation::Obfuscator>>,
+    #[cfg(not(target_os = "android"))]
+    _shadowsocks_relay: Option<ShadowsocksRelay>,
 }
 
 #[cfg(target_os = "linux")]
@@ -105,40 +117,45 @@ lazy_static! {
         .unwrap_or(false);
 }
 
-struct TcpProxy {
+/// Relays the UDP traffic of a peer through a Shadowsocks bridge.
+#[cfg(not(target_os = "android"))]
+struct ShadowsocksRelay {
     local_addr: SocketAddr,
-    abort_handle: futures::future::AbortHandle,
+    close_handle: Option<Box<dyn crate::proxy::ProxyMonitorCloseHandle>>,
 }
 
-impl TcpProxy {
-    pub fn new(runtime: &tokio::runtime::Handle, endpoint: SocketAddr) -> Result<Self> {
-        let listen_addr = if endpoint.is_ipv4() {
-            SocketAddr::new("127.0.0.1".parse().unwrap(), 0)
-        } else {
-            SocketAddr::new("::1".parse().unwrap(), 0)
+#[cfg(not(target_os = "android"))]
+impl ShadowsocksRelay {
+    pub fn new(
+        settings: &ShadowsocksProxySettings,
+        endpoint: SocketAddr,
+        resource_dir: &Path,
+        log_path: Option<&Path>,
+    ) -> Result<Self> {
+        let resource_data = crate::proxy::ProxyResourceData {
+            resource_dir: resource_dir.to_path_buf(),
+            log_dir: log_path.and_then(Path::parent).map(Path::to_path_buf),
         };
-
-        let udp2tcp = runtime
-            .block_on(Udp2Tcp::new(
-                listen_addr,
-                endpoint,
-                TcpOptions {
-                    #[cfg(target_os = "linux")]
-                    fwmark: Some(crate::linux::TUNNEL_FW_MARK),
-                    ..TcpOptions::default()
-                },
-            ))
-            .map_err(Error::Udp2TcpError)?;
-        let local_addr = udp2tcp
-            .local_udp_addr()
-            .map_err(Error::GetLocalUdpAddress)?;
-
-        let (udp2tcp_future, abort_handle) = abortable(udp2tcp.run());
-        runtime.spawn(udp2tcp_future);
+        let mut monitor = crate::proxy::start_udp_relay(settings, endpoint, &resource_data)
+            .map_err(Error::ShadowsocksRelayError)?;
+        let local_addr = SocketAddr::new("127.0.0.1".parse().unwrap(), monitor.port());
+        let close_handle = monitor.close_handle();
+        std::thread::spawn(move || match monitor.wait() {
+            Ok(crate::proxy::WaitResult::UnexpectedExit(reason)) => {
+                log::error!("Shadowsocks UDP relay exited unexpectedly: {}", reason);
+            }
+            Ok(crate::proxy::WaitResult::ProperShutdown) => (),
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to wait for Shadowsocks UDP relay")
+                );
+            }
+        });
 
         Ok(Self {
             local_addr,
-            abort_handle,
+            close_handle: Some(close_handle),
         })
     }
 
@@ -147,9 +164,17 @@ impl TcpProxy {
     }
 }
 
-impl Drop for TcpProxy {
+#[cfg(not(target_os = "android"))]
+impl Drop for ShadowsocksRelay {
     fn drop(&mut self) {
-        self.abort_handle.abort();
+        if let Some(close_handle) = self.close_handle.take() {
+            if let Err(error) = close_handle.close() {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to stop Shadowsocks UDP relay")
+                );
+            }
+        }
     }
 }
 
@@ -164,24 +189,66 @@ impl WireguardMonitor {
     >(
         runtime: tokio::runtime::Handle,
         mut config: Config,
+        proxy: Option<&ShadowsocksProxySettings>,
+        obfuscation: Option<&ObfuscatorConfig>,
         log_path: Option<&Path>,
         resource_dir: &Path,
         on_event: F,
         tun_provider: &mut TunProvider,
         route_manager: &mut routing::RouteManager,
     ) -> Result<WireguardMonitor> {
-        let mut tcp_proxies = vec![];
         let mut endpoint_addrs = vec![];
 
-        for peer in &mut config.peers {
-            endpoint_addrs.push(peer.endpoint.ip());
-            if peer.protocol == TransportProtocol::Tcp {
-                let udp2tcp = TcpProxy::new(&runtime, peer.endpoint.clone())?;
+        // Traffic to the entry peer is relayed through the bridge, if there is one
+        #[cfg(not(target_os = "android"))]
+        let shadowsocks_relay = match (proxy, config.peers.first_mut()) {
+            (Some(proxy), Some(entry_peer)) => {
+                let relay =
+                    ShadowsocksRelay::new(proxy, entry_peer.endpoint, resource_dir, log_path)?;
+                endpoint_addrs.push(proxy.peer.ip());
+                entry_peer.endpoint = relay.local_udp_addr();
+                Some(relay)
+            }
+            _ => None,
+        };
+        #[cfg(not(target_os = "android"))]
+        let bridged = shadowsocks_relay.is_some();
+        #[cfg(target_os = "android")]
+        let bridged = {
+            if proxy.is_some() {
+                log::warn!("Ignoring Shadowsocks bridge, which is not supported on Android");
+            }
+            false
+        };
 
-                // Replace remote peer with proxy
-                peer.endpoint = udp2tcp.local_udp_addr();
-                tcp_proxies.push(udp2tcp);
+        // Otherwise, it is relayed through the obfuscator, if there is one
+        let obfuscator = match (obfuscation, config.peers.first_mut()) {
+            (Some(_), Some(_)) if bridged => {
+                log::warn!("Ignoring obfuscator, since a bridge is used");
+                None
             }
+            (Some(obfuscation), Some(entry_peer)) => {
+                let obfuscator = obfuscation::start_obfuscator(&runtime, obfuscation)
+                    .map_err(Error::ObfuscatorError)?;
+                endpoint_addrs.push(obfuscation.get_endpoint().address.ip());
+                entry_peer.endpoint = obfuscator.local_udp_addr();
+                Some(obfuscator)
+            }
+            (None, Some(entry_peer))
+                if !bridged && entry_peer.protocol == TransportProtocol::Tcp =>
+            {
+                return Err(Error::MissingObfuscator);
+            }
+            _ => None,
+        };
+        let relayed_peers = if bridged || obfuscator.is_some() {
+            1
+        } else {
+            0
+        };
+
+        for peer in config.peers.iter().skip(relayed_peers) {
+            endpoint_addrs.push(peer.endpoint.ip());
         }
 
         let tunnel =
@@ -204,13 +271,15 @@ impl WireguardMonitor {
             #[cfg(target_os = "windows")]
             stop_setup_tx: Some(stop_setup_tx),
             pinger_stop_sender: pinger_tx,
-            _tcp_proxies: tcp_proxies,
+            _obfuscator: obfuscator,
+            #[cfg(not(target_os = "android"))]
+            _shadowsocks_relay: shadowsocks_relay,
         };
 
-        let gateway = config.ipv4_gateway;
+        let ping_target = Self::ping_target(&config.peers, config.ipv4_gateway);
         let close_sender = monitor.close_msg_sender.clone();
         let mut connectivity_monitor = connectivity_check::ConnectivityMonitor::new(
-            gateway,
+            ping_target,
             #[cfg(not(target_os = "windows"))]
             iface_name.clone(),
             Arc::downgrade(&monitor.tunnel),
@@ -429,6 +498,25 @@ impl WireguardMonitor {
         }
     }
 
+    /// Returns the address to ping to check the connectivity of the tunnel. This is the gateway,
+    /// unless the peers only route traffic to specific hosts, as the entry peer of an OpenVPN
+    /// tunnel does. One of those hosts is pinged instead.
+    fn ping_target(peers: &[wireguard::PeerConfig], gateway: Ipv4Addr) -> Ipv4Addr {
+        let mut allowed_ips = peers.iter().flat_map(|peer| peer.allowed_ips.iter());
+        if allowed_ips
+            .clone()
+            .any(|allowed_ip| allowed_ip.contains(IpAddr::V4(gateway)))
+        {
+            return gateway;
+        }
+        allowed_ips
+            .find_map(|allowed_ip| match allowed_ip {
+                ipnetwork::IpNetwork::V4(network) => Some(network.ip()),
+                ipnetwork::IpNetwork::V6(_) => None,
+            })
+            .unwrap_or(gateway)
+    }
+
     fn get_tunnel_destinations(config: &Config) -> impl Iterator<Item = ipnetwork::IpNetwork> + '_ {
         let routes = config
             .peers
@@ -639,3 +727,35 @@ pub enum TunnelError {
     #[error(display = "Failed to set up logging")]
     LoggingError(#[error(source)] logging::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn peer(allowed_ips: &[&str]) -> wireguard::PeerConfig {
+        wireguard::PeerConfig {
+            public_key: wireguard::PrivateKey::new_from_random().public_key(),
+            allowed_ips: allowed_ips.iter().map(|ip| ip.parse().unwrap()).collect(),
+            endpoint: "1.2.3.4:51820".parse().unwrap(),
+            protocol: TransportProtocol::Udp,
+        }
+    }
+
+    #[test]
+    fn test_ping_target() {
+        let gateway = Ipv4Addr::new(10, 64, 0, 1);
+        assert_eq!(
+            WireguardMonitor::ping_target(&[peer(&["0.0.0.0/0", "::/0"])], gateway),
+            gateway
+        );
+        // The entry peer of an OpenVPN tunnel only routes traffic to the OpenVPN server
+        assert_eq!(
+            WireguardMonitor::ping_target(&[peer(&["2001:db8::1/128", "5.6.7.8/32"])], gateway),
+            Ipv4Addr::new(5, 6, 7, 8)
+        );
+        assert_eq!(
+            WireguardMonitor::ping_target(&[peer(&["2001:db8::1/128"])], gateway),
+            gateway
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use super::Error;
+use futures::future::{abortable, AbortHandle};
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+use talpid_types::net::{http_connect::http_connect, openvpn::HttpConnectProxySettings};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+
+/// Accepts TCP connections on localhost and forwards them to a remote address through an HTTP
+/// proxy, using the `CONNECT` method.
+pub struct HttpConnectForwarder {
+    local_addr: SocketAddr,
+    abort_handle: AbortHandle,
+}
+
+impl HttpConnectForwarder {
+    /// Starts listening on localhost. Connections are forwarded to `target` through `proxy`.
+    pub fn start(
+        runtime: &tokio::runtime::Handle,
+        proxy: HttpConnectProxySettings,
+        target: SocketAddr,
+    ) -> Result<Self, Error> {
+        let listen_addr = if target.is_ipv4() {
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)
+        } else {
+            SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0)
+        };
+        let listener = runtime
+            .block_on(TcpListener::bind(listen_addr))
+            .map_err(Error::StartHttpProxyForwarder)?;
+        let local_addr = listener
+            .local_addr()
+            .map_err(Error::StartHttpProxyForwarder)?;
+
+        let (forwarder, abort_handle) = abortable(Self::run(listener, proxy, target));
+        runtime.spawn(forwarder);
+
+        Ok(Self {
+            local_addr,
+            abort_handle,
+        })
+    }
+
+    /// Returns the local address that connections should be made to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    async fn run(listener: TcpListener, proxy: HttpConnectProxySettings, target: SocketAddr) {
+        loop {
+            let (mut client, _) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(error) => {
+                    log::error!(
+                        "Failed to accept connection to HTTP proxy forwarder: {}",
+                        error
+                    );
+                    return;
+                }
+            };
+            let proxy = proxy.clone();
+            tokio::spawn(async move {
+                let mut upstream = match connect(&proxy, target).await {
+                    Ok(upstream) => upstream,
+                    Err(error) => {
+                        log::error!(
+                            "Failed to connect to {} through HTTP proxy at {}: {}",
+                            target,
+                            proxy.address,
+                            error
+                        );
+                        return;
+                    }
+                };
+                let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+            });
+        }
+    }
+}
+
+impl Drop for HttpConnectForwarder {
+    fn drop(&mut self) {
+        self.abort_handle.abort();
+    }
+}
+
+/// Opens a connection to `target` through the HTTP proxy.
+async fn connect(proxy: &HttpConnectProxySettings, target: SocketAddr) -> io::Result<TcpStream> {
+    let socket = match proxy.address {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    #[cfg(target_os = "linux")]
+    socket2::SockRef::from(&socket).set_mark(crate::linux::TUNNEL_FW_MARK)?;
+
+    let mut stream = socket.connect(proxy.address).await?;
+    http_connect(&mut stream, target, proxy.auth.as_ref()).await?;
+    Ok(stream)
+}
@@ -0,0 +1,76 @@
+//! Obfuscators that WireGuard traffic to a peer can be relayed through. An obfuscator listens on
+//! a local UDP socket, which the WireGuard peer endpoint is pointed at, and forwards the
+//! datagrams to the remote obfuscation server in some other form.
+
+use std::net::SocketAddr;
+use talpid_types::net::obfuscation::ObfuscatorConfig;
+
+mod http_connect;
+mod udp2tcp;
+
+pub use self::udp2tcp::Udp2TcpObfuscator;
+
+/// Errors that can happen when starting an obfuscator.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to connect to the UDP-over-TCP server
+    #[error(display = "Failed to start UDP-over-TCP obfuscator")]
+    Udp2TcpError(#[error(source)] udp_over_tcp::udp2tcp::ConnectError),
+
+    /// Failed to obtain the local UDP socket address
+    #[error(display = "Failed to obtain local address for the UDP socket of the obfuscator")]
+    GetLocalUdpAddress(#[error(source)] std::io::Error),
+
+    /// Failed to start forwarding connections through an HTTP proxy
+    #[error(display = "Failed to start HTTP proxy forwarder")]
+    StartHttpProxyForwarder(#[error(source)] std::io::Error),
+
+    /// The obfuscator has already been started
+    #[error(display = "The obfuscator has already been started")]
+    AlreadyStarted,
+}
+
+/// An obfuscation protocol implementation.
+pub trait Obfuscator: Send {
+    /// Starts relaying traffic between the local UDP socket and the remote server.
+    fn start(&mut self, runtime: &tokio::runtime::Handle) -> Result<(), Error>;
+
+    /// Returns the address of the local UDP socket that WireGuard should send traffic to.
+    fn local_udp_addr(&self) -> SocketAddr;
+
+    /// Stops relaying traffic. Calling this more than once has no effect.
+    fn stop(&mut self);
+}
+
+/// Creates and starts the obfuscator described by `config`.
+pub fn start_obfuscator(
+    runtime: &tokio::runtime::Handle,
+    config: &ObfuscatorConfig,
+) -> Result<Box<dyn Obfuscator>, Error> {
+    let mut obfuscator: Box<dyn Obfuscator> = match config {
+        ObfuscatorConfig::Udp2Tcp {
+            endpoint,
+            proxy: Some(proxy),
+        } => Box::new(Udp2TcpObfuscator::new_proxied(runtime, *endpoint, proxy)?),
+        ObfuscatorConfig::Udp2Tcp {
+            endpoint,
+            proxy: None,
+        } => Box::new(Udp2TcpObfuscator::new(
+            runtime,
+            *endpoint,
+            udp_over_tcp::TcpOptions {
+                #[cfg(target_os = "linux")]
+                fwmark: Some(crate::linux::TUNNEL_FW_MARK),
+                ..udp_over_tcp::TcpOptions::default()
+            },
+        )?),
+    };
+    obfuscator.start(runtime)?;
+    log::debug!(
+        "Started {} obfuscator at {}",
+        config.get_type(),
+        obfuscator.local_udp_addr()
+    );
+    Ok(obfuscator)
+}
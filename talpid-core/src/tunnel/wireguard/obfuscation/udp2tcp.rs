@@ -0,0 +1,193 @@
+use super::{http_connect::HttpConnectForwarder, Error, Obfuscator};
+use futures::future::{abortable, AbortHandle};
+use std::net::SocketAddr;
+use talpid_types::net::openvpn::HttpConnectProxySettings;
+use udp_over_tcp::{TcpOptions, Udp2Tcp};
+
+/// Sends WireGuard datagrams over a TCP stream to a UDP-over-TCP server.
+pub struct Udp2TcpObfuscator {
+    udp2tcp: Option<Udp2Tcp>,
+    local_addr: SocketAddr,
+    abort_handle: Option<AbortHandle>,
+    _proxy_forwarder: Option<HttpConnectForwarder>,
+}
+
+impl Udp2TcpObfuscator {
+    /// Binds a local UDP socket and connects to the UDP-over-TCP server at `endpoint`.
+    pub fn new(
+        runtime: &tokio::runtime::Handle,
+        endpoint: SocketAddr,
+        options: TcpOptions,
+    ) -> Result<Self, Error> {
+        let listen_addr = if endpoint.is_ipv4() {
+            SocketAddr::new("127.0.0.1".parse().unwrap(), 0)
+        } else {
+            SocketAddr::new("::1".parse().unwrap(), 0)
+        };
+
+        let udp2tcp = runtime
+            .block_on(Udp2Tcp::new(listen_addr, endpoint, options))
+            .map_err(Error::Udp2TcpError)?;
+        let local_addr = udp2tcp
+            .local_udp_addr()
+            .map_err(Error::GetLocalUdpAddress)?;
+
+        Ok(Self {
+            udp2tcp: Some(udp2tcp),
+            local_addr,
+            abort_handle: None,
+            _proxy_forwarder: None,
+        })
+    }
+
+    /// Like [`Udp2TcpObfuscator::new`], but the TCP stream to `endpoint` is tunneled through
+    /// an HTTP proxy.
+    pub fn new_proxied(
+        runtime: &tokio::runtime::Handle,
+        endpoint: SocketAddr,
+        proxy: &HttpConnectProxySettings,
+    ) -> Result<Self, Error> {
+        let forwarder = HttpConnectForwarder::start(runtime, proxy.clone(), endpoint)?;
+        let mut obfuscator = Self::new(runtime, forwarder.local_addr(), TcpOptions::default())?;
+        obfuscator._proxy_forwarder = Some(forwarder);
+        Ok(obfuscator)
+    }
+}
+
+impl Obfuscator for Udp2TcpObfuscator {
+    fn start(&mut self, runtime: &tokio::runtime::Handle) -> Result<(), Error> {
+        let udp2tcp = self.udp2tcp.take().ok_or(Error::AlreadyStarted)?;
+        let (udp2tcp_future, abort_handle) = abortable(udp2tcp.run());
+        runtime.spawn(udp2tcp_future);
+        self.abort_handle = Some(abort_handle);
+        Ok(())
+    }
+
+    fn local_udp_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    fn stop(&mut self) {
+        if let Some(abort_handle) = self.abort_handle.take() {
+            abort_handle.abort();
+        }
+    }
+}
+
+impl Drop for Udp2TcpObfuscator {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{net::UdpSocket, time::Duration};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// Starts a TCP server that sends back everything it receives, and returns its address.
+    fn start_echo_server(runtime: &tokio::runtime::Runtime) -> SocketAddr {
+        let listener = runtime
+            .block_on(TcpListener::bind("127.0.0.1:0"))
+            .expect("Failed to bind echo server");
+        let addr = listener.local_addr().unwrap();
+        runtime.spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut reader, mut writer) = stream.into_split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+        addr
+    }
+
+    /// Starts an HTTP proxy that accepts any `CONNECT` request, but then sends back everything it
+    /// receives instead of connecting to the target. Returns its address.
+    fn start_echoing_http_proxy(runtime: &tokio::runtime::Runtime) -> SocketAddr {
+        let listener = runtime
+            .block_on(TcpListener::bind("127.0.0.1:0"))
+            .expect("Failed to bind proxy");
+        let addr = listener.local_addr().unwrap();
+        runtime.spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = vec![];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        request.push(stream.read_u8().await.unwrap());
+                    }
+                    assert!(request.starts_with(b"CONNECT "));
+                    stream
+                        .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                        .await
+                        .unwrap();
+                    let (mut reader, mut writer) = stream.into_split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+        addr
+    }
+
+    /// Sends a datagram through the obfuscator and checks that it is echoed back.
+    fn assert_round_trip(obfuscator: &Udp2TcpObfuscator) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        socket
+            .send_to(b"wireguard datagram", obfuscator.local_udp_addr())
+            .unwrap();
+
+        // Datagrams are framed the same way in both directions, so the echoed stream decodes to
+        // the datagram that was sent
+        let mut buffer = [0u8; 64];
+        let (len, from) = socket
+            .recv_from(&mut buffer)
+            .expect("No reply from obfuscator");
+        assert_eq!(from, obfuscator.local_udp_addr());
+        assert_eq!(&buffer[..len], b"wireguard datagram");
+    }
+
+    #[test]
+    fn test_udp2tcp_round_trip() {
+        let runtime = tokio::runtime::Runtime::new().expect("Failed to initialize runtime");
+        let server_addr = start_echo_server(&runtime);
+
+        let mut obfuscator =
+            Udp2TcpObfuscator::new(runtime.handle(), server_addr, TcpOptions::default())
+                .expect("Failed to create obfuscator");
+        obfuscator.start(runtime.handle()).unwrap();
+        assert!(matches!(
+            obfuscator.start(runtime.handle()),
+            Err(Error::AlreadyStarted)
+        ));
+
+        assert_round_trip(&obfuscator);
+
+        obfuscator.stop();
+    }
+
+    #[test]
+    fn test_udp2tcp_through_http_proxy() {
+        let runtime = tokio::runtime::Runtime::new().expect("Failed to initialize runtime");
+        let proxy = HttpConnectProxySettings {
+            address: start_echoing_http_proxy(&runtime),
+            auth: None,
+        };
+
+        // The target is never connected to, since the proxy echoes the traffic itself
+        let server_addr = "192.0.2.1:443".parse().unwrap();
+        let mut obfuscator = Udp2TcpObfuscator::new_proxied(runtime.handle(), server_addr, &proxy)
+            .expect("Failed to create obfuscator");
+        obfuscator.start(runtime.handle()).unwrap();
+
+        assert_round_trip(&obfuscator);
+
+        obfuscator.stop();
+    }
+}
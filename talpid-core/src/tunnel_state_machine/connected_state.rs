@@ -31,6 +31,7 @@ pub(crate) type TunnelEventsReceiver =
 
 pub struct ConnectedStateBootstrap {
     pub metadata: TunnelMetadata,
+    pub entry_metadata: Option<TunnelMetadata>,
     pub tunnel_events: TunnelEventsReceiver,
     pub tunnel_parameters: TunnelParameters,
     pub tunnel_close_event: TunnelCloseEvent,
@@ -40,6 +41,7 @@ pub struct ConnectedStateBootstrap {
 /// The tunnel is up and working.
 pub struct ConnectedState {
     metadata: TunnelMetadata,
+    entry_metadata: Option<TunnelMetadata>,
     tunnel_events: TunnelEventsReceiver,
     tunnel_parameters: TunnelParameters,
     tunnel_close_event: TunnelCloseEvent,
@@ -50,6 +52,7 @@ impl ConnectedState {
     fn from(bootstrap: ConnectedStateBootstrap) -> Self {
         ConnectedState {
             metadata: bootstrap.metadata,
+            entry_metadata: bootstrap.entry_metadata,
             tunnel_events: bootstrap.tunnel_events,
             tunnel_parameters: bootstrap.tunnel_parameters,
             tunnel_close_event: bootstrap.tunnel_close_event,
@@ -110,6 +113,7 @@ impl ConnectedState {
         FirewallPolicy::Connected {
             peer_endpoint: self.tunnel_parameters.get_next_hop_endpoint(),
             tunnel: self.metadata.clone(),
+            entry_tunnel: self.entry_metadata.clone(),
             allow_lan: shared_values.allow_lan,
             #[cfg(not(target_os = "android"))]
             dns_servers: self.get_dns_servers(shared_values),
@@ -118,6 +122,8 @@ impl ConnectedState {
                 &shared_values.resource_dir,
                 &self.tunnel_parameters,
             ),
+            #[cfg(target_os = "linux")]
+            connectivity_probe_port: shared_values.connectivity_probe_port(),
         }
     }
 
@@ -235,9 +241,9 @@ impl ConnectedState {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self.into())
             }
-            Some(TunnelCommand::IsOffline(is_offline)) => {
-                shared_values.is_offline = is_offline;
-                if is_offline {
+            Some(TunnelCommand::Connectivity(connectivity)) => {
+                shared_values.connectivity = connectivity;
+                if connectivity.is_offline() {
                     self.disconnect(
                         shared_values,
                         AfterDisconnect::Block(ErrorStateCause::IsOffline),
@@ -246,6 +252,18 @@ impl ConnectedState {
                     SameState(self.into())
                 }
             }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::ConnectivityProbe(probe)) => {
+                if shared_values.set_connectivity_probe(probe) {
+                    if let Err(error) = self.set_firewall_policy(shared_values) {
+                        return self.disconnect(
+                            shared_values,
+                            AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                        );
+                    }
+                }
+                SameState(self.into())
+            }
             Some(TunnelCommand::Connect) => {
                 self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
             }
@@ -255,6 +273,14 @@ impl ConnectedState {
             Some(TunnelCommand::Block(reason)) => {
                 self.disconnect(shared_values, AfterDisconnect::Block(reason))
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::CaptivePortalLogin(login)) => {
+                shared_values.open_captive_portal_window(login, true);
+                self.disconnect(
+                    shared_values,
+                    AfterDisconnect::Block(ErrorStateCause::CaptivePortalLogin),
+                )
+            }
             #[cfg(target_os = "android")]
             Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                 shared_values.bypass_socket(fd, done_tx);
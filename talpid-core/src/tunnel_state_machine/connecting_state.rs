@@ -1,7 +1,7 @@
 use super::{
-    AfterDisconnect, ConnectedState, ConnectedStateBootstrap, DisconnectingState, ErrorState,
-    EventConsequence, EventResult, SharedTunnelStateValues, TunnelCommand, TunnelCommandReceiver,
-    TunnelState, TunnelStateTransition, TunnelStateWrapper,
+    connectivity_block_reason, AfterDisconnect, ConnectedState, ConnectedStateBootstrap,
+    DisconnectingState, ErrorState, EventConsequence, EventResult, SharedTunnelStateValues,
+    TunnelCommand, TunnelCommandReceiver, TunnelState, TunnelStateTransition, TunnelStateWrapper,
 };
 use crate::{
     firewall::FirewallPolicy,
@@ -49,6 +49,7 @@ pub struct ConnectingState {
     tunnel_events: TunnelEventsReceiver,
     tunnel_parameters: TunnelParameters,
     tunnel_metadata: Option<TunnelMetadata>,
+    entry_tunnel_metadata: Option<TunnelMetadata>,
     tunnel_close_event: TunnelCloseEvent,
     close_handle: Option<CloseHandle>,
     retry_attempt: u32,
@@ -59,6 +60,7 @@ impl ConnectingState {
         shared_values: &mut SharedTunnelStateValues,
         params: &TunnelParameters,
         tunnel_metadata: &Option<TunnelMetadata>,
+        entry_tunnel_metadata: &Option<TunnelMetadata>,
     ) -> Result<(), FirewallPolicyError> {
         #[cfg(target_os = "linux")]
         shared_values.disable_connectivity_check();
@@ -68,10 +70,13 @@ impl ConnectingState {
         let policy = FirewallPolicy::Connecting {
             peer_endpoint,
             tunnel: tunnel_metadata.clone(),
+            entry_tunnel: entry_tunnel_metadata.clone(),
             allow_lan: shared_values.allow_lan,
             allowed_endpoint: shared_values.allowed_endpoint.clone(),
             #[cfg(windows)]
             relay_client: TunnelMonitor::get_relay_client(&shared_values.resource_dir, &params),
+            #[cfg(target_os = "linux")]
+            connectivity_probe_port: shared_values.connectivity_probe_port(),
         };
         shared_values
             .firewall
@@ -127,6 +132,7 @@ impl ConnectingState {
             tunnel_events: event_rx.fuse(),
             tunnel_parameters: parameters,
             tunnel_metadata: None,
+            entry_tunnel_metadata: None,
             tunnel_close_event,
             close_handle,
             retry_attempt,
@@ -202,6 +208,7 @@ impl ConnectingState {
     fn into_connected_state_bootstrap(self, metadata: TunnelMetadata) -> ConnectedStateBootstrap {
         ConnectedStateBootstrap {
             metadata,
+            entry_metadata: self.entry_tunnel_metadata,
             tunnel_events: self.tunnel_events,
             tunnel_parameters: self.tunnel_parameters,
             tunnel_close_event: self.tunnel_close_event,
@@ -254,6 +261,7 @@ impl ConnectingState {
                         shared_values,
                         &self.tunnel_parameters,
                         &self.tunnel_metadata,
+                        &self.entry_tunnel_metadata,
                     ) {
                         Ok(()) => {
                             cfg_if! {
@@ -277,6 +285,7 @@ impl ConnectingState {
                         shared_values,
                         &self.tunnel_parameters,
                         &self.tunnel_metadata,
+                        &self.entry_tunnel_metadata,
                     ) {
                         return self.disconnect(
                             shared_values,
@@ -299,16 +308,29 @@ impl ConnectingState {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self.into())
             }
-            Some(TunnelCommand::IsOffline(is_offline)) => {
-                shared_values.is_offline = is_offline;
-                if is_offline {
-                    self.disconnect(
+            Some(TunnelCommand::Connectivity(connectivity)) => {
+                shared_values.connectivity = connectivity;
+                match connectivity_block_reason(connectivity) {
+                    Some(reason) => self.disconnect(shared_values, AfterDisconnect::Block(reason)),
+                    None => SameState(self.into()),
+                }
+            }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::ConnectivityProbe(probe)) => {
+                if shared_values.set_connectivity_probe(probe) {
+                    if let Err(error) = Self::set_firewall_policy(
                         shared_values,
-                        AfterDisconnect::Block(ErrorStateCause::IsOffline),
-                    )
-                } else {
-                    SameState(self.into())
+                        &self.tunnel_parameters,
+                        &self.tunnel_metadata,
+                        &self.entry_tunnel_metadata,
+                    ) {
+                        return self.disconnect(
+                            shared_values,
+                            AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                        );
+                    }
                 }
+                SameState(self.into())
             }
             Some(TunnelCommand::Connect) => {
                 self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
@@ -319,6 +341,14 @@ impl ConnectingState {
             Some(TunnelCommand::Block(reason)) => {
                 self.disconnect(shared_values, AfterDisconnect::Block(reason))
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::CaptivePortalLogin(login)) => {
+                shared_values.open_captive_portal_window(login, true);
+                self.disconnect(
+                    shared_values,
+                    AfterDisconnect::Block(ErrorStateCause::CaptivePortalLogin),
+                )
+            }
             #[cfg(target_os = "android")]
             Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                 shared_values.bypass_socket(fd, done_tx);
@@ -366,6 +396,22 @@ impl ConnectingState {
                     shared_values,
                     &self.tunnel_parameters,
                     &self.tunnel_metadata,
+                    &self.entry_tunnel_metadata,
+                ) {
+                    Ok(()) => SameState(self.into()),
+                    Err(error) => self.disconnect(
+                        shared_values,
+                        AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                    ),
+                }
+            }
+            Some((TunnelEvent::EntryInterfaceUp(metadata), _done_tx)) => {
+                self.entry_tunnel_metadata = Some(metadata);
+                match Self::set_firewall_policy(
+                    shared_values,
+                    &self.tunnel_parameters,
+                    &self.tunnel_metadata,
+                    &self.entry_tunnel_metadata,
                 ) {
                     Ok(()) => SameState(self.into()),
                     Err(error) => self.disconnect(
@@ -419,7 +465,7 @@ fn should_retry(error: &tunnel::Error, retry_attempt: u32) -> bool {
     use tunnel::wireguard::{Error, TunnelError};
 
     match error {
-        tunnel::Error::WireguardTunnelMonitoringError(Error::Udp2TcpError(_)) => true,
+        tunnel::Error::WireguardTunnelMonitoringError(Error::ObfuscatorError(_)) => true,
 
         #[cfg(not(windows))]
         tunnel::Error::WireguardTunnelMonitoringError(Error::TunnelError(
@@ -470,8 +516,8 @@ impl TunnelState for ConnectingState {
         shared_values: &mut SharedTunnelStateValues,
         retry_attempt: u32,
     ) -> (TunnelStateWrapper, TunnelStateTransition) {
-        if shared_values.is_offline {
-            return ErrorState::enter(shared_values, ErrorStateCause::IsOffline);
+        if let Some(reason) = connectivity_block_reason(shared_values.connectivity) {
+            return ErrorState::enter(shared_values, reason);
         }
         match shared_values
             .tunnel_parameters_generator
@@ -494,7 +540,7 @@ impl TunnelState for ConnectingState {
                 }
 
                 if let Err(error) =
-                    Self::set_firewall_policy(shared_values, &tunnel_parameters, &None)
+                    Self::set_firewall_policy(shared_values, &tunnel_parameters, &None, &None)
                 {
                     ErrorState::enter(
                         shared_values,
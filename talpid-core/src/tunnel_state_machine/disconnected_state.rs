@@ -4,6 +4,8 @@ use super::{
 };
 use crate::firewall::FirewallPolicy;
 use futures::StreamExt;
+#[cfg(not(target_os = "android"))]
+use talpid_types::tunnel::ErrorStateCause;
 use talpid_types::ErrorExt;
 
 /// No tunnel is running.
@@ -18,6 +20,8 @@ impl DisconnectedState {
             let policy = FirewallPolicy::Blocked {
                 allow_lan: shared_values.allow_lan,
                 allowed_endpoint: shared_values.allowed_endpoint.clone(),
+                #[cfg(target_os = "linux")]
+                connectivity_probe_port: shared_values.connectivity_probe_port(),
             };
             shared_values.firewall.apply_policy(policy).map_err(|e| {
                 e.display_chain_with_msg(
@@ -131,14 +135,34 @@ impl TunnelState for DisconnectedState {
                 }
                 SameState(self.into())
             }
-            Some(TunnelCommand::IsOffline(is_offline)) => {
-                shared_values.is_offline = is_offline;
+            Some(TunnelCommand::Connectivity(connectivity)) => {
+                shared_values.connectivity = connectivity;
+                SameState(self.into())
+            }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::ConnectivityProbe(probe)) => {
+                if shared_values.set_connectivity_probe(probe) {
+                    Self::set_firewall_policy(shared_values, false);
+                }
                 SameState(self.into())
             }
             Some(TunnelCommand::Connect) => NewState(ConnectingState::enter(shared_values, 0)),
             Some(TunnelCommand::Block(reason)) => {
                 NewState(ErrorState::enter(shared_values, reason))
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::CaptivePortalLogin(login)) => {
+                if shared_values.block_when_disconnected {
+                    shared_values.open_captive_portal_window(login, false);
+                    NewState(ErrorState::enter(
+                        shared_values,
+                        ErrorStateCause::CaptivePortalLogin,
+                    ))
+                } else {
+                    log::debug!("Ignoring captive portal login since traffic is not blocked");
+                    SameState(self.into())
+                }
+            }
             #[cfg(target_os = "android")]
             Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                 shared_values.bypass_socket(fd, done_tx);
@@ -1,7 +1,8 @@
 use super::{
-    connecting_state::TunnelCloseEvent, ConnectingState, DisconnectedState, ErrorState,
-    EventConsequence, EventResult, SharedTunnelStateValues, TunnelCommand, TunnelCommandReceiver,
-    TunnelState, TunnelStateTransition, TunnelStateWrapper,
+    connecting_state::TunnelCloseEvent, connectivity_block_reason, is_connectivity_block_reason,
+    ConnectingState, DisconnectedState, ErrorState, EventConsequence, EventResult,
+    SharedTunnelStateValues, TunnelCommand, TunnelCommandReceiver, TunnelState,
+    TunnelStateTransition, TunnelStateWrapper,
 };
 use crate::tunnel::CloseHandle;
 use futures::{future::FusedFuture, StreamExt};
@@ -47,13 +48,27 @@ impl DisconnectingState {
                     shared_values.block_when_disconnected = block_when_disconnected;
                     AfterDisconnect::Nothing
                 }
-                Some(TunnelCommand::IsOffline(is_offline)) => {
-                    shared_values.is_offline = is_offline;
+                Some(TunnelCommand::Connectivity(connectivity)) => {
+                    shared_values.connectivity = connectivity;
+                    AfterDisconnect::Nothing
+                }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::ConnectivityProbe(probe)) => {
+                    let _ = shared_values.set_connectivity_probe(probe);
                     AfterDisconnect::Nothing
                 }
                 Some(TunnelCommand::Connect) => AfterDisconnect::Reconnect(0),
                 Some(TunnelCommand::Disconnect) | None => AfterDisconnect::Nothing,
                 Some(TunnelCommand::Block(reason)) => AfterDisconnect::Block(reason),
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::CaptivePortalLogin(login)) => {
+                    if shared_values.block_when_disconnected {
+                        shared_values.open_captive_portal_window(login, false);
+                        AfterDisconnect::Block(ErrorStateCause::CaptivePortalLogin)
+                    } else {
+                        AfterDisconnect::Nothing
+                    }
+                }
                 #[cfg(target_os = "android")]
                 Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                     shared_values.bypass_socket(fd, done_tx);
@@ -85,17 +100,30 @@ impl DisconnectingState {
                     shared_values.block_when_disconnected = block_when_disconnected;
                     AfterDisconnect::Block(reason)
                 }
-                Some(TunnelCommand::IsOffline(is_offline)) => {
-                    shared_values.is_offline = is_offline;
-                    if !is_offline && reason == ErrorStateCause::IsOffline {
-                        AfterDisconnect::Reconnect(0)
-                    } else {
+                Some(TunnelCommand::Connectivity(connectivity)) => {
+                    shared_values.connectivity = connectivity;
+                    if !is_connectivity_block_reason(&reason) {
                         AfterDisconnect::Block(reason)
+                    } else {
+                        match connectivity_block_reason(connectivity) {
+                            Some(new_reason) => AfterDisconnect::Block(new_reason),
+                            None => AfterDisconnect::Reconnect(0),
+                        }
                     }
                 }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::ConnectivityProbe(probe)) => {
+                    let _ = shared_values.set_connectivity_probe(probe);
+                    AfterDisconnect::Block(reason)
+                }
                 Some(TunnelCommand::Connect) => AfterDisconnect::Reconnect(0),
                 Some(TunnelCommand::Disconnect) => AfterDisconnect::Nothing,
                 Some(TunnelCommand::Block(new_reason)) => AfterDisconnect::Block(new_reason),
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::CaptivePortalLogin(login)) => {
+                    shared_values.open_captive_portal_window(login, true);
+                    AfterDisconnect::Block(ErrorStateCause::CaptivePortalLogin)
+                }
                 #[cfg(target_os = "android")]
                 Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                     shared_values.bypass_socket(fd, done_tx);
@@ -128,17 +156,26 @@ impl DisconnectingState {
                     shared_values.block_when_disconnected = block_when_disconnected;
                     AfterDisconnect::Reconnect(retry_attempt)
                 }
-                Some(TunnelCommand::IsOffline(is_offline)) => {
-                    shared_values.is_offline = is_offline;
-                    if is_offline {
-                        AfterDisconnect::Block(ErrorStateCause::IsOffline)
-                    } else {
-                        AfterDisconnect::Reconnect(retry_attempt)
+                Some(TunnelCommand::Connectivity(connectivity)) => {
+                    shared_values.connectivity = connectivity;
+                    match connectivity_block_reason(connectivity) {
+                        Some(reason) => AfterDisconnect::Block(reason),
+                        None => AfterDisconnect::Reconnect(retry_attempt),
                     }
                 }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::ConnectivityProbe(probe)) => {
+                    let _ = shared_values.set_connectivity_probe(probe);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
                 Some(TunnelCommand::Connect) => AfterDisconnect::Reconnect(retry_attempt),
                 Some(TunnelCommand::Disconnect) | None => AfterDisconnect::Nothing,
                 Some(TunnelCommand::Block(reason)) => AfterDisconnect::Block(reason),
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::CaptivePortalLogin(login)) => {
+                    shared_values.open_captive_portal_window(login, true);
+                    AfterDisconnect::Block(ErrorStateCause::CaptivePortalLogin)
+                }
                 #[cfg(target_os = "android")]
                 Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                     shared_values.bypass_socket(fd, done_tx);
@@ -160,6 +197,17 @@ impl DisconnectingState {
         block_reason: Option<ErrorStateCause>,
         shared_values: &mut SharedTunnelStateValues,
     ) -> (TunnelStateWrapper, TunnelStateTransition) {
+        // The captive portal login window is only kept if nothing else has been requested since
+        #[cfg(not(target_os = "android"))]
+        if block_reason.is_some()
+            || !matches!(
+                self.after_disconnect,
+                AfterDisconnect::Block(ErrorStateCause::CaptivePortalLogin)
+            )
+        {
+            shared_values.captive_portal_window = None;
+        }
+
         if let Some(reason) = block_reason {
             return ErrorState::enter(shared_values, reason);
         }
@@ -1,8 +1,11 @@
 use super::{
-    ConnectingState, DisconnectedState, EventConsequence, SharedTunnelStateValues, TunnelCommand,
-    TunnelCommandReceiver, TunnelState, TunnelStateTransition, TunnelStateWrapper,
+    connectivity_block_reason, is_connectivity_block_reason, ConnectingState, DisconnectedState,
+    EventConsequence, SharedTunnelStateValues, TunnelCommand, TunnelCommandReceiver, TunnelState,
+    TunnelStateTransition, TunnelStateWrapper,
 };
 use crate::firewall::FirewallPolicy;
+#[cfg(not(target_os = "android"))]
+use futures::FutureExt;
 use futures::StreamExt;
 use talpid_types::{
     tunnel::{self as talpid_tunnel, ErrorStateCause, FirewallPolicyError},
@@ -19,10 +22,18 @@ impl ErrorState {
     fn set_firewall_policy(
         shared_values: &mut SharedTunnelStateValues,
     ) -> Result<(), FirewallPolicyError> {
-        let policy = FirewallPolicy::Blocked {
+        let blocked_policy = FirewallPolicy::Blocked {
             allow_lan: shared_values.allow_lan,
             allowed_endpoint: shared_values.allowed_endpoint.clone(),
+            #[cfg(target_os = "linux")]
+            connectivity_probe_port: shared_values.connectivity_probe_port(),
         };
+        #[cfg(not(target_os = "android"))]
+        let policy = shared_values
+            .captive_portal_policy()
+            .unwrap_or(blocked_policy);
+        #[cfg(target_os = "android")]
+        let policy = blocked_policy;
 
         #[cfg(target_os = "linux")]
         shared_values.disable_connectivity_check();
@@ -45,6 +56,40 @@ impl ErrorState {
             })
     }
 
+    /// Waits for the next command. Returns `None` if the captive portal login window elapses
+    /// before a command is received.
+    #[cfg(not(target_os = "android"))]
+    fn next_command(
+        runtime: &tokio::runtime::Handle,
+        commands: &mut TunnelCommandReceiver,
+        shared_values: &SharedTunnelStateValues,
+    ) -> Option<Option<TunnelCommand>> {
+        match shared_values.captive_portal_window.as_ref() {
+            Some(window) => {
+                let window_elapsed = tokio::time::sleep_until(window.deadline);
+                runtime.block_on(async {
+                    futures::select! {
+                        command = commands.next() => Some(command),
+                        _ = window_elapsed.fuse() => None,
+                    }
+                })
+            }
+            None => Some(runtime.block_on(commands.next())),
+        }
+    }
+
+    /// Leaves the captive portal login window, either by reconnecting or by disconnecting.
+    #[cfg(not(target_os = "android"))]
+    fn close_captive_portal_window(
+        shared_values: &mut SharedTunnelStateValues,
+    ) -> (TunnelStateWrapper, TunnelStateTransition) {
+        log::info!("Captive portal login window has elapsed");
+        match shared_values.captive_portal_window.take() {
+            Some(window) if window.reconnect => ConnectingState::enter(shared_values, 0),
+            _ => DisconnectedState::enter(shared_values, true),
+        }
+    }
+
     /// Returns true if a new tunnel device was successfully created.
     #[cfg(target_os = "android")]
     fn create_blocking_tun(shared_values: &mut SharedTunnelStateValues) -> bool {
@@ -107,7 +152,15 @@ impl TunnelState for ErrorState {
     ) -> EventConsequence {
         use self::EventConsequence::*;
 
-        match runtime.block_on(commands.next()) {
+        #[cfg(not(target_os = "android"))]
+        let command = match Self::next_command(runtime, commands, shared_values) {
+            Some(command) => command,
+            None => return NewState(Self::close_captive_portal_window(shared_values)),
+        };
+        #[cfg(target_os = "android")]
+        let command = runtime.block_on(commands.next());
+
+        match command {
             Some(TunnelCommand::AllowLan(allow_lan)) => {
                 if let Err(error_state_cause) = shared_values.set_allow_lan(allow_lan) {
                     NewState(Self::enter(shared_values, error_state_cause))
@@ -144,23 +197,56 @@ impl TunnelState for ErrorState {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self.into())
             }
-            Some(TunnelCommand::IsOffline(is_offline)) => {
-                shared_values.is_offline = is_offline;
-                if !is_offline && self.block_reason == ErrorStateCause::IsOffline {
-                    NewState(ConnectingState::enter(shared_values, 0))
-                } else {
-                    SameState(self.into())
+            Some(TunnelCommand::Connectivity(connectivity)) => {
+                shared_values.connectivity = connectivity;
+                if !is_connectivity_block_reason(&self.block_reason) {
+                    return SameState(self.into());
+                }
+                match connectivity_block_reason(connectivity) {
+                    None => NewState(ConnectingState::enter(shared_values, 0)),
+                    Some(reason) if reason != self.block_reason => {
+                        NewState(Self::enter(shared_values, reason))
+                    }
+                    Some(_) => SameState(self.into()),
                 }
             }
-            Some(TunnelCommand::Connect) => NewState(ConnectingState::enter(shared_values, 0)),
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::ConnectivityProbe(probe)) => {
+                if shared_values.set_connectivity_probe(probe) {
+                    let _ = Self::set_firewall_policy(shared_values);
+                }
+                SameState(self.into())
+            }
+            Some(TunnelCommand::Connect) => {
+                #[cfg(not(target_os = "android"))]
+                shared_values.captive_portal_window = None;
+                NewState(ConnectingState::enter(shared_values, 0))
+            }
             Some(TunnelCommand::Disconnect) | None => {
+                #[cfg(not(target_os = "android"))]
+                shared_values.captive_portal_window = None;
                 #[cfg(target_os = "linux")]
                 shared_values.reset_connectivity_check();
                 NewState(DisconnectedState::enter(shared_values, true))
             }
             Some(TunnelCommand::Block(reason)) => {
+                #[cfg(not(target_os = "android"))]
+                shared_values.captive_portal_window = None;
                 NewState(ErrorState::enter(shared_values, reason))
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::CaptivePortalLogin(login)) => {
+                let reconnect = shared_values
+                    .captive_portal_window
+                    .as_ref()
+                    .map(|window| window.reconnect)
+                    .unwrap_or(true);
+                shared_values.open_captive_portal_window(login, reconnect);
+                NewState(ErrorState::enter(
+                    shared_values,
+                    ErrorStateCause::CaptivePortalLogin,
+                ))
+            }
             #[cfg(target_os = "android")]
             Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                 shared_values.bypass_socket(fd, done_tx);
@@ -11,6 +11,8 @@ use self::{
     disconnecting_state::{AfterDisconnect, DisconnectingState},
     error_state::ErrorState,
 };
+#[cfg(not(target_os = "android"))]
+use crate::firewall::FirewallPolicy;
 #[cfg(windows)]
 use crate::split_tunnel;
 use crate::{
@@ -30,6 +32,8 @@ use futures::{
 };
 #[cfg(target_os = "android")]
 use std::os::unix::io::RawFd;
+#[cfg(not(target_os = "android"))]
+use std::time::Duration;
 use std::{
     collections::HashSet,
     io,
@@ -37,10 +41,12 @@ use std::{
     path::{Path, PathBuf},
     sync::{mpsc as sync_mpsc, Arc},
 };
+#[cfg(target_os = "linux")]
+use talpid_types::net::{ConnectedNetwork, ConnectivityProbe};
 #[cfg(target_os = "android")]
 use talpid_types::{android::AndroidContext, ErrorExt};
 use talpid_types::{
-    net::{Endpoint, TunnelParameters},
+    net::{Connectivity, Endpoint, TunnelParameters},
     tunnel::{ErrorStateCause, ParameterGenerationError, TunnelStateTransition},
 };
 
@@ -90,6 +96,9 @@ pub struct InitialTunnelState {
     pub allowed_endpoint: Endpoint,
     /// Whether to reset any existing firewall rules when initializing the disconnected state.
     pub reset_firewall: bool,
+    /// HTTP server used to detect captive portals.
+    #[cfg(target_os = "linux")]
+    pub connectivity_probe: Option<ConnectivityProbe>,
     /// Programs to exclude from the tunnel using the split tunnel driver.
     #[cfg(windows)]
     pub exclude_paths: Vec<OsString>,
@@ -104,7 +113,8 @@ pub async fn spawn(
     resource_dir: PathBuf,
     cache_dir: impl AsRef<Path> + Send + 'static,
     state_change_listener: impl Sender<TunnelStateTransition> + Send + 'static,
-    offline_state_listener: mpsc::UnboundedSender<bool>,
+    offline_state_listener: mpsc::UnboundedSender<Connectivity>,
+    #[cfg(target_os = "linux")] network_listener: mpsc::UnboundedSender<Vec<ConnectedNetwork>>,
     shutdown_tx: oneshot::Sender<()>,
     #[cfg(target_os = "android")] android_context: AndroidContext,
 ) -> Result<Arc<mpsc::UnboundedSender<TunnelCommand>>, Error> {
@@ -130,6 +140,8 @@ pub async fn spawn(
             initial_settings,
             weak_command_tx,
             offline_state_listener,
+            #[cfg(target_os = "linux")]
+            network_listener,
             tunnel_parameters_generator,
             tun_provider,
             log_dir,
@@ -175,13 +187,20 @@ pub enum TunnelCommand {
     /// Enable or disable the block_when_disconnected feature.
     BlockWhenDisconnected(bool),
     /// Notify the state machine of the connectivity of the device.
-    IsOffline(bool),
+    Connectivity(Connectivity),
+    /// Set the HTTP server used to detect captive portals, or disable the detection.
+    #[cfg(target_os = "linux")]
+    ConnectivityProbe(Option<ConnectivityProbe>),
     /// Open tunnel connection.
     Connect,
     /// Close tunnel connection.
     Disconnect,
     /// Disconnect any open tunnel and block all network access
     Block(ErrorStateCause),
+    /// Disconnect any open tunnel and temporarily allow the traffic needed to log in to a captive
+    /// portal. The tunnel is reconnected once the login window has elapsed.
+    #[cfg(not(target_os = "android"))]
+    CaptivePortalLogin(CaptivePortalLogin),
     /// Bypass a socket, allowing traffic to flow through outside the tunnel.
     #[cfg(target_os = "android")]
     BypassSocket(RawFd, oneshot::Sender<()>),
@@ -193,6 +212,18 @@ pub enum TunnelCommand {
     ),
 }
 
+/// Parameters for a captive portal login window.
+#[cfg(not(target_os = "android"))]
+#[derive(Debug, Clone)]
+pub struct CaptivePortalLogin {
+    /// How long the traffic needed to log in is allowed for.
+    pub duration: Duration,
+    /// Class ID of a net_cls cgroup whose HTTP, HTTPS and DNS traffic is allowed during the login
+    /// window.
+    #[cfg(target_os = "linux")]
+    pub browser_cgroup: Option<u32>,
+}
+
 type TunnelCommandReceiver = stream::Fuse<mpsc::UnboundedReceiver<TunnelCommand>>;
 
 enum EventResult {
@@ -218,7 +249,8 @@ impl TunnelStateMachine {
         runtime: tokio::runtime::Handle,
         settings: InitialTunnelState,
         command_tx: std::sync::Weak<mpsc::UnboundedSender<TunnelCommand>>,
-        offline_state_tx: mpsc::UnboundedSender<bool>,
+        offline_state_tx: mpsc::UnboundedSender<Connectivity>,
+        #[cfg(target_os = "linux")] network_tx: mpsc::UnboundedSender<Vec<ConnectedNetwork>>,
         tunnel_parameters_generator: impl TunnelParametersGenerator,
         tun_provider: TunProvider,
         log_dir: Option<PathBuf>,
@@ -254,28 +286,32 @@ impl TunnelStateMachine {
         let (offline_tx, mut offline_rx) = mpsc::unbounded();
         let initial_offline_state_tx = offline_state_tx.clone();
         tokio::spawn(async move {
-            while let Some(offline) = offline_rx.next().await {
+            while let Some(connectivity) = offline_rx.next().await {
                 if let Some(tx) = command_tx.upgrade() {
-                    let _ = tx.unbounded_send(TunnelCommand::IsOffline(offline));
+                    let _ = tx.unbounded_send(TunnelCommand::Connectivity(connectivity));
                 } else {
                     break;
                 }
-                let _ = offline_state_tx.unbounded_send(offline);
+                let _ = offline_state_tx.unbounded_send(connectivity);
             }
         });
         let mut offline_monitor = offline::spawn_monitor(
             offline_tx,
             #[cfg(target_os = "linux")]
+            network_tx,
+            #[cfg(target_os = "linux")]
             route_manager
                 .handle()
                 .map_err(Error::InitRouteManagerError)?,
+            #[cfg(target_os = "linux")]
+            settings.connectivity_probe.clone(),
             #[cfg(target_os = "android")]
             android_context,
         )
         .await
         .map_err(Error::OfflineMonitorError)?;
-        let is_offline = offline_monitor.is_offline().await;
-        let _ = initial_offline_state_tx.unbounded_send(is_offline);
+        let connectivity = offline_monitor.connectivity().await;
+        let _ = initial_offline_state_tx.unbounded_send(connectivity);
 
         #[cfg(windows)]
         split_tunnel
@@ -289,18 +325,22 @@ impl TunnelStateMachine {
             firewall,
             dns_monitor,
             route_manager,
-            _offline_monitor: offline_monitor,
+            offline_monitor,
             allow_lan: settings.allow_lan,
             block_when_disconnected: settings.block_when_disconnected,
-            is_offline,
+            connectivity,
             dns_servers: settings.dns_servers,
             allowed_endpoint: settings.allowed_endpoint,
+            #[cfg(target_os = "linux")]
+            connectivity_probe: settings.connectivity_probe,
             tunnel_parameters_generator: Box::new(tunnel_parameters_generator),
             tun_provider,
             log_dir,
             resource_dir,
             #[cfg(target_os = "linux")]
             connectivity_check_was_enabled: None,
+            #[cfg(not(target_os = "android"))]
+            captive_portal_window: None,
         };
 
         let (initial_state, _) =
@@ -365,17 +405,21 @@ struct SharedTunnelStateValues {
     firewall: Firewall,
     dns_monitor: DnsMonitor,
     route_manager: RouteManager,
-    _offline_monitor: offline::MonitorHandle,
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    offline_monitor: offline::MonitorHandle,
     /// Should LAN access be allowed outside the tunnel.
     allow_lan: bool,
     /// Should network access be allowed when in the disconnected state.
     block_when_disconnected: bool,
-    /// True when the computer is known to be offline.
-    is_offline: bool,
+    /// Connectivity of the host, as reported by the offline monitor.
+    connectivity: Connectivity,
     /// DNS servers to use (overriding default).
     dns_servers: Option<Vec<IpAddr>>,
     /// Endpoint that should not be blocked by the firewall.
     allowed_endpoint: Endpoint,
+    /// HTTP server used to detect captive portals.
+    #[cfg(target_os = "linux")]
+    connectivity_probe: Option<ConnectivityProbe>,
     /// The generator of new `TunnelParameter`s
     tunnel_parameters_generator: Box<dyn TunnelParametersGenerator>,
     /// The provider of tunnel devices.
@@ -388,6 +432,23 @@ struct SharedTunnelStateValues {
     /// NetworkManager's connecitivity check state.
     #[cfg(target_os = "linux")]
     connectivity_check_was_enabled: Option<bool>,
+
+    /// Captive portal login window that is currently open, if any.
+    #[cfg(not(target_os = "android"))]
+    captive_portal_window: Option<CaptivePortalWindow>,
+}
+
+/// An open captive portal login window.
+#[cfg(not(target_os = "android"))]
+struct CaptivePortalWindow {
+    #[cfg(target_os = "linux")]
+    browser_cgroup: Option<u32>,
+    /// Networks that the host was assigned when the window was opened.
+    allowed_networks: Vec<ipnetwork::IpNetwork>,
+    deadline: tokio::time::Instant,
+    /// Whether to connect once the window has elapsed. Otherwise, the disconnected state is
+    /// entered.
+    reconnect: bool,
 }
 
 impl SharedTunnelStateValues {
@@ -427,6 +488,23 @@ impl SharedTunnelStateValues {
         }
     }
 
+    #[cfg(target_os = "linux")]
+    pub fn set_connectivity_probe(&mut self, probe: Option<ConnectivityProbe>) -> bool {
+        if self.connectivity_probe != probe {
+            self.offline_monitor.set_connectivity_probe(probe.clone());
+            self.connectivity_probe = probe;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the port that the firewall must let the connectivity probe connect to.
+    #[cfg(target_os = "linux")]
+    pub fn connectivity_probe_port(&self) -> Option<u16> {
+        self.connectivity_probe.as_ref().map(|probe| probe.port)
+    }
+
     pub fn set_dns_servers(
         &mut self,
         dns_servers: Option<Vec<IpAddr>>,
@@ -479,6 +557,53 @@ impl SharedTunnelStateValues {
         }
     }
 
+    /// Opens a captive portal login window. The window replaces any window that is already open.
+    #[cfg(not(target_os = "android"))]
+    pub fn open_captive_portal_window(&mut self, login: CaptivePortalLogin, reconnect: bool) {
+        #[cfg(unix)]
+        let allowed_networks = crate::firewall::get_interface_networks().unwrap_or_else(|error| {
+            log::error!(
+                "Failed to obtain the networks of the network interfaces: {}",
+                error
+            );
+            vec![]
+        });
+        #[cfg(windows)]
+        let allowed_networks = vec![];
+
+        log::info!(
+            "Allowing captive portal login for {} seconds",
+            login.duration.as_secs()
+        );
+        self.captive_portal_window = Some(CaptivePortalWindow {
+            deadline: tokio::time::Instant::now() + login.duration,
+            #[cfg(target_os = "linux")]
+            browser_cgroup: login.browser_cgroup,
+            allowed_networks,
+            reconnect,
+        });
+    }
+
+    /// Returns the firewall policy to apply while a captive portal login window is open.
+    #[cfg(not(target_os = "android"))]
+    pub fn captive_portal_policy(&self) -> Option<FirewallPolicy> {
+        self.captive_portal_window
+            .as_ref()
+            .map(|window| FirewallPolicy::CaptivePortalLogin {
+                allow_lan: self.allow_lan,
+                allowed_endpoint: self.allowed_endpoint.clone(),
+                allowed_networks: window.allowed_networks.clone(),
+                // The tunnel DNS servers have been reset when this policy is applied, so the
+                // resolvers of the host are in effect again
+                #[cfg(target_os = "linux")]
+                allowed_resolvers: crate::dns::get_system_resolvers(),
+                #[cfg(target_os = "linux")]
+                browser_cgroup: window.browser_cgroup,
+                #[cfg(target_os = "linux")]
+                connectivity_probe_port: self.connectivity_probe_port(),
+            })
+    }
+
     #[cfg(target_os = "android")]
     pub fn bypass_socket(&mut self, fd: RawFd, tx: oneshot::Sender<()>) {
         if let Err(err) = self.tun_provider.bypass(fd) {
@@ -488,6 +613,26 @@ impl SharedTunnelStateValues {
     }
 }
 
+/// Returns the reason why no tunnel can be established, given the connectivity of the host.
+fn connectivity_block_reason(connectivity: Connectivity) -> Option<ErrorStateCause> {
+    match connectivity {
+        Connectivity::Offline => Some(ErrorStateCause::IsOffline),
+        #[cfg(target_os = "linux")]
+        Connectivity::CaptivePortal => Some(ErrorStateCause::CaptivePortal),
+        _ => None,
+    }
+}
+
+/// Returns whether the error state should be left as soon as the host is online again.
+fn is_connectivity_block_reason(reason: &ErrorStateCause) -> bool {
+    match reason {
+        ErrorStateCause::IsOffline => true,
+        #[cfg(target_os = "linux")]
+        ErrorStateCause::CaptivePortal => true,
+        _ => false,
+    }
+}
+
 /// Asynchronous result of an attempt to progress a state.
 enum EventConsequence {
     /// Transition to a new state.
@@ -27,6 +27,11 @@ const CONNECTIVITY_CHECK_KEY: &str = "ConnectivityCheckEnabled";
 const NM_DNS_MANAGER: &str = "org.freedesktop.NetworkManager.DnsManager";
 const NM_DNS_MANAGER_PATH: &str = "/org/freedesktop/NetworkManager/DnsManager";
 const NM_DEVICE: &str = "org.freedesktop.NetworkManager.Device";
+const NM_DEVICE_WIRELESS: &str = "org.freedesktop.NetworkManager.Device.Wireless";
+const NM_ACCESS_POINT: &str = "org.freedesktop.NetworkManager.AccessPoint";
+
+const NM_CONNECTION_TYPE_ETHERNET: &str = "802-3-ethernet";
+const NM_CONNECTION_TYPE_WIRELESS: &str = "802-11-wireless";
 
 const NM_IP4_CONFIG: &str = "org.freedesktop.NetworkManager.IP4Config";
 const NM_IP6_CONFIG: &str = "org.freedesktop.NetworkManager.IP6Config";
@@ -142,6 +147,85 @@ impl NetworkManager {
         Ok(tunnel)
     }
 
+    /// Returns the Ethernet and Wi-Fi networks that are currently active. Virtual connections,
+    /// such as tunnels, are left out.
+    pub fn active_networks(&self) -> Result<Vec<ActiveNetwork>> {
+        let connection_paths: Vec<dbus::Path<'static>> = self
+            .as_manager()
+            .get(NM_MANAGER, "ActiveConnections")
+            .map_err(Error::Dbus)?;
+
+        let connections = connection_paths
+            .iter()
+            .map(|connection_path| self.get_active_connection_properties(connection_path))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(active_networks_from_properties(connections))
+    }
+
+    /// Reads the properties of an active connection that describe the network it connects to.
+    fn get_active_connection_properties(
+        &self,
+        connection_path: &dbus::Path<'_>,
+    ) -> Result<ActiveConnectionProperties> {
+        let connection = self.as_path(connection_path);
+        let connection_type: String = connection
+            .get(NM_CONNECTION_ACTIVE, "Type")
+            .map_err(Error::Dbus)?;
+        let gateway = self.get_gateway(connection_path)?;
+        let device_paths: Vec<dbus::Path<'static>> = connection
+            .get(NM_CONNECTION_ACTIVE, "Devices")
+            .map_err(Error::Dbus)?;
+
+        let mut devices = vec![];
+        for device_path in device_paths {
+            let device = self.as_path(&device_path);
+            let interface: String = device.get(NM_DEVICE, "Interface").map_err(Error::Dbus)?;
+            let ssid = if connection_type == NM_CONNECTION_TYPE_WIRELESS {
+                self.get_ssid(&device_path)?
+            } else {
+                None
+            };
+            devices.push(ActiveDeviceProperties { interface, ssid });
+        }
+
+        Ok(ActiveConnectionProperties {
+            connection_type,
+            gateway,
+            devices,
+        })
+    }
+
+    /// Returns the IPv4 gateway of an active connection, if it has an IPv4 configuration.
+    fn get_gateway(&self, connection: &dbus::Path<'_>) -> Result<Option<String>> {
+        let config: dbus::Path<'static> = self
+            .as_path(connection)
+            .get(NM_CONNECTION_ACTIVE, "Ip4Config")
+            .map_err(Error::Dbus)?;
+        if &*config == "/" {
+            return Ok(None);
+        }
+        self.as_path(&config)
+            .get(NM_IP4_CONFIG, "Gateway")
+            .map(Some)
+            .map_err(Error::Dbus)
+    }
+
+    /// Returns the SSID of the access point that a Wi-Fi device is associated with, if any.
+    fn get_ssid(&self, device: &dbus::Path<'_>) -> Result<Option<Vec<u8>>> {
+        let access_point: dbus::Path<'static> = self
+            .as_path(device)
+            .get(NM_DEVICE_WIRELESS, "ActiveAccessPoint")
+            .map_err(Error::Dbus)?;
+        // NetworkManager uses "/" to signify that no access point is active
+        if &*access_point == "/" {
+            return Ok(None);
+        }
+        self.as_path(&access_point)
+            .get(NM_ACCESS_POINT, "Ssid")
+            .map(Some)
+            .map_err(Error::Dbus)
+    }
+
     pub fn get_interface_name(&self, tunnel: &WireguardTunnel) -> Result<String> {
         tunnel
             .device_proxy(&*self.connection)
@@ -675,6 +759,64 @@ impl dbus::message::SignalArgs for DeviceStateChange {
     const INTERFACE: &'static str = NM_DEVICE;
 }
 
+/// A network that the host is connected to through NetworkManager.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveNetwork {
+    /// Name of the network interface.
+    pub interface: String,
+    /// SSID of the Wi-Fi network, if the interface is a Wi-Fi device.
+    pub ssid: Option<String>,
+    /// The IPv4 default gateway of the connection.
+    pub gateway: Option<IpAddr>,
+}
+
+/// Properties of an active NetworkManager connection, as they are read from D-Bus.
+#[derive(Debug)]
+struct ActiveConnectionProperties {
+    connection_type: String,
+    /// `Gateway` of the IPv4 configuration, if the connection has one.
+    gateway: Option<String>,
+    devices: Vec<ActiveDeviceProperties>,
+}
+
+/// Properties of a device that belongs to an active connection.
+#[derive(Debug)]
+struct ActiveDeviceProperties {
+    interface: String,
+    /// `Ssid` of the access point that a Wi-Fi device is associated with, if any.
+    ssid: Option<Vec<u8>>,
+}
+
+/// Returns the Ethernet and Wi-Fi networks described by the properties of the active
+/// connections.
+fn active_networks_from_properties(
+    connections: Vec<ActiveConnectionProperties>,
+) -> Vec<ActiveNetwork> {
+    connections
+        .into_iter()
+        .filter(|connection| {
+            connection.connection_type == NM_CONNECTION_TYPE_ETHERNET
+                || connection.connection_type == NM_CONNECTION_TYPE_WIRELESS
+        })
+        .flat_map(|connection| {
+            // The gateway is an empty string if there is none
+            let gateway = connection
+                .gateway
+                .and_then(|gateway| gateway.parse::<IpAddr>().ok());
+            connection
+                .devices
+                .into_iter()
+                .map(move |device| ActiveNetwork {
+                    interface: device.interface,
+                    ssid: device
+                        .ssid
+                        .map(|ssid| String::from_utf8_lossy(&ssid).into_owned()),
+                    gateway,
+                })
+        })
+        .collect()
+}
+
 #[derive(Debug)]
 pub struct WireguardTunnel {
     config_path: dbus::Path<'static>,
@@ -740,6 +882,194 @@ fn eq_file_content<P: AsRef<Path>>(a: &P, b: &P) -> bool {
 #[cfg(test)]
 mod test {
     use super::*;
+    use dbus::{
+        blocking::LocalConnection,
+        channel::{MatchingReceiver, Sender},
+        Message,
+    };
+    use std::{
+        sync::{atomic::AtomicBool, mpsc},
+        thread,
+    };
+
+    /// Returns the value of a property exposed by the mock NetworkManager service. The host has
+    /// an Ethernet connection, a Wi-Fi connection and a WireGuard tunnel.
+    fn mock_property(path: &str, interface: &str, property: &str) -> Option<VariantRefArg> {
+        let path = path.strip_prefix(NM_MANAGER_PATH)?;
+        let paths = |paths: &[&str]| -> Box<dyn RefArg> {
+            Box::new(
+                paths
+                    .iter()
+                    .map(|path| dbus::Path::new(format!("{}{}", NM_MANAGER_PATH, path)).unwrap())
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let value: Box<dyn RefArg> = match (path, interface, property) {
+            ("", NM_MANAGER, "ActiveConnections") => paths(&[
+                "/ActiveConnection/1",
+                "/ActiveConnection/2",
+                "/ActiveConnection/3",
+            ]),
+            ("/ActiveConnection/1", NM_CONNECTION_ACTIVE, "Type") => {
+                Box::new(NM_CONNECTION_TYPE_ETHERNET.to_owned())
+            }
+            ("/ActiveConnection/2", NM_CONNECTION_ACTIVE, "Type") => {
+                Box::new(NM_CONNECTION_TYPE_WIRELESS.to_owned())
+            }
+            ("/ActiveConnection/3", NM_CONNECTION_ACTIVE, "Type") => {
+                Box::new("wireguard".to_owned())
+            }
+            ("/ActiveConnection/1", NM_CONNECTION_ACTIVE, "Ip4Config") => {
+                Box::new(dbus::Path::new(format!("{}/IP4Config/1", NM_MANAGER_PATH)).unwrap())
+            }
+            ("/ActiveConnection/2", NM_CONNECTION_ACTIVE, "Ip4Config")
+            | ("/ActiveConnection/3", NM_CONNECTION_ACTIVE, "Ip4Config") => {
+                Box::new(dbus::Path::new("/").unwrap())
+            }
+            ("/IP4Config/1", NM_IP4_CONFIG, "Gateway") => Box::new("192.168.1.1".to_owned()),
+            ("/ActiveConnection/1", NM_CONNECTION_ACTIVE, "Devices") => paths(&["/Devices/1"]),
+            ("/ActiveConnection/2", NM_CONNECTION_ACTIVE, "Devices") => paths(&["/Devices/2"]),
+            ("/ActiveConnection/3", NM_CONNECTION_ACTIVE, "Devices") => paths(&["/Devices/3"]),
+            ("/Devices/1", NM_DEVICE, "Interface") => Box::new("eth0".to_owned()),
+            ("/Devices/2", NM_DEVICE, "Interface") => Box::new("wlan0".to_owned()),
+            ("/Devices/3", NM_DEVICE, "Interface") => Box::new("wg-mullvad".to_owned()),
+            ("/Devices/2", NM_DEVICE_WIRELESS, "ActiveAccessPoint") => {
+                Box::new(dbus::Path::new(format!("{}/AccessPoint/1", NM_MANAGER_PATH)).unwrap())
+            }
+            ("/AccessPoint/1", NM_ACCESS_POINT, "Ssid") => Box::new(b"Home network".to_vec()),
+            _ => return None,
+        };
+        Some(Variant(value))
+    }
+
+    /// Answers `Properties.Get` calls on the session bus, using the name of NetworkManager,
+    /// until `stop` is set.
+    fn run_mock_network_manager(ready_tx: mpsc::Sender<()>, stop: Arc<AtomicBool>) {
+        let connection =
+            LocalConnection::new_session().expect("Failed to connect to the session bus");
+        connection
+            .request_name(NM_BUS, false, true, true)
+            .expect("Failed to acquire the NetworkManager bus name");
+        connection.start_receive(
+            MatchRule::new_method_call(),
+            Box::new(|message: Message, connection: &LocalConnection| {
+                let reply = message
+                    .read2::<&str, &str>()
+                    .ok()
+                    .and_then(|(interface, property)| {
+                        mock_property(&message.path()?, interface, property)
+                    })
+                    .map(|value| message.method_return().append1(value))
+                    .unwrap_or_else(|| {
+                        dbus::Message::error(
+                            &message,
+                            &"org.freedesktop.DBus.Error.UnknownProperty".into(),
+                            &std::ffi::CString::new("Unknown property").unwrap(),
+                        )
+                    });
+                let _ = connection.send(reply);
+                true
+            }),
+        );
+        let _ = ready_tx.send(());
+
+        while !stop.load(Ordering::Acquire) {
+            let _ = connection.process(Duration::from_millis(100));
+        }
+    }
+
+    /// Queries the mock service over a real session bus, to test the D-Bus side of the lookup.
+    #[test]
+    #[ignore = "requires a D-Bus session bus"]
+    fn test_active_networks() {
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let server_stop = stop.clone();
+        let server = thread::spawn(move || run_mock_network_manager(ready_tx, server_stop));
+        ready_rx
+            .recv()
+            .expect("The mock NetworkManager service failed to start");
+
+        let network_manager = NetworkManager {
+            connection: Arc::new(SyncConnection::new_session().unwrap()),
+        };
+        let networks = network_manager.active_networks();
+        stop.store(true, Ordering::Release);
+        server.join().unwrap();
+
+        assert_eq!(
+            networks.expect("Failed to query active networks"),
+            vec![
+                ActiveNetwork {
+                    interface: "eth0".to_owned(),
+                    ssid: None,
+                    gateway: Some("192.168.1.1".parse().unwrap()),
+                },
+                ActiveNetwork {
+                    interface: "wlan0".to_owned(),
+                    ssid: Some("Home network".to_owned()),
+                    gateway: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_active_networks_from_properties() {
+        let connections = vec![
+            ActiveConnectionProperties {
+                connection_type: NM_CONNECTION_TYPE_ETHERNET.to_owned(),
+                gateway: Some("192.168.1.1".to_owned()),
+                devices: vec![ActiveDeviceProperties {
+                    interface: "eth0".to_owned(),
+                    ssid: None,
+                }],
+            },
+            ActiveConnectionProperties {
+                connection_type: NM_CONNECTION_TYPE_WIRELESS.to_owned(),
+                gateway: Some("".to_owned()),
+                devices: vec![
+                    ActiveDeviceProperties {
+                        interface: "wlan0".to_owned(),
+                        ssid: Some(b"Home network".to_vec()),
+                    },
+                    ActiveDeviceProperties {
+                        interface: "wlan1".to_owned(),
+                        ssid: None,
+                    },
+                ],
+            },
+            ActiveConnectionProperties {
+                connection_type: "wireguard".to_owned(),
+                gateway: None,
+                devices: vec![ActiveDeviceProperties {
+                    interface: "wg-mullvad".to_owned(),
+                    ssid: None,
+                }],
+            },
+        ];
+
+        assert_eq!(
+            active_networks_from_properties(connections),
+            vec![
+                ActiveNetwork {
+                    interface: "eth0".to_owned(),
+                    ssid: None,
+                    gateway: Some("192.168.1.1".parse().unwrap()),
+                },
+                ActiveNetwork {
+                    interface: "wlan0".to_owned(),
+                    ssid: Some("Home network".to_owned()),
+                    gateway: None,
+                },
+                ActiveNetwork {
+                    interface: "wlan1".to_owned(),
+                    ssid: None,
+                    gateway: None,
+                },
+            ]
+        );
+    }
 
     #[test]
     fn test_valid_versions() {
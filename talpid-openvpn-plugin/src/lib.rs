@@ -41,6 +41,7 @@ pub static INTERESTING_EVENTS: &'static [EventType] = &[
     EventType::Up,
     EventType::RouteUp,
     EventType::RoutePredown,
+    EventType::TlsVerify,
 ];
 
 openvpn_plugin!(
@@ -89,19 +90,21 @@ fn openvpn_close(_handle: Mutex<Option<EventProcessor>>) {
 
 fn openvpn_event(
     event: EventType,
-    _args: Vec<CString>,
+    args: Vec<CString>,
     env: HashMap<CString, CString>,
     handle: &mut Mutex<Option<EventProcessor>>,
 ) -> Result<EventResult, Error> {
     log::debug!("Received event: {:?}", event);
 
+    let parsed_args =
+        openvpn_plugin::ffi::parse::string_array_utf8(&args).map_err(Error::ParseArgsFailed)?;
     let parsed_env = openvpn_plugin::ffi::parse::env_utf8(&env).map_err(Error::ParseEnvFailed)?;
 
     let mut ctx = handle
         .lock()
         .expect("failed to obtain mutex for EventProcessor");
     if let Some(processor) = ctx.as_mut() {
-        match processor.process_event(event, parsed_env) {
+        match processor.process_event(event, parsed_args, parsed_env) {
             Ok(()) => Ok(EventResult::Success),
             Err(e) => {
                 log::error!("{}", e.display_chain());
@@ -55,10 +55,34 @@ impl EventProcessor {
     pub fn process_event(
         &mut self,
         event: openvpn_plugin::EventType,
+        args: Vec<String>,
         env: HashMap<String, String>,
     ) -> Result<(), Error> {
         log::debug!("Processing \"{:?}\" event", event);
 
+        match event {
+            openvpn_plugin::EventType::TlsVerify => {
+                let details = proto::TlsVerifyDetails {
+                    certificate_depth: Self::certificate_depth(&args),
+                    env,
+                };
+                // Failing to report the event must not cause OpenVPN to reject the certificate.
+                if let Err(error) = self.runtime.block_on(self.ipc_client.tls_verify(details)) {
+                    log::warn!("Failed to report TLS verification: {}", error);
+                }
+                return Ok(());
+            }
+            openvpn_plugin::EventType::RoutePredown => {
+                // OpenVPN exports the final counters when tearing down the tunnel.
+                if let Some(details) = Self::byte_count_from_env(&env) {
+                    if let Err(error) = self.runtime.block_on(self.ipc_client.byte_count(details)) {
+                        log::warn!("Failed to report byte count: {}", error);
+                    }
+                }
+            }
+            _ => (),
+        }
+
         let details = proto::EventDetails { env };
 
         let response = match event {
@@ -76,4 +100,19 @@ impl EventProcessor {
         };
         response.map(|_| ()).map_err(Error::SendEvent)
     }
+
+    /// Returns the certificate depth passed to the `TlsVerify` event. The first argument is the
+    /// path to the plugin, followed by the depth and the subject of the certificate.
+    fn certificate_depth(args: &[String]) -> u32 {
+        args.get(1)
+            .and_then(|depth| depth.parse().ok())
+            .unwrap_or(0)
+    }
+
+    fn byte_count_from_env(env: &HashMap<String, String>) -> Option<proto::ByteCountDetails> {
+        Some(proto::ByteCountDetails {
+            bytes_sent: env.get("bytes_sent")?.parse().ok()?,
+            bytes_received: env.get("bytes_received")?.parse().ok()?,
+        })
+    }
 }
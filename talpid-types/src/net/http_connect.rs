@@ -0,0 +1,160 @@
+//! Client side of the HTTP `CONNECT` method, used to reach remote hosts through HTTP proxies.
+
+use crate::net::openvpn::ProxyAuth;
+use std::{io, net::SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Upper limit on the size of the response header sent by the proxy.
+const MAX_RESPONSE_HEADER_SIZE: usize = 8 * 1024;
+
+/// Asks the HTTP proxy at the other end of `stream` to connect to `target`, using the `CONNECT`
+/// method. Only basic authentication is supported.
+pub async fn http_connect<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    target: SocketAddr,
+    auth: Option<&ProxyAuth>,
+) -> io::Result<()> {
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+    if let Some(auth) = auth {
+        let credentials = base64::encode(format!("{}:{}", auth.username, auth.password));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read one byte at a time, so that no tunneled data is consumed
+    let mut response = vec![];
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_RESPONSE_HEADER_SIZE {
+            return Err(http_connect_error("response header too large"));
+        }
+        response.push(stream.read_u8().await?);
+    }
+
+    let status = std::str::from_utf8(&response)
+        .ok()
+        .and_then(|response| response.split_whitespace().nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| http_connect_error("invalid response from proxy"))?;
+    if !(200..300).contains(&status) {
+        return Err(http_connect_error(&format!(
+            "proxy refused to connect, status {}",
+            status
+        )));
+    }
+    Ok(())
+}
+
+fn http_connect_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("HTTP CONNECT: {}", message))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Accepts HTTP `CONNECT` requests and relays the traffic of each client to the requested
+    /// target. The `Proxy-Authorization` header must match `authorization`, if it is given.
+    async fn run_http_proxy(listener: TcpListener, authorization: Option<String>) {
+        while let Ok((mut client, _)) = listener.accept().await {
+            let authorization = authorization.clone();
+            tokio::spawn(async move {
+                let mut request = vec![];
+                while !request.ends_with(b"\r\n\r\n") {
+                    request.push(client.read_u8().await.unwrap());
+                }
+                let request = String::from_utf8(request).unwrap();
+                let target: SocketAddr = request
+                    .strip_prefix("CONNECT ")
+                    .and_then(|request| request.split_whitespace().next())
+                    .and_then(|target| target.parse().ok())
+                    .expect("invalid CONNECT request");
+
+                if let Some(authorization) = authorization {
+                    let header = format!("Proxy-Authorization: {}\r\n", authorization);
+                    if !request.contains(&header) {
+                        client
+                            .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                            .await
+                            .unwrap();
+                        return;
+                    }
+                }
+
+                let mut upstream = TcpStream::connect(target).await.unwrap();
+                client
+                    .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                    .await
+                    .unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+            });
+        }
+    }
+
+    #[test]
+    fn test_http_connect_auth() {
+        let runtime = tokio::runtime::Runtime::new().expect("Failed to initialize runtime");
+        runtime.block_on(async {
+            let target_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let target = target_listener.local_addr().unwrap();
+            let proxy_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let proxy_address = proxy_listener.local_addr().unwrap();
+
+            tokio::spawn(run_http_proxy(
+                proxy_listener,
+                Some(format!("Basic {}", base64::encode("user:pass"))),
+            ));
+            tokio::spawn(async move {
+                let (mut stream, _) = target_listener.accept().await.unwrap();
+                stream.write_all(b"hello").await.unwrap();
+            });
+
+            let mut stream = TcpStream::connect(proxy_address).await.unwrap();
+            let wrong_auth = ProxyAuth {
+                username: "user".to_owned(),
+                password: "wrong".to_owned(),
+            };
+            assert!(http_connect(&mut stream, target, Some(&wrong_auth))
+                .await
+                .is_err());
+
+            let mut stream = TcpStream::connect(proxy_address).await.unwrap();
+            let auth = ProxyAuth {
+                username: "user".to_owned(),
+                password: "pass".to_owned(),
+            };
+            http_connect(&mut stream, target, Some(&auth))
+                .await
+                .expect("Failed to connect through proxy");
+            let mut message = [0u8; 5];
+            stream.read_exact(&mut message).await.unwrap();
+            assert_eq!(&message, b"hello");
+        });
+    }
+
+    #[test]
+    fn test_http_connect_refused() {
+        let runtime = tokio::runtime::Runtime::new().expect("Failed to initialize runtime");
+        runtime.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+
+            tokio::spawn(async move {
+                let (mut client, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                while !request.ends_with(b"\r\n\r\n") {
+                    request.push(client.read_u8().await.unwrap());
+                }
+                client
+                    .write_all(b"HTTP/1.1 403 Forbidden\r\n\r\n")
+                    .await
+                    .unwrap();
+            });
+
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            let result = http_connect(&mut stream, "127.0.0.1:443".parse().unwrap(), None).await;
+            assert!(result.is_err());
+        });
+    }
+}
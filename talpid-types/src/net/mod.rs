@@ -2,11 +2,14 @@
 use jnix::IntoJava;
 use serde::{Deserialize, Serialize};
 use std::{
+    convert::TryFrom,
     fmt,
     net::{IpAddr, SocketAddr},
     str::FromStr,
 };
 
+pub mod http_connect;
+pub mod obfuscation;
 pub mod openvpn;
 pub mod proxy;
 pub mod wireguard;
@@ -28,7 +31,10 @@ impl TunnelParameters {
                 tunnel_type: TunnelType::OpenVpn,
                 endpoint: params.config.endpoint,
                 proxy: params.proxy.as_ref().map(|proxy| proxy.get_endpoint()),
-                entry_endpoint: None,
+                entry_endpoint: params
+                    .wireguard_entry
+                    .as_ref()
+                    .map(|entry| entry.connection.get_endpoint()),
             },
             TunnelParameters::Wireguard(params) => TunnelEndpoint {
                 tunnel_type: TunnelType::Wireguard,
@@ -36,7 +42,12 @@ impl TunnelParameters {
                     .connection
                     .get_exit_endpoint()
                     .unwrap_or(params.connection.get_endpoint()),
-                proxy: None,
+                proxy: params
+                    .get_proxy_endpoint()
+                    .map(|endpoint| proxy::ProxyEndpoint {
+                        endpoint,
+                        proxy_type: proxy::ProxyType::Shadowsocks,
+                    }),
                 entry_endpoint: params
                     .connection
                     .get_exit_endpoint()
@@ -48,19 +59,30 @@ impl TunnelParameters {
     // Returns the endpoint that will be connected to
     pub fn get_next_hop_endpoint(&self) -> Endpoint {
         match self {
-            TunnelParameters::OpenVpn(params) => params
-                .proxy
-                .as_ref()
-                .map(|proxy| proxy.get_endpoint().endpoint)
-                .unwrap_or(params.config.endpoint),
-            TunnelParameters::Wireguard(params) => params.connection.get_endpoint(),
+            TunnelParameters::OpenVpn(params) => match &params.wireguard_entry {
+                Some(entry) => entry
+                    .get_proxy_endpoint()
+                    .unwrap_or(entry.connection.get_endpoint()),
+                None => params
+                    .proxy
+                    .as_ref()
+                    .map(|proxy| proxy.get_endpoint().endpoint)
+                    .unwrap_or(params.config.endpoint),
+            },
+            TunnelParameters::Wireguard(params) => params
+                .get_proxy_endpoint()
+                .or_else(|| params.get_obfuscator_endpoint())
+                .unwrap_or(params.connection.get_endpoint()),
         }
     }
 
     // Returns the exit endpoint, if it differs from the next hop endpoint
     pub fn get_exit_hop_endpoint(&self) -> Option<Endpoint> {
         match self {
-            TunnelParameters::OpenVpn(_params) => None,
+            TunnelParameters::OpenVpn(params) => params
+                .wireguard_entry
+                .as_ref()
+                .map(|_| params.config.endpoint),
             TunnelParameters::Wireguard(params) => params.connection.get_exit_endpoint(),
         }
     }
@@ -124,21 +146,15 @@ pub struct TunnelEndpoint {
 impl fmt::Display for TunnelEndpoint {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         write!(f, "{} - {}", self.tunnel_type, self.endpoint)?;
-        match self.tunnel_type {
-            TunnelType::OpenVpn => {
-                if let Some(ref proxy) = self.proxy {
-                    write!(
-                        f,
-                        " via {} {} over {}",
-                        proxy.proxy_type, proxy.endpoint.address, proxy.endpoint.protocol
-                    )?;
-                }
-            }
-            TunnelType::Wireguard => {
-                if let Some(ref entry_endpoint) = self.entry_endpoint {
-                    write!(f, " via {}", entry_endpoint)?;
-                }
-            }
+        if let Some(ref entry_endpoint) = self.entry_endpoint {
+            write!(f, " via {}", entry_endpoint)?;
+        }
+        if let Some(ref proxy) = self.proxy {
+            write!(
+                f,
+                " via {} {} over {}",
+                proxy.proxy_type, proxy.endpoint.address, proxy.endpoint.protocol
+            )?;
         }
         Ok(())
     }
@@ -252,6 +268,151 @@ pub struct GenericTunnelOptions {
     pub enable_ipv6: bool,
 }
 
+/// Connectivity of the host, as inferred by the offline monitor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Connectivity {
+    /// There is no usable link or route to the internet.
+    Offline,
+    /// The host appears to be connected to the internet.
+    Online,
+    /// The host has a route to the internet, but the connectivity probe was answered by
+    /// something other than the expected server. This usually means that a captive portal is
+    /// intercepting traffic. This is only ever detected on Linux, and only if a
+    /// [`ConnectivityProbe`] is set.
+    CaptivePortal,
+}
+
+impl Connectivity {
+    /// Returns `true` if no tunnel can possibly be established.
+    pub fn is_offline(&self) -> bool {
+        *self == Connectivity::Offline
+    }
+}
+
+impl From<bool> for Connectivity {
+    /// Converts the result of a plain offline check into a `Connectivity`.
+    fn from(is_offline: bool) -> Self {
+        if is_offline {
+            Connectivity::Offline
+        } else {
+            Connectivity::Online
+        }
+    }
+}
+
+impl fmt::Display for Connectivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Connectivity::Offline => "offline".fmt(f),
+            Connectivity::Online => "online".fmt(f),
+            Connectivity::CaptivePortal => "captive portal suspected".fmt(f),
+        }
+    }
+}
+
+/// HTTP server that is used to actively probe for captive portals. The server must respond with
+/// `204 No Content`. It is given as a URL on the form `http://host[:port][/path]`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ConnectivityProbe {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl FromStr for ConnectivityProbe {
+    type Err = ConnectivityProbeParseError;
+
+    fn from_str(url: &str) -> Result<Self, Self::Err> {
+        let url = url
+            .strip_prefix("http://")
+            .ok_or(ConnectivityProbeParseError)?;
+        let (authority, path) = match url.find('/') {
+            Some(index) => (&url[..index], url[index..].to_string()),
+            None => (url, "/".to_string()),
+        };
+        let (host, port) = match authority.rfind(':') {
+            // Only treat the colon as a port separator if it's not part of an IPv6 literal
+            Some(index) if !authority[index..].contains(']') => (
+                &authority[..index],
+                authority[index + 1..]
+                    .parse()
+                    .map_err(|_| ConnectivityProbeParseError)?,
+            ),
+            _ => (authority, 80),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(ConnectivityProbeParseError);
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+}
+
+impl TryFrom<String> for ConnectivityProbe {
+    type Error = ConnectivityProbeParseError;
+
+    fn try_from(url: String) -> Result<Self, Self::Error> {
+        url.parse()
+    }
+}
+
+impl From<ConnectivityProbe> for String {
+    fn from(probe: ConnectivityProbe) -> Self {
+        probe.to_string()
+    }
+}
+
+impl fmt::Display for ConnectivityProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.host.parse::<IpAddr>() {
+            Ok(IpAddr::V6(address)) => write!(f, "http://[{}]:{}{}", address, self.port, self.path),
+            _ => write!(f, "http://{}:{}{}", self.host, self.port, self.path),
+        }
+    }
+}
+
+/// Returned when `ConnectivityProbe::from_str` fails to parse a URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectivityProbeParseError;
+
+impl fmt::Display for ConnectivityProbeParseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Not a valid connectivity probe URL. Expected http://host[:port][/path]")
+    }
+}
+
+impl std::error::Error for ConnectivityProbeParseError {}
+
+/// A physical network that the host is connected to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ConnectedNetwork {
+    /// Name of the network interface.
+    pub interface: String,
+    /// SSID of the network, if it is a Wi-Fi network.
+    pub ssid: Option<String>,
+    /// MAC address of the default gateway, formatted as lowercase colon-separated hex digits.
+    pub gateway_mac: Option<String>,
+}
+
+impl fmt::Display for ConnectedNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.ssid {
+            Some(ssid) => write!(f, "Wi-Fi network \"{}\" on {}", ssid, self.interface)?,
+            None => write!(f, "{}", self.interface)?,
+        }
+        if let Some(gateway_mac) = &self.gateway_mac {
+            write!(f, " (gateway {})", gateway_mac)?;
+        }
+        Ok(())
+    }
+}
+
 /// Returns a vector of IP networks representing all of the internet, 0.0.0.0/0.
 /// This may be used in [`crate::net::wireguard::PeerConfig`] to route all traffic
 /// to the tunnel interface.
@@ -261,3 +422,50 @@ pub fn all_of_the_internet() -> Vec<ipnetwork::IpNetwork> {
         "::0/0".parse().expect("Failed to parse ipv6 network"),
     ]
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_connectivity_probe() {
+        assert_eq!(
+            "http://probe.example.com/generate_204".parse(),
+            Ok(ConnectivityProbe {
+                host: "probe.example.com".to_string(),
+                port: 80,
+                path: "/generate_204".to_string(),
+            })
+        );
+        assert_eq!(
+            "http://10.0.0.1:8080".parse(),
+            Ok(ConnectivityProbe {
+                host: "10.0.0.1".to_string(),
+                port: 8080,
+                path: "/".to_string(),
+            })
+        );
+        let ipv6_probe: ConnectivityProbe = "http://[::1]/check".parse().unwrap();
+        assert_eq!(
+            ipv6_probe,
+            ConnectivityProbe {
+                host: "::1".to_string(),
+                port: 80,
+                path: "/check".to_string(),
+            }
+        );
+        assert_eq!(ipv6_probe.to_string(), "http://[::1]:80/check");
+        assert_eq!(
+            "https://probe.example.com".parse::<ConnectivityProbe>(),
+            Err(ConnectivityProbeParseError)
+        );
+        assert_eq!(
+            "http://:80/".parse::<ConnectivityProbe>(),
+            Err(ConnectivityProbeParseError)
+        );
+        assert_eq!(
+            "http://probe.example.com:http/".parse::<ConnectivityProbe>(),
+            Err(ConnectivityProbeParseError)
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use crate::net::{
+    openvpn::HttpConnectProxySettings, wireguard::PeerConfig, Endpoint, TransportProtocol,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, net::SocketAddr};
+
+/// Protocols that WireGuard traffic can be obfuscated with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObfuscationType {
+    /// WireGuard datagrams are sent over a TCP stream using `udp-over-tcp`.
+    #[serde(rename = "udp2tcp")]
+    Udp2Tcp,
+}
+
+impl ObfuscationType {
+    /// Returns the transport protocol used to reach the obfuscation server.
+    pub fn transport_protocol(&self) -> TransportProtocol {
+        match self {
+            ObfuscationType::Udp2Tcp => TransportProtocol::Tcp,
+        }
+    }
+}
+
+impl fmt::Display for ObfuscationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            ObfuscationType::Udp2Tcp => write!(f, "UDP-over-TCP"),
+        }
+    }
+}
+
+/// Settings for the obfuscator that traffic to a WireGuard peer is relayed through.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObfuscatorConfig {
+    #[serde(rename = "udp2tcp")]
+    Udp2Tcp {
+        /// Address of the UDP-over-TCP server.
+        endpoint: SocketAddr,
+        /// HTTP proxy that the connection to the server is tunneled through.
+        #[serde(default)]
+        proxy: Option<HttpConnectProxySettings>,
+    },
+}
+
+impl ObfuscatorConfig {
+    /// Returns the obfuscator needed to reach `peer`, or `None` if the peer is reached directly
+    /// over UDP. TCP connections are tunneled through `proxy`, if one is given.
+    pub fn for_peer(peer: &PeerConfig, proxy: Option<HttpConnectProxySettings>) -> Option<Self> {
+        match peer.protocol {
+            TransportProtocol::Udp => None,
+            TransportProtocol::Tcp => Some(ObfuscatorConfig::Udp2Tcp {
+                endpoint: peer.endpoint,
+                proxy,
+            }),
+        }
+    }
+
+    pub fn get_type(&self) -> ObfuscationType {
+        match self {
+            ObfuscatorConfig::Udp2Tcp { .. } => ObfuscationType::Udp2Tcp,
+        }
+    }
+
+    /// Returns the remote endpoint that the obfuscator connects to. This is the proxy, if the
+    /// obfuscator is proxied.
+    pub fn get_endpoint(&self) -> Endpoint {
+        match self {
+            ObfuscatorConfig::Udp2Tcp {
+                proxy: Some(proxy), ..
+            } => proxy.get_endpoint(),
+            ObfuscatorConfig::Udp2Tcp { endpoint, .. } => Endpoint {
+                address: *endpoint,
+                protocol: TransportProtocol::Tcp,
+            },
+        }
+    }
+}
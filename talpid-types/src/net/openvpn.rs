@@ -1,18 +1,22 @@
 use crate::net::{
     proxy::{ProxyEndpoint, ProxyType},
-    Endpoint, GenericTunnelOptions, TransportProtocol,
+    wireguard, Endpoint, GenericTunnelOptions, TransportProtocol,
 };
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use std::{fmt, net::SocketAddr, str::FromStr};
 
 /// Information needed by `OpenVpnMonitor` to establish a tunnel connection.
 /// See [`crate::net::TunnelParameters`].
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 pub struct TunnelParameters {
     pub config: ConnectionConfig,
     pub options: TunnelOptions,
     pub generic_options: GenericTunnelOptions,
     pub proxy: Option<ProxySettings>,
+    /// WireGuard tunnel that traffic to the OpenVPN server is routed through. Only the address
+    /// of the OpenVPN server should be allowed for its peer.
+    #[serde(default)]
+    pub wireguard_entry: Option<wireguard::TunnelParameters>,
 }
 
 /// Connection configuration used by [`TunnelParameters`].
@@ -42,8 +46,99 @@ pub struct TunnelOptions {
     /// Optional argument for openvpn to try and limit TCP packet size,
     /// as discussed [here](https://openvpn.net/archive/openvpn-users/2003-11/msg00154.html)
     pub mssfix: Option<u16>,
+    /// Restricts the data channel to a single cipher. The cipher is negotiated with the server if
+    /// this is unset.
+    #[serde(default)]
+    pub cipher: Option<Cipher>,
+    /// Lowest TLS version that is accepted for the control channel.
+    #[serde(default)]
+    pub tls_version_min: TlsVersion,
+}
+
+/// Data channel ciphers that OpenVPN tunnels can be restricted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Cipher {
+    #[serde(rename = "aes-256-gcm")]
+    Aes256Gcm,
+    #[serde(rename = "chacha20-poly1305")]
+    Chacha20Poly1305,
+}
+
+impl Cipher {
+    /// All ciphers that are supported by the relays, unless a relay states otherwise.
+    pub const ALL: &'static [Cipher] = &[Cipher::Aes256Gcm, Cipher::Chacha20Poly1305];
+
+    /// Returns the name of the cipher as understood by OpenVPN.
+    pub fn openvpn_name(&self) -> &'static str {
+        match self {
+            Cipher::Aes256Gcm => "AES-256-GCM",
+            Cipher::Chacha20Poly1305 => "CHACHA20-POLY1305",
+        }
+    }
+}
+
+impl fmt::Display for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.openvpn_name())
+    }
+}
+
+impl FromStr for Cipher {
+    type Err = InvalidTunnelOption;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Cipher::ALL
+            .iter()
+            .find(|cipher| cipher.openvpn_name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| InvalidTunnelOption(format!("unsupported cipher \"{}\"", s)))
+    }
+}
+
+/// TLS versions that the OpenVPN control channel can be restricted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TlsVersion {
+    #[serde(rename = "1.2")]
+    V1_2,
+    #[serde(rename = "1.3")]
+    V1_3,
+}
+
+impl Default for TlsVersion {
+    fn default() -> Self {
+        TlsVersion::V1_3
+    }
+}
+
+impl fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsVersion::V1_2 => f.write_str("1.2"),
+            TlsVersion::V1_3 => f.write_str("1.3"),
+        }
+    }
+}
+
+impl FromStr for TlsVersion {
+    type Err = InvalidTunnelOption;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1.2" => Ok(TlsVersion::V1_2),
+            "1.3" => Ok(TlsVersion::V1_3),
+            _ => Err(InvalidTunnelOption(format!(
+                "unsupported TLS version \"{}\"",
+                s
+            ))),
+        }
+    }
 }
 
+/// Returned when an OpenVPN tunnel option cannot be parsed.
+#[derive(err_derive::Error, Debug, Clone, PartialEq, Eq)]
+#[error(display = "Invalid OpenVPN tunnel option: {}", _0)]
+pub struct InvalidTunnelOption(pub String);
+
 /// Proxy server options to be used by `OpenVpnMonitor` when starting a tunnel.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
@@ -51,6 +146,7 @@ pub enum ProxySettings {
     Local(LocalProxySettings),
     Remote(RemoteProxySettings),
     Shadowsocks(ShadowsocksProxySettings),
+    HttpConnect(HttpConnectProxySettings),
 }
 
 
@@ -69,6 +165,10 @@ impl ProxySettings {
                 endpoint: settings.get_endpoint(),
                 proxy_type: ProxyType::Shadowsocks,
             },
+            ProxySettings::HttpConnect(settings) => ProxyEndpoint {
+                endpoint: settings.get_endpoint(),
+                proxy_type: ProxyType::Custom,
+            },
         }
     }
 }
@@ -105,6 +205,23 @@ impl RemoteProxySettings {
     }
 }
 
+/// Options for an HTTP proxy that tunnels TCP connections using the `CONNECT` method.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub struct HttpConnectProxySettings {
+    pub address: SocketAddr,
+    /// Credentials sent using basic authentication.
+    pub auth: Option<ProxyAuth>,
+}
+
+impl HttpConnectProxySettings {
+    pub fn get_endpoint(&self) -> Endpoint {
+        Endpoint {
+            address: self.address,
+            protocol: TransportProtocol::Tcp,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
 pub struct ProxyAuth {
     pub username: String,
@@ -192,6 +309,23 @@ pub fn validate_proxy_settings(proxy: &ProxySettings) -> Result<(), String> {
                 return Err(String::from("Invalid cipher"));
             }
         }
+        ProxySettings::HttpConnect(http) => {
+            if http.address.port() == 0 {
+                return Err(String::from("Invalid port number"));
+            }
+            if http.address.ip().is_loopback() {
+                return Err(String::from("localhost is not a valid remote server"));
+            }
+            if let Some(ref auth) = http.auth {
+                // Basic authentication separates the username and password with a colon
+                if auth.username.is_empty() || auth.username.contains(':') {
+                    return Err(String::from("Invalid username"));
+                }
+                if auth.username.contains('\n') || auth.password.contains('\n') {
+                    return Err(String::from("Credentials may not contain line breaks"));
+                }
+            }
+        }
     };
     Ok(())
 }
@@ -1,4 +1,7 @@
-use crate::net::{Endpoint, GenericTunnelOptions, TransportProtocol};
+use crate::net::{
+    obfuscation::ObfuscatorConfig, openvpn::ShadowsocksProxySettings, Endpoint,
+    GenericTunnelOptions, TransportProtocol,
+};
 use ipnetwork::IpNetwork;
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
@@ -18,6 +21,29 @@ pub struct TunnelParameters {
     pub connection: ConnectionConfig,
     pub options: TunnelOptions,
     pub generic_options: GenericTunnelOptions,
+    /// Shadowsocks bridge that traffic to the entry peer is relayed through.
+    #[serde(default)]
+    pub proxy: Option<ShadowsocksProxySettings>,
+    /// Obfuscator that traffic to the entry peer is relayed through.
+    #[serde(default)]
+    pub obfuscation: Option<ObfuscatorConfig>,
+}
+
+impl TunnelParameters {
+    /// Returns the endpoint of the Shadowsocks bridge, if one is used.
+    pub fn get_proxy_endpoint(&self) -> Option<Endpoint> {
+        self.proxy.as_ref().map(|proxy| Endpoint {
+            address: proxy.peer,
+            protocol: TransportProtocol::Udp,
+        })
+    }
+
+    /// Returns the endpoint of the obfuscation server, if one is used.
+    pub fn get_obfuscator_endpoint(&self) -> Option<Endpoint> {
+        self.obfuscation
+            .as_ref()
+            .map(ObfuscatorConfig::get_endpoint)
+    }
 }
 
 /// Connection-specific configuration in [`TunnelParameters`].
@@ -56,7 +82,8 @@ pub struct PeerConfig {
     /// IP address of the WireGuard server.
     pub endpoint: SocketAddr,
     /// Transport protocol. WireGuard only supports UDP directly.
-    /// If this is set to TCP, then traffic is proxied using [`udp_to_tcp::Udp2Tcp`].
+    /// If this is set to TCP, then traffic must be relayed through an obfuscator, such as
+    /// [`ObfuscatorConfig::Udp2Tcp`].
     #[serde(default = "default_peer_transport")]
     pub protocol: TransportProtocol,
 }
@@ -107,6 +107,15 @@ pub enum ErrorStateCause {
     /// Error reported by split tunnel module.
     #[cfg(target_os = "windows")]
     SplitTunnelError,
+    /// A captive portal appears to intercept traffic, so no tunnel can be established.
+    #[cfg(target_os = "linux")]
+    CaptivePortal,
+    /// Traffic is blocked, except what is needed to log in to a captive portal.
+    #[cfg(not(target_os = "android"))]
+    CaptivePortalLogin,
+    /// The account has run out of time.
+    #[cfg(not(target_os = "android"))]
+    AccountExpired,
 }
 
 /// Errors that can occur when generating tunnel parameters.
@@ -199,6 +208,12 @@ impl fmt::Display for ErrorStateCause {
             VpnPermissionDenied => "The Android VPN permission was denied when creating the tunnel",
             #[cfg(target_os = "windows")]
             SplitTunnelError => "The split tunneling module reported an error",
+            #[cfg(target_os = "linux")]
+            CaptivePortal => "A captive portal is intercepting traffic",
+            #[cfg(not(target_os = "android"))]
+            CaptivePortalLogin => "Allowing traffic needed to log in to a captive portal",
+            #[cfg(not(target_os = "android"))]
+            AccountExpired => "The account has no time left",
         };
 
         write!(f, "{}", description)
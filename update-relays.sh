@@ -3,4 +3,10 @@
 echo "Updating relay list..."
 set -e
 
-cargo +stable run --bin relay_list --release > dist-assets/relays.json
+cargo +stable run --bin relay_list --release -- fetch dist-assets/relays.json
+
+# Sign the bundled relay list, for builds that require signed relay lists
+if [[ -n "${RELAY_LIST_SIGNING_KEY:-""}" ]]; then
+    cargo +stable run --bin relay_list --release -- sign "$RELAY_LIST_SIGNING_KEY" \
+        dist-assets/relays.json
+fi
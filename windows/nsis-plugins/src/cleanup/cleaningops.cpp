@@ -329,8 +329,15 @@ void RemoveSettingsServiceUser()
 
 void RemoveRelayCacheServiceUser()
 {
-	const auto cacheFile = GetSystemCacheDirectory().append(L"relays.json");
-	std::filesystem::remove(cacheFile);
+	const auto cacheDir = GetSystemCacheDirectory();
+
+	//
+	// The signature and entity tag are stored next to the relay list.
+	//
+	for (const auto fileName : { L"relays.json", L"relays.json.sig", L"relays.json.etag" })
+	{
+		std::filesystem::remove(std::filesystem::path(cacheDir).append(fileName));
+	}
 }
 
 void RemoveApiAddressCacheServiceUser()